@@ -6,7 +6,7 @@ pub struct BPlusTreeNode<K, V> {
     // 葉ノードの場合のみ、キーに対応する値のベクタ
     values: Option<Vec<V>>,
     // 内部ノードの場合のみ、子ノードへの参照（子の数は keys.len() + 1 になる）
-    children: Option<Vec<Box<BPlusTreeNode<K, V>>>>,
+    children: Option<Vec<BPlusTreeNode<K, V>>>,
     is_leaf: bool,
     // 葉ノード同士の連結（範囲検索高速化用）
     next: Option<Box<BPlusTreeNode<K, V>>>,
@@ -32,12 +32,34 @@ impl<K: Ord + Clone, V: Clone> BPlusTreeNode<K, V> {
         self.values.as_ref()
     }
 
-    pub fn children(&self) -> Option<&Vec<Box<BPlusTreeNode<K, V>>>> {
+    pub fn children(&self) -> Option<&Vec<BPlusTreeNode<K, V>>> {
         self.children.as_ref()
     }
 
-    pub fn next(&self) -> Option<&Box<BPlusTreeNode<K, V>>> {
-        self.next.as_ref()
+    /// 部分木に含まれるキーの数を数える。値には触れずに葉のキー数だけを合計する。
+    pub fn key_count(&self) -> usize {
+        match &self.children {
+            Some(children) if !self.is_leaf => children.iter().map(|c| c.key_count()).sum(),
+            _ => self.keys.len(),
+        }
+    }
+
+    /// 部分木の最小キー（最も左の葉の先頭キー）
+    pub fn first_key(&self) -> Option<&K> {
+        if self.is_leaf {
+            self.keys.first()
+        } else {
+            self.children.as_ref()?.first()?.first_key()
+        }
+    }
+
+    /// 部分木の最大キー（最も右の葉の末尾キー）
+    pub fn last_key(&self) -> Option<&K> {
+        if self.is_leaf {
+            self.keys.last()
+        } else {
+            self.children.as_ref()?.last()?.last_key()
+        }
     }
 
     /// 検索処理  
@@ -54,13 +76,61 @@ impl<K: Ord + Clone, V: Clone> BPlusTreeNode<K, V> {
         }
     }
 
+    /// 検索して値への可変参照を返す。辿り方は `search` と同じ。
+    pub fn search_mut(&mut self, key: &K) -> Option<&mut V> {
+        if self.is_leaf {
+            let i = self.keys.binary_search(key).ok()?;
+            self.values.as_mut()?.get_mut(i)
+        } else {
+            let i = match self.keys.binary_search(key) {
+                Ok(i) => i + 1,
+                Err(i) => i,
+            };
+            self.children.as_mut()?.get_mut(i)?.search_mut(key)
+        }
+    }
+
+    /// キーを削除して値を返す。  
+    /// - 葉ノードからはキーと値を取り除くだけで、兄弟ノードとの併合や再分配は行わない。  
+    /// - 子ノードが空になった場合は、その子と区切りキーを 1 つ取り除く（区切りキーは残りの子の
+    ///   範囲を正しく分けたままになる）。
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        if self.is_leaf {
+            let i = self.keys.binary_search(key).ok()?;
+            self.keys.remove(i);
+            return Some(self.values.as_mut()?.remove(i));
+        }
+        let idx = match self.keys.binary_search(key) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        };
+        let children = self.children.as_mut()?;
+        let value = children.get_mut(idx)?.remove(key)?;
+        if children[idx].is_empty() {
+            children.remove(idx);
+            if !self.keys.is_empty() {
+                self.keys.remove(idx.saturating_sub(1));
+            }
+        }
+        Some(value)
+    }
+
+    /// キーも子ノードも持たないノードかどうか
+    fn is_empty(&self) -> bool {
+        if self.is_leaf {
+            self.keys.is_empty()
+        } else {
+            self.children.as_ref().is_none_or(|c| c.is_empty())
+        }
+    }
+
     /// 非満杯ノードへの挿入  
     /// - 葉ノードの場合：binary_search() により挿入位置を決定し、重複キーは無視する。  
     /// - 内部ノードの場合：対象の子ノードが満杯なら先に分割し、再度挿入先を決定して再帰的に挿入する。
     pub fn insert_non_full(&mut self, key: K, value: V, t: usize) {
         if self.is_leaf {
             match self.keys.binary_search(&key) {
-                Ok(_) => return, // 重複キーは挿入しない
+                Ok(_) => (), // 重複キーは挿入しない
                 Err(pos) => {
                     self.keys.insert(pos, key);
                     self.values.as_mut().unwrap().insert(pos, value);
@@ -84,14 +154,16 @@ impl<K: Ord + Clone, V: Clone> BPlusTreeNode<K, V> {
     }
 
     /// 子ノード分割処理  
+    ///
     /// 【葉ノードの場合】  
     /// - 満杯の葉ノード（2t-1 個のキー）を、左側に t 個、右側に (t-1) 個に分割する。  
     /// - 右側新しい葉の先頭キーを親にコピーし、葉連結（next）を更新する。  
+    ///
     /// 【内部ノードの場合】  
     /// - 満杯の内部ノード（2t-1 個のキー）を、左側に t-1 個、右側に t 個とし、  
     ///   左側の最後のキーを親に昇格させる。
     pub fn split_child(&mut self, index: usize, t: usize) {
-        let child = self.children.as_mut().unwrap()[index].as_mut();
+        let child = &mut self.children.as_mut().unwrap()[index];
         if child.is_leaf {
             let split_index = t;
             let mut new_leaf = BPlusTreeNode::new(true);
@@ -102,7 +174,7 @@ impl<K: Ord + Clone, V: Clone> BPlusTreeNode<K, V> {
             new_leaf.next = child.next.take();
             child.next = Some(Box::new(new_leaf.clone()));
             self.keys.insert(index, new_leaf.keys[0].clone());
-            self.children.as_mut().unwrap().insert(index + 1, Box::new(new_leaf));
+            self.children.as_mut().unwrap().insert(index + 1, new_leaf);
         } else {
             let split_index = t - 1;
             let mut new_node = BPlusTreeNode::new(false);
@@ -112,7 +184,7 @@ impl<K: Ord + Clone, V: Clone> BPlusTreeNode<K, V> {
                 new_node.children = Some(child_list.split_off(t));
             }
             self.keys.insert(index, median);
-            self.children.as_mut().unwrap().insert(index + 1, Box::new(new_node));
+            self.children.as_mut().unwrap().insert(index + 1, new_node);
         }
     }
 }
@@ -143,7 +215,7 @@ impl<K: Ord + Clone, V: Clone> BPlusTree<K, V> {
         let root = self.root.as_mut().unwrap();
         if root.keys.len() == 2 * self.degree - 1 {
             let mut new_root = BPlusTreeNode::new(false);
-            new_root.children = Some(vec![std::mem::replace(root, Box::new(BPlusTreeNode::new(true)))]);
+            new_root.children = Some(vec![*std::mem::replace(root, Box::new(BPlusTreeNode::new(true)))]);
             new_root.split_child(0, self.degree);
             new_root.insert_non_full(key, value, self.degree);
             self.root = Some(Box::new(new_root));
@@ -155,6 +227,113 @@ impl<K: Ord + Clone, V: Clone> BPlusTree<K, V> {
     pub fn search(&self, key: &K) -> Option<&V> {
         (**self.root.as_ref()?).search(key)
     }
+
+    pub fn search_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.root.as_mut()?.search_mut(key)
+    }
+
+    /// キーがあれば値を置き換え、なければ挿入する。置き換えた場合は true を返す。
+    pub fn upsert(&mut self, key: K, value: V) -> bool {
+        match self.search_mut(&key) {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => {
+                self.insert(key, value);
+                false
+            }
+        }
+    }
+
+    /// キーを削除して値を返す。根が空になれば木を空にし、子が 1 つだけの内部ノードの根は子に置き換える。
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let root = self.root.as_mut()?;
+        let value = root.remove(key)?;
+        if root.is_empty() {
+            self.root = None;
+        }
+        while let Some(root) = self.root.as_mut() {
+            match root.children.as_mut() {
+                Some(children) if !root.is_leaf && children.len() == 1 => {
+                    let child = children.pop().unwrap();
+                    self.root = Some(Box::new(child));
+                }
+                _ => break,
+            }
+        }
+        Some(value)
+    }
+
+    /// 登録されているキーの数
+    pub fn key_count(&self) -> usize {
+        self.root.as_ref().map(|root| root.key_count()).unwrap_or(0)
+    }
+
+    /// 最小キー。値を走査せず、最も左の葉だけを辿る。
+    pub fn first_key(&self) -> Option<&K> {
+        self.root.as_ref()?.first_key()
+    }
+
+    /// 最大キー。値を走査せず、最も右の葉だけを辿る。
+    pub fn last_key(&self) -> Option<&K> {
+        self.root.as_ref()?.last_key()
+    }
+
+    /// キーの昇順に (キー, 値) を返すカーソル。値は複製せず参照で返す。
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut iter = Iter { stack: Vec::new(), leaf: None, pos: 0 };
+        if let Some(root) = self.root.as_deref() {
+            iter.descend(root);
+        }
+        iter
+    }
+}
+
+/// `BPlusTree::iter` のカーソル。内部ノードと次に降りる子の位置をスタックに積み、葉を左から順に辿る。
+pub struct Iter<'a, K, V> {
+    stack: Vec<(&'a BPlusTreeNode<K, V>, usize)>,
+    /// 読んでいる葉のキーと値
+    leaf: Option<(&'a [K], &'a [V])>,
+    pos: usize,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    fn descend(&mut self, node: &'a BPlusTreeNode<K, V>) {
+        if node.is_leaf {
+            self.leaf = Some((&node.keys, node.values.as_deref().unwrap_or(&[])));
+            self.pos = 0;
+        } else {
+            self.stack.push((node, 0));
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((keys, values)) = self.leaf {
+                if let (Some(key), Some(value)) = (keys.get(self.pos), values.get(self.pos)) {
+                    self.pos += 1;
+                    return Some((key, value));
+                }
+                self.leaf = None;
+            }
+            let top = self.stack.last_mut()?;
+            let children: &'a [BPlusTreeNode<K, V>] = top.0.children.as_deref().unwrap_or(&[]);
+            match children.get(top.1) {
+                Some(child) => {
+                    top.1 += 1;
+                    self.descend(child);
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
 }
 
 impl<K: Ord + Clone, V: Clone> Default for BPlusTree<K, V> {
@@ -227,6 +406,84 @@ mod tests {
         assert_eq!(tree.search(&10), Some(&"A".to_string()));
     }
     
+    #[test]
+    fn test_upsert() {
+        let mut tree = BPlusTree::new(2);
+        for k in 1..=20 {
+            tree.insert(k, k * 10);
+        }
+        // 既存キーは値だけを置き換え、新しいキーは挿入する
+        assert!(tree.upsert(7, 0));
+        assert!(!tree.upsert(21, 210));
+        *tree.search_mut(&15).unwrap() += 1;
+        assert_eq!(tree.search(&7), Some(&0));
+        assert_eq!(tree.search(&15), Some(&151));
+        assert_eq!(tree.search(&21), Some(&210));
+        assert_eq!(tree.key_count(), 21);
+        assert_eq!(tree.search_mut(&99), None);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut tree = BPlusTree::new(2);
+        for k in 1..=200 {
+            tree.insert(k, k * 10);
+        }
+        // 偶数を削除してから、削除したキーの一部を入れ直す
+        for k in (2..=200).step_by(2) {
+            assert_eq!(tree.remove(&k), Some(k * 10));
+        }
+        assert_eq!(tree.remove(&2), None);
+        for k in [10, 100, 200] {
+            tree.insert(k, k);
+        }
+        let mut keys = Vec::new();
+        collect_leaf_keys(tree.get_root().unwrap(), &mut keys);
+        let mut expected: Vec<i32> = (1..=200).filter(|k| k % 2 == 1).chain([10, 100, 200]).collect();
+        expected.sort();
+        assert_eq!(keys, expected);
+        assert_eq!(tree.search(&100), Some(&100));
+        assert_eq!(tree.search(&101), Some(&1010));
+        assert_eq!(tree.last_key(), Some(&200));
+        // すべて削除すると空の木に戻る
+        for k in expected {
+            assert!(tree.remove(&k).is_some());
+        }
+        assert_eq!(tree.key_count(), 0);
+        assert!(tree.get_root().is_none());
+        assert_eq!(tree.first_key(), None);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut tree = BPlusTree::new(2);
+        assert_eq!(tree.iter().next(), None);
+        for k in (1..=500).rev() {
+            tree.insert(k, k * 10);
+        }
+        for k in (1..=500).step_by(3) {
+            tree.remove(&k);
+        }
+        let expected: Vec<(i32, i32)> = (1..=500).filter(|k| k % 3 != 1).map(|k| (k, k * 10)).collect();
+        assert_eq!(tree.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), expected);
+        // 途中で止めれば残りの葉は読まない
+        assert_eq!(tree.iter().nth(1), Some((&3, &30)));
+    }
+
+    #[test]
+    fn test_len_and_key_bounds() {
+        let mut tree: BPlusTree<i32, i32> = BPlusTree::new(2);
+        assert_eq!(tree.key_count(), 0);
+        assert_eq!(tree.first_key(), None);
+        for k in [50, 10, 40, 20, 30, 60, 5] {
+            tree.insert(k, k * 10);
+        }
+        tree.insert(10, 0);
+        assert_eq!(tree.key_count(), 7);
+        assert_eq!(tree.first_key(), Some(&5));
+        assert_eq!(tree.last_key(), Some(&60));
+    }
+
     #[test]
     fn test_random_insert_search() {
         let mut tree = BPlusTree::new(2);
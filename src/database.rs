@@ -1,103 +1,1178 @@
 use serde::{Deserialize, Serialize};
+use serde_json::error::Category;
+use crate::error::DbError;
 use crate::table::Table;
 use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use crate::parser::Query;
+use crate::executor::catalog;
+use crate::executor::foreign_key::{self, Check};
+use crate::executor::{self, ResultSet};
+use crate::parser::ast::{ColumnDef, Delete, ForeignKey, Insert, SelectItem, SelectStatement, TableRef, TriggerEvent, TriggerTiming, Update};
+use crate::parser::{parse_query, split_statements, Query};
+use crate::row::Rows;
+use crate::statement::Statement;
+use crate::stats::TableStats;
+use crate::trigger::{self, RowPair, Trigger};
+use crate::value::Value;
+
+/// トリガーの本体や外部キーの参照動作から、さらに文を実行できる深さの上限。
+/// 自分のテーブルに書き込むトリガーなどの無限再帰を止める。
+const MAX_DEPTH: usize = 16;
+
+/// 保存するファイルの形式。`version` のないファイルは、値をすべて文字列で保存していた旧形式（0）として読み込む。
+/// 1 は主キーをそのまま B+Tree のキーにしていた形式で、2 からは数値アフィニティを適用したキーで格納する。
+const FORMAT_VERSION: u32 = 2;
+
+/// `execute_query` の結果。SELECT と RETURNING 付きの文では `columns` / `rows` に結果の表が入る。
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    /// INSERT / UPDATE / DELETE で書き込んだ（削除した）行数。それ以外の文では 0。
+    pub rows_affected: usize,
+}
+
+impl From<ResultSet> for QueryResult {
+    fn from(result: ResultSet) -> Self {
+        QueryResult { columns: result.columns, rows: result.rows, rows_affected: 0 }
+    }
+}
+
+/// `CREATE [MATERIALIZED] VIEW` で定義したビュー。定義の SELECT 文は SQL の文字列で保存し、使うたびに解析する。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct View {
+    /// 定義の SELECT 文
+    pub query: String,
+    /// `CREATE VIEW v (a, b) AS ...` で付けたカラム名。省略した場合は空で、SELECT 文の出力カラム名をそのまま使う。
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// マテリアライズドビューなら、結果を同名のテーブルに保存している。
+    #[serde(default)]
+    pub materialized: bool,
+}
+
+impl View {
+    /// 定義の SELECT 文を解析する。
+    pub fn statement(&self) -> Result<SelectStatement, DbError> {
+        match parse_query(&self.query)? {
+            Query::Select(statement) => Ok(statement),
+            _ => Err(DbError::Corrupted(format!("View definition is not a SELECT statement: {}", self.query))),
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct Database {
+    /// ファイルの形式（`FORMAT_VERSION`）
+    #[serde(default)]
+    version: u32,
     tables: HashMap<String, Table>,
+    /// `ANALYZE` で集めたテーブルごとの統計情報。まだ `ANALYZE` していないテーブルは含まない。
+    #[serde(default)]
+    statistics: HashMap<String, TableStats>,
+    /// ビューの定義。マテリアライズドビューの結果は `tables` に同名のテーブルとして入っている。
+    #[serde(default)]
+    views: HashMap<String, View>,
+    /// 作成した順に並ぶトリガー。同じ行に複数のトリガーがあれば、この順に発火する。
+    #[serde(default)]
+    triggers: Vec<Trigger>,
+    /// トリガーや参照動作で入れ子に実行している文の深さ。0 なら最も外側の文を実行している。
+    #[serde(skip)]
+    depth: usize,
+    /// 最も外側の文（トランザクションの中では `COMMIT`）まで遅延している外部キーの確認（`INITIALLY DEFERRED`）。
+    #[serde(skip)]
+    deferred: Vec<Check>,
+    /// `open` で開いたファイル。`new` で作ったメモリ上のデータベースでは None。
+    #[serde(skip)]
+    path: Option<String>,
+    /// `open_readonly` で開いたなら、書き込む文をすべて拒否し、ファイルにも保存しない。
+    #[serde(skip)]
+    readonly: bool,
+    /// 書き込む文の後に自動でファイルへ保存するか。false なら `save` を呼んだときだけ保存する。
+    #[serde(skip)]
+    autosave: bool,
+    /// `atomically` やトランザクションの中で書き込んだ行の記録。文（`ROLLBACK` ではトランザクション）が
+    /// 失敗したら、逆順にたどって書き込む前に戻す。
+    #[serde(skip)]
+    undo: Option<Vec<Undo>>,
+    /// `BEGIN` から `COMMIT` / `ROLLBACK` までの間か。
+    #[serde(skip)]
+    in_transaction: bool,
+    /// テーブル・ビューの定義か統計情報が変わるたびに振り直す番号。プリペアドステートメントはこれを見て計画を立て直す。
+    #[serde(skip)]
+    catalog_version: u64,
+}
+
+/// 1 回の書き込みを取り消すための記録。`written` は格納した行の主キー、`replaced` は消えた（置き換わった）行。
+struct Undo {
+    table: String,
+    written: Vec<Value>,
+    replaced: Vec<Vec<Value>>,
+}
+
+/// カタログの版の採番。別のデータベースと同じ番号にならないよう、プロセス全体で数える。
+static CATALOG_VERSION: AtomicU64 = AtomicU64::new(1);
+
+fn next_catalog_version() -> u64 {
+    CATALOG_VERSION.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Database::new()
+    }
 }
 
 impl Database {
+    /// メモリ上の空のデータベースを作る。`save` してもどこにも書き込まない。
     pub fn new() -> Self {
         Database {
+            version: FORMAT_VERSION,
             tables: HashMap::new(),
+            statistics: HashMap::new(),
+            views: HashMap::new(),
+            triggers: Vec::new(),
+            depth: 0,
+            deferred: Vec::new(),
+            path: None,
+            readonly: false,
+            autosave: true,
+            undo: None,
+            in_transaction: false,
+            catalog_version: next_catalog_version(),
+        }
+    }
+
+    /// ファイルに保存されたデータベースを開く。ファイルがなければ空のデータベースになり、
+    /// 最初の書き込みで作成される。
+    pub fn open(path: &str) -> Result<Self, DbError> {
+        let mut db = Database::new();
+        db.load_data(path)?;
+        db.path = Some(path.to_string());
+        Ok(db)
+    }
+
+    /// ファイルに保存されたデータベースを読み取り専用で開く。`open` と違い、ファイルがなければエラーになる。
+    /// 書き込む文は `DbError::ReadOnly` になり、ファイルは変更しない。
+    pub fn open_readonly(path: &str) -> Result<Self, DbError> {
+        fs::metadata(path)?;
+        let mut db = Database::open(path)?;
+        db.readonly = true;
+        Ok(db)
+    }
+
+    /// 読み取り専用で開いたデータベースか。
+    pub fn is_readonly(&self) -> bool {
+        self.readonly
+    }
+
+    /// 書き込む文の後に自動で保存するかを切り替える。既定では保存する。
+    pub fn set_autosave(&mut self, autosave: bool) {
+        self.autosave = autosave;
+    }
+
+    /// 書き込む文の後に自動で保存するか。
+    pub fn is_autosave(&self) -> bool {
+        self.autosave
+    }
+
+    /// SQL 文を 1 つ実行し、書き込んだ（削除した）行数を返す。
+    /// `open` で開いたデータベースでは、SELECT 以外の文の後にファイルへ保存する（`set_autosave(false)` なら保存しない）。
+    pub fn execute(&mut self, sql: &str) -> Result<usize, DbError> {
+        self.prepare(sql)?.execute(self)
+    }
+
+    /// セミコロンで区切った複数の SQL 文を順に実行する。引用符やコメントの中、トリガーの
+    /// BEGIN ... END の中のセミコロンでは区切らない。エラーになった文で止まり、それより前の文の結果は残る。
+    /// `open` で開いたデータベースでは、1 文ごとではなく最後にまとめてファイルへ保存する。
+    pub fn execute_batch(&mut self, script: &str) -> Result<(), DbError> {
+        self.execute_batch_with(script, |_, _| {})
+    }
+
+    /// `execute_batch` と同じく文を順に実行し、成功した文ごとにその文と結果を `on_result` に渡す。
+    pub fn execute_batch_with(&mut self, script: &str, mut on_result: impl FnMut(&Query, &QueryResult)) -> Result<(), DbError> {
+        let mut written = false;
+        let mut result = Ok(());
+        for sql in split_statements(script) {
+            result = parse_query(&sql).and_then(|query| {
+                written |= query.needs_save();
+                on_result(&query, &self.execute_query(&query)?);
+                Ok(())
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+        if written && self.autosave {
+            let saved = self.save();
+            result = result.and(saved);
+        }
+        result
+    }
+
+    /// SELECT 文を実行して結果の行を返す。
+    pub fn query(&self, sql: &str) -> Result<Rows, DbError> {
+        self.prepare(sql)?.query(self)
+    }
+
+    /// SQL 文を解析して、パラメータ（`?` / `$1` / `:name`）に値をバインドして繰り返し実行できる文を作る。
+    pub fn prepare(&self, sql: &str) -> Result<Statement, DbError> {
+        Statement::new(sql)
+    }
+
+    /// `open` で開いたファイルに保存する。メモリ上のデータベースと読み取り専用のデータベースでは何もしない。
+    /// トランザクションの途中では、まだ確定していない書き込みを残さないよう `COMMIT` まで保存しない。
+    pub fn save(&self) -> Result<(), DbError> {
+        match &self.path {
+            Some(path) if !self.readonly && !self.in_transaction => self.save_data(path),
+            _ => Ok(()),
         }
     }
-    
+
     pub fn get_table(&self, name: &str) -> Option<&Table> {
         self.tables.get(name)
     }
 
-    pub fn create_table(&mut self, name: &str, columns: Vec<String>) {
-        if self.tables.contains_key(name) {
-            println!("Error: Table '{}' already exists.", name);
-            return;
+    /// テーブル（マテリアライズドビューの結果を含む）の名前を昇順に返す。
+    pub fn table_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.tables.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    /// ビュー（マテリアライズドビューを含む）の名前を昇順に返す。
+    pub fn view_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.views.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    pub fn get_view(&self, name: &str) -> Option<&View> {
+        self.views.get(name)
+    }
+
+    /// テーブルかビューのカラム名。ビューでは `DESCRIBE` と同じく定義の SELECT 文の出力カラムになる。
+    pub fn relation_columns(&self, name: &str) -> Vec<String> {
+        catalog::columns(self, name)
+    }
+
+    /// 実行計画に関わるカタログの現在の版。
+    pub(crate) fn catalog_version(&self) -> u64 {
+        self.catalog_version
+    }
+
+    /// `CREATE [MATERIALIZED] VIEW` を実行する。定義の SELECT 文はここで計画を立てて、参照するテーブルやカラムを確かめる。
+    /// マテリアライズドビューでは SELECT 文を実行し、結果を同名のテーブルに保存する。
+    pub fn create_view(
+        &mut self,
+        name: &str,
+        columns: &[String],
+        statement: &SelectStatement,
+        materialized: bool,
+    ) -> Result<QueryResult, DbError> {
+        if self.tables.contains_key(name) || self.views.contains_key(name) {
+            return Err(DbError::DuplicateTable(name.to_string()));
+        }
+        let view = View { query: statement.to_string(), columns: columns.to_vec(), materialized };
+        let names = self.view_columns(&view, statement)?;
+        if materialized {
+            let table = self.materialize(names, statement)?;
+            self.tables.insert(name.to_string(), table);
         }
-        self.tables.insert(name.to_string(), Table::new(columns));
-        println!("Table '{}' created.", name);
+        self.views.insert(name.to_string(), view);
+        self.catalog_version = next_catalog_version();
+        Ok(QueryResult::default())
     }
 
-    pub fn insert_into(&mut self, table_name: &str, values: Vec<String>) {
-        if let Some(table) = self.tables.get_mut(table_name) {
-            table.insert(values);
-        } else {
-            println!("Error: Table '{}' does not exist.", table_name);
+    /// `REFRESH MATERIALIZED VIEW` を実行し、定義の SELECT 文の結果でテーブルを置き換える。失敗したら元の結果のまま残す。
+    pub fn refresh_materialized_view(&mut self, name: &str) -> Result<QueryResult, DbError> {
+        let view = match self.views.get(name) {
+            Some(view) if view.materialized => view.clone(),
+            Some(_) => return Err(DbError::InvalidQuery(format!("'{}' is not a materialized view.", name))),
+            None => return Err(DbError::UndefinedTable(name.to_string())),
+        };
+        let statement = view.statement()?;
+        let names = self.view_columns(&view, &statement)?;
+        let table = self.materialize(names, &statement)?;
+        let rows_affected = table.row_count();
+        self.tables.insert(name.to_string(), table);
+        Ok(QueryResult { rows_affected, ..QueryResult::default() })
+    }
+
+    /// ビューのカラム名。カラム名の指定があれば SELECT 文の出力カラムと同じ数でなければならない。
+    fn view_columns(&self, view: &View, statement: &SelectStatement) -> Result<Vec<String>, DbError> {
+        let output = executor::output_names(self, statement)?;
+        let names = match view.columns.len() {
+            0 => output,
+            n if n == output.len() => view.columns.clone(),
+            n => {
+                return Err(DbError::InvalidQuery(format!(
+                    "View has {} column names but the SELECT returns {} columns.",
+                    n,
+                    output.len()
+                )))
+            }
+        };
+        if let Some(dup) = names.iter().enumerate().find(|(i, c)| names[..*i].contains(c)).map(|(_, c)| c) {
+            return Err(DbError::DuplicateColumn(dup.clone()));
         }
+        Ok(names)
+    }
+
+    /// SELECT 文を実行して、結果を入れたテーブルを作る。他のテーブルと同じく最初のカラムが主キーになる。
+    fn materialize(&self, columns: Vec<String>, statement: &SelectStatement) -> Result<Table, DbError> {
+        let rows = executor::execute_select(self, statement, &[])?.rows;
+        let mut table = Table::new(columns.clone(), vec![Value::Null; columns.len()]);
+        table.insert_rows(rows)?;
+        Ok(table)
     }
 
-    pub fn select_all(&self, table_name: &str, selected_colums: Vec<String>) {
-        if let Some(table) = self.tables.get(table_name) {
-            table.select_all_with_columns(selected_colums);
-        } else {
-            println!("Error: Table '{}' does not exist.", table_name);
+    /// ビューへの INSERT / UPDATE / DELETE はできない。
+    fn check_writable(&self, name: &str) -> Result<(), DbError> {
+        match self.views.contains_key(name) {
+            true => Err(DbError::InvalidQuery(format!("Cannot modify view '{}'.", name))),
+            false => Ok(()),
         }
     }
 
-    /// WHERE 句による検索：指定されたテーブルの、任意カラムの値が search_value と一致する行を表示する。
-    /// 主キー検索は B+Tree による高速検索で行い、それ以外は全件走査してフィルタリングする。
-    pub fn select_where(&self, table_name: &str, selected_columns: &Vec<String>, condition_column: &str, search_value: &str) {
-       if let Some(table) = self.get_table(table_name) {
-           let results = table.select_where(condition_column, search_value);
-           if results.is_empty() {
-               println!("No matching row found for {} = '{}'.", condition_column, search_value);
-           } else {
-               println!("Columns: {:?}", selected_columns);
-               for row in results {
-                   println!("Row: {:?}", row);
-               }
-           }
-       } else {
-           println!("Error: Table '{}' does not exist.", table_name);
-       }
-    } 
-    
-    pub fn execute_query(&mut self, query: Query) {
-        match query {
-            Query::CreateTable { table_name, columns } => {
-                self.create_table(&table_name, columns);
-            },
-            Query::Insert { table_name, values } => {
-                self.insert_into(&table_name, values);
-            },
-            Query::Select { table_name, select_columns, condition } => {
-                match condition {
-                    Some(cond) => self.select_where(&table_name, &select_columns, &cond.column, &cond.value),
-                    None => self.select_all(&table_name, select_columns),
+    pub fn triggers(&self) -> &[Trigger] {
+        &self.triggers
+    }
+
+    /// `CREATE TRIGGER` を実行する。本体の文はここで解析し、`NEW` / `OLD` のカラムを確かめる。
+    pub fn create_trigger(&mut self, trigger: Trigger) -> Result<QueryResult, DbError> {
+        if self.triggers.iter().any(|t| t.name == trigger.name) {
+            return Err(DbError::DuplicateTrigger(trigger.name));
+        }
+        if self.views.contains_key(&trigger.table) {
+            return Err(DbError::InvalidQuery(format!("Cannot create a trigger on view '{}'.", trigger.table)));
+        }
+        let table = self.tables.get(&trigger.table).ok_or_else(|| DbError::UndefinedTable(trigger.table.clone()))?;
+        trigger.compile(&table.columns)?;
+        self.triggers.push(trigger);
+        Ok(QueryResult::default())
+    }
+
+    /// `table` への書き込みで発火するトリガーがあるか。
+    fn has_triggers(&self, table: &str, event: TriggerEvent) -> bool {
+        self.triggers.iter().any(|t| t.table == table && t.event == event)
+    }
+
+    /// 書き込む行ごとに、該当するトリガーの本体を実行する。トリガーが書き込んだテーブルのトリガーも入れ子で発火する。
+    fn fire(&mut self, table: &str, timing: TriggerTiming, event: TriggerEvent, rows: &[RowPair]) -> Result<(), DbError> {
+        let triggers: Vec<&Trigger> =
+            self.triggers.iter().filter(|t| t.table == table && t.timing == timing && t.event == event).collect();
+        if triggers.is_empty() || rows.is_empty() {
+            return Ok(());
+        }
+        let columns = &self.tables.get(table).ok_or_else(|| DbError::UndefinedTable(table.to_string()))?.columns;
+        let width = columns.len();
+        let bodies = triggers.iter().map(|t| t.compile(columns)).collect::<Result<Vec<_>, _>>()?;
+        rows.iter().try_for_each(|row| self.execute_nested(bodies.iter().flatten(), &trigger::params(*row, width)))
+    }
+
+    /// トリガーの本体や参照動作の文を、入れ子の文として実行する。
+    fn execute_nested<'q>(&mut self, queries: impl IntoIterator<Item = &'q Query>, params: &[Value]) -> Result<(), DbError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(DbError::InvalidQuery(format!(
+                "Triggers and foreign key actions nested more than {} levels deep.",
+                MAX_DEPTH
+            )));
+        }
+        self.depth += 1;
+        let result = queries.into_iter().try_for_each(|query| self.execute_query_with_params(query, params).map(|_| ()));
+        self.depth -= 1;
+        result
+    }
+
+    /// 外部キーの確認を行う。遅延する確認は最も外側の文が終わるまでとっておく。
+    fn check_references(&mut self, checks: Vec<Check>) -> Result<(), DbError> {
+        let deferred = foreign_key::check(self, checks, true)?;
+        self.deferred.extend(deferred);
+        Ok(())
+    }
+
+    /// 親テーブル `table` の行を削除した、または主キーを変えた後に、ON DELETE / ON UPDATE の動作を行い、
+    /// 残った参照を確かめる。`changes` は (元の主キー, 新しい主キー) の組で、削除では新しい主キーが None。
+    fn referenced_keys_changed(&mut self, table: &str, changes: &[(Value, Option<Value>)]) -> Result<(), DbError> {
+        let (queries, checks) = foreign_key::referential_actions(self, table, changes);
+        self.execute_nested(&queries, &[])?;
+        self.check_references(checks)
+    }
+
+    /// 他のテーブルの外部キーから参照されているか。
+    fn is_referenced(&self, table: &str) -> bool {
+        self.tables.values().any(|t| t.foreign_keys.iter().any(|fk| fk.table == table))
+    }
+
+    /// 書き込む前の行を主キーで引く。トリガーがなければ使わないので引かない。
+    fn old_rows<'k>(&self, table: &str, event: TriggerEvent, keys: impl Iterator<Item = &'k Value>) -> Vec<Vec<Value>> {
+        match self.tables.get(table) {
+            Some(t) if self.has_triggers(table, event) => keys.filter_map(|k| t.find_by_key(k).cloned()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// INSERT / UPDATE / DELETE を実行し、遅延していた外部キーを確かめる。失敗したら、入れ子の文を含めて
+    /// 書き込んだ行を逆順に取り消す。文そのものは検証してから書き込むので、戻す必要があるのはトリガーや
+    /// 参照動作が途中まで書き込んだ場合と、書き込んだ後の外部キーの確認に失敗した場合だけ。
+    /// トランザクションの中では、失敗した文の書き込みだけを取り消し、遅延した確認は `COMMIT` まで残す。
+    fn atomically(&mut self, f: impl FnOnce(&mut Self) -> Result<QueryResult, DbError>) -> Result<QueryResult, DbError> {
+        if self.depth > 0 {
+            return f(self);
+        }
+        if self.in_transaction {
+            let (written, deferred) = (self.undo.as_ref().map_or(0, Vec::len), self.deferred.len());
+            let result = f(self);
+            if result.is_err() {
+                self.revert_to(written);
+                self.deferred.truncate(deferred);
+            }
+            return result;
+        }
+        let has_foreign_keys = self.tables.values().any(|t| !t.foreign_keys.is_empty());
+        if self.triggers.is_empty() && !has_foreign_keys {
+            return f(self);
+        }
+        self.undo = Some(Vec::new());
+        let result = f(self).and_then(|result| self.check_deferred().map(|_| result));
+        if result.is_err() {
+            self.revert_to(0);
+            self.deferred.clear();
+        }
+        self.undo = None;
+        result
+    }
+
+    /// 遅延していた外部キーの確認をすべて行う。
+    fn check_deferred(&mut self) -> Result<(), DbError> {
+        let deferred = std::mem::take(&mut self.deferred);
+        foreign_key::check(self, deferred, false).map(|_| ())
+    }
+
+    /// 取り消しの記録の `len` 番目以降の書き込みを、新しいものから順に取り消す。
+    fn revert_to(&mut self, len: usize) {
+        let entries = self.undo.as_mut().map(|undo| undo.split_off(len)).unwrap_or_default();
+        for entry in entries.into_iter().rev() {
+            if let Some(table) = self.tables.get_mut(&entry.table) {
+                table.revert(&entry.written, entry.replaced);
+            }
+        }
+    }
+
+    /// `BEGIN` を実行する。`COMMIT` までの書き込みは `ROLLBACK` でまとめて取り消せる。
+    fn begin(&mut self) -> Result<QueryResult, DbError> {
+        if self.in_transaction {
+            return Err(DbError::InvalidQuery("A transaction is already in progress.".into()));
+        }
+        self.in_transaction = true;
+        self.undo = Some(Vec::new());
+        Ok(QueryResult::default())
+    }
+
+    /// `COMMIT` を実行する。遅延していた外部キーを確かめ、失敗すればトランザクション全体を取り消す。
+    fn commit(&mut self) -> Result<QueryResult, DbError> {
+        if !self.in_transaction {
+            return Err(DbError::InvalidQuery("There is no transaction in progress.".into()));
+        }
+        let result = self.check_deferred();
+        if result.is_err() {
+            self.revert_to(0);
+        }
+        self.in_transaction = false;
+        self.undo = None;
+        result.map(|_| QueryResult::default())
+    }
+
+    /// `ROLLBACK` を実行し、トランザクションの中の書き込みをすべて取り消す。
+    fn rollback(&mut self) -> Result<QueryResult, DbError> {
+        if !self.in_transaction {
+            return Err(DbError::InvalidQuery("There is no transaction in progress.".into()));
+        }
+        self.revert_to(0);
+        self.deferred.clear();
+        self.in_transaction = false;
+        self.undo = None;
+        Ok(QueryResult::default())
+    }
+
+    /// 書き込みで消える（置き換わる）行を主キーで引く。取り消しの記録をとっていなければ引かない。
+    fn replaced_rows<'k>(&self, table: &str, keys: impl Iterator<Item = &'k Value>) -> Vec<Vec<Value>> {
+        match self.tables.get(table) {
+            Some(t) if self.undo.is_some() => keys.filter_map(|k| t.find_by_key(k).cloned()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// 書き込みを取り消しの記録に加える。
+    fn log_write(&mut self, table: &str, written: &[Vec<Value>], replaced: Vec<Vec<Value>>) {
+        if let Some(undo) = &mut self.undo {
+            undo.push(Undo { table: table.to_string(), written: written.iter().map(|row| row[0].clone()).collect(), replaced });
+        }
+    }
+
+    /// `ANALYZE` で集めたテーブルの統計情報。まだ `ANALYZE` していなければ None。
+    pub fn table_stats(&self, name: &str) -> Option<&TableStats> {
+        self.statistics.get(name)
+    }
+
+    /// `ANALYZE [table]` を実行し、テーブル（省略時はすべてのテーブル）の統計情報を集め直す。
+    pub fn analyze(&mut self, table: Option<&str>) -> Result<QueryResult, DbError> {
+        let names: Vec<String> = match table {
+            Some(name) if !self.tables.contains_key(name) => return Err(DbError::UndefinedTable(name.to_string())),
+            Some(name) => vec![name.to_string()],
+            None => self.tables.keys().cloned().collect(),
+        };
+        for name in names {
+            let stats = TableStats::collect(&self.tables[&name]);
+            self.statistics.insert(name, stats);
+        }
+        self.catalog_version = next_catalog_version();
+        Ok(QueryResult::default())
+    }
+
+    /// `CREATE TABLE` を実行する。外部キーは参照先のテーブルの主キーを指していなければならない。
+    pub fn create_table_with_constraints(
+        &mut self,
+        name: &str,
+        columns: &[ColumnDef],
+        foreign_keys: &[ForeignKey],
+        params: &[Value],
+    ) -> Result<QueryResult, DbError> {
+        let defaults = executor::insert::column_defaults(self, columns, params)?;
+        let names: Vec<String> = columns.iter().map(|c| c.name.clone()).collect();
+        for foreign_key in foreign_keys {
+            if !names.contains(&foreign_key.column) {
+                return Err(DbError::UndefinedColumn(foreign_key.column.clone()));
+            }
+            if self.views.contains_key(&foreign_key.table) {
+                return Err(DbError::InvalidQuery(format!("Foreign key cannot reference view '{}'.", foreign_key.table)));
+            }
+            // 自分自身を参照する外部キーは、作成するテーブルのカラムで確かめる
+            let parent_columns = match self.tables.get(&foreign_key.table) {
+                _ if foreign_key.table == name => &names,
+                Some(parent) => &parent.columns,
+                None => return Err(DbError::UndefinedTable(foreign_key.table.clone())),
+            };
+            match &foreign_key.referenced {
+                Some(column) if !parent_columns.contains(column) => return Err(DbError::UndefinedColumn(column.clone())),
+                Some(column) if *column != parent_columns[0] => {
+                    return Err(DbError::InvalidQuery(format!(
+                        "Foreign key must reference the primary key '{}' of '{}'.",
+                        parent_columns[0], foreign_key.table
+                    )))
                 }
-            },
-            // その他のクエリタイプが追加された場合はここで処理
+                _ => {}
+            }
+        }
+        self.create_table_with_defaults(name, names, defaults)?;
+        self.tables.get_mut(name).expect("table was just created").foreign_keys = foreign_keys.to_vec();
+        Ok(QueryResult::default())
+    }
+
+    /// 既定値付きでテーブルを作成する。`defaults` はカラムと同じ順に並ぶ。
+    pub fn create_table_with_defaults(&mut self, name: &str, columns: Vec<String>, defaults: Vec<Value>) -> Result<(), DbError> {
+        if self.tables.contains_key(name) || self.views.contains_key(name) {
+            return Err(DbError::DuplicateTable(name.to_string()));
+        }
+        if let Some(dup) = columns.iter().enumerate().find(|(i, c)| columns[..*i].contains(c)).map(|(_, c)| c) {
+            return Err(DbError::DuplicateColumn(dup.clone()));
+        }
+        self.tables.insert(name.to_string(), Table::new(columns, defaults));
+        self.catalog_version = next_catalog_version();
+        Ok(())
+    }
+
+    /// INSERT 文を実行する。挿入する行をすべて求めて検証してから書き込むので、
+    /// どれか 1 行でも失敗すればテーブルは変更されない。
+    pub fn insert(&mut self, insert: &Insert, params: &[Value]) -> Result<QueryResult, DbError> {
+        self.check_writable(&insert.table_name)?;
+        let table_ref = TableRef::Table { name: insert.table_name.clone(), alias: None };
+        executor::check_returning(self, &table_ref, &insert.returning)?;
+        let rows = executor::insert::rows(self, insert, params)?;
+        let new: Vec<RowPair> = rows.iter().map(|row| (Some(row.as_slice()), None)).collect();
+        self.fire(&insert.table_name, TriggerTiming::Before, TriggerEvent::Insert, &new)?;
+        let replaced = match insert.on_conflict {
+            Some(_) => self.replaced_rows(&insert.table_name, rows.iter().map(|row| &row[0])),
+            None => Vec::new(),
+        };
+        let written = match self.tables.get_mut(&insert.table_name) {
+            // ON CONFLICT の衝突は解決済みなので、残った重複は置き換える行
+            Some(table) if insert.on_conflict.is_some() => table.upsert_rows(rows)?,
+            Some(table) => table.insert_rows(rows)?,
+            None => return Err(DbError::UndefinedTable(insert.table_name.clone())),
+        };
+        self.log_write(&insert.table_name, &written, replaced);
+        self.check_references(foreign_key::written_rows(&insert.table_name, &self.tables[&insert.table_name], &written))?;
+        let new: Vec<RowPair> = written.iter().map(|row| (Some(row.as_slice()), None)).collect();
+        self.fire(&insert.table_name, TriggerTiming::After, TriggerEvent::Insert, &new)?;
+        self.written(&table_ref, written, &insert.returning, params)
+    }
+
+    /// UPDATE 文を実行する。INSERT と同じく、すべての行を検証してから書き換える。
+    pub fn update(&mut self, update: &Update, params: &[Value]) -> Result<QueryResult, DbError> {
+        self.check_writable(&update.table_name)?;
+        let table_ref = TableRef::Table { name: update.table_name.clone(), alias: update.alias.clone() };
+        executor::check_returning(self, &table_ref, &update.returning)?;
+        let updates = executor::update::rows(self, update, params)?;
+        let old = self.old_rows(&update.table_name, TriggerEvent::Update, updates.iter().map(|(key, _)| key));
+        let pairs: Vec<RowPair> = updates.iter().zip(&old).map(|((_, new), old)| (Some(new.as_slice()), Some(old.as_slice()))).collect();
+        self.fire(&update.table_name, TriggerTiming::Before, TriggerEvent::Update, &pairs)?;
+        let old_keys: Vec<Value> = match self.is_referenced(&update.table_name) {
+            true => updates.iter().map(|(key, _)| key.clone()).collect(),
+            false => Vec::new(),
+        };
+        let replaced = self.replaced_rows(&update.table_name, updates.iter().map(|(key, _)| key));
+        let written = match self.tables.get_mut(&update.table_name) {
+            Some(table) => table.update_rows(updates)?,
+            None => return Err(DbError::UndefinedTable(update.table_name.clone())),
+        };
+        self.log_write(&update.table_name, &written, replaced);
+        let changes: Vec<(Value, Option<Value>)> = old_keys
+            .into_iter()
+            .zip(&written)
+            .filter(|(old, row)| *old != row[0])
+            .map(|(old, row)| (old, Some(row[0].clone())))
+            .collect();
+        self.referenced_keys_changed(&update.table_name, &changes)?;
+        self.check_references(foreign_key::written_rows(&update.table_name, &self.tables[&update.table_name], &written))?;
+        let pairs: Vec<RowPair> = written.iter().zip(&old).map(|(new, old)| (Some(new.as_slice()), Some(old.as_slice()))).collect();
+        self.fire(&update.table_name, TriggerTiming::After, TriggerEvent::Update, &pairs)?;
+        self.written(&table_ref, written, &update.returning, params)
+    }
+
+    /// DELETE 文を実行する。
+    pub fn delete(&mut self, delete: &Delete, params: &[Value]) -> Result<QueryResult, DbError> {
+        self.check_writable(&delete.table_name)?;
+        let table_ref = TableRef::Table { name: delete.table_name.clone(), alias: delete.alias.clone() };
+        executor::check_returning(self, &table_ref, &delete.returning)?;
+        let keys = executor::delete::keys(self, delete, params)?;
+        let old = self.old_rows(&delete.table_name, TriggerEvent::Delete, keys.iter());
+        let pairs: Vec<RowPair> = old.iter().map(|row| (None, Some(row.as_slice()))).collect();
+        self.fire(&delete.table_name, TriggerTiming::Before, TriggerEvent::Delete, &pairs)?;
+        let replaced = self.replaced_rows(&delete.table_name, keys.iter());
+        let deleted = match self.tables.get_mut(&delete.table_name) {
+            Some(table) => table.delete_rows(&keys),
+            None => return Err(DbError::UndefinedTable(delete.table_name.clone())),
+        };
+        self.log_write(&delete.table_name, &[], replaced);
+        let changes: Vec<(Value, Option<Value>)> = match self.is_referenced(&delete.table_name) {
+            true => deleted.iter().map(|row| (row[0].clone(), None)).collect(),
+            false => Vec::new(),
+        };
+        self.referenced_keys_changed(&delete.table_name, &changes)?;
+        let pairs: Vec<RowPair> = deleted.iter().map(|row| (None, Some(row.as_slice()))).collect();
+        self.fire(&delete.table_name, TriggerTiming::After, TriggerEvent::Delete, &pairs)?;
+        self.written(&table_ref, deleted, &delete.returning, params)
+    }
+
+    /// 書き込んだ（削除した）行から結果を作る。RETURNING 句があれば、その表を結果に入れる。
+    fn written(
+        &self,
+        table_ref: &TableRef,
+        rows: Vec<Vec<Value>>,
+        returning: &[SelectItem],
+        params: &[Value],
+    ) -> Result<QueryResult, DbError> {
+        let rows_affected = rows.len();
+        let mut result = QueryResult { rows_affected, ..QueryResult::default() };
+        if !returning.is_empty() {
+            let returned = executor::returning(self, table_ref, &rows, returning, params)?;
+            result.columns = returned.columns;
+            result.rows = returned.rows;
+        }
+        Ok(result)
+    }
+
+    /// SELECT 文を実行する。
+    /// WHERE 句の主キー検索は B+Tree による高速検索で行い、それ以外は全件走査してフィルタリングする。
+    pub fn select(&self, select: &SelectStatement, params: &[Value]) -> Result<QueryResult, DbError> {
+        executor::execute_select(self, select, params).map(QueryResult::from)
+    }
+
+    /// `EXPLAIN [ANALYZE]` を実行し、実行計画を "QUERY PLAN" カラムの行として返す。
+    /// ANALYZE では文を実際に実行して、ノードごとの行数と所要時間を添える。
+    pub fn explain(&self, select: &SelectStatement, analyze: bool, params: &[Value]) -> Result<QueryResult, DbError> {
+        executor::explain(self, select, analyze, params).map(QueryResult::from)
+    }
+
+    /// `SHOW TABLES` を実行し、テーブルとビューの名前と種類を返す。
+    pub fn show_tables(&self) -> QueryResult {
+        let columns = vec!["table_name".to_string(), "table_type".to_string()];
+        QueryResult { columns, rows: catalog::show_tables(self), rows_affected: 0 }
+    }
+
+    /// `DESCRIBE table` を実行し、カラムごとの定義を返す。
+    pub fn describe(&self, name: &str) -> Result<QueryResult, DbError> {
+        let columns = ["column_name", "is_nullable", "key", "column_default", "references"].map(String::from).to_vec();
+        Ok(QueryResult { columns, rows: catalog::describe(self, name)?, rows_affected: 0 })
+    }
+
+    /// `SHOW CREATE TABLE` を実行し、テーブル（ビュー）を作り直す文を返す。
+    pub fn show_create_table(&self, name: &str) -> Result<QueryResult, DbError> {
+        let columns = vec!["table_name".to_string(), "create_statement".to_string()];
+        let row = vec![Value::from(name), Value::Text(catalog::create_statement(self, name)?)];
+        Ok(QueryResult { columns, rows: vec![row], rows_affected: 0 })
+    }
+
+    pub fn execute_query(&mut self, query: &Query) -> Result<QueryResult, DbError> {
+        self.execute_query_with_params(query, &[])
+    }
+
+    /// 解析済みの文を実行する。`params[0]` が `$1` の値。
+    pub fn execute_query_with_params(&mut self, query: &Query, params: &[Value]) -> Result<QueryResult, DbError> {
+        if self.readonly && !query.is_read_only() {
+            return Err(DbError::ReadOnly);
+        }
+        // 取り消しの記録は行の書き込みしか戻せないので、テーブルなどの定義はトランザクションの中で変えられない
+        let changes_schema = matches!(
+            query,
+            Query::CreateTable { .. } | Query::CreateView { .. } | Query::RefreshMaterializedView { .. } | Query::CreateTrigger { .. } | Query::Analyze { .. }
+        );
+        if self.in_transaction && changes_schema {
+            return Err(DbError::InvalidQuery("Schema changes are not allowed inside a transaction.".into()));
+        }
+        match query {
+            Query::CreateTable { table_name, columns, foreign_keys } => {
+                self.create_table_with_constraints(table_name, columns, foreign_keys, params)
+            }
+            Query::Insert(insert) => self.atomically(|db| db.insert(insert, params)),
+            Query::Select(select) => self.select(select, params),
+            Query::Update(update) => self.atomically(|db| db.update(update, params)),
+            Query::Delete(delete) => self.atomically(|db| db.delete(delete, params)),
+            Query::Explain { analyze, statement } => self.explain(statement, *analyze, params),
+            Query::Analyze { table } => self.analyze(table.as_deref()),
+            Query::CreateView { name, columns, statement, materialized } => self.create_view(name, columns, statement, *materialized),
+            Query::RefreshMaterializedView { name } => self.refresh_materialized_view(name),
+            Query::ShowTables => Ok(self.show_tables()),
+            Query::Describe { table } => self.describe(table),
+            Query::ShowCreateTable { table } => self.show_create_table(table),
+            Query::Begin => self.begin(),
+            Query::Commit => self.commit(),
+            Query::Rollback => self.rollback(),
+            Query::CreateTrigger { name, timing, event, table, body } => self.create_trigger(Trigger {
+                name: name.clone(),
+                timing: *timing,
+                event: *event,
+                table: table.clone(),
+                body: body.clone(),
+            }),
+        }
+    }
+
+    /// データベース全体を JSON としてファイルに保存する。
+    pub fn save_data(&self, path: &str) -> Result<(), DbError> {
+        let file = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self).map_err(|e| DbError::Io(e.into()))?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// 保存したファイルを読み込む。ファイルがなければ空のまま何もしない。
+    pub fn load_data(&mut self, path: &str) -> Result<(), DbError> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let loaded: Database = serde_json::from_reader(BufReader::new(file)).map_err(|e| match e.classify() {
+            Category::Io => DbError::Io(e.into()),
+            _ => DbError::Corrupted(e.to_string()),
+        })?;
+        self.tables = loaded.tables;
+        if loaded.version < FORMAT_VERSION {
+            // 旧形式では数値も文字列で保存されているので、数値として比較・検索できるように作り直す。
+            // 形式 1 の値はそのまま使えるが、B+Tree のキーを今の形に揃える
+            for (name, table) in std::mem::take(&mut self.tables) {
+                let table = if loaded.version == 0 { table.migrate() } else { table.rebuild() };
+                let table = table.map_err(|e| DbError::Corrupted(format!("Cannot convert table '{}': {}", name, e)))?;
+                self.tables.insert(name, table);
+            }
+        }
+        self.statistics = loaded.statistics;
+        self.views = loaded.views;
+        self.triggers = loaded.triggers;
+        self.catalog_version = next_catalog_version();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::test_util::execute;
+    use std::fs;
+
+    #[test]
+    fn test_error_codes() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE users (id, name)").unwrap();
+        execute(&mut db, "INSERT INTO users VALUES (1, 'John')").unwrap();
+        let code = |db: &mut Database, sql: &str| execute(db, sql).unwrap_err().code();
+        assert_eq!(code(&mut db, "SELEC * FROM users"), "42601");
+        assert_eq!(code(&mut db, "CREATE TABLE users (id)"), "42P07");
+        assert_eq!(code(&mut db, "SELECT * FROM nope"), "42P01");
+        assert_eq!(code(&mut db, "SELECT nope FROM users"), "42703");
+        assert_eq!(code(&mut db, "INSERT INTO users VALUES (1, 'Dup')"), "23505");
+        assert_eq!(code(&mut db, "INSERT INTO users (name) VALUES ('NoKey')"), "23502");
+        assert_eq!(code(&mut db, "SELECT id / 0 FROM users"), "22012");
+        assert_eq!(code(&mut db, "SELECT name + 1 FROM users"), "42804");
+        let err = execute(&mut db, "INSERT INTO users VALUES (1, 'Dup')").unwrap_err();
+        assert_eq!(err.to_string(), "Duplicate primary key '1'.");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("ferrdb-test-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE users (id, name)").unwrap();
+        execute(&mut db, "INSERT INTO users VALUES (1, 'John')").unwrap();
+        execute(&mut db, "ANALYZE users").unwrap();
+        execute(&mut db, "CREATE VIEW names AS SELECT name FROM users").unwrap();
+        execute(&mut db, "CREATE TABLE copies (name)").unwrap();
+        execute(&mut db, "CREATE TRIGGER copy AFTER INSERT ON users FOR EACH ROW BEGIN INSERT INTO copies VALUES (NEW.name); END").unwrap();
+        db.save_data(path).unwrap();
+        let mut loaded = Database::new();
+        loaded.load_data(path).unwrap();
+        // 統計情報も一緒に保存される
+        assert_eq!(loaded.table_stats("users"), db.table_stats("users"));
+        assert!(loaded.table_stats("users").is_some());
+        // ビューの定義も保存される
+        assert_eq!(loaded.get_view("names"), db.get_view("names"));
+        assert_eq!(execute(&mut loaded, "SELECT * FROM names").unwrap().rows, vec![vec![Value::Text("John".into())]]);
+        // トリガーも保存される
+        assert_eq!(loaded.triggers(), db.triggers());
+        assert_eq!(execute(&mut loaded, "SELECT * FROM users").unwrap().rows, vec![vec![Value::Integer(1), Value::Text("John".into())]]);
+        execute(&mut loaded, "INSERT INTO users VALUES (2, 'Mike')").unwrap();
+        assert_eq!(execute(&mut loaded, "SELECT * FROM copies").unwrap().rows, vec![vec![Value::Text("Mike".into())]]);
+
+        // 壊れたファイルはパニックせずエラーにする
+        fs::write(path, "{ not json").unwrap();
+        let err = Database::new().load_data(path).unwrap_err();
+        assert_eq!(err.code(), "XX001");
+        fs::remove_file(path).unwrap();
+        // ファイルがなければ空のデータベースのまま
+        assert!(Database::new().load_data(path).is_ok());
+        assert!(matches!(db.save_data("/nonexistent-dir/db.json"), Err(DbError::Io(_))));
+    }
+
+    #[test]
+    fn test_load_legacy_file() {
+        // 以前の形式: version がなく、キーも値もすべて文字列
+        let mut data: crate::btree::BPlusTree<String, Vec<String>> = crate::btree::BPlusTree::new(2);
+        for (id, name) in [("1", "John"), ("2", "Mike"), ("10", "Kate")] {
+            data.insert(id.to_string(), vec![id.to_string(), name.to_string()]);
         }
+        let legacy = serde_json::json!({ "tables": { "users": { "columns": ["id", "name"], "data": data } } });
+        let path = std::env::temp_dir().join(format!("ferrdb-legacy-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, legacy.to_string()).unwrap();
+        let mut db = Database::open(path).unwrap();
+        assert_eq!(db.query("SELECT name FROM users WHERE id = 1").unwrap().len(), 1);
+        let max = db.query("SELECT MAX(id) FROM users").unwrap();
+        assert_eq!(max.iter().next().unwrap().get::<Value>(0).unwrap(), Value::Integer(10));
+        assert_eq!(db.execute("INSERT INTO users VALUES (1, 'Anna')").unwrap_err().code(), "23505");
+        // 作り直したテーブルは新しい形式で保存され、開き直しても変わらない
+        db.execute("INSERT INTO users VALUES (3, '007')").unwrap();
+        let db = Database::open(path).unwrap();
+        let rows = db.query("SELECT id, name FROM users ORDER BY id").unwrap();
+        let ids: Vec<i64> = rows.iter().map(|r| r.get(0).unwrap()).collect();
+        assert_eq!(ids, [1, 2, 3, 10]);
+        assert_eq!(db.query("SELECT name FROM users WHERE id = 3").unwrap().iter().next().unwrap().get::<Value>(0).unwrap(), Value::from("007"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_quoted_values_keep_their_type() {
+        let mut db = Database::new();
+        db.execute_batch("CREATE TABLE z (id, zip); INSERT INTO z VALUES (1, '007'), (2, '1e3'), (3, 42)").unwrap();
+        db.execute("UPDATE z SET zip = '0123' WHERE id = 3").unwrap();
+        let zips: Vec<Value> = db.query("SELECT zip FROM z").unwrap().iter().map(|r| r.get(0).unwrap()).collect();
+        assert_eq!(zips, [Value::from("007"), Value::from("1e3"), Value::from("0123")]);
+        let mut insert = db.prepare("INSERT INTO z VALUES (?, ?)").unwrap();
+        insert.bind(1, 4).unwrap().bind(2, Value::Text("08".into())).unwrap();
+        insert.execute(&mut db).unwrap();
+        assert_eq!(db.query("SELECT zip FROM z WHERE id = 4").unwrap().iter().next().unwrap().get::<Value>(0).unwrap(), Value::from("08"));
+        // 主キーは '1' と 1 を同じキーとして扱う
+        assert_eq!(db.execute("INSERT INTO z VALUES ('1', 'x')").unwrap_err().code(), "23505");
+        assert_eq!(db.query("SELECT zip FROM z WHERE id = '2'").unwrap().len(), 1);
+        // '05' を先に格納しても、5 や '5' で同じ行が見つかり、格納した表記はそのまま残る
+        db.execute("INSERT INTO z VALUES ('05', 'y')").unwrap();
+        assert_eq!(db.execute("INSERT INTO z VALUES (5, 'x')").unwrap_err().code(), "23505");
+        assert_eq!(db.query("SELECT zip FROM z WHERE id = 5").unwrap().len(), 1);
+        assert_eq!(db.execute("INSERT INTO z VALUES ('5', 'x') ON CONFLICT (id) DO UPDATE SET zip = 'z'").unwrap(), 1);
+        let max = db.query("SELECT MAX(id) FROM z").unwrap();
+        assert_eq!(max.iter().next().unwrap().get::<Value>(0).unwrap(), Value::from("05"));
+        assert_eq!(db.execute("DELETE FROM z WHERE id = 5").unwrap(), 1);
+        assert_eq!(db.query("SELECT * FROM z").unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_open() {
+        let path = std::env::temp_dir().join(format!("ferrdb-open-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let mut db = Database::open(path).unwrap();
+        db.execute("CREATE TABLE users (id, name)").unwrap();
+        assert_eq!(db.execute("INSERT INTO users VALUES (1, 'John'), (2, 'Mike')").unwrap(), 2);
+        assert_eq!(db.execute("DELETE FROM users WHERE id = 2").unwrap(), 1);
+        // 書き込みのたびに保存されているので、開き直しても残っている
+        let db = Database::open(path).unwrap();
+        let rows = db.query("SELECT id, name FROM users").unwrap();
+        assert_eq!(rows.len(), 1);
+        let row = rows.iter().next().unwrap();
+        assert_eq!((row.get::<i64>("id").unwrap(), row.get::<String>(1).unwrap()), (1, "John".to_string()));
+        assert_eq!(db.query("DELETE FROM users").unwrap_err().code(), "42000");
+        // カタログを見る文も query で結果の行を返す
+        let tables = db.query("SHOW TABLES").unwrap();
+        assert_eq!(tables.iter().next().unwrap().get::<String>("table_name").unwrap(), "users");
+        assert_eq!(db.query("DESCRIBE users").unwrap().len(), 2);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_transaction() {
+        let path = std::env::temp_dir().join(format!("ferrdb-transaction-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let mut db = Database::open(path).unwrap();
+        db.execute_batch("CREATE TABLE users (id, name); INSERT INTO users VALUES (1, 'John')").unwrap();
+        let names = |db: &Database| -> Vec<String> { db.query("SELECT name FROM users").unwrap().iter().map(|r| r.get(0).unwrap()).collect() };
+
+        // ROLLBACK で BEGIN からの書き込みをすべて取り消す
+        db.execute_batch("BEGIN; INSERT INTO users VALUES (2, 'Mike'); UPDATE users SET id = 3, name = 'Johnny' WHERE id = 1").unwrap();
+        assert_eq!(names(&db), vec!["Mike", "Johnny"]);
+        // COMMIT までファイルには保存しない
+        assert_eq!(names(&Database::open(path).unwrap()), vec!["John"]);
+        db.execute("ROLLBACK").unwrap();
+        assert_eq!(names(&db), vec!["John"]);
+
+        // 失敗した文の書き込みだけを取り消し、トランザクションは続く
+        db.execute("BEGIN TRANSACTION").unwrap();
+        db.execute("INSERT INTO users VALUES (2, 'Mike')").unwrap();
+        assert_eq!(db.execute("INSERT INTO users VALUES (2, 'Anna')").unwrap_err().code(), "23505");
+        assert_eq!(db.execute("CREATE TABLE t (id)").unwrap_err().code(), "42000");
+        assert_eq!(db.execute("BEGIN").unwrap_err().code(), "42000");
+        db.execute("COMMIT").unwrap();
+        assert_eq!(names(&Database::open(path).unwrap()), vec!["John", "Mike"]);
+        assert_eq!(db.execute("COMMIT").unwrap_err().code(), "42000");
+        assert_eq!(db.execute("ROLLBACK").unwrap_err().code(), "42000");
+        fs::remove_file(path).unwrap();
     }
 
-    pub fn save_data(&self, path: &str) {
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(path)
-            .expect("Unable to open file for saving data");
-        let writer = BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, self).expect("Failed to save data");
+    #[test]
+    fn test_execute_batch() {
+        let path = std::env::temp_dir().join(format!("ferrdb-batch-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let mut db = Database::open(path).unwrap();
+        // 引用符・コメント・トリガー本体の中のセミコロンでは区切らず、文は複数行にまたがってよい
+        db.execute_batch(
+            "-- schema
+             CREATE TABLE users (id, name);
+             CREATE TABLE log (id, note);
+             CREATE TRIGGER log_insert AFTER INSERT ON users FOR EACH ROW BEGIN
+                 INSERT INTO log VALUES (NEW.id, CASE WHEN NEW.name = 'a;b' THEN 'semi;colon' ELSE 'plain' END);
+             END;
+             /* two rows; one statement */
+             INSERT INTO users
+             VALUES (1, 'a;b'), (2, \"it's\");
+             SELECT * FROM users",
+        )
+        .unwrap();
+        let notes: Vec<String> = db.query("SELECT note FROM log ORDER BY id").unwrap().iter().map(|r| r.get(0).unwrap()).collect();
+        assert_eq!(notes, ["semi;colon", "plain"]);
+        // エラーの文で止まり、それまでの文の結果は残ってファイルにも保存される
+        let err = db.execute_batch("INSERT INTO users VALUES (3, 'c'); INSERT INTO users VALUES (1, 'x'); INSERT INTO users VALUES (4, 'd')");
+        assert_eq!(err.unwrap_err().code(), "23505");
+        let mut db = Database::open(path).unwrap();
+        assert_eq!(db.query("SELECT id FROM users").unwrap().len(), 3);
+        // 文ごとの結果を受け取れる。自動保存を切れば、save を呼ぶまでファイルは変わらない
+        db.set_autosave(false);
+        let mut affected = Vec::new();
+        db.execute_batch_with("DELETE FROM users WHERE id = 3; SELECT * FROM users", |query, result| {
+            affected.push((query.is_read_only(), result.rows_affected, result.rows.len()))
+        })
+        .unwrap();
+        assert_eq!(affected, [(false, 1, 0), (true, 0, 2)]);
+        assert_eq!(Database::open(path).unwrap().query("SELECT id FROM users").unwrap().len(), 3);
+        db.save().unwrap();
+        assert_eq!(Database::open(path).unwrap().query("SELECT id FROM users").unwrap().len(), 2);
+        assert!(split_statements(" ; -- nothing\n /* here */ ").is_empty());
+        assert!(!crate::parser::is_complete("SELECT 'a;"));
+        assert!(!crate::parser::is_complete("CREATE TRIGGER t AFTER INSERT ON users FOR EACH ROW BEGIN DELETE FROM log;"));
+        assert!(crate::parser::is_complete("SELECT * FROM users; -- done"));
+        fs::remove_file(path).unwrap();
     }
 
-    pub fn load_data(&mut self, path: &str) {
-        if let Ok(file) = File::open(path) {
-            let reader = BufReader::new(file);
-            *self = serde_json::from_reader(reader).expect("Failed to load data");
+    #[test]
+    fn test_open_readonly() {
+        let path = std::env::temp_dir().join(format!("ferrdb-readonly-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        // 読み取り専用ではファイルを作らない
+        assert_eq!(Database::open_readonly(path).err().unwrap().code(), "58030");
+        let mut db = Database::open(path).unwrap();
+        db.execute_batch("CREATE TABLE users (id, name); INSERT INTO users VALUES (1, 'John')").unwrap();
+        let saved = fs::read_to_string(path).unwrap();
+        let mut db = Database::open_readonly(path).unwrap();
+        assert!(db.is_readonly());
+        assert_eq!(db.query("SELECT name FROM users").unwrap().len(), 1);
+        assert_eq!(db.execute("SHOW TABLES").unwrap(), 0);
+        for sql in ["INSERT INTO users VALUES (2, 'Mike')", "DELETE FROM users", "CREATE TABLE t (id)", "ANALYZE"] {
+            assert_eq!(db.execute(sql).unwrap_err().code(), "25006", "{}", sql);
         }
+        assert_eq!(db.query("SELECT * FROM users").unwrap().len(), 1);
+        // トランザクションは始められ、その中の書き込みだけを拒否する
+        db.execute("BEGIN").unwrap();
+        assert_eq!(db.query("SELECT * FROM users").unwrap().len(), 1);
+        assert_eq!(db.execute("INSERT INTO users VALUES (2, 'Mike')").unwrap_err().code(), "25006");
+        db.execute("COMMIT").unwrap();
+        db.execute_batch("START TRANSACTION; SELECT name FROM users; ROLLBACK").unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), saved);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_view() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE users (id, name, age)").unwrap();
+        execute(&mut db, "INSERT INTO users VALUES (1, 'John', 30), (2, 'Mike', 40), (3, 'Anna', 25)").unwrap();
+        execute(&mut db, "CREATE VIEW adults (uid, who) AS SELECT id, name FROM users WHERE age >= 30").unwrap();
+        let names = |db: &mut Database, sql: &str| execute(db, sql).unwrap().rows;
+        assert_eq!(names(&mut db, "SELECT who FROM adults ORDER BY uid"), vec![vec![Value::from("John")], vec![Value::from("Mike")]]);
+        // ビューは参照するたびに展開するので、元のテーブルの変更がそのまま見える
+        execute(&mut db, "INSERT INTO users VALUES (4, 'Ken', 50)").unwrap();
+        let sql = "SELECT a.who, u.age FROM adults a JOIN users u ON a.uid = u.id WHERE a.uid > 2";
+        assert_eq!(names(&mut db, sql), vec![vec![Value::from("Ken"), Value::Integer(50)]]);
+        // 外側の CTE と同じ名前のテーブルを参照していても、ビューは実テーブルを読む
+        let sql = "WITH users AS (SELECT id FROM users WHERE id = 1) SELECT count(*) FROM adults";
+        assert_eq!(names(&mut db, sql), vec![vec![Value::Integer(3)]]);
+        // カラム名はカラムの並びを省いたビューでも定義の SELECT 文から分かる
+        execute(&mut db, "CREATE VIEW names AS SELECT name, age * 2 AS double_age FROM users").unwrap();
+        assert_eq!(db.relation_columns("names"), ["name", "double_age"]);
+        assert_eq!(db.relation_columns("adults"), ["uid", "who"]);
+        assert_eq!(db.relation_columns("users"), ["id", "name", "age"]);
+
+        let code = |db: &mut Database, sql: &str| execute(db, sql).unwrap_err().code();
+        assert_eq!(code(&mut db, "CREATE VIEW users AS SELECT id FROM users"), "42P07");
+        assert_eq!(code(&mut db, "CREATE TABLE adults (id)"), "42P07");
+        assert_eq!(code(&mut db, "CREATE VIEW v AS SELECT nope FROM users"), "42703");
+        assert_eq!(code(&mut db, "CREATE VIEW v (a) AS SELECT id, name FROM users"), "42000");
+        assert_eq!(code(&mut db, "CREATE VIEW v AS SELECT id, id FROM users"), "42701");
+        assert_eq!(code(&mut db, "INSERT INTO adults VALUES (5, 'Bob')"), "42000");
+        assert_eq!(code(&mut db, "DELETE FROM adults"), "42000");
+        assert_eq!(code(&mut db, "REFRESH MATERIALIZED VIEW adults"), "42000");
+        assert!(db.get_view("v").is_none());
+    }
+
+    #[test]
+    fn test_materialized_view() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE orders (id, user_id, total)").unwrap();
+        execute(&mut db, "INSERT INTO orders VALUES (1, 1, 100), (2, 1, 50), (3, 2, 70)").unwrap();
+        let sql = "CREATE MATERIALIZED VIEW totals AS SELECT user_id, sum(total) AS total FROM orders GROUP BY user_id";
+        execute(&mut db, sql).unwrap();
+        let totals = |db: &mut Database| execute(db, "SELECT * FROM totals ORDER BY user_id").unwrap().rows;
+        let expected = vec![vec![Value::Integer(1), Value::Integer(150)], vec![Value::Integer(2), Value::Integer(70)]];
+        assert_eq!(totals(&mut db), expected);
+        // REFRESH するまでは作った時点の結果のまま
+        execute(&mut db, "INSERT INTO orders VALUES (4, 2, 30)").unwrap();
+        assert_eq!(totals(&mut db), expected);
+        assert_eq!(execute(&mut db, "REFRESH MATERIALIZED VIEW totals").unwrap().rows_affected, 2);
+        assert_eq!(totals(&mut db)[1], vec![Value::Integer(2), Value::Integer(100)]);
+        assert_eq!(execute(&mut db, "UPDATE totals SET total = 0").unwrap_err().code(), "42000");
+        assert_eq!(execute(&mut db, "REFRESH MATERIALIZED VIEW nope").unwrap_err().code(), "42P01");
+        // 最初のカラムが主キーになるので、重複すると作れない。REFRESH に失敗したら元の結果が残る
+        let sql = "CREATE MATERIALIZED VIEW dup AS SELECT user_id FROM orders";
+        assert_eq!(execute(&mut db, sql).unwrap_err().code(), "23505");
+        assert!(db.get_view("dup").is_none() && db.get_table("dup").is_none());
+    }
+
+    #[test]
+    fn test_trigger() {
+        let mut db = Database::new();
+        for sql in [
+            "CREATE TABLE users (id, name, age)",
+            "CREATE TABLE log (entry, detail)",
+            "CREATE TABLE counts (name, n)",
+            "INSERT INTO counts VALUES ('users', 0)",
+            "CREATE TRIGGER log_insert AFTER INSERT ON users FOR EACH ROW BEGIN
+                INSERT INTO log VALUES ('ins:' || NEW.id, NEW.name);
+                UPDATE counts SET n = n + 1 WHERE name = 'users';
+            END",
+            "CREATE TRIGGER log_update AFTER UPDATE ON users FOR EACH ROW BEGIN
+                INSERT INTO log VALUES ('upd:' || NEW.id, OLD.name || '->' || NEW.name);
+            END",
+            "CREATE TRIGGER count_delete AFTER DELETE ON users FOR EACH ROW BEGIN
+                UPDATE counts SET n = n - 1 WHERE name = 'users';
+            END",
+            // 99 歳以上の行は削除できない（0 除算のエラーで文全体を取り消す）
+            "CREATE TRIGGER keep_old BEFORE DELETE ON users FOR EACH ROW BEGIN
+                SELECT 1 / 0 FROM counts WHERE OLD.age >= 99;
+            END",
+        ] {
+            execute(&mut db, sql).unwrap();
+        }
+        let rows = |db: &mut Database, sql: &str| execute(db, sql).unwrap().rows;
+        let count = |db: &mut Database| rows(db, "SELECT n FROM counts")[0][0].clone();
+
+        let result = execute(&mut db, "INSERT INTO users VALUES (1, 'John', 30), (2, 'Mike', 99), (3, 'Anna', 25)").unwrap();
+        // トリガーの書き込みは行数に含めない
+        assert_eq!(result.rows_affected, 3);
+        assert_eq!(count(&mut db), Value::Integer(3));
+        execute(&mut db, "UPDATE users SET name = 'Johnny' WHERE id = 1").unwrap();
+        assert_eq!(rows(&mut db, "SELECT detail FROM log WHERE entry = 'upd:1'"), vec![vec![Value::from("John->Johnny")]]);
+        execute(&mut db, "DELETE FROM users WHERE id = 3").unwrap();
+        assert_eq!(count(&mut db), Value::Integer(2));
+
+        // BEFORE トリガーが失敗すると、どの行も削除されない
+        assert_eq!(execute(&mut db, "DELETE FROM users").unwrap_err().code(), "22012");
+        assert_eq!(rows(&mut db, "SELECT count(*) FROM users"), vec![vec![Value::Integer(2)]]);
+        assert_eq!(count(&mut db), Value::Integer(2));
+        // AFTER トリガーが失敗すると、書き込んだ行も他のトリガーの書き込みも取り消す
+        execute(&mut db, "INSERT INTO log VALUES ('ins:5', 'taken')").unwrap();
+        let err = execute(&mut db, "INSERT INTO users VALUES (4, 'Ken', 40), (5, 'Bob', 50)").unwrap_err();
+        assert_eq!(err.code(), "23505");
+        assert_eq!(rows(&mut db, "SELECT count(*) FROM users"), vec![vec![Value::Integer(2)]]);
+        assert_eq!(count(&mut db), Value::Integer(2));
+        assert!(rows(&mut db, "SELECT * FROM log WHERE entry = 'ins:4'").is_empty());
+        // 主キーを変えた行や ON CONFLICT で置き換えた行も、元の行に戻す
+        execute(&mut db, "INSERT INTO log VALUES ('upd:7', 'taken')").unwrap();
+        assert_eq!(execute(&mut db, "UPDATE users SET id = id + 5").unwrap_err().code(), "23505");
+        let users = vec![vec![Value::Integer(1), Value::from("Johnny")], vec![Value::Integer(2), Value::from("Mike")]];
+        assert_eq!(rows(&mut db, "SELECT id, name FROM users"), users);
+        assert!(rows(&mut db, "SELECT * FROM log WHERE entry = 'upd:6'").is_empty());
+        let err = execute(&mut db, "INSERT INTO users VALUES (1, 'Jo', 31) ON CONFLICT (id) DO UPDATE SET name = excluded.name").unwrap_err();
+        assert_eq!(err.code(), "23505");
+        assert_eq!(rows(&mut db, "SELECT id, name FROM users"), users);
+        assert!(db.undo.is_none());
+    }
+
+    #[test]
+    fn test_trigger_errors() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE t (id)").unwrap();
+        execute(&mut db, "CREATE VIEW v AS SELECT id FROM t").unwrap();
+        let code = |db: &mut Database, sql: &str| execute(db, sql).unwrap_err().code();
+        let body = "FOR EACH ROW BEGIN INSERT INTO t VALUES (NEW.id + 1); END";
+        assert_eq!(code(&mut db, &format!("CREATE TRIGGER a AFTER INSERT ON nope {}", body)), "42P01");
+        assert_eq!(code(&mut db, &format!("CREATE TRIGGER a AFTER INSERT ON v {}", body)), "42000");
+        assert_eq!(code(&mut db, &format!("CREATE TRIGGER a AFTER DELETE ON t {}", body)), "42000");
+        assert_eq!(code(&mut db, "CREATE TRIGGER a AFTER INSERT ON t FOR EACH ROW BEGIN DELETE FROM t WHERE id = NEW.nope; END"), "42703");
+        assert_eq!(code(&mut db, "CREATE TRIGGER a AFTER INSERT ON t FOR EACH ROW BEGIN CREATE TABLE x (id); END"), "42601");
+        assert_eq!(code(&mut db, "CREATE TRIGGER a AFTER INSERT ON t FOR EACH ROW BEGIN DELETE FROM t END"), "42601");
+        assert_eq!(code(&mut db, "CREATE TRIGGER a AFTER INSERT ON t FOR EACH ROW BEGIN END"), "42601");
+        execute(&mut db, &format!("CREATE TRIGGER a AFTER INSERT ON t {}", body)).unwrap();
+        assert_eq!(code(&mut db, &format!("CREATE TRIGGER a BEFORE INSERT ON t {}", body)), "42710");
+        // 自分のテーブルに書き込み続けるトリガーは深さの上限で止め、最初の INSERT ごと取り消す
+        let err = execute(&mut db, "INSERT INTO t VALUES (1)").unwrap_err();
+        assert_eq!(err.to_string(), format!("Triggers and foreign key actions nested more than {} levels deep.", MAX_DEPTH));
+        assert!(execute(&mut db, "SELECT * FROM t").unwrap().rows.is_empty());
     }
 }
@@ -0,0 +1,114 @@
+use std::fmt;
+use std::io;
+
+/// パーサ・カタログ・ストレージ共通のエラー型。
+/// `code()` で PostgreSQL 互換の SQLSTATE を返す。
+#[derive(Debug)]
+pub enum DbError {
+    /// SQL の構文エラー。
+    Syntax(String),
+    /// 存在しないテーブルを参照した。
+    UndefinedTable(String),
+    /// 存在しないカラムを参照した。
+    UndefinedColumn(String),
+    /// 存在しないパラメータに値をバインドしようとした。
+    UndefinedParameter(String),
+    /// 同名のテーブルが既にある。
+    DuplicateTable(String),
+    /// 同名のトリガーが既にある。
+    DuplicateTrigger(String),
+    /// 同じカラムが 2 回以上指定された。
+    DuplicateColumn(String),
+    /// どのテーブルのカラムか決められない。
+    AmbiguousColumn(String),
+    /// 一意性のないカラムを ON CONFLICT の対象に指定した。
+    InvalidColumnReference(String),
+    /// 構文は正しいが意味的に実行できないクエリ（集約の使い方、ORDER BY の位置など）。
+    InvalidQuery(String),
+    /// 主キーの重複。値は重複したキー。
+    UniqueViolation(String),
+    /// 外部キー制約違反。親テーブルにない値を参照した、または参照されている行を削除・変更した。
+    ForeignKeyViolation(String),
+    /// NOT NULL 制約違反。値はカラム名。
+    NotNullViolation(String),
+    /// 型が合わない演算・比較・変換。
+    TypeMismatch(String),
+    /// 関数の引数や LIMIT などに不正な値が渡された。
+    InvalidValue(String),
+    DivisionByZero,
+    /// 1 行（1 列）であるべき結果がそうでなかった。
+    CardinalityViolation(String),
+    /// データファイルの読み書きに失敗した。
+    Io(io::Error),
+    /// データファイルの内容が壊れていて読み込めない。
+    Corrupted(String),
+    /// 読み取り専用で開いたデータベースに書き込もうとした。
+    ReadOnly,
+}
+
+impl DbError {
+    /// SQLSTATE コード。
+    pub fn code(&self) -> &'static str {
+        match self {
+            DbError::Syntax(_) => "42601",
+            DbError::UndefinedTable(_) => "42P01",
+            DbError::UndefinedColumn(_) => "42703",
+            DbError::UndefinedParameter(_) => "42P02",
+            DbError::DuplicateTable(_) => "42P07",
+            DbError::DuplicateTrigger(_) => "42710",
+            DbError::DuplicateColumn(_) => "42701",
+            DbError::AmbiguousColumn(_) => "42702",
+            DbError::InvalidColumnReference(_) => "42P10",
+            DbError::InvalidQuery(_) => "42000",
+            DbError::UniqueViolation(_) => "23505",
+            DbError::ForeignKeyViolation(_) => "23503",
+            DbError::NotNullViolation(_) => "23502",
+            DbError::TypeMismatch(_) => "42804",
+            DbError::InvalidValue(_) => "22023",
+            DbError::DivisionByZero => "22012",
+            DbError::CardinalityViolation(_) => "21000",
+            DbError::Io(_) => "58030",
+            DbError::Corrupted(_) => "XX001",
+            DbError::ReadOnly => "25006",
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Syntax(msg) => write!(f, "Invalid syntax: {}", msg),
+            DbError::UndefinedTable(name) => write!(f, "Table '{}' does not exist.", name),
+            DbError::UndefinedColumn(name) => write!(f, "Column '{}' does not exist.", name),
+            DbError::UndefinedParameter(name) => write!(f, "Parameter '{}' does not exist.", name),
+            DbError::DuplicateTable(name) => write!(f, "Table '{}' already exists.", name),
+            DbError::DuplicateTrigger(name) => write!(f, "Trigger '{}' already exists.", name),
+            DbError::DuplicateColumn(name) => write!(f, "Column '{}' specified more than once.", name),
+            DbError::AmbiguousColumn(name) => write!(f, "Column reference '{}' is ambiguous.", name),
+            DbError::UniqueViolation(key) => write!(f, "Duplicate primary key '{}'.", key),
+            DbError::NotNullViolation(column) => write!(f, "Primary key '{}' cannot be NULL.", column),
+            DbError::DivisionByZero => write!(f, "Division by zero"),
+            DbError::Io(e) => write!(f, "I/O error: {}", e),
+            DbError::Corrupted(msg) => write!(f, "Data file is corrupted: {}", msg),
+            DbError::ReadOnly => write!(f, "Cannot write to a read-only database."),
+            DbError::InvalidQuery(msg) | DbError::InvalidColumnReference(msg) | DbError::ForeignKeyViolation(msg) | DbError::TypeMismatch(msg) | DbError::InvalidValue(msg) | DbError::CardinalityViolation(msg) => {
+                write!(f, "{}", msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DbError {
+    fn from(e: io::Error) -> Self {
+        DbError::Io(e)
+    }
+}
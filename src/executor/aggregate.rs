@@ -0,0 +1,363 @@
+use crate::error::DbError;
+use crate::executor::eval::{eval, Scope};
+use crate::executor::{is_primary_key, output_columns, project, table_columns, window, ColumnInfo, ExecContext, ResultSet};
+use crate::parser::ast::{AggregateFunction, Expr, Select, SelectItem};
+use crate::table::Table;
+use crate::value::Value;
+use std::collections::{HashMap, HashSet};
+
+/// 集約関数 1 つ分の途中結果
+pub struct Accumulator {
+    func: AggregateFunction,
+    seen: Option<HashSet<Value>>, // DISTINCT 指定時のみ使用
+    count: i64,
+    sum: Option<Value>,
+    extreme: Option<Value>, // MIN / MAX の現在値
+}
+
+impl Accumulator {
+    pub fn new(func: AggregateFunction, distinct: bool) -> Self {
+        Accumulator {
+            func,
+            seen: if distinct { Some(HashSet::new()) } else { None },
+            count: 0,
+            sum: None,
+            extreme: None,
+        }
+    }
+
+    /// 1 行分の値を取り込む。`value` が None なのは COUNT(*) の場合。
+    pub fn update(&mut self, value: Option<Value>) -> Result<(), DbError> {
+        let value = match value {
+            None => {
+                self.count += 1;
+                return Ok(());
+            }
+            Some(Value::Null) => return Ok(()), // NULL は集約対象外
+            Some(v) => v,
+        };
+        if let Some(seen) = &mut self.seen {
+            if !seen.insert(value.clone()) {
+                return Ok(());
+            }
+        }
+        self.count += 1;
+        match self.func {
+            AggregateFunction::Count => {}
+            AggregateFunction::Sum | AggregateFunction::Avg => {
+                let value = value.numeric_affinity();
+                if !value.is_numeric() {
+                    return Err(DbError::TypeMismatch(format!("{}() requires numeric values, found '{}'", self.func, value)));
+                }
+                self.sum = Some(match self.sum.take() {
+                    None => value,
+                    Some(acc) => add(acc, value),
+                });
+            }
+            AggregateFunction::Min => {
+                if self.extreme.as_ref().is_none_or(|m| value < *m) {
+                    self.extreme = Some(value);
+                }
+            }
+            AggregateFunction::Max => {
+                if self.extreme.as_ref().is_none_or(|m| value > *m) {
+                    self.extreme = Some(value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(&self) -> Value {
+        match self.func {
+            AggregateFunction::Count => Value::Integer(self.count),
+            AggregateFunction::Sum => self.sum.clone().unwrap_or(Value::Null),
+            AggregateFunction::Avg => match &self.sum {
+                Some(sum) => Value::Float(sum.as_f64().unwrap_or(0.0) / self.count as f64),
+                None => Value::Null,
+            },
+            AggregateFunction::Min | AggregateFunction::Max => self.extreme.clone().unwrap_or(Value::Null),
+        }
+    }
+}
+
+/// 整数同士は整数のまま加算し、オーバーフローした場合や Float を含む場合は Float にする。
+fn add(a: Value, b: Value) -> Value {
+    match (&a, &b) {
+        (Value::Integer(x), Value::Integer(y)) => match x.checked_add(*y) {
+            Some(s) => Value::Integer(s),
+            None => Value::Float(*x as f64 + *y as f64),
+        },
+        _ => Value::Float(a.as_f64().unwrap_or(0.0) + b.as_f64().unwrap_or(0.0)),
+    }
+}
+
+/// 集約クエリかどうか（集約関数・GROUP BY・HAVING のいずれかを含む）。
+/// `extra` は ORDER BY などで追加評価する式。
+pub fn is_aggregate_query(select: &Select, extra: &[Expr]) -> bool {
+    !select.group_by.is_empty()
+        || select.having.is_some()
+        || extra.iter().any(Expr::contains_aggregate)
+        || select.select_columns.iter().any(|item| match item {
+            SelectItem::Expr { expr, .. } => expr.contains_aggregate(),
+            SelectItem::Wildcard | SelectItem::QualifiedWildcard(_) => false,
+        })
+}
+
+/// GROUP BY をハッシュ集約で実行する。グループはハッシュマップで管理し、出現順に出力する。
+pub fn execute(
+    ctx: &ExecContext,
+    columns: &[ColumnInfo],
+    rows: Vec<Vec<Value>>,
+    select: &Select,
+    extra: &[Expr],
+) -> Result<ResultSet, DbError> {
+    validate(select, extra)?;
+    let aggregates = collect_aggregates(select, extra);
+
+    let mut index: HashMap<Vec<Value>, usize> = HashMap::new();
+    let mut groups: Vec<(Vec<Value>, Vec<Accumulator>)> = Vec::new();
+    for row in rows {
+        let scope = Scope::new(ctx, columns, &row);
+        let key = select
+            .group_by
+            .iter()
+            .map(|e| eval(e, &scope))
+            .collect::<Result<Vec<_>, _>>()?;
+        let group = match index.get(&key) {
+            Some(&i) => i,
+            None => {
+                index.insert(key, groups.len());
+                groups.push((row.clone(), new_accumulators(&aggregates)));
+                groups.len() - 1
+            }
+        };
+        for (acc, agg) in groups[group].1.iter_mut().zip(&aggregates) {
+            let value = match agg {
+                Expr::Aggregate { arg: Some(arg), .. } => Some(eval(arg, &scope)?),
+                _ => None,
+            };
+            acc.update(value)?;
+        }
+    }
+    // GROUP BY なしの集約は、行が 0 件でも 1 行を返す
+    if select.group_by.is_empty() && groups.is_empty() {
+        groups.push((Vec::new(), new_accumulators(&aggregates)));
+    }
+
+    let mut kept = Vec::new();
+    for (row, accumulators) in groups {
+        let values: Vec<(Expr, Value)> = aggregates
+            .iter()
+            .cloned()
+            .zip(accumulators.iter().map(Accumulator::finish))
+            .collect();
+        if having(&Scope::new(ctx, columns, &row).with_aggregates(&values), select)? {
+            kept.push((row, values));
+        }
+    }
+
+    // ウィンドウ関数は HAVING を通ったグループの行に対して計算する
+    let mut result = ResultSet { columns: output_columns(columns, &select.select_columns, extra)?, rows: Vec::new() };
+    let windows = window::collect(select, extra);
+    let scopes: Vec<Scope> = kept
+        .iter()
+        .map(|(row, values)| Scope::new(ctx, columns, row).with_aggregates(values))
+        .collect();
+    let window_values = window::compute(&windows, &scopes)?;
+    for (scope, values) in scopes.into_iter().zip(&window_values) {
+        result.rows.push(project(&scope.with_windows(&windows, values), &select.select_columns, extra)?);
+    }
+    Ok(result)
+}
+
+/// `SELECT COUNT(*) FROM t` や `MIN/MAX(主キー)` のように、値を走査せず B+Tree から直接求められる集約かどうか。
+/// WHERE 句のない集約クエリについて使う。GROUP BY やウィンドウ関数がある場合、または他の集約を含む場合は false。
+pub fn uses_index(table: &Table, select: &Select) -> bool {
+    select.group_by.is_empty()
+        && window::collect(select, &[]).is_empty()
+        && collect_aggregates(select, &[]).iter().all(|agg| index_value(table, select, agg).is_some())
+}
+
+/// `uses_index` が true の集約を B+Tree から求める。
+pub fn from_index(ctx: &ExecContext, table: &Table, select: &Select) -> Result<ResultSet, DbError> {
+    validate(select, &[])?;
+    let mut values = Vec::new();
+    for agg in collect_aggregates(select, &[]) {
+        let value = index_value(table, select, &agg)
+            .ok_or_else(|| DbError::InvalidQuery(format!("'{}' cannot be answered from the index.", agg)))?;
+        values.push((agg, value));
+    }
+    let columns = table_columns(table, &select.from);
+    let mut result = ResultSet { columns: output_columns(&columns, &select.select_columns, &[])?, rows: Vec::new() };
+    let scope = Scope::new(ctx, &columns, &[]).with_aggregates(&values);
+    if having(&scope, select)? {
+        result.rows.push(project(&scope, &select.select_columns, &[])?);
+    }
+    Ok(result)
+}
+
+fn index_value(table: &Table, select: &Select, agg: &Expr) -> Option<Value> {
+    match agg {
+        Expr::Aggregate { func: AggregateFunction::Count, arg: None, .. } => Some(Value::Integer(table.row_count() as i64)),
+        Expr::Aggregate { func: AggregateFunction::Min, arg: Some(arg), .. } if is_primary_key(table, &select.from, arg) => {
+            Some(table.rows().next().map_or(Value::Null, |row| row[0].clone()))
+        }
+        Expr::Aggregate { func: AggregateFunction::Max, arg: Some(arg), .. } if is_primary_key(table, &select.from, arg) => {
+            Some(table.data.last_key().and_then(|key| table.data.search(key)).map_or(Value::Null, |row| row[0].clone()))
+        }
+        _ => None,
+    }
+}
+
+/// グループが HAVING 句を満たすかどうか（HAVING がなければ常に true）。
+fn having(scope: &Scope, select: &Select) -> Result<bool, DbError> {
+    match &select.having {
+        Some(having) => Ok(eval(having, scope)?.is_truthy()),
+        None => Ok(true),
+    }
+}
+
+/// GROUP BY の式と同じ式かどうか。カラム参照は片方だけがテーブル名で修飾されていても同じとみなす。
+pub fn same_expr(group: &Expr, expr: &Expr) -> bool {
+    match (group, expr) {
+        (Expr::Column { table: t1, name: n1 }, Expr::Column { table: t2, name: n2 }) => {
+            n1 == n2 && (t1.is_none() || t2.is_none() || t1 == t2)
+        }
+        _ => group == expr,
+    }
+}
+
+fn new_accumulators(aggregates: &[Expr]) -> Vec<Accumulator> {
+    aggregates
+        .iter()
+        .map(|agg| match agg {
+            Expr::Aggregate { func, distinct, .. } => Accumulator::new(*func, *distinct),
+            _ => unreachable!("collect_aggregates only returns aggregate calls"),
+        })
+        .collect()
+}
+
+/// SELECT 句・HAVING 句・追加の式に現れる集約関数を重複なく集める。
+fn collect_aggregates(select: &Select, extra: &[Expr]) -> Vec<Expr> {
+    let mut found = Vec::new();
+    for item in &select.select_columns {
+        if let SelectItem::Expr { expr: e, .. } = item {
+            find_aggregates(e, &mut found);
+        }
+    }
+    for e in select.having.iter().chain(extra) {
+        find_aggregates(e, &mut found);
+    }
+    found
+}
+
+fn find_aggregates(expr: &Expr, found: &mut Vec<Expr>) {
+    expr.walk(&mut |e| {
+        if matches!(e, Expr::Aggregate { .. }) && !found.contains(e) {
+            found.push(e.clone());
+        }
+    });
+}
+
+/// 集約クエリの SELECT 句・HAVING 句で、GROUP BY に含まれないカラムが集約の外で使われていないか確認する。
+fn validate(select: &Select, extra: &[Expr]) -> Result<(), DbError> {
+    for item in &select.select_columns {
+        match item {
+            SelectItem::Wildcard | SelectItem::QualifiedWildcard(_) => {
+                return Err(DbError::InvalidQuery("'*' cannot be used in an aggregate query".into()))
+            }
+            SelectItem::Expr { expr, .. } => check_grouped(expr, &select.group_by)?,
+        }
+    }
+    for e in select.having.iter().chain(extra) {
+        check_grouped(e, &select.group_by)?;
+    }
+    Ok(())
+}
+
+fn check_grouped(expr: &Expr, group_by: &[Expr]) -> Result<(), DbError> {
+    if group_by.iter().any(|g| same_expr(g, expr)) {
+        return Ok(());
+    }
+    match expr {
+        // サブクエリ内のカラムはサブクエリ側で解決される
+        Expr::Aggregate { .. } | Expr::Exists(_) | Expr::Subquery(_) => Ok(()),
+        Expr::Column { name, .. } => Err(DbError::InvalidQuery(format!(
+            "Column '{}' must appear in the GROUP BY clause or be used in an aggregate function",
+            name
+        ))),
+        _ => expr.children().into_iter().try_for_each(|e| check_grouped(e, group_by)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::executor::test_util::{database, explain, query};
+    use crate::value::Value;
+
+    fn setup() -> Database {
+        database(&[
+            "CREATE TABLE emp (id, dept, salary)",
+            "INSERT INTO emp VALUES (1, 'dev', 300), (2, 'dev', 500), (3, 'ops', 200), (4, 'sales', 400), (5, 'ops', 200)",
+        ])
+    }
+
+    #[test]
+    fn test_count_sum_avg_min_max() {
+        let db = setup();
+        let result = query(&db, "SELECT COUNT(*), SUM(salary), AVG(salary), MIN(salary), MAX(salary) FROM emp").unwrap();
+        assert_eq!(result.columns, vec!["COUNT(*)", "SUM(salary)", "AVG(salary)", "MIN(salary)", "MAX(salary)"]);
+        assert_eq!(
+            result.rows,
+            vec![vec![Value::Integer(5), Value::Integer(1600), Value::Float(320.0), Value::Integer(200), Value::Integer(500)]]
+        );
+    }
+
+    #[test]
+    fn test_count_distinct() {
+        let db = setup();
+        let result = query(&db, "SELECT COUNT(DISTINCT dept), COUNT(DISTINCT salary) FROM emp").unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Integer(3), Value::Integer(4)]]);
+    }
+
+    #[test]
+    fn test_group_by_having() {
+        let db = setup();
+        let result = query(&db, "SELECT dept, COUNT(*), SUM(salary) FROM emp GROUP BY dept HAVING COUNT(*) > 1").unwrap();
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![Value::Text("dev".into()), Value::Integer(2), Value::Integer(800)],
+                vec![Value::Text("ops".into()), Value::Integer(2), Value::Integer(400)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aggregate_with_where_and_empty_input() {
+        let db = setup();
+        let result = query(&db, "SELECT COUNT(*), SUM(salary) FROM emp WHERE dept = 'none'").unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Integer(0), Value::Null]]);
+        let result = query(&db, "SELECT dept, COUNT(*) FROM emp WHERE dept = 'none' GROUP BY dept").unwrap();
+        assert!(result.rows.is_empty());
+    }
+
+    #[test]
+    fn test_count_and_pk_bounds_from_index() {
+        let db = setup();
+        let sql = "SELECT COUNT(*), MIN(id), MAX(id) FROM emp";
+        assert_eq!(explain(&db, &format!("EXPLAIN {}", sql)), vec!["Index Aggregate on emp (COUNT(*), MIN(id), MAX(id))"]);
+        assert_eq!(query(&db, sql).unwrap().rows, vec![vec![Value::Integer(5), Value::Integer(1), Value::Integer(5)]]);
+        // 主キー以外の MIN は B+Tree からは求めない
+        assert!(!explain(&db, "EXPLAIN SELECT MIN(salary) FROM emp")[0].starts_with("Index Aggregate"));
+    }
+
+    #[test]
+    fn test_ungrouped_column_is_rejected() {
+        let db = setup();
+        assert!(query(&db, "SELECT dept, salary FROM emp GROUP BY dept").is_err());
+        assert!(query(&db, "SELECT id, COUNT(*) FROM emp").is_err());
+    }
+}
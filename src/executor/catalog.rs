@@ -0,0 +1,299 @@
+use crate::database::Database;
+use crate::error::DbError;
+use crate::executor::output_names;
+use crate::parser::ast::{Expr, ForeignKey, ReferentialAction};
+use crate::table::Table;
+use crate::value::Value;
+
+/// `information_schema` の仮想テーブル。参照するたびにテーブルとビューの定義から行を作る。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CatalogTable {
+    /// テーブルとビューの一覧
+    Tables,
+    /// テーブルとビューのカラムの一覧
+    Columns,
+    /// インデックスの一覧。インデックスは各テーブルの主キーの B+Tree だけ
+    Indexes,
+}
+
+impl CatalogTable {
+    /// `information_schema.tables` のような名前から仮想テーブルを探す。大文字・小文字は区別しない。
+    pub fn lookup(name: &str) -> Option<Self> {
+        let (schema, table) = name.split_once('.')?;
+        if !schema.eq_ignore_ascii_case("information_schema") {
+            return None;
+        }
+        match table.to_ascii_lowercase().as_str() {
+            "tables" => Some(CatalogTable::Tables),
+            "columns" => Some(CatalogTable::Columns),
+            "indexes" => Some(CatalogTable::Indexes),
+            _ => None,
+        }
+    }
+
+    pub fn columns(self) -> Vec<String> {
+        let names: &[&str] = match self {
+            CatalogTable::Tables => &["table_name", "table_type", "row_count"],
+            CatalogTable::Columns => &["table_name", "column_name", "ordinal_position", "column_default", "is_nullable", "references"],
+            CatalogTable::Indexes => &["table_name", "index_name", "column_name", "is_unique", "is_primary"],
+        };
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    /// 仮想テーブルの行。テーブル名の昇順に並ぶ。
+    pub fn rows(self, db: &Database) -> Vec<Vec<Value>> {
+        let mut rows = Vec::new();
+        for (name, kind) in relations(db) {
+            let table = db.get_table(name).filter(|_| kind != RelationKind::View);
+            match self {
+                CatalogTable::Tables => {
+                    let row_count = table.map_or(Value::Null, |t| Value::Integer(t.row_count() as i64));
+                    rows.push(vec![Value::from(name), Value::from(kind.as_str()), row_count]);
+                }
+                CatalogTable::Columns => {
+                    for (i, column) in columns(db, name).into_iter().enumerate() {
+                        let (default, nullable, references) = match table {
+                            Some(t) => (
+                                column_default(t, i).map_or(Value::Null, |d| Value::Text(d.to_string())),
+                                Value::from(if i == 0 { "NO" } else { "YES" }),
+                                foreign_key(t, &column).map_or(Value::Null, |fk| Value::Text(references(fk))),
+                            ),
+                            None => (Value::Null, Value::from("YES"), Value::Null),
+                        };
+                        rows.push(vec![Value::from(name), Value::from(column), Value::Integer(i as i64 + 1), default, nullable, references]);
+                    }
+                }
+                CatalogTable::Indexes => {
+                    if let Some(column) = table.and_then(Table::primary_key) {
+                        let index = format!("{}_pkey", name);
+                        rows.push(vec![Value::from(name), Value::Text(index), Value::from(column), Value::Boolean(true), Value::Boolean(true)]);
+                    }
+                }
+            }
+        }
+        rows
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RelationKind {
+    Table,
+    View,
+    MaterializedView,
+}
+
+impl RelationKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            RelationKind::Table => "BASE TABLE",
+            RelationKind::View => "VIEW",
+            RelationKind::MaterializedView => "MATERIALIZED VIEW",
+        }
+    }
+}
+
+/// テーブルとビューを名前の昇順に並べる。
+fn relations(db: &Database) -> Vec<(&str, RelationKind)> {
+    let mut relations: Vec<(&str, RelationKind)> = db
+        .table_names()
+        .into_iter()
+        .filter(|name| db.get_view(name).is_none())
+        .map(|name| (name, RelationKind::Table))
+        .collect();
+    relations.extend(db.view_names().into_iter().map(|name| match db.get_view(name) {
+        Some(view) if view.materialized => (name, RelationKind::MaterializedView),
+        _ => (name, RelationKind::View),
+    }));
+    relations.sort_by_key(|(name, _)| *name);
+    relations
+}
+
+/// テーブルかビューのカラム名。定義が壊れていて計画を立てられないビューは空にする。
+pub fn columns(db: &Database, name: &str) -> Vec<String> {
+    match (db.get_view(name), db.get_table(name)) {
+        (Some(view), _) if !view.columns.is_empty() => view.columns.clone(),
+        (Some(view), None) => view.statement().and_then(|s| output_names(db, &s)).unwrap_or_default(),
+        (_, Some(table)) => table.columns.clone(),
+        (None, None) => Vec::new(),
+    }
+}
+
+/// `DEFAULT` を指定したカラムの既定値。指定していなければ None。
+fn column_default(table: &Table, index: usize) -> Option<Expr> {
+    match table.default_value(index) {
+        Value::Null => None,
+        value => Some(Expr::Literal(value)),
+    }
+}
+
+fn foreign_key<'a>(table: &'a Table, column: &str) -> Option<&'a ForeignKey> {
+    table.foreign_keys.iter().find(|fk| fk.column == column)
+}
+
+/// `REFERENCES` 句の SQL 表記。既定の動作は省く。
+fn references(foreign_key: &ForeignKey) -> String {
+    let mut sql = foreign_key.table.clone();
+    if let Some(column) = &foreign_key.referenced {
+        sql += &format!(" ({})", column);
+    }
+    if foreign_key.on_delete != ReferentialAction::NoAction {
+        sql += &format!(" ON DELETE {}", foreign_key.on_delete);
+    }
+    if foreign_key.on_update != ReferentialAction::NoAction {
+        sql += &format!(" ON UPDATE {}", foreign_key.on_update);
+    }
+    if foreign_key.deferred {
+        sql += " DEFERRABLE INITIALLY DEFERRED";
+    }
+    sql
+}
+
+/// `SHOW TABLES` の結果の行（テーブル名と種類）。
+pub fn show_tables(db: &Database) -> Vec<Vec<Value>> {
+    relations(db).into_iter().map(|(name, kind)| vec![Value::from(name), Value::from(kind.as_str())]).collect()
+}
+
+/// `DESCRIBE table` の結果の行。カラムごとに、NULL を許すか・キーの種類・既定値・参照先を返す。
+/// 1 つのカラムに外部キーが複数あれば、最初のものを表示する。
+pub fn describe(db: &Database, name: &str) -> Result<Vec<Vec<Value>>, DbError> {
+    if db.get_table(name).is_none() && db.get_view(name).is_none() {
+        return Err(DbError::UndefinedTable(name.to_string()));
+    }
+    let table = db.get_table(name).filter(|_| db.get_view(name).is_none_or(|v| v.materialized));
+    let rows = columns(db, name).into_iter().enumerate().map(|(i, column)| {
+        let Some(table) = table else {
+            return vec![Value::from(column), Value::from("YES"), Value::Null, Value::Null, Value::Null];
+        };
+        let foreign_key = foreign_key(table, &column);
+        let key = match (i, foreign_key) {
+            (0, _) => Value::from("PRIMARY"),
+            (_, Some(_)) => Value::from("FOREIGN"),
+            _ => Value::Null,
+        };
+        vec![
+            Value::from(column.as_str()),
+            Value::from(if i == 0 { "NO" } else { "YES" }),
+            key,
+            column_default(table, i).map_or(Value::Null, |d| Value::Text(d.to_string())),
+            foreign_key.map_or(Value::Null, |fk| Value::Text(references(fk))),
+        ]
+    });
+    Ok(rows.collect())
+}
+
+/// `SHOW CREATE TABLE` の結果。そのまま実行すると同じ定義のテーブル（ビュー）を作る文を返す。
+pub fn create_statement(db: &Database, name: &str) -> Result<String, DbError> {
+    if let Some(view) = db.get_view(name) {
+        let materialized = if view.materialized { "MATERIALIZED " } else { "" };
+        let columns = match view.columns.is_empty() {
+            true => String::new(),
+            false => format!(" ({})", view.columns.join(", ")),
+        };
+        return Ok(format!("CREATE {}VIEW {}{} AS {}", materialized, name, columns, view.query));
+    }
+    let table = db.get_table(name).ok_or_else(|| DbError::UndefinedTable(name.to_string()))?;
+    let mut elements: Vec<String> = table
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let mut sql = column.clone();
+            if let Some(default) = column_default(table, i) {
+                sql += &format!(" DEFAULT {}", default);
+            }
+            if let Some(foreign_key) = foreign_key(table, column) {
+                sql += &format!(" REFERENCES {}", references(foreign_key));
+            }
+            sql
+        })
+        .collect();
+    // カラムごとに最初の外部キーはカラムの後に書き、同じカラムの 2 つ目以降は表の制約として書く
+    for (i, fk) in table.foreign_keys.iter().enumerate() {
+        if table.foreign_keys[..i].iter().any(|other| other.column == fk.column) {
+            elements.push(format!("FOREIGN KEY ({}) REFERENCES {}", fk.column, references(fk)));
+        }
+    }
+    Ok(format!("CREATE TABLE {} ({})", name, elements.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::executor::test_util::{database, execute, query, rows};
+
+    fn setup() -> Database {
+        database(&[
+            "CREATE TABLE users (id, name, country DEFAULT 'it''s')",
+            "CREATE TABLE orders (id, user_id REFERENCES users ON DELETE CASCADE, total DEFAULT 0, \
+             FOREIGN KEY (user_id) REFERENCES users (id) INITIALLY DEFERRED)",
+            "INSERT INTO users VALUES (1, 'John', 'JP'), (2, 'Mike', 'US')",
+            "CREATE VIEW names (who) AS SELECT name FROM users",
+            "CREATE MATERIALIZED VIEW totals AS SELECT user_id, sum(total) AS total FROM orders GROUP BY user_id",
+        ])
+    }
+
+    #[test]
+    fn test_show_tables() {
+        let db = setup();
+        let expected = vec![
+            vec!["names", "VIEW"],
+            vec!["orders", "BASE TABLE"],
+            vec!["totals", "MATERIALIZED VIEW"],
+            vec!["users", "BASE TABLE"],
+        ];
+        assert_eq!(rows(&query(&db, "SHOW TABLES").unwrap()), expected);
+        let sql = "SELECT table_name, row_count FROM information_schema.tables WHERE table_type <> 'VIEW' ORDER BY row_count DESC, table_name";
+        assert_eq!(rows(&query(&db, sql).unwrap()), vec![vec!["users", "2"], vec!["orders", "0"], vec!["totals", "0"]]);
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut db = setup();
+        let expected = vec![
+            vec!["id", "NO", "PRIMARY", "NULL", "NULL"],
+            vec!["user_id", "YES", "FOREIGN", "NULL", "users ON DELETE CASCADE"],
+            vec!["total", "YES", "NULL", "0", "NULL"],
+        ];
+        assert_eq!(rows(&query(&db, "DESCRIBE orders").unwrap()), expected);
+        assert_eq!(rows(&query(&db, "DESC names").unwrap()), vec![vec!["who", "YES", "NULL", "NULL", "NULL"]]);
+        assert_eq!(execute(&mut db, "DESCRIBE nope").unwrap_err().code(), "42P01");
+    }
+
+    #[test]
+    fn test_show_create_table() {
+        let db = setup();
+        let sql = |db: &Database, name: &str| rows(&query(db, &format!("SHOW CREATE TABLE {}", name)).unwrap())[0][1].clone();
+        assert_eq!(sql(&db, "users"), "CREATE TABLE users (id, name, country DEFAULT 'it''s')");
+        assert_eq!(
+            sql(&db, "orders"),
+            "CREATE TABLE orders (id, user_id REFERENCES users ON DELETE CASCADE, total DEFAULT 0, \
+             FOREIGN KEY (user_id) REFERENCES users (id) DEFERRABLE INITIALLY DEFERRED)"
+        );
+        assert_eq!(sql(&db, "names"), "CREATE VIEW names (who) AS SELECT name FROM users");
+        // 出力した文を別のデータベースで実行すると同じ定義になる
+        let mut copy = Database::new();
+        for name in ["users", "orders", "names"] {
+            execute(&mut copy, &sql(&db, name)).unwrap();
+        }
+        assert_eq!(copy.get_table("orders").unwrap().foreign_keys, db.get_table("orders").unwrap().foreign_keys);
+        assert_eq!(copy.get_table("users").unwrap().defaults, db.get_table("users").unwrap().defaults);
+        assert_eq!(copy.get_view("names"), db.get_view("names"));
+    }
+
+    #[test]
+    fn test_information_schema() {
+        let mut db = setup();
+        let sql = "SELECT c.column_name, c.ordinal_position, c.references FROM information_schema.columns c \
+                   WHERE c.table_name = 'orders' AND c.column_default IS NULL ORDER BY 2";
+        assert_eq!(rows(&query(&db, sql).unwrap()), vec![vec!["id", "1", "NULL"], vec!["user_id", "2", "users ON DELETE CASCADE"]]);
+        // ビューのカラムも含み、スキーマを省いた名前で修飾できる
+        let sql = "SELECT columns.column_name FROM information_schema.columns WHERE columns.table_name = 'totals' ORDER BY 1";
+        assert_eq!(rows(&query(&db, sql).unwrap()), vec![vec!["total"], vec!["user_id"]]);
+        let sql = "SELECT t.table_name, i.index_name, i.column_name FROM information_schema.tables t \
+                   JOIN information_schema.indexes i ON i.table_name = t.table_name WHERE t.table_type = 'BASE TABLE' ORDER BY 1";
+        assert_eq!(rows(&query(&db, sql).unwrap()), vec![vec!["orders", "orders_pkey", "id"], vec!["users", "users_pkey", "id"]]);
+        // 仮想テーブルは読み取り専用
+        assert_eq!(execute(&mut db, "DELETE FROM information_schema.tables").unwrap_err().code(), "42601");
+        assert_eq!(execute(&mut db, "SELECT * FROM information_schema.nope").unwrap_err().code(), "42P01");
+    }
+}
@@ -0,0 +1,154 @@
+use crate::executor::eval::resolve_column;
+use crate::executor::join::JoinStrategy;
+use crate::executor::ColumnInfo;
+use crate::parser::ast::{BinaryOperator, Expr, JoinKind, UnaryOperator};
+use crate::stats::ColumnStats;
+use crate::value::Value;
+
+/// 統計情報のないカラムの `=` の選択率
+const DEFAULT_EQ_SELECTIVITY: f64 = 0.1;
+/// 統計情報のないカラムや、値が実行時に決まる比較（`<` など）の選択率
+const DEFAULT_RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+/// 形から見積もれない条件の選択率
+const DEFAULT_SELECTIVITY: f64 = 0.5;
+
+/// 計画のノードが返す行数と、そこまでの処理にかかるコストの見積もり。
+/// コストは読む行数・評価する組み合わせの数を単位にした相対的な値。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub rows: f64,
+    pub cost: f64,
+}
+
+/// B+Tree を 1 回 search するコスト（木の高さ）
+pub fn lookup_cost(table_rows: f64) -> f64 {
+    table_rows.max(1.0).log2() + 1.0
+}
+
+/// テーブルの葉の見積もり。`index_seek` なら主キーで 1 行だけ読み、そうでなければ全件を読む。
+/// `selectivity` は押し下げた条件を満たす行の割合。
+pub fn scan(table_rows: f64, index_seek: bool, selectivity: f64) -> Estimate {
+    match index_seek {
+        true => Estimate { rows: table_rows.min(1.0) * selectivity, cost: lookup_cost(table_rows) },
+        false => Estimate { rows: table_rows * selectivity, cost: table_rows },
+    }
+}
+
+/// 結合の見積もり。`table_rows` は右テーブルの行数で、Index Nested Loop の search のコストに使う。
+/// `selectivity` は左右の組み合わせのうち結合条件を満たす割合。
+pub fn join(strategy: &JoinStrategy, kind: JoinKind, left: Estimate, right: Estimate, table_rows: f64, selectivity: f64) -> Estimate {
+    let cost = match strategy {
+        JoinStrategy::NestedLoop => left.cost + right.cost + left.rows * right.rows,
+        JoinStrategy::HashJoin { .. } => left.cost + right.cost + left.rows + right.rows,
+        // 右テーブルは走査せず、左の行ごとに B+Tree を引く
+        JoinStrategy::IndexNestedLoop { .. } => left.cost + left.rows * lookup_cost(table_rows),
+    };
+    let mut rows = left.rows * right.rows * selectivity;
+    // 外部結合では、対応する行のない側の行も NULL 埋めで残る
+    if matches!(kind, JoinKind::Left | JoinKind::Full) {
+        rows = rows.max(left.rows);
+    }
+    if matches!(kind, JoinKind::Right | JoinKind::Full) {
+        rows = rows.max(right.rows);
+    }
+    Estimate { rows, cost }
+}
+
+/// 条件を満たす行の割合の見積もり。`stats` は `columns` と同じ順に並ぶカラムの統計情報で、
+/// 統計情報のないカラムは None。
+pub fn selectivity(expr: &Expr, columns: &[ColumnInfo], stats: &[Option<&ColumnStats>]) -> f64 {
+    let column = |e: &Expr| match e {
+        Expr::Column { table, name } => resolve_column(columns, table.as_deref(), name).ok().and_then(|i| stats[i]),
+        _ => None,
+    };
+    let s = match expr {
+        Expr::Literal(Value::Boolean(b)) => f64::from(u8::from(*b)),
+        Expr::Literal(Value::Null) => 0.0,
+        Expr::Binary { left, op: BinaryOperator::And, right } => {
+            selectivity(left, columns, stats) * selectivity(right, columns, stats)
+        }
+        Expr::Binary { left, op: BinaryOperator::Or, right } => {
+            let (l, r) = (selectivity(left, columns, stats), selectivity(right, columns, stats));
+            l + r - l * r
+        }
+        Expr::Unary { op: UnaryOperator::Not, expr } => 1.0 - selectivity(expr, columns, stats),
+        Expr::Binary { left, op: op @ (BinaryOperator::Eq | BinaryOperator::Ne), right } => {
+            let eq = match (column(left), column(right)) {
+                // 値の範囲が重なっているとみなし、異なる値の多い側に合わせる
+                (Some(l), Some(r)) => 1.0 / l.distinct.max(r.distinct).max(1) as f64,
+                (Some(c), None) | (None, Some(c)) => c.eq_selectivity(),
+                (None, None) => DEFAULT_EQ_SELECTIVITY,
+            };
+            if *op == BinaryOperator::Eq { eq } else { 1.0 - eq }
+        }
+        Expr::Binary { left, op: op @ (BinaryOperator::Lt | BinaryOperator::Le | BinaryOperator::Gt | BinaryOperator::Ge), right } => {
+            // `定数 < カラム` は `カラム > 定数` として扱う
+            let (c, value, below) = match (column(left), column(right), left.as_ref(), right.as_ref()) {
+                (Some(c), None, _, Expr::Literal(v)) => (c, v, matches!(op, BinaryOperator::Lt | BinaryOperator::Le)),
+                (None, Some(c), Expr::Literal(v), _) => (c, v, matches!(op, BinaryOperator::Gt | BinaryOperator::Ge)),
+                _ => return DEFAULT_RANGE_SELECTIVITY,
+            };
+            let lt = c.lt_selectivity(value);
+            if below { lt } else { 1.0 - c.null_fraction - lt }
+        }
+        Expr::IsNull { expr, negated } => match column(expr) {
+            Some(c) if *negated => 1.0 - c.null_fraction,
+            Some(c) => c.null_fraction,
+            None => DEFAULT_RANGE_SELECTIVITY,
+        },
+        Expr::InList { expr, list, negated } => {
+            let eq = column(expr).map_or(DEFAULT_EQ_SELECTIVITY, |c| c.eq_selectivity());
+            let s = (eq * list.len() as f64).min(1.0);
+            if *negated { 1.0 - s } else { s }
+        }
+        _ => DEFAULT_SELECTIVITY,
+    };
+    s.clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ast::SetExpr;
+    use crate::parser::{parse_query, Query};
+    use crate::stats::TableStats;
+    use crate::table::Table;
+
+    fn condition(sql: &str) -> Expr {
+        let Query::Select(statement) = parse_query(&format!("SELECT * FROM t WHERE {}", sql)).unwrap() else {
+            panic!("not a select");
+        };
+        let SetExpr::Select(select) = statement.body else {
+            panic!("not a select block");
+        };
+        select.condition.unwrap()
+    }
+
+    #[test]
+    fn test_selectivity() {
+        // id は 1〜100、grp は 10 種類、note は半分が NULL
+        let mut table = Table::new(vec!["id".into(), "grp".into(), "note".into()], vec![Value::Null; 3]);
+        let rows = (1..=100).map(|i| vec![Value::Integer(i), Value::Integer(i % 10), if i % 2 == 0 { Value::Null } else { Value::from("x") }]);
+        table.insert_rows(rows.collect()).unwrap();
+        let stats = TableStats::collect(&table);
+        let columns = crate::executor::qualify(&table.columns, "t");
+        let column_stats: Vec<Option<&ColumnStats>> = stats.columns.iter().map(Some).collect();
+        let s = |sql: &str| selectivity(&condition(sql), &columns, &column_stats);
+        let close = |a: f64, b: f64| (a - b).abs() < 0.02;
+
+        assert!(close(s("id = 5"), 0.01));
+        assert!(close(s("grp = 3"), 0.1));
+        assert!(close(s("grp <> 3"), 0.9));
+        assert!(close(s("id < 26"), 0.25));
+        assert!(close(s("26 > id"), 0.25));
+        assert!(close(s("id >= 26"), 0.75));
+        assert!(close(s("note IS NULL"), 0.5));
+        assert!(close(s("grp = 3 AND note IS NOT NULL"), 0.05));
+        assert!(close(s("grp = 3 OR grp = 4"), 0.19));
+        assert!(close(s("grp IN (1, 2, 3)"), 0.3));
+        assert!(close(s("NOT grp = 3"), 0.9));
+        assert!(close(s("id = grp"), 0.01));
+        // パラメータは値がわからないので既定値
+        assert!(close(s("id < $1"), DEFAULT_RANGE_SELECTIVITY));
+    }
+}
@@ -0,0 +1,219 @@
+use crate::error::DbError;
+use crate::executor::{run_query, run_set_expr, set_operation, ExecContext, ResultSet};
+use crate::parser::ast::{Cte, Expr, Select, SelectItem, SelectStatement, SetExpr, SetOperator, TableRef, With};
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// WITH RECURSIVE の再帰項を繰り返す上限。UNION ALL で循環するデータを辿った場合などに
+/// 無限ループせずエラーにするためのもの。
+pub const MAX_RECURSIVE_ITERATIONS: usize = 1000;
+
+/// WITH 句の各 CTE を先頭から順に実行し、その結果を名前で参照できるコンテキストを返す。
+/// 後の CTE からは前の CTE を参照できる。
+pub fn bind<'a>(ctx: &ExecContext<'a>, with: &With) -> Result<ExecContext<'a>, DbError> {
+    let mut ctx = ctx.clone();
+    for cte in &with.ctes {
+        let result = if with.recursive && references_statement(&cte.query, &cte.name) {
+            execute_recursive(&ctx, cte)?
+        } else {
+            let result = run_query(&ctx, &cte.query)?;
+            ResultSet { columns: column_names(cte, &result.columns)?, rows: result.rows }
+        };
+        ctx.ctes.insert(cte.name.clone(), Rc::new(result));
+    }
+    Ok(ctx)
+}
+
+/// `WITH t(a, b) AS (...)` のカラム名の指定があればそれを使う。
+pub fn column_names(cte: &Cte, columns: &[String]) -> Result<Vec<String>, DbError> {
+    if cte.columns.is_empty() {
+        return Ok(columns.to_vec());
+    }
+    if cte.columns.len() != columns.len() {
+        return Err(DbError::InvalidQuery(format!(
+            "WITH query '{}' has {} columns available but {} columns specified.",
+            cte.name,
+            columns.len(),
+            cte.columns.len()
+        )));
+    }
+    Ok(cte.columns.clone())
+}
+
+/// `<非再帰項> UNION [ALL] <再帰項>` の形の再帰 CTE を実行する。
+/// 前回の繰り返しで新しく得られた行（ワークテーブル）だけを CTE 名に束縛して再帰項を実行し、
+/// 新しい行がなくなるまで繰り返す。UNION（ALL なし）では既出の行を捨てるので、循環があっても止まる。
+pub fn execute_recursive(ctx: &ExecContext, cte: &Cte) -> Result<ResultSet, DbError> {
+    let (left, all, right) = recursive_terms(cte)?;
+    let ctx = match &cte.query.with {
+        Some(with) => bind(ctx, with)?,
+        None => ctx.clone(),
+    };
+
+    let anchor = run_set_expr(&ctx, left)?;
+    let columns = column_names(cte, &anchor.columns)?;
+    let mut seen = HashSet::new();
+    let mut keep = |row: &Vec<Value>| all || seen.insert(row.clone());
+    let mut rows: Vec<Vec<Value>> = anchor.rows.into_iter().filter(|row| keep(row)).collect();
+    let mut working = rows.clone();
+
+    let mut iterations = 0;
+    while !working.is_empty() {
+        iterations += 1;
+        if iterations > MAX_RECURSIVE_ITERATIONS {
+            return Err(DbError::InvalidQuery(format!(
+                "Recursive query '{}' exceeded the limit of {} iterations.",
+                cte.name, MAX_RECURSIVE_ITERATIONS
+            )));
+        }
+        // ワークテーブルは繰り返しごとに変わるので、サブクエリの結果のキャッシュも作り直す
+        let cache = RefCell::new(HashMap::new());
+        let mut step_ctx = ExecContext { db: ctx.db, outer: ctx.outer, cache: &cache, ctes: ctx.ctes.clone(), params: ctx.params };
+        let working_table = ResultSet { columns: columns.clone(), rows: working };
+        step_ctx.ctes.insert(cte.name.clone(), Rc::new(working_table.clone()));
+        let step = run_set_expr(&step_ctx, right)?;
+        set_operation::check_compatible(&working_table, SetOperator::Union, &step)?;
+        working = step.rows.into_iter().filter(|row| keep(row)).collect();
+        rows.extend(working.iter().cloned());
+    }
+    Ok(ResultSet { columns, rows })
+}
+
+/// 再帰 CTE を非再帰項・UNION に ALL が付くか・再帰項に分ける。
+pub fn recursive_terms(cte: &Cte) -> Result<(&SetExpr, bool, &SetExpr), DbError> {
+    let SetExpr::SetOperation { left, op: SetOperator::Union, all, right } = &cte.query.body else {
+        return Err(DbError::InvalidQuery(format!(
+            "Recursive query '{}' must be of the form <non-recursive term> UNION [ALL] <recursive term>.",
+            cte.name
+        )));
+    };
+    if references_set_expr(left, &cte.name) {
+        return Err(DbError::InvalidQuery(format!("Recursive reference to query '{}' must not appear in its non-recursive term.", cte.name)));
+    }
+    Ok((left, *all, right))
+}
+
+/// 文の中に `name` という名前のテーブル参照があるかどうか（サブクエリの中も含む）。
+pub fn references_statement(statement: &SelectStatement, name: &str) -> bool {
+    let in_with = statement.with.iter().flat_map(|w| &w.ctes).any(|c| references_statement(&c.query, name));
+    in_with || references_set_expr(&statement.body, name)
+}
+
+fn references_set_expr(body: &SetExpr, name: &str) -> bool {
+    match body {
+        SetExpr::Select(select) => references_select(select, name),
+        SetExpr::SetOperation { left, right, .. } => references_set_expr(left, name) || references_set_expr(right, name),
+    }
+}
+
+fn references_select(select: &Select, name: &str) -> bool {
+    let mut tables = std::iter::once(&select.from).chain(select.joins.iter().map(|j| &j.table));
+    let mut exprs = select
+        .select_columns
+        .iter()
+        .filter_map(|item| match item {
+            SelectItem::Expr { expr, .. } => Some(expr),
+            _ => None,
+        })
+        .chain(select.joins.iter().filter_map(|j| j.constraint.as_ref()))
+        .chain(&select.condition)
+        .chain(&select.group_by)
+        .chain(&select.having);
+    tables.any(|t| references_table(t, name)) || exprs.any(|e| references_expr(e, name))
+}
+
+fn references_table(table: &TableRef, name: &str) -> bool {
+    match table {
+        TableRef::Table { name: table, .. } => table == name,
+        TableRef::Derived { subquery, .. } => references_statement(subquery, name),
+    }
+}
+
+fn references_expr(expr: &Expr, name: &str) -> bool {
+    let mut found = false;
+    expr.walk(&mut |e| match e {
+        Expr::InSubquery { subquery, .. } | Expr::Exists(subquery) | Expr::Subquery(subquery) => {
+            found |= references_statement(subquery, name);
+        }
+        _ => {}
+    });
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MAX_RECURSIVE_ITERATIONS;
+    use crate::database::Database;
+    use crate::executor::test_util::{database, query, rows};
+
+    fn setup() -> Database {
+        database(&[
+            "CREATE TABLE emp (id, name, boss)",
+            "INSERT INTO emp VALUES (1, 'Ceo', NULL), (2, 'Cto', 1), (3, 'Dev', 2), (4, 'Cfo', 1), (5, 'Ops', 9)",
+            "CREATE TABLE edges (id, src, dst)",
+            "INSERT INTO edges VALUES (1, 1, 2), (2, 2, 3), (3, 3, 1)",
+        ])
+    }
+
+    #[test]
+    fn test_simple_cte() {
+        let db = setup();
+        let result = query(
+            &db,
+            "WITH managers AS (SELECT boss FROM emp), top(id) AS (SELECT boss FROM managers WHERE boss = 1) \
+             SELECT name FROM emp WHERE id IN (SELECT id FROM top)",
+        )
+        .unwrap();
+        assert_eq!(rows(&result), vec![vec!["Ceo"]]);
+        // CTE は同名のテーブルより優先される（CTE の定義の中では元のテーブルを指す）
+        let result = query(&db, "WITH emp AS (SELECT name FROM emp WHERE id = 1) SELECT * FROM emp").unwrap();
+        assert_eq!(result.columns, vec!["name"]);
+        assert_eq!(rows(&result), vec![vec!["Ceo"]]);
+        let err = query(&db, "WITH t(a, b) AS (SELECT id FROM emp) SELECT * FROM t").unwrap_err();
+        assert!(err.to_string().contains("columns specified"));
+    }
+
+    #[test]
+    fn test_recursive_hierarchy() {
+        let db = setup();
+        let result = query(
+            &db,
+            "WITH RECURSIVE sub(id) AS ( \
+               SELECT id FROM emp WHERE id = 1 \
+               UNION ALL \
+               SELECT e.id FROM emp e JOIN sub ON e.boss = sub.id \
+             ) SELECT name FROM emp WHERE id IN (SELECT id FROM sub)",
+        )
+        .unwrap();
+        assert_eq!(rows(&result), vec![vec!["Ceo"], vec!["Cto"], vec!["Dev"], vec!["Cfo"]]);
+    }
+
+    #[test]
+    fn test_recursive_cycle() {
+        let db = setup();
+        // UNION は既出の行を捨てるので、循環していても止まる
+        let result = query(
+            &db,
+            "WITH RECURSIVE reach(node) AS ( \
+               SELECT src FROM edges WHERE src = 1 \
+               UNION \
+               SELECT dst FROM edges JOIN reach ON edges.src = reach.node \
+             ) SELECT * FROM reach",
+        )
+        .unwrap();
+        assert_eq!(rows(&result), vec![vec!["1"], vec!["2"], vec!["3"]]);
+        // UNION ALL では上限に達してエラーになる
+        let err = query(
+            &db,
+            "WITH RECURSIVE reach(node) AS ( \
+               SELECT src FROM edges WHERE src = 1 \
+               UNION ALL \
+               SELECT dst FROM edges JOIN reach ON edges.src = reach.node \
+             ) SELECT * FROM reach",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains(&MAX_RECURSIVE_ITERATIONS.to_string()));
+    }
+}
@@ -0,0 +1,57 @@
+use crate::database::Database;
+use crate::error::DbError;
+use crate::executor::eval::check_columns;
+use crate::executor::{lookup_table, scan, table_columns, ExecContext};
+use crate::parser::ast::{Delete, TableRef};
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// DELETE で削除する行の主キーを求める。テーブルはまだ変更しない。
+/// WHERE 句に「主キー = 定数」があれば B+Tree で対象の行を探す。
+pub fn keys(db: &Database, delete: &Delete, params: &[Value]) -> Result<Vec<Value>, DbError> {
+    let cache = RefCell::new(HashMap::new());
+    let ctx = ExecContext { db, outer: None, cache: &cache, ctes: HashMap::new(), params };
+    let table = lookup_table(db, &delete.table_name)?;
+    let table_ref = TableRef::Table { name: delete.table_name.clone(), alias: delete.alias.clone() };
+    if let Some(condition) = &delete.condition {
+        check_columns(condition, &table_columns(table, &table_ref), &ctx)?;
+    }
+    let relation = scan(&ctx, &table_ref, delete.condition.as_ref())?;
+    Ok(relation.rows.into_iter().map(|mut row| row.swap_remove(0)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::executor::test_util::{database, execute, query, rows};
+
+    fn setup() -> Database {
+        let values: Vec<String> = (1..=30).map(|id| format!("({}, 'user{}', {})", id, id, 20 + id % 5)).collect();
+        database(&["CREATE TABLE users (id, name, age)", &format!("INSERT INTO users VALUES {}", values.join(", "))])
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut db = setup();
+        execute(&mut db, "DELETE FROM users WHERE id = 1").unwrap();
+        execute(&mut db, "DELETE FROM users u WHERE u.age <> 20 AND u.id > 3").unwrap();
+        let result = query(&db, "SELECT id FROM users").unwrap();
+        assert_eq!(rows(&result), vec![vec!["2"], vec!["3"], vec!["5"], vec!["10"], vec!["15"], vec!["20"], vec!["25"], vec!["30"]]);
+        // 削除後も主キー検索・挿入ができる
+        execute(&mut db, "INSERT INTO users VALUES (1, 'again', 20)").unwrap();
+        let result = query(&db, "SELECT name FROM users WHERE id = 1").unwrap();
+        assert_eq!(rows(&result), vec![vec!["again"]]);
+        assert_eq!(execute(&mut db, "DELETE FROM users").unwrap().rows_affected, 9);
+        let result = query(&db, "SELECT COUNT(*) FROM users").unwrap();
+        assert_eq!(rows(&result), vec![vec!["0"]]);
+    }
+
+    #[test]
+    fn test_delete_with_subquery() {
+        let mut db = setup();
+        execute(&mut db, "DELETE FROM users WHERE age = (SELECT MAX(age) FROM users)").unwrap();
+        let result = query(&db, "SELECT COUNT(*), MAX(age) FROM users").unwrap();
+        assert_eq!(rows(&result), vec![vec!["24", "23"]]);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::error::DbError;
+use crate::executor::{function, run_query, ColumnInfo, ExecContext, ResultSet};
+use crate::parser::ast::{BinaryOperator, Expr, ScalarFunction, SelectStatement, UnaryOperator};
+use crate::value::Value;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+/// 式を評価するときの環境。現在の行と、集約済みの値（HAVING や集約クエリの SELECT 句用）を持つ。
+/// 相関サブクエリからは `ctx.outer` を辿って外側のクエリの行を参照する。
+pub struct Scope<'a> {
+    pub ctx: &'a ExecContext<'a>,
+    pub columns: &'a [ColumnInfo],
+    pub row: &'a [Value],
+    pub aggregates: &'a [(Expr, Value)],
+    /// ウィンドウ関数の式と、この行について計算済みの値（同じ位置どうしが対応する）
+    windows: &'a [Expr],
+    window_values: &'a [Value],
+    /// このスコープの行が内側のサブクエリから参照されたかどうか（相関の検出用）
+    referenced: Cell<bool>,
+}
+
+impl<'a> Scope<'a> {
+    pub fn new(ctx: &'a ExecContext<'a>, columns: &'a [ColumnInfo], row: &'a [Value]) -> Self {
+        Scope { ctx, columns, row, aggregates: &[], windows: &[], window_values: &[], referenced: Cell::new(false) }
+    }
+
+    pub fn with_aggregates(mut self, aggregates: &'a [(Expr, Value)]) -> Self {
+        self.aggregates = aggregates;
+        self
+    }
+
+    pub fn with_windows(mut self, windows: &'a [Expr], values: &'a [Value]) -> Self {
+        self.windows = windows;
+        self.window_values = values;
+        self
+    }
+
+    /// カラムの値を取り出す。このスコープにない場合は外側のクエリの行を探す。
+    fn lookup(&self, table: Option<&str>, name: &str) -> Result<Value, DbError> {
+        match resolve_column(self.columns, table, name) {
+            Ok(index) => Ok(self.row.get(index).cloned().unwrap_or(Value::Null)),
+            Err(e) => match self.ctx.outer {
+                Some(outer) if !is_ambiguous(self.columns, table, name) => {
+                    outer.referenced.set(true);
+                    outer.lookup(table, name).map_err(|_| e)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+}
+
+/// 式を 1 行分評価する。
+pub fn eval(expr: &Expr, scope: &Scope) -> Result<Value, DbError> {
+    match expr {
+        Expr::Literal(v) => Ok(v.clone()),
+        Expr::Parameter(index) => {
+            let unbound = || DbError::InvalidValue(format!("No value is bound to parameter '${}'.", index + 1));
+            scope.ctx.params.get(*index).cloned().ok_or_else(unbound)
+        }
+        Expr::Column { table, name } => scope.lookup(table.as_deref(), name),
+        Expr::Unary { op, expr } => {
+            let value = eval(expr, scope)?;
+            match op {
+                UnaryOperator::Not => Ok(match truth(&value) {
+                    Some(b) => Value::Boolean(!b),
+                    None => Value::Null,
+                }),
+                UnaryOperator::Minus => negate(value),
+            }
+        }
+        Expr::Binary { left, op, right } => {
+            let l = eval(left, scope)?;
+            // AND / OR は短絡評価する（三値論理）
+            match op {
+                BinaryOperator::And if truth(&l) == Some(false) => return Ok(Value::Boolean(false)),
+                BinaryOperator::Or if truth(&l) == Some(true) => return Ok(Value::Boolean(true)),
+                _ => {}
+            }
+            let r = eval(right, scope)?;
+            eval_binary(&l, *op, &r)
+        }
+        Expr::IsNull { expr, negated } => {
+            let value = eval(expr, scope)?;
+            Ok(Value::Boolean(value.is_null() != *negated))
+        }
+        Expr::Aggregate { .. } => scope
+            .aggregates
+            .iter()
+            .find(|(agg, _)| agg == expr)
+            .map(|(_, v)| v.clone())
+            .ok_or_else(|| DbError::InvalidQuery(format!("Aggregate function {} is not allowed here.", expr))),
+        Expr::Window { .. } => scope
+            .windows
+            .iter()
+            .position(|w| w == expr)
+            .and_then(|i| scope.window_values.get(i))
+            .cloned()
+            .ok_or_else(|| DbError::InvalidQuery(format!("Window function {} is not allowed here.", expr))),
+        Expr::InList { expr, list, negated } => {
+            let value = eval(expr, scope)?;
+            let mut candidates = Vec::with_capacity(list.len());
+            for item in list {
+                candidates.push(eval(item, scope)?);
+            }
+            Ok(in_values(&value, candidates.iter(), *negated))
+        }
+        Expr::InSubquery { expr, subquery, negated } => {
+            let value = eval(expr, scope)?;
+            let result = execute_subquery(subquery, scope)?;
+            if result.columns.len() != 1 {
+                return Err(DbError::CardinalityViolation("Subquery in IN must return exactly one column.".into()));
+            }
+            Ok(in_values(&value, result.rows.iter().map(|r| &r[0]), *negated))
+        }
+        Expr::Exists(subquery) => {
+            let result = execute_subquery(subquery, scope)?;
+            Ok(Value::Boolean(!result.rows.is_empty()))
+        }
+        Expr::Subquery(subquery) => {
+            let result = execute_subquery(subquery, scope)?;
+            if result.columns.len() != 1 {
+                return Err(DbError::CardinalityViolation("Scalar subquery must return exactly one column.".into()));
+            }
+            match result.rows.len() {
+                0 => Ok(Value::Null),
+                1 => Ok(result.rows[0][0].clone()),
+                _ => Err(DbError::CardinalityViolation("Scalar subquery returned more than one row.".into())),
+            }
+        }
+        Expr::Function { func: ScalarFunction::Coalesce, args } => {
+            // COALESCE は最初の NULL でない引数までしか評価しない
+            for arg in args {
+                let value = eval(arg, scope)?;
+                if !value.is_null() {
+                    return Ok(value);
+                }
+            }
+            Ok(Value::Null)
+        }
+        Expr::Function { func, args } => {
+            let values = args.iter().map(|a| eval(a, scope)).collect::<Result<Vec<_>, _>>()?;
+            function::call(*func, values)
+        }
+        Expr::Case { operand, branches, else_result } => {
+            let operand = operand.as_ref().map(|o| eval(o, scope)).transpose()?;
+            for (when, then) in branches {
+                let when = eval(when, scope)?;
+                let matched = match &operand {
+                    Some(operand) => operand.sql_cmp(&when) == Some(Ordering::Equal),
+                    None => truth(&when) == Some(true),
+                };
+                if matched {
+                    return eval(then, scope);
+                }
+            }
+            match else_result {
+                Some(e) => eval(e, scope),
+                None => Ok(Value::Null),
+            }
+        }
+        Expr::Cast { expr, data_type } => function::cast(eval(expr, scope)?, *data_type),
+    }
+}
+
+/// サブクエリを実行する。外側の行を参照しない（相関のない）サブクエリの結果は
+/// 文の実行中キャッシュし、行ごとに再実行しない。
+fn execute_subquery(subquery: &SelectStatement, scope: &Scope) -> Result<Rc<ResultSet>, DbError> {
+    let key = subquery as *const SelectStatement as usize;
+    if let Some(result) = scope.ctx.cache.borrow().get(&key) {
+        return Ok(Rc::clone(result));
+    }
+    scope.referenced.set(false);
+    let ctx = ExecContext {
+        db: scope.ctx.db,
+        outer: Some(scope),
+        cache: scope.ctx.cache,
+        ctes: scope.ctx.ctes.clone(),
+        params: scope.ctx.params,
+    };
+    let result = Rc::new(run_query(&ctx, subquery)?);
+    if !scope.referenced.get() {
+        scope.ctx.cache.borrow_mut().insert(key, Rc::clone(&result));
+    }
+    Ok(result)
+}
+
+/// IN の判定。一致すれば真、一致せず候補に NULL が含まれれば NULL、それ以外は偽。
+fn in_values<'v>(value: &Value, candidates: impl Iterator<Item = &'v Value>, negated: bool) -> Value {
+    if value.is_null() {
+        return Value::Null;
+    }
+    let mut saw_null = false;
+    for candidate in candidates {
+        match value.sql_cmp(candidate) {
+            Some(Ordering::Equal) => return Value::Boolean(!negated),
+            None => saw_null = true,
+            _ => {}
+        }
+    }
+    if saw_null {
+        Value::Null
+    } else {
+        Value::Boolean(negated)
+    }
+}
+
+/// カラム参照（`name` または `table.name`）をカラム位置に解決する。
+/// 修飾なしの名前が複数のテーブルに存在する場合はエラーにする。
+pub fn resolve_column(columns: &[ColumnInfo], table: Option<&str>, name: &str) -> Result<usize, DbError> {
+    let mut found = columns.iter().enumerate().filter(|(_, c)| c.matches(table, name)).map(|(i, _)| i);
+    let display = match table {
+        Some(t) => format!("{}.{}", t, name),
+        None => name.to_string(),
+    };
+    match (found.next(), found.next()) {
+        (Some(i), None) => Ok(i),
+        (Some(_), Some(_)) => Err(DbError::AmbiguousColumn(display)),
+        (None, _) => Err(DbError::UndefinedColumn(display)),
+    }
+}
+
+fn is_ambiguous(columns: &[ColumnInfo], table: Option<&str>, name: &str) -> bool {
+    columns.iter().filter(|c| c.matches(table, name)).count() > 1
+}
+
+/// 式中のカラム参照がすべて解決できるか確認する（外側のクエリのカラムも含む）。
+/// 結果が 0 行で式が一度も評価されない場合でも、存在しないカラムをエラーにするために使う。
+/// サブクエリの中身はサブクエリの実行時に確認する。
+pub fn check_columns(expr: &Expr, columns: &[ColumnInfo], ctx: &ExecContext) -> Result<(), DbError> {
+    match expr {
+        Expr::Column { table, name } => match resolve_column(columns, table.as_deref(), name) {
+            Ok(_) => Ok(()),
+            Err(e) if is_ambiguous(columns, table.as_deref(), name) => Err(e),
+            Err(e) => {
+                let mut outer = ctx.outer;
+                while let Some(scope) = outer {
+                    if resolve_column(scope.columns, table.as_deref(), name).is_ok() {
+                        return Ok(());
+                    }
+                    outer = scope.ctx.outer;
+                }
+                Err(e)
+            }
+        },
+        _ => expr.children().into_iter().try_for_each(|e| check_columns(e, columns, ctx)),
+    }
+}
+
+/// 真偽値として評価する。NULL は None（不明）。
+pub fn truth(value: &Value) -> Option<bool> {
+    if value.is_null() {
+        None
+    } else {
+        Some(value.is_truthy())
+    }
+}
+
+fn eval_binary(l: &Value, op: BinaryOperator, r: &Value) -> Result<Value, DbError> {
+    Ok(match op {
+        BinaryOperator::And => match (truth(l), truth(r)) {
+            (Some(false), _) | (_, Some(false)) => Value::Boolean(false),
+            (Some(true), Some(true)) => Value::Boolean(true),
+            _ => Value::Null,
+        },
+        BinaryOperator::Or => match (truth(l), truth(r)) {
+            (Some(true), _) | (_, Some(true)) => Value::Boolean(true),
+            (Some(false), Some(false)) => Value::Boolean(false),
+            _ => Value::Null,
+        },
+        BinaryOperator::Concat => {
+            if l.is_null() || r.is_null() {
+                Value::Null
+            } else {
+                Value::Text(format!("{}{}", l, r))
+            }
+        }
+        BinaryOperator::Plus
+        | BinaryOperator::Minus
+        | BinaryOperator::Multiply
+        | BinaryOperator::Divide
+        | BinaryOperator::Modulo => return arithmetic(l, op, r),
+        BinaryOperator::Eq
+        | BinaryOperator::Ne
+        | BinaryOperator::Gt
+        | BinaryOperator::Lt
+        | BinaryOperator::Ge
+        | BinaryOperator::Le => match l.sql_cmp(r) {
+            None => Value::Null,
+            Some(ord) => Value::Boolean(match op {
+                BinaryOperator::Eq => ord == Ordering::Equal,
+                BinaryOperator::Ne => ord != Ordering::Equal,
+                BinaryOperator::Gt => ord == Ordering::Greater,
+                BinaryOperator::Lt => ord == Ordering::Less,
+                BinaryOperator::Ge => ord != Ordering::Less,
+                _ => ord != Ordering::Greater,
+            }),
+        },
+    })
+}
+
+/// 四則演算と剰余。数値として読める文字列は数値として扱う。
+/// 整数同士の結果は整数（除算は切り捨て）、どちらかが小数なら小数になる。0 での除算はエラー。
+pub fn arithmetic(l: &Value, op: BinaryOperator, r: &Value) -> Result<Value, DbError> {
+    let (l, r) = match (l.clone().numeric_affinity(), r.clone().numeric_affinity()) {
+        (Value::Null, _) | (_, Value::Null) => return Ok(Value::Null),
+        (l @ (Value::Integer(_) | Value::Float(_)), r @ (Value::Integer(_) | Value::Float(_))) => (l, r),
+        (l, r) => return Err(DbError::TypeMismatch(format!("Cannot apply '{}' to '{}' and '{}'", op, l, r))),
+    };
+    if matches!(op, BinaryOperator::Divide | BinaryOperator::Modulo) && r.as_f64() == Some(0.0) {
+        return Err(DbError::DivisionByZero);
+    }
+    if let (Value::Integer(a), Value::Integer(b)) = (&l, &r) {
+        let result = match op {
+            BinaryOperator::Plus => a.checked_add(*b),
+            BinaryOperator::Minus => a.checked_sub(*b),
+            BinaryOperator::Multiply => a.checked_mul(*b),
+            BinaryOperator::Divide => a.checked_div(*b),
+            _ => a.checked_rem(*b),
+        };
+        return result.map(Value::Integer).ok_or_else(|| DbError::InvalidValue("Integer overflow".into()));
+    }
+    let (a, b) = (l.as_f64().unwrap_or_default(), r.as_f64().unwrap_or_default());
+    Ok(Value::Float(match op {
+        BinaryOperator::Plus => a + b,
+        BinaryOperator::Minus => a - b,
+        BinaryOperator::Multiply => a * b,
+        BinaryOperator::Divide => a / b,
+        _ => a % b,
+    }))
+}
+
+fn negate(value: Value) -> Result<Value, DbError> {
+    match value.numeric_affinity() {
+        Value::Null => Ok(Value::Null),
+        Value::Integer(i) => i.checked_neg().map(Value::Integer).ok_or_else(|| DbError::InvalidValue("Integer overflow".into())),
+        Value::Float(f) => Ok(Value::Float(-f)),
+        other => Err(DbError::TypeMismatch(format!("Cannot negate non-numeric value '{}'", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::executor::test_util::{database, query, rows};
+
+    fn setup() -> Database {
+        database(&[
+            "CREATE TABLE users (id, name, age)",
+            "CREATE TABLE orders (oid, user_id, total)",
+            "INSERT INTO users VALUES (1, 'John', 30), (2, 'Mike', 25), (3, 'Anna', 30)",
+            "INSERT INTO orders VALUES (10, 1, 100), (11, 1, 50), (12, 2, 70)",
+        ])
+    }
+
+    #[test]
+    fn test_scalar_subquery() {
+        let db = setup();
+        let result = query(&db, "SELECT name FROM users WHERE age = (SELECT MIN(age) FROM users)").unwrap();
+        assert_eq!(rows(&result), vec![vec!["Mike"]]);
+
+        let err = query(&db, "SELECT name FROM users WHERE id = (SELECT id FROM users)").unwrap_err();
+        assert!(err.to_string().contains("more than one row"));
+        assert_eq!(err.code(), "21000");
+        let result = query(&db, "SELECT (SELECT name FROM users WHERE id = 9) FROM users WHERE id = 1").unwrap();
+        assert_eq!(rows(&result), vec![vec!["NULL"]]);
+    }
+
+    #[test]
+    fn test_in_and_exists() {
+        let db = setup();
+        let result = query(&db, "SELECT name FROM users WHERE id IN (SELECT user_id FROM orders)").unwrap();
+        assert_eq!(rows(&result), vec![vec!["John"], vec!["Mike"]]);
+        let result = query(&db, "SELECT name FROM users WHERE id NOT IN (SELECT user_id FROM orders)").unwrap();
+        assert_eq!(rows(&result), vec![vec!["Anna"]]);
+        let result = query(&db, "SELECT name FROM users WHERE id IN (1, 3)").unwrap();
+        assert_eq!(rows(&result), vec![vec!["John"], vec!["Anna"]]);
+        // NOT IN に NULL が含まれると一致しない行は NULL（偽扱い）になる
+        let result = query(&db, "SELECT name FROM users WHERE id NOT IN (2, NULL)").unwrap();
+        assert!(result.rows.is_empty());
+        let result = query(&db, "SELECT name FROM users WHERE EXISTS (SELECT oid FROM orders WHERE total > 90)").unwrap();
+        assert_eq!(result.rows.len(), 3);
+    }
+
+    #[test]
+    fn test_correlated_subquery() {
+        let db = setup();
+        let result = query(
+            &db,
+            "SELECT name FROM users u WHERE NOT EXISTS (SELECT oid FROM orders o WHERE o.user_id = u.id)",
+        )
+        .unwrap();
+        assert_eq!(rows(&result), vec![vec!["Anna"]]);
+        let result = query(
+            &db,
+            "SELECT name, (SELECT SUM(total) FROM orders WHERE user_id = id) FROM users",
+        )
+        .unwrap();
+        // 修飾なしの id は内側の orders にないので外側の users.id を参照する
+        assert_eq!(rows(&result), vec![vec!["John", "150"], vec!["Mike", "70"], vec!["Anna", "NULL"]]);
+    }
+
+    #[test]
+    fn test_derived_table() {
+        let db = setup();
+        let result = query(&db, "SELECT t.name FROM (SELECT name, age FROM users WHERE age > 26) AS t").unwrap();
+        assert_eq!(result.columns, vec!["t.name"]);
+        assert_eq!(rows(&result), vec![vec!["John"], vec!["Anna"]]);
+        let result = query(
+            &db,
+            "SELECT u.name, t.total FROM users u JOIN (SELECT user_id, total FROM orders WHERE total > 60) t ON t.user_id = u.id",
+        )
+        .unwrap();
+        assert_eq!(rows(&result), vec![vec!["John", "100"], vec!["Mike", "70"]]);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::database::Database;
+use crate::error::DbError;
+use crate::parser::ast::{Assignment, Delete, Expr, ForeignKey, ReferentialAction, Update};
+use crate::parser::Query;
+use crate::table::Table;
+use crate::value::Value;
+use std::collections::{BTreeMap, HashSet};
+
+/// 外部キーの確認。書き込んだ直後に行うか、`deferred` なら文とそのトリガーがすべて終わってから行う。
+#[derive(Debug, Clone, PartialEq)]
+pub enum Check {
+    /// `table` の主キーが `key` の行について、`foreign_key` 番目の外部キーで参照する行が親テーブルにあるか。
+    /// 確認するまでにその行が削除されていれば何もしない。
+    Child { table: String, foreign_key: usize, key: Value, deferred: bool },
+    /// 親テーブルからなくなった `key` を、`table` の `foreign_key` 番目の外部キーで参照する行が残っていないか。
+    Parent { table: String, foreign_key: usize, key: Value, deferred: bool },
+}
+
+impl Check {
+    fn deferred(&self) -> bool {
+        match self {
+            Check::Child { deferred, .. } | Check::Parent { deferred, .. } => *deferred,
+        }
+    }
+}
+
+/// 外部キーのカラムの位置。カラムがあることは CREATE TABLE で確かめている。
+fn column_index(table: &Table, foreign_key: &ForeignKey) -> usize {
+    table.columns.iter().position(|c| *c == foreign_key.column).expect("foreign key column exists")
+}
+
+/// `table` に書き込んだ行について、外部キーごとの確認を作る。
+pub fn written_rows(name: &str, table: &Table, rows: &[Vec<Value>]) -> Vec<Check> {
+    let mut checks = Vec::new();
+    for (i, foreign_key) in table.foreign_keys.iter().enumerate() {
+        checks.extend(rows.iter().map(|row| Check::Child {
+            table: name.to_string(),
+            foreign_key: i,
+            key: row[0].clone(),
+            deferred: foreign_key.deferred,
+        }));
+    }
+    checks
+}
+
+/// 親テーブル `parent` の行を削除した、または主キーを変えた後に、参照している子テーブルの行に対して行うこと。
+/// `changes` は (元の主キー, 新しい主キー) の組で、削除では新しい主キーが None。
+/// CASCADE / SET NULL は子テーブルへの DELETE / UPDATE 文として返し、NO ACTION / RESTRICT は確認として返す。
+pub fn referential_actions(db: &Database, parent: &str, changes: &[(Value, Option<Value>)]) -> (Vec<Query>, Vec<Check>) {
+    let mut queries = Vec::new();
+    let mut checks = Vec::new();
+    if changes.is_empty() {
+        return (queries, checks);
+    }
+    let deleted = changes[0].1.is_none();
+    for name in db.table_names() {
+        let table = db.get_table(name).expect("table exists");
+        for (i, foreign_key) in table.foreign_keys.iter().enumerate().filter(|(_, fk)| fk.table == parent) {
+            let column = || Expr::Column { table: None, name: foreign_key.column.clone() };
+            let condition = Expr::InList {
+                expr: Box::new(column()),
+                list: changes.iter().map(|(old, _)| Expr::Literal(old.clone())).collect(),
+                negated: false,
+            };
+            let action = if deleted { foreign_key.on_delete } else { foreign_key.on_update };
+            let assign = |value: Expr| {
+                let assignments = vec![Assignment { column: foreign_key.column.clone(), value }];
+                Query::Update(Update { table_name: name.to_string(), alias: None, assignments, condition: Some(condition.clone()), returning: Vec::new() })
+            };
+            match action {
+                ReferentialAction::Cascade if deleted => queries.push(Query::Delete(Delete {
+                    table_name: name.to_string(),
+                    alias: None,
+                    condition: Some(condition),
+                    returning: Vec::new(),
+                })),
+                // 1 つの文で置き換えるので、1 → 2 と 2 → 3 を同時に変えても連鎖しない
+                ReferentialAction::Cascade => queries.push(assign(Expr::Case {
+                    operand: Some(Box::new(column())),
+                    branches: changes.iter().map(|(old, new)| (Expr::Literal(old.clone()), Expr::Literal(new.clone().unwrap_or(Value::Null)))).collect(),
+                    else_result: Some(Box::new(column())),
+                })),
+                ReferentialAction::SetNull => queries.push(assign(Expr::Literal(Value::Null))),
+                ReferentialAction::NoAction | ReferentialAction::Restrict => checks.extend(changes.iter().map(|(old, _)| Check::Parent {
+                    table: name.to_string(),
+                    foreign_key: i,
+                    key: old.clone(),
+                    deferred: foreign_key.deferred && action == ReferentialAction::NoAction,
+                })),
+            }
+        }
+    }
+    (queries, checks)
+}
+
+/// 外部キーの確認を行う。`defer` なら遅延する確認は行わずに返す。
+/// 子テーブルの値は親テーブルの B+Tree を主キーで引いて確かめる。親テーブルからなくなった主キーへの参照は、
+/// 子テーブルを全件走査して探すので、同じ外部キーの確認をまとめて 1 回で走査する。
+pub fn check(db: &Database, checks: Vec<Check>, defer: bool) -> Result<Vec<Check>, DbError> {
+    let (deferred, now): (Vec<Check>, Vec<Check>) = checks.into_iter().partition(|c| defer && c.deferred());
+    let mut removed: BTreeMap<(&str, usize), HashSet<Value>> = BTreeMap::new();
+    for check in &now {
+        match check {
+            Check::Child { table, foreign_key, key, .. } => {
+                let Some(child) = db.get_table(table) else { continue };
+                let Some(row) = child.find_by_key(key) else { continue };
+                let foreign_key = &child.foreign_keys[*foreign_key];
+                let value = &row[column_index(child, foreign_key)];
+                if value.is_null() || db.get_table(&foreign_key.table).is_some_and(|p| p.find_by_key(value).is_some()) {
+                    continue;
+                }
+                return Err(DbError::ForeignKeyViolation(format!(
+                    "Key ({})=({}) in '{}' is not present in table '{}'.",
+                    foreign_key.column, value, table, foreign_key.table
+                )));
+            }
+            Check::Parent { table, foreign_key, key, .. } => {
+                let Some(child) = db.get_table(table) else { continue };
+                // 同じ文の中で同じ主キーの行が入れ直されていれば、参照は切れていない
+                if db.get_table(&child.foreign_keys[*foreign_key].table).is_some_and(|p| p.find_by_key(key).is_some()) {
+                    continue;
+                }
+                // find_by_key と同じく、`'1'` の参照も `1` の主キーを指す
+                removed.entry((table, *foreign_key)).or_default().insert(key.clone().numeric_affinity());
+            }
+        }
+    }
+    for ((table, foreign_key), keys) in removed {
+        let child = db.get_table(table).expect("table exists");
+        let foreign_key = &child.foreign_keys[foreign_key];
+        let column = column_index(child, foreign_key);
+        if let Some(row) = child.rows().find(|row| keys.contains(&row[column].clone().numeric_affinity())) {
+            return Err(DbError::ForeignKeyViolation(format!(
+                "Key ({}) in '{}' is still referenced from table '{}'.",
+                row[column], foreign_key.table, table
+            )));
+        }
+    }
+    Ok(deferred)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::executor::test_util::{database, execute, query, rows};
+
+    #[test]
+    fn test_foreign_key_checks() {
+        let mut db = database(&[
+            "CREATE TABLE users (id, name)",
+            "CREATE TABLE orders (id, user_id REFERENCES users, total)",
+            "INSERT INTO users VALUES (1, 'John'), (2, 'Mike')",
+            "INSERT INTO orders VALUES (10, 1, 100), (11, NULL, 50)",
+        ]);
+        let err = execute(&mut db, "INSERT INTO orders VALUES (12, 2, 70), (13, 9, 30)").unwrap_err();
+        assert_eq!(err.to_string(), "Key (user_id)=(9) in 'orders' is not present in table 'users'.");
+        // 1 行でも違反していれば、どの行も書き込まない
+        assert_eq!(rows(&query(&db, "SELECT count(*) FROM orders").unwrap()), vec![vec!["2"]]);
+        assert_eq!(execute(&mut db, "UPDATE orders SET user_id = 3 WHERE id = 11").unwrap_err().code(), "23503");
+        // 数値に見える文字列でも、同じ値の数値の主キーを参照できる
+        execute(&mut db, "UPDATE orders SET user_id = '2' WHERE id = 11").unwrap();
+
+        let err = execute(&mut db, "DELETE FROM users WHERE id = 1").unwrap_err();
+        assert_eq!(err.to_string(), "Key (1) in 'users' is still referenced from table 'orders'.");
+        assert_eq!(execute(&mut db, "UPDATE users SET id = 5 WHERE id = 2").unwrap_err().code(), "23503");
+        // 名前だけの変更や、参照されていない行の削除はできる
+        execute(&mut db, "UPDATE users SET name = 'Johnny'").unwrap();
+        execute(&mut db, "DELETE FROM orders WHERE user_id = 1").unwrap();
+        execute(&mut db, "DELETE FROM users WHERE id = 1").unwrap();
+        assert_eq!(rows(&query(&db, "SELECT id FROM users").unwrap()), vec![vec!["2"]]);
+    }
+
+    #[test]
+    fn test_create_table_errors() {
+        let mut db = database(&["CREATE TABLE users (id, name)", "CREATE VIEW names AS SELECT name FROM users"]);
+        assert_eq!(execute(&mut db, "CREATE TABLE t (id, uid REFERENCES nope)").unwrap_err().code(), "42P01");
+        assert_eq!(execute(&mut db, "CREATE TABLE t (id, uid REFERENCES names)").unwrap_err().code(), "42000");
+        assert_eq!(execute(&mut db, "CREATE TABLE t (id, uid REFERENCES users (name))").unwrap_err().code(), "42000");
+        assert_eq!(execute(&mut db, "CREATE TABLE t (id, uid REFERENCES users (nope))").unwrap_err().code(), "42703");
+        assert_eq!(execute(&mut db, "CREATE TABLE t (id, FOREIGN KEY (uid) REFERENCES users)").unwrap_err().code(), "42703");
+        assert_eq!(execute(&mut db, "CREATE TABLE t (id, a, b, FOREIGN KEY (a, b) REFERENCES users)").unwrap_err().code(), "42601");
+        assert_eq!(execute(&mut db, "CREATE TABLE t (id, uid REFERENCES users ON DELETE NOTHING)").unwrap_err().code(), "42601");
+        assert!(db.get_table("t").is_none());
+        let sql = "CREATE TABLE t (id, uid DEFAULT 1, FOREIGN KEY (uid) REFERENCES users (id) ON UPDATE SET NULL NOT DEFERRABLE)";
+        execute(&mut db, sql).unwrap();
+        assert_eq!(db.get_table("t").unwrap().foreign_keys[0].on_update, crate::parser::ast::ReferentialAction::SetNull);
+    }
+
+    #[test]
+    fn test_referential_actions() {
+        let mut db = database(&[
+            "CREATE TABLE users (id, name)",
+            "CREATE TABLE posts (id, author REFERENCES users ON DELETE CASCADE ON UPDATE CASCADE)",
+            "CREATE TABLE comments (id, post REFERENCES posts ON DELETE CASCADE)",
+            "CREATE TABLE likes (id, user_id REFERENCES users (id) ON DELETE SET NULL ON UPDATE SET NULL)",
+            "CREATE TABLE pins (id, user_id REFERENCES users ON DELETE RESTRICT)",
+            "INSERT INTO users VALUES (1, 'John'), (2, 'Mike'), (3, 'Anna')",
+            "INSERT INTO posts VALUES (10, 1), (11, 2), (12, 3)",
+            "INSERT INTO comments VALUES (100, 10), (101, 11)",
+            "INSERT INTO likes VALUES (1000, 1), (1001, 2)",
+            "INSERT INTO pins VALUES (5, 3)",
+        ]);
+        // 削除した行を参照する行を連鎖して削除し、SET NULL の行は NULL にする
+        execute(&mut db, "DELETE FROM users WHERE id = 1").unwrap();
+        assert_eq!(rows(&query(&db, "SELECT id FROM posts ORDER BY id").unwrap()), vec![vec!["11"], vec!["12"]]);
+        assert_eq!(rows(&query(&db, "SELECT id FROM comments").unwrap()), vec![vec!["101"]]);
+        assert_eq!(rows(&query(&db, "SELECT user_id FROM likes ORDER BY id").unwrap()), vec![vec!["NULL"], vec!["2"]]);
+        // 主キーを入れ替えても、1 つの文で置き換えるので連鎖しない
+        execute(&mut db, "UPDATE users SET id = CASE id WHEN 2 THEN 3 ELSE 2 END WHERE id IN (2, 3)").unwrap();
+        assert_eq!(rows(&query(&db, "SELECT author FROM posts ORDER BY id").unwrap()), vec![vec!["3"], vec!["2"]]);
+        assert_eq!(rows(&query(&db, "SELECT user_id FROM likes ORDER BY id").unwrap()), vec![vec!["NULL"], vec!["NULL"]]);
+        // RESTRICT で失敗すると、他の外部キーの CASCADE も取り消す
+        execute(&mut db, "INSERT INTO likes VALUES (1002, 3)").unwrap();
+        assert_eq!(execute(&mut db, "DELETE FROM users WHERE id = 3").unwrap_err().code(), "23503");
+        assert_eq!(rows(&query(&db, "SELECT count(*) FROM posts WHERE author = 3").unwrap()), vec![vec!["1"]]);
+        assert_eq!(rows(&query(&db, "SELECT user_id FROM likes WHERE id = 1002").unwrap()), vec![vec!["3"]]);
+    }
+
+    #[test]
+    fn test_self_reference() {
+        let mut db = database(&[
+            "CREATE TABLE employees (id, manager REFERENCES employees ON DELETE CASCADE)",
+            // 同じ文で書き込んだ行も参照できる
+            "INSERT INTO employees VALUES (3, 2), (2, 1), (1, NULL), (4, 1)",
+        ]);
+        assert_eq!(execute(&mut db, "INSERT INTO employees VALUES (5, 6)").unwrap_err().code(), "23503");
+        execute(&mut db, "DELETE FROM employees WHERE id = 2").unwrap();
+        assert_eq!(rows(&query(&db, "SELECT id FROM employees ORDER BY id").unwrap()), vec![vec!["1"], vec!["4"]]);
+        execute(&mut db, "DELETE FROM employees WHERE id = 1").unwrap();
+        assert!(rows(&query(&db, "SELECT id FROM employees").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_deferred() {
+        let mut db = database(&[
+            "CREATE TABLE parents (id)",
+            "CREATE TABLE eager (id, pid REFERENCES parents)",
+            "CREATE TABLE lazy (id, pid REFERENCES parents DEFERRABLE INITIALLY DEFERRED)",
+            "CREATE TRIGGER eager_parent AFTER INSERT ON eager FOR EACH ROW BEGIN INSERT INTO parents VALUES (NEW.pid); END",
+            "CREATE TRIGGER lazy_parent AFTER INSERT ON lazy FOR EACH ROW BEGIN INSERT INTO parents VALUES (NEW.pid); END",
+        ]);
+        // すぐに確かめる外部キーは、トリガーが親の行を作る前に失敗する
+        assert_eq!(execute(&mut db, "INSERT INTO eager VALUES (1, 10)").unwrap_err().code(), "23503");
+        assert!(rows(&query(&db, "SELECT * FROM parents").unwrap()).is_empty());
+        // 遅延する外部キーは、トリガーまで終わってから確かめる
+        execute(&mut db, "INSERT INTO lazy VALUES (1, 10)").unwrap();
+        assert_eq!(rows(&query(&db, "SELECT * FROM parents").unwrap()), vec![vec!["10"]]);
+        // 文の最後でも参照先がなければ、文全体を取り消す
+        execute(&mut db, "CREATE TABLE orphans (id, pid REFERENCES parents INITIALLY DEFERRED)").unwrap();
+        assert_eq!(execute(&mut db, "INSERT INTO orphans VALUES (1, 99)").unwrap_err().code(), "23503");
+        assert!(rows(&query(&db, "SELECT * FROM orphans").unwrap()).is_empty());
+        // 親を消しても、同じ文の中で入れ直せば参照は切れない
+        execute(&mut db, "CREATE TRIGGER revive AFTER DELETE ON parents FOR EACH ROW BEGIN INSERT INTO parents VALUES (OLD.id); END").unwrap();
+        execute(&mut db, "DELETE FROM parents WHERE id = 10").unwrap();
+        assert_eq!(rows(&query(&db, "SELECT * FROM parents").unwrap()), vec![vec!["10"]]);
+
+        // トランザクションの中では COMMIT まで遅延するので、親の行を後の文で作ればよい
+        for sql in ["BEGIN", "INSERT INTO orphans VALUES (1, 20)", "INSERT INTO parents VALUES (20)", "COMMIT"] {
+            execute(&mut db, sql).unwrap();
+        }
+        assert_eq!(rows(&query(&db, "SELECT * FROM orphans").unwrap()), vec![vec!["1", "20"]]);
+        // すぐに確かめる外部キーは、トランザクションの中でも文ごとに確かめる
+        execute(&mut db, "BEGIN").unwrap();
+        assert_eq!(execute(&mut db, "INSERT INTO eager VALUES (2, 30)").unwrap_err().code(), "23503");
+        // COMMIT で参照先がなければ、トランザクション全体を取り消す
+        execute(&mut db, "INSERT INTO parents VALUES (40)").unwrap();
+        execute(&mut db, "INSERT INTO orphans VALUES (2, 99)").unwrap();
+        assert_eq!(execute(&mut db, "COMMIT").unwrap_err().code(), "23503");
+        assert_eq!(rows(&query(&db, "SELECT * FROM parents").unwrap()), vec![vec!["10"], vec!["20"]]);
+        assert_eq!(rows(&query(&db, "SELECT id FROM orphans").unwrap()), vec![vec!["1"]]);
+        assert_eq!(execute(&mut db, "ROLLBACK").unwrap_err().code(), "42000");
+    }
+}
@@ -0,0 +1,238 @@
+use crate::error::DbError;
+use crate::executor::eval::arithmetic;
+use crate::parser::ast::{BinaryOperator, DataType, ScalarFunction};
+use crate::value::{parse_number, Value};
+use std::cmp::Ordering;
+
+/// スカラー関数を呼び出す。引数の数はパース時に確認済み。
+/// COALESCE 以外は、引数のどれかが NULL なら NULL を返す。
+pub fn call(func: ScalarFunction, args: Vec<Value>) -> Result<Value, DbError> {
+    match func {
+        ScalarFunction::Coalesce => return Ok(args.into_iter().find(|v| !v.is_null()).unwrap_or(Value::Null)),
+        ScalarFunction::Nullif => {
+            return Ok(match args[0].sql_cmp(&args[1]) {
+                Some(Ordering::Equal) => Value::Null,
+                _ => args[0].clone(),
+            })
+        }
+        _ => {}
+    }
+    if args.iter().any(Value::is_null) {
+        return Ok(Value::Null);
+    }
+    match func {
+        ScalarFunction::Upper => Ok(Value::Text(args[0].to_string().to_uppercase())),
+        ScalarFunction::Lower => Ok(Value::Text(args[0].to_string().to_lowercase())),
+        ScalarFunction::Length => Ok(Value::Integer(args[0].to_string().chars().count() as i64)),
+        ScalarFunction::Substr => {
+            let length = args.get(2).map(|a| integer_arg(func, a)).transpose()?;
+            substr(&args[0].to_string(), integer_arg(func, &args[1])?, length)
+        }
+        ScalarFunction::Trim | ScalarFunction::Ltrim | ScalarFunction::Rtrim => {
+            let text = args[0].to_string();
+            let chars: Vec<char> = match args.get(1) {
+                Some(set) => set.to_string().chars().collect(),
+                None => vec![' '],
+            };
+            let trimmed = match func {
+                ScalarFunction::Ltrim => text.trim_start_matches(chars.as_slice()),
+                ScalarFunction::Rtrim => text.trim_end_matches(chars.as_slice()),
+                _ => text.trim_matches(chars.as_slice()),
+            };
+            Ok(Value::Text(trimmed.to_string()))
+        }
+        ScalarFunction::Replace => {
+            let (text, from, to) = (args[0].to_string(), args[1].to_string(), args[2].to_string());
+            if from.is_empty() {
+                return Ok(Value::Text(text));
+            }
+            Ok(Value::Text(text.replace(&from, &to)))
+        }
+        ScalarFunction::Abs => match numeric_arg(func, &args[0])? {
+            Value::Integer(i) => i.checked_abs().map(Value::Integer).ok_or_else(|| DbError::InvalidValue("Integer overflow".into())),
+            v => Ok(Value::Float(v.as_f64().unwrap_or_default().abs())),
+        },
+        ScalarFunction::Round => {
+            let value = numeric_arg(func, &args[0])?;
+            let digits = args.get(1).map(|a| integer_arg(func, a)).transpose()?.unwrap_or(0);
+            match value {
+                Value::Integer(i) if digits >= 0 => Ok(Value::Integer(i)),
+                v => {
+                    let scale = 10f64.powi(digits as i32);
+                    Ok(Value::Float((v.as_f64().unwrap_or_default() * scale).round() / scale))
+                }
+            }
+        }
+        ScalarFunction::Ceil | ScalarFunction::Floor => match numeric_arg(func, &args[0])? {
+            Value::Integer(i) => Ok(Value::Integer(i)),
+            v => {
+                let f = v.as_f64().unwrap_or_default();
+                Ok(Value::Float(if func == ScalarFunction::Ceil { f.ceil() } else { f.floor() }))
+            }
+        },
+        ScalarFunction::Sqrt => {
+            let f = float_arg(func, &args[0])?;
+            if f < 0.0 {
+                return Err(DbError::InvalidValue("Cannot take the square root of a negative number".into()));
+            }
+            Ok(Value::Float(f.sqrt()))
+        }
+        ScalarFunction::Power => Ok(Value::Float(float_arg(func, &args[0])?.powf(float_arg(func, &args[1])?))),
+        ScalarFunction::Mod => {
+            let (l, r) = (numeric_arg(func, &args[0])?, numeric_arg(func, &args[1])?);
+            arithmetic(&l, BinaryOperator::Modulo, &r)
+        }
+        ScalarFunction::Coalesce | ScalarFunction::Nullif => unreachable!(),
+    }
+}
+
+/// SUBSTR(text, start[, length])。位置は 1 始まりで、範囲外の部分は切り捨てる。
+fn substr(text: &str, start: i64, length: Option<i64>) -> Result<Value, DbError> {
+    let chars: Vec<char> = text.chars().collect();
+    let begin = start - 1;
+    let end = match length {
+        Some(len) if len < 0 => return Err(DbError::InvalidValue("Negative substring length not allowed".into())),
+        Some(len) => begin.saturating_add(len),
+        None => chars.len() as i64,
+    };
+    let clamp = |i: i64| i.clamp(0, chars.len() as i64) as usize;
+    Ok(Value::Text(chars[clamp(begin)..clamp(end).max(clamp(begin))].iter().collect()))
+}
+
+fn numeric_arg(func: ScalarFunction, value: &Value) -> Result<Value, DbError> {
+    match value.clone().numeric_affinity() {
+        v @ (Value::Integer(_) | Value::Float(_)) => Ok(v),
+        other => Err(DbError::TypeMismatch(format!("{} expects a numeric argument, got '{}'", func, other))),
+    }
+}
+
+fn float_arg(func: ScalarFunction, value: &Value) -> Result<f64, DbError> {
+    Ok(numeric_arg(func, value)?.as_f64().unwrap_or_default())
+}
+
+fn integer_arg(func: ScalarFunction, value: &Value) -> Result<i64, DbError> {
+    match numeric_arg(func, value)? {
+        Value::Integer(i) => Ok(i),
+        other => Err(DbError::TypeMismatch(format!("{} expects an integer argument, got '{}'", func, other))),
+    }
+}
+
+/// CAST(value AS type)。変換できない値はエラーにする。
+pub fn cast(value: Value, data_type: DataType) -> Result<Value, DbError> {
+    let invalid = |v: &Value| DbError::TypeMismatch(format!("Cannot cast '{}' to {}", v, data_type));
+    match (data_type, value) {
+        (_, Value::Null) => Ok(Value::Null),
+        (DataType::Text, v) => Ok(Value::Text(v.to_string())),
+        (DataType::Integer, Value::Integer(i)) => Ok(Value::Integer(i)),
+        (DataType::Integer, Value::Boolean(b)) => Ok(Value::Integer(b as i64)),
+        (DataType::Integer, Value::Float(f)) => float_to_integer(f).ok_or_else(|| invalid(&Value::Float(f))),
+        (DataType::Integer, Value::Text(s)) => match parse_number(&s) {
+            Some(Value::Integer(i)) => Ok(Value::Integer(i)),
+            Some(Value::Float(f)) => float_to_integer(f).ok_or_else(|| invalid(&Value::Text(s))),
+            _ => Err(invalid(&Value::Text(s))),
+        },
+        (DataType::Float, Value::Boolean(b)) => Ok(Value::Float(if b { 1.0 } else { 0.0 })),
+        (DataType::Float, v) => v.as_f64().map(Value::Float).ok_or_else(|| invalid(&v)),
+        (DataType::Boolean, Value::Boolean(b)) => Ok(Value::Boolean(b)),
+        (DataType::Boolean, Value::Integer(i)) => Ok(Value::Boolean(i != 0)),
+        (DataType::Boolean, Value::Float(f)) => Ok(Value::Boolean(f != 0.0)),
+        (DataType::Boolean, Value::Text(s)) => match s.trim().to_lowercase().as_str() {
+            "true" | "t" | "yes" | "y" | "on" | "1" => Ok(Value::Boolean(true)),
+            "false" | "f" | "no" | "n" | "off" | "0" => Ok(Value::Boolean(false)),
+            _ => Err(invalid(&Value::Text(s))),
+        },
+    }
+}
+
+/// 小数点以下を切り捨てて整数にする。i64 の範囲外なら None。
+fn float_to_integer(f: f64) -> Option<Value> {
+    let t = f.trunc();
+    if t >= i64::MIN as f64 && t < i64::MAX as f64 {
+        Some(Value::Integer(t as i64))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::executor::test_util::{database, query, rows};
+
+    fn setup() -> Database {
+        database(&[
+            "CREATE TABLE items (id, name, price, qty)",
+            "INSERT INTO items VALUES (1, ' apple ', 1.5, 4), (2, 'Banana', 2, 3), (3, 'cherry', 10, '')",
+        ])
+    }
+
+    #[test]
+    fn test_arithmetic_and_aliases() {
+        let db = setup();
+        let result = query(&db, "SELECT id, price * qty AS total, id + 2 * 3, 7 / 2, 7 % 3 FROM items WHERE id < 3").unwrap();
+        assert_eq!(result.columns, vec!["id", "total", "id + 2 * 3", "7 / 2", "7 % 3"]);
+        assert_eq!(rows(&result), vec![vec!["1", "6.0", "7", "3", "1"], vec!["2", "6", "8", "3", "1"]]);
+        let result = query(&db, "SELECT (id + 2) * 3 t FROM items WHERE id = 1").unwrap();
+        assert_eq!(result.columns, vec!["t"]);
+        assert_eq!(rows(&result), vec![vec!["9"]]);
+        assert!(query(&db, "SELECT id / 0 FROM items").unwrap_err().to_string().contains("Division by zero"));
+        assert!(query(&db, "SELECT name + 1 FROM items").is_err());
+    }
+
+    #[test]
+    fn test_string_functions() {
+        let db = setup();
+        let result = query(
+            &db,
+            "SELECT UPPER(name), LOWER(name), LENGTH(TRIM(name)), SUBSTR(name, 2, 3), REPLACE(name, 'a', 'o'), \
+             'no.' || id FROM items WHERE id = 2",
+        )
+        .unwrap();
+        assert_eq!(rows(&result), vec![vec!["BANANA", "banana", "6", "ana", "Bonono", "no.2"]]);
+        let result = query(&db, "SELECT LTRIM(name), RTRIM(name), TRIM('xxhixx', 'x') FROM items WHERE id = 1").unwrap();
+        assert_eq!(rows(&result), vec![vec!["apple ", " apple", "hi"]]);
+        assert!(query(&db, "SELECT UPPER(name, id) FROM items").is_err());
+        assert!(query(&db, "SELECT FOO(name) FROM items").is_err());
+    }
+
+    #[test]
+    fn test_math_and_null_functions() {
+        let db = setup();
+        let result = query(
+            &db,
+            "SELECT ABS(-3), ROUND(2.567, 2), CEIL(1.2), FLOOR(-1.2), SQRT(16), POWER(2, 10), MOD(10, 4) FROM items WHERE id = 1",
+        )
+        .unwrap();
+        assert_eq!(rows(&result), vec![vec!["3", "2.57", "2.0", "-2.0", "4.0", "1024.0", "2"]]);
+        // 空文字列の qty は数値として読めないので、ここでは NULLIF で NULL に置き換える
+        let result = query(&db, "SELECT COALESCE(NULLIF(qty, ''), 0), NULLIF(id, 3) FROM items").unwrap();
+        assert_eq!(rows(&result), vec![vec!["4", "1"], vec!["3", "2"], vec!["0", "NULL"]]);
+    }
+
+    #[test]
+    fn test_case_and_cast() {
+        let db = setup();
+        let result = query(
+            &db,
+            "SELECT CASE WHEN price >= 10 THEN 'high' WHEN price >= 2 THEN 'mid' ELSE 'low' END, \
+             CASE id WHEN 1 THEN 'one' END FROM items",
+        )
+        .unwrap();
+        assert_eq!(rows(&result), vec![vec!["low", "one"], vec!["mid", "NULL"], vec!["high", "NULL"]]);
+        let result = query(
+            &db,
+            "SELECT CAST(price AS INTEGER), CAST(id AS TEXT) || 'x', CAST('1.5' AS FLOAT), CAST('yes' AS BOOLEAN) \
+             FROM items WHERE id = 1",
+        )
+        .unwrap();
+        assert_eq!(rows(&result), vec![vec!["1", "1x", "1.5", "TRUE"]]);
+        assert!(query(&db, "SELECT CAST(name AS INTEGER) FROM items").unwrap_err().to_string().contains("Cannot cast"));
+    }
+
+    #[test]
+    fn test_unknown_column_in_expression() {
+        let db = setup();
+        let err = query(&db, "SELECT UPPER(nmae) FROM items WHERE id = 99").unwrap_err();
+        assert!(err.to_string().contains("nmae"));
+    }
+}
@@ -0,0 +1,289 @@
+use crate::database::Database;
+use crate::error::DbError;
+use crate::executor::eval::{eval, Scope};
+use crate::executor::{execute_select, lookup_table, qualify, ExecContext};
+use crate::parser::ast::{Assignment, ColumnDef, ConflictAction, Expr, Insert, InsertSource, OnConflict};
+use crate::table::Table;
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// INSERT で書き込む行を、テーブルの全カラムを揃えた形で求める。テーブルはまだ変更しない。
+/// 列リストで省略されたカラムには既定値を入れる。ON CONFLICT があれば、既存の行との衝突を
+/// 解決した結果（新しい行と、置き換える行）を返す。
+pub fn rows(db: &Database, insert: &Insert, params: &[Value]) -> Result<Vec<Vec<Value>>, DbError> {
+    let table = lookup_table(db, &insert.table_name)?;
+    let targets = target_columns(table, &insert.columns)?;
+    let source = match &insert.source {
+        InsertSource::Values(rows) => {
+            let mut values = Vec::with_capacity(rows.len());
+            for row in rows {
+                if row.len() != targets.len() {
+                    return Err(DbError::InvalidQuery(format!("INSERT has {} target columns but {} values.", targets.len(), row.len())));
+                }
+                values.push(row.iter().map(|e| eval_constant(db, e, params)).collect::<Result<Vec<_>, _>>()?);
+            }
+            values
+        }
+        InsertSource::Select(select) => {
+            let result = execute_select(db, select, params)?;
+            if result.columns.len() != targets.len() {
+                return Err(DbError::InvalidQuery(format!(
+                    "INSERT has {} target columns but the query returns {} columns.",
+                    targets.len(),
+                    result.columns.len()
+                )));
+            }
+            result.rows
+        }
+    };
+    let rows = source
+        .into_iter()
+        .map(|values| {
+            let mut row: Vec<Value> = (0..table.columns.len()).map(|i| table.default_value(i)).collect();
+            for (&i, value) in targets.iter().zip(values) {
+                row[i] = value;
+            }
+            row
+        })
+        .collect();
+    match &insert.on_conflict {
+        Some(on_conflict) => resolve_conflicts(db, insert, table, on_conflict, rows, params),
+        None => Ok(rows),
+    }
+}
+
+/// 主キーが既存の行（またはこの文で先に書き込む行）と重複する行を、ON CONFLICT の指定に従って
+/// 読み飛ばす・置き換える・更新後の行に変える。
+fn resolve_conflicts(
+    db: &Database,
+    insert: &Insert,
+    table: &Table,
+    on_conflict: &OnConflict,
+    rows: Vec<Vec<Value>>,
+    params: &[Value],
+) -> Result<Vec<Vec<Value>>, DbError> {
+    let primary_key = table.primary_key().unwrap_or_default();
+    if let Some(column) = on_conflict.target.iter().find(|c| !table.columns.contains(c)) {
+        return Err(DbError::UndefinedColumn(column.clone()));
+    }
+    if !on_conflict.target.is_empty() && on_conflict.target != [primary_key] {
+        return Err(DbError::InvalidColumnReference(format!(
+            "There is no unique index on ({}); ON CONFLICT can only target the primary key '{}'.",
+            on_conflict.target.join(", "),
+            primary_key
+        )));
+    }
+    let mut index: HashMap<Value, usize> = HashMap::new(); // 主キー → out の位置
+    let mut out: Vec<Vec<Value>> = Vec::new();
+    for row in rows {
+        let key = row[0].clone();
+        let pending = index.get(&key.clone().numeric_affinity()).copied();
+        let existing = match pending {
+            Some(i) => Some(out[i].clone()),
+            None => table.find_by_key(&key).cloned(),
+        };
+        let row = match (existing, &on_conflict.action) {
+            (None, _) => row,
+            (Some(_), ConflictAction::Nothing) => continue,
+            (Some(_), ConflictAction::Replace) => row,
+            (Some(_), ConflictAction::Update { .. }) if pending.is_some() => {
+                return Err(DbError::CardinalityViolation(format!(
+                    "ON CONFLICT DO UPDATE cannot affect the row with key '{}' twice.",
+                    key
+                )))
+            }
+            (Some(existing), ConflictAction::Update { assignments, condition }) => {
+                let cols = qualify(&table.columns, &insert.table_name);
+                let excluded_cols = qualify(&table.columns, "excluded");
+                let cache = RefCell::new(HashMap::new());
+                let base = ExecContext { db, outer: None, cache: &cache, ctes: HashMap::new(), params };
+                // `excluded.col` は外側のスコープとして解決するので、修飾なしのカラムは既存の行を指す
+                let excluded = Scope::new(&base, &excluded_cols, &row);
+                let ctx = ExecContext { outer: Some(&excluded), ..base.clone() };
+                let scope = Scope::new(&ctx, &cols, &existing);
+                if let Some(condition) = condition {
+                    if !eval(condition, &scope)?.is_truthy() {
+                        continue;
+                    }
+                }
+                let updated = apply_assignments(table, &existing, assignments, &scope)?;
+                if updated[0] != existing[0] {
+                    return Err(DbError::InvalidQuery("ON CONFLICT DO UPDATE cannot change the primary key.".into()));
+                }
+                updated
+            }
+        };
+        match pending {
+            Some(i) => out[i] = row,
+            None => {
+                index.insert(key.numeric_affinity(), out.len());
+                out.push(row);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// SET 句を適用した新しい行を作る。右辺はすべて変更前の行（`scope`）で評価する。
+pub fn apply_assignments(table: &Table, row: &[Value], assignments: &[Assignment], scope: &Scope) -> Result<Vec<Value>, DbError> {
+    let mut updated = row.to_vec();
+    for (n, assignment) in assignments.iter().enumerate() {
+        let index = table
+            .columns
+            .iter()
+            .position(|c| *c == assignment.column)
+            .ok_or_else(|| DbError::UndefinedColumn(assignment.column.clone()))?;
+        if assignments[..n].iter().any(|a| a.column == assignment.column) {
+            return Err(DbError::DuplicateColumn(assignment.column.clone()));
+        }
+        updated[index] = eval(&assignment.value, scope)?;
+    }
+    Ok(updated)
+}
+
+/// CREATE TABLE の DEFAULT 句を評価する。DEFAULT のないカラムは NULL。
+pub fn column_defaults(db: &Database, columns: &[ColumnDef], params: &[Value]) -> Result<Vec<Value>, DbError> {
+    columns
+        .iter()
+        .map(|c| match &c.default {
+            Some(e) => eval_constant(db, e, params),
+            None => Ok(Value::Null),
+        })
+        .collect()
+}
+
+/// 値を挿入するカラムの位置。列リストがなければ全カラムを定義順に使う。
+fn target_columns(table: &Table, columns: &[String]) -> Result<Vec<usize>, DbError> {
+    if columns.is_empty() {
+        return Ok((0..table.columns.len()).collect());
+    }
+    let mut targets = Vec::with_capacity(columns.len());
+    for name in columns {
+        let index = table
+            .columns
+            .iter()
+            .position(|c| c == name)
+            .ok_or_else(|| DbError::UndefinedColumn(name.clone()))?;
+        if targets.contains(&index) {
+            return Err(DbError::DuplicateColumn(name.clone()));
+        }
+        targets.push(index);
+    }
+    Ok(targets)
+}
+
+/// カラムを参照しない式（VALUES の値や DEFAULT 句）を評価する。
+fn eval_constant(db: &Database, expr: &Expr, params: &[Value]) -> Result<Value, DbError> {
+    let cache = RefCell::new(HashMap::new());
+    let ctx = ExecContext { db, outer: None, cache: &cache, ctes: HashMap::new(), params };
+    eval(expr, &Scope::new(&ctx, &[], &[]))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::executor::test_util::{database, execute, query, rows};
+
+    fn setup() -> Database {
+        database(&[
+            "CREATE TABLE users (id, name, age DEFAULT 20)",
+            "CREATE TABLE staff (id, name)",
+            "INSERT INTO staff VALUES (10, 'Anna'), (11, 'Tom')",
+        ])
+    }
+
+    #[test]
+    fn test_values() {
+        let mut db = setup();
+        // 引用符内のカンマは値の一部として扱う
+        execute(&mut db, "INSERT INTO users VALUES (1, 'Smith, John', 30), (2, 'Mike', 10 * 4)").unwrap();
+        execute(&mut db, "INSERT INTO users (name, id) VALUES ('Anna', 3)").unwrap();
+        execute(&mut db, "INSERT INTO users (id) VALUES (4)").unwrap();
+        let result = query(&db, "SELECT * FROM users").unwrap();
+        assert_eq!(
+            rows(&result),
+            vec![
+                vec!["1", "Smith, John", "30"],
+                vec!["2", "Mike", "40"],
+                vec!["3", "Anna", "20"],
+                vec!["4", "NULL", "20"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_select() {
+        let mut db = setup();
+        execute(&mut db, "INSERT INTO users (id, name) SELECT id, UPPER(name) FROM staff WHERE id > 10").unwrap();
+        let result = query(&db, "SELECT * FROM users").unwrap();
+        assert_eq!(rows(&result), vec![vec!["11", "TOM", "20"]]);
+    }
+
+    #[test]
+    fn test_on_conflict() {
+        let mut db = setup();
+        execute(&mut db, "INSERT INTO users VALUES (1, 'John', 30), (2, 'Mike', 40)").unwrap();
+        execute(&mut db, "INSERT INTO users VALUES (1, 'Johnny', 31), (3, 'Anna', 25) ON CONFLICT (id) DO NOTHING").unwrap();
+        execute(&mut db, "INSERT INTO users VALUES (2, 'Mike', 41) ON CONFLICT (id) DO UPDATE SET age = excluded.age WHERE age < excluded.age").unwrap();
+        // WHERE を満たさない行は更新しない
+        execute(&mut db, "INSERT INTO users VALUES (3, 'Anna', 1) ON CONFLICT (id) DO UPDATE SET age = excluded.age WHERE users.age < excluded.age").unwrap();
+        execute(&mut db, "INSERT INTO users (id, name) VALUES (3, 'Ann') ON CONFLICT (id) DO UPDATE SET name = name || '/' || excluded.name, age = age + 1").unwrap();
+        let result = query(&db, "SELECT * FROM users").unwrap();
+        assert_eq!(rows(&result), vec![vec!["1", "John", "30"], vec!["2", "Mike", "41"], vec!["3", "Anna/Ann", "26"]]);
+    }
+
+    #[test]
+    fn test_insert_or_replace_and_ignore() {
+        let mut db = setup();
+        execute(&mut db, "INSERT INTO users VALUES (1, 'John', 30), (2, 'Mike', 40)").unwrap();
+        // 同じ文の中で重複した場合も後の行が勝つ
+        execute(&mut db, "INSERT OR REPLACE INTO users (id, name) VALUES (1, 'Johnny'), (4, 'Kate'), (4, 'Katie')").unwrap();
+        execute(&mut db, "INSERT OR IGNORE INTO users VALUES (2, 'Ignored', 0), (5, 'Tom', 50)").unwrap();
+        let result = query(&db, "SELECT * FROM users").unwrap();
+        assert_eq!(
+            rows(&result),
+            vec![vec!["1", "Johnny", "20"], vec!["2", "Mike", "40"], vec!["4", "Katie", "20"], vec!["5", "Tom", "50"]]
+        );
+    }
+
+    #[test]
+    fn test_on_conflict_errors() {
+        let mut db = setup();
+        execute(&mut db, "INSERT INTO users VALUES (1, 'John', 30)").unwrap();
+        let mut error = |sql: &str| execute(&mut db, sql).unwrap_err().to_string();
+        assert!(error("INSERT INTO users VALUES (1, 'A', 1) ON CONFLICT (name) DO NOTHING").contains("no unique index"));
+        assert!(error("INSERT INTO users VALUES (1, 'A', 1), (1, 'B', 2) ON CONFLICT (id) DO UPDATE SET age = 0").contains("twice"));
+        assert!(error("INSERT INTO users VALUES (1, 'A', 1) ON CONFLICT (id) DO UPDATE SET id = 9").contains("primary key"));
+        assert!(error("INSERT OR REPLACE INTO users VALUES (1, 'A', 1) ON CONFLICT DO NOTHING").starts_with("Invalid syntax"));
+    }
+
+    #[test]
+    fn test_on_conflict_target() {
+        let mut db = setup();
+        execute(&mut db, "INSERT INTO users VALUES (1, 'John', 30)").unwrap();
+        // 主キー以外のカラムでは重複を判定できないので、主キーとして扱わずにエラーにする
+        for sql in [
+            "INSERT INTO users VALUES (2, 'John', 1) ON CONFLICT (name) DO UPDATE SET age = 0",
+            "INSERT INTO users VALUES (2, 'A', 1) ON CONFLICT (id, name) DO NOTHING",
+        ] {
+            assert_eq!(execute(&mut db, sql).unwrap_err().code(), "42P10");
+        }
+        assert_eq!(execute(&mut db, "INSERT INTO users VALUES (2, 'A', 1) ON CONFLICT (nope) DO NOTHING").unwrap_err().code(), "42703");
+        assert_eq!(rows(&query(&db, "SELECT id FROM users").unwrap()), vec![vec!["1"]]);
+    }
+
+    #[test]
+    fn test_insert_is_atomic() {
+        let mut db = setup();
+        execute(&mut db, "INSERT INTO users VALUES (1, 'John', 30)").unwrap();
+        // 2 行目が主キー重複なので、1 行目も挿入されない
+        assert!(execute(&mut db, "INSERT INTO users VALUES (2, 'Mike', 40), (1, 'Dup', 50)").is_err());
+        assert!(execute(&mut db, "INSERT INTO users VALUES (3, 'Anna', 20), (3, 'Anna', 20)").is_err());
+        assert!(execute(&mut db, "INSERT INTO users (name) VALUES ('NoKey')").is_err());
+        assert!(execute(&mut db, "INSERT INTO users VALUES (4, 'Kate')").is_err());
+        assert!(execute(&mut db, "INSERT INTO users (id, nope) VALUES (5, 1)").is_err());
+        let result = query(&db, "SELECT id FROM users").unwrap();
+        assert_eq!(rows(&result), vec![vec!["1"]]);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::error::DbError;
+use crate::executor::eval::{eval, resolve_column, Scope};
+use crate::executor::{is_primary_key, ColumnInfo, ExecContext, Relation};
+use crate::executor::operator::Stats;
+use crate::parser::ast::{BinaryOperator, Expr, Join, JoinKind};
+use crate::table::Table;
+use crate::value::Value;
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+/// JOIN の実行方式
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinStrategy {
+    /// 左右の全組み合わせについて ON 条件を評価する
+    NestedLoop,
+    /// 左の各行から `left_key` を求め、右テーブルの主キー B+Tree を search する
+    IndexNestedLoop { left_key: Expr },
+    /// 右の行を等値条件のキーでハッシュ表にし、左の行で探索する
+    HashJoin { left_keys: Vec<Expr>, right_keys: Vec<Expr> },
+}
+
+/// ON 条件の形から実行方式を選ぶ。統計情報がないときに使う規則で、`candidates` の先頭を選ぶ。
+pub fn choose_strategy(join: &Join, left: &[ColumnInfo], right: &[ColumnInfo], table: Option<&Table>) -> JoinStrategy {
+    candidates(join, left, right, table).swap_remove(0)
+}
+
+/// ON 条件の形から使える実行方式を、規則で優先する順に並べる。
+/// - 「左側の式 = 右テーブルの主キー」があり、INNER / LEFT JOIN なら B+Tree を使う Index Nested Loop
+/// - 左右のカラム同士の等値条件があればハッシュ結合
+/// - Nested Loop はどの JOIN にも使える（CROSS JOIN や不等号のみの条件ではこれだけ）
+pub fn candidates(join: &Join, left: &[ColumnInfo], right: &[ColumnInfo], table: Option<&Table>) -> Vec<JoinStrategy> {
+    let Some(constraint) = &join.constraint else {
+        return vec![JoinStrategy::NestedLoop];
+    };
+    let mut strategies = Vec::new();
+    let pairs = equi_join_pairs(constraint, left, right);
+    if let (Some(table), JoinKind::Inner | JoinKind::Left) = (table, join.kind) {
+        if let Some((left_key, _)) = pairs.iter().find(|(_, r)| is_primary_key(table, &join.table, r)) {
+            strategies.push(JoinStrategy::IndexNestedLoop { left_key: left_key.clone() });
+        }
+    }
+    if !pairs.is_empty() {
+        let (left_keys, right_keys) = pairs.into_iter().unzip();
+        strategies.push(JoinStrategy::HashJoin { left_keys, right_keys });
+    }
+    strategies.push(JoinStrategy::NestedLoop);
+    strategies
+}
+
+/// AND で結ばれた ON 条件から「左側だけを参照する式 = 右側だけを参照する式」の組を集める。
+fn equi_join_pairs(constraint: &Expr, left: &[ColumnInfo], right: &[ColumnInfo]) -> Vec<(Expr, Expr)> {
+    let mut pairs = Vec::new();
+    collect_equi_pairs(constraint, left, right, &mut pairs);
+    pairs
+}
+
+fn collect_equi_pairs(expr: &Expr, left: &[ColumnInfo], right: &[ColumnInfo], pairs: &mut Vec<(Expr, Expr)>) {
+    let Expr::Binary { left: l, op, right: r } = expr else {
+        return;
+    };
+    match op {
+        BinaryOperator::And => {
+            collect_equi_pairs(l, left, right, pairs);
+            collect_equi_pairs(r, left, right, pairs);
+        }
+        BinaryOperator::Eq => {
+            if refers_only_to(l, left) && refers_only_to(r, right) {
+                pairs.push((l.as_ref().clone(), r.as_ref().clone()));
+            } else if refers_only_to(r, left) && refers_only_to(l, right) {
+                pairs.push((r.as_ref().clone(), l.as_ref().clone()));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 式中のカラム参照がすべて `columns` の中で一意に解決でき、かつ 1 つ以上のカラムを参照しているか。
+fn refers_only_to(expr: &Expr, columns: &[ColumnInfo]) -> bool {
+    let mut found = false;
+    let mut resolvable = true;
+    expr.walk(&mut |e| match e {
+        Expr::Column { table, name } => {
+            found = true;
+            resolvable &= resolve_column(columns, table.as_deref(), name).is_ok();
+        }
+        Expr::Aggregate { .. } | Expr::InSubquery { .. } | Expr::Exists(_) | Expr::Subquery(_) => resolvable = false,
+        _ => {}
+    });
+    resolvable && found
+}
+
+fn concat(left: &[Value], right: &[Value]) -> Vec<Value> {
+    let mut row = left.to_vec();
+    row.extend_from_slice(right);
+    row
+}
+
+fn nulls(n: usize) -> Vec<Value> {
+    vec![Value::Null; n]
+}
+
+/// 結合の右側。左の行を 1 行ずつ受け取って、ON 条件を満たす右の行と組み合わせる。
+/// 左の行は読み捨てていけるので、左側の入力を全件ためずに結合できる。
+pub struct Probe<'a> {
+    join: &'a Join,
+    /// 結合後の行のカラム（左のカラムの後に右のカラム）
+    columns: &'a [ColumnInfo],
+    left_columns: &'a [ColumnInfo],
+    right_width: usize,
+    inner: Inner<'a>,
+    /// 左のどれかの行と一致した右の行。RIGHT / FULL JOIN で一致しなかった行を NULL 埋めするのに使う
+    matched: Vec<bool>,
+}
+
+enum Inner<'a> {
+    /// 右の全行について ON 条件を評価する（Nested Loop）
+    Rows(Vec<Vec<Value>>),
+    /// 右の行を等値条件のキーでまとめたハッシュ表（ハッシュ結合）
+    Hash { rows: Vec<Vec<Value>>, buckets: HashMap<Vec<Value>, Vec<usize>>, left_keys: &'a [Expr] },
+    /// 左の行から求めた `left_key` で右テーブルの主キー B+Tree を search する（Index Nested Loop）。
+    /// EXPLAIN ANALYZE では、search の回数と見つかった行数を `stats` の右側のノードに記録する
+    Index { table: &'a Table, left_key: &'a Expr, stats: Option<(&'a Stats, usize)> },
+}
+
+impl<'a> Probe<'a> {
+    /// 左右の全組み合わせについて ON 条件を評価する。
+    pub fn nested_loop(join: &'a Join, columns: &'a [ColumnInfo], left_columns: &'a [ColumnInfo], right: Relation) -> Self {
+        let right_width = right.columns.len();
+        let matched = vec![false; right.rows.len()];
+        Probe { join, columns, left_columns, right_width, inner: Inner::Rows(right.rows), matched }
+    }
+
+    /// 右の行を等値条件のキーでハッシュ表にし、左の行で探索する。
+    pub fn hash(
+        ctx: &ExecContext,
+        join: &'a Join,
+        columns: &'a [ColumnInfo],
+        left_columns: &'a [ColumnInfo],
+        right: Relation,
+        left_keys: &'a [Expr],
+        right_keys: &[Expr],
+    ) -> Result<Self, DbError> {
+        // 右側の行をキーごとにまとめる（NULL を含むキーはどの行とも一致しない）
+        let mut buckets: HashMap<Vec<Value>, Vec<usize>> = HashMap::new();
+        for (i, r) in right.rows.iter().enumerate() {
+            if let Some(key) = join_key(ctx, right_keys, &right.columns, r)? {
+                buckets.entry(key).or_default().push(i);
+            }
+        }
+        let right_width = right.columns.len();
+        let matched = vec![false; right.rows.len()];
+        let inner = Inner::Hash { rows: right.rows, buckets, left_keys };
+        Ok(Probe { join, columns, left_columns, right_width, inner, matched })
+    }
+
+    /// 左の各行から `left_key` を求め、右テーブルの主キー B+Tree を search する。INNER / LEFT JOIN のみ。
+    pub fn index(
+        join: &'a Join,
+        columns: &'a [ColumnInfo],
+        left_columns: &'a [ColumnInfo],
+        table: &'a Table,
+        left_key: &'a Expr,
+        stats: Option<(&'a Stats, usize)>,
+    ) -> Self {
+        let right_width = table.columns.len();
+        Probe { join, columns, left_columns, right_width, inner: Inner::Index { table, left_key, stats }, matched: Vec::new() }
+    }
+
+    /// 左の 1 行と結合した行を `out` に追加する。一致する右の行がなければ、LEFT / FULL JOIN では右を NULL 埋めした行を追加する。
+    pub fn probe(&mut self, ctx: &ExecContext, l: &[Value], out: &mut VecDeque<Vec<Value>>) -> Result<(), DbError> {
+        let before = out.len();
+        match &self.inner {
+            Inner::Rows(rows) => {
+                for (i, r) in rows.iter().enumerate() {
+                    let row = concat(l, r);
+                    if matches_constraint(ctx, self.join, self.columns, &row)? {
+                        self.matched[i] = true;
+                        out.push_back(row);
+                    }
+                }
+            }
+            Inner::Hash { rows, buckets, left_keys } => {
+                if let Some(key) = join_key(ctx, left_keys, self.left_columns, l)? {
+                    for &i in buckets.get(&key).map(|v| v.as_slice()).unwrap_or(&[]) {
+                        let row = concat(l, &rows[i]);
+                        if matches_constraint(ctx, self.join, self.columns, &row)? {
+                            self.matched[i] = true;
+                            out.push_back(row);
+                        }
+                    }
+                }
+            }
+            Inner::Index { table, left_key, stats } => {
+                let start = Instant::now();
+                let key = eval(left_key, &Scope::new(ctx, self.left_columns, l))?;
+                let found = if key.is_null() { None } else { table.find_by_key(&key) };
+                if let Some((stats, node)) = stats {
+                    let mut stats = stats.borrow_mut();
+                    let entry = stats.entry(*node).or_default();
+                    entry.loops += 1;
+                    entry.rows += usize::from(found.is_some());
+                    entry.time += start.elapsed();
+                }
+                if let Some(r) = found {
+                    let row = concat(l, r);
+                    // 主キー以外の条件も含めて ON 条件全体を確認する
+                    if matches_constraint(ctx, self.join, self.columns, &row)? {
+                        out.push_back(row);
+                    }
+                }
+            }
+        }
+        if out.len() == before && matches!(self.join.kind, JoinKind::Left | JoinKind::Full) {
+            out.push_back(concat(l, &nulls(self.right_width)));
+        }
+        Ok(())
+    }
+
+    /// 左の行をすべて探索した後に呼ぶ。RIGHT / FULL JOIN で、左のどの行とも一致しなかった右の行を NULL 埋めして返す。
+    pub fn unmatched(self) -> Vec<Vec<Value>> {
+        let rows = match self.inner {
+            Inner::Rows(rows) | Inner::Hash { rows, .. } => rows,
+            Inner::Index { .. } => return Vec::new(),
+        };
+        if !matches!(self.join.kind, JoinKind::Right | JoinKind::Full) {
+            return Vec::new();
+        }
+        let left_width = self.left_columns.len();
+        rows.iter().zip(&self.matched).filter(|(_, m)| !**m).map(|(r, _)| concat(&nulls(left_width), r)).collect()
+    }
+}
+
+fn matches_constraint(ctx: &ExecContext, join: &Join, columns: &[ColumnInfo], row: &[Value]) -> Result<bool, DbError> {
+    match &join.constraint {
+        Some(c) => Ok(eval(c, &Scope::new(ctx, columns, row))?.is_truthy()),
+        None => Ok(true),
+    }
+}
+
+fn join_key(
+    ctx: &ExecContext,
+    keys: &[Expr],
+    columns: &[ColumnInfo],
+    row: &[Value],
+) -> Result<Option<Vec<Value>>, DbError> {
+    let mut values = Vec::with_capacity(keys.len());
+    for k in keys {
+        let v = eval(k, &Scope::new(ctx, columns, row))?;
+        if v.is_null() {
+            return Ok(None);
+        }
+        values.push(v.numeric_affinity());
+    }
+    Ok(Some(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::executor::table_columns;
+    use crate::executor::test_util::{database, query, rows};
+    use crate::parser::ast::{SelectStatement, SetExpr};
+    use crate::parser::{parse_query, Query};
+
+    fn setup() -> Database {
+        database(&[
+            "CREATE TABLE users (id, name)",
+            "CREATE TABLE orders (oid, user_id, total)",
+            "INSERT INTO users VALUES (1, 'John'), (2, 'Mike'), (3, 'Anna')",
+            "INSERT INTO orders VALUES (10, 1, 100), (11, 1, 50), (12, 2, 70), (13, 9, 30)",
+        ])
+    }
+
+    #[test]
+    fn test_inner_join_with_aliases() {
+        let db = setup();
+        let result = query(&db, "SELECT u.name, o.total FROM users u JOIN orders AS o ON o.user_id = u.id").unwrap();
+        assert_eq!(result.columns, vec!["u.name", "o.total"]);
+        assert_eq!(rows(&result), vec![vec!["John", "100"], vec!["John", "50"], vec!["Mike", "70"]]);
+    }
+
+    #[test]
+    fn test_outer_joins() {
+        let db = setup();
+        let left = query(&db, "SELECT u.name, o.oid FROM users u LEFT JOIN orders o ON u.id = o.user_id WHERE o.oid IS NULL").unwrap();
+        assert_eq!(rows(&left), vec![vec!["Anna", "NULL"]]);
+        let right = query(&db, "SELECT u.name, o.oid FROM users u RIGHT JOIN orders o ON u.id = o.user_id WHERE u.id IS NULL").unwrap();
+        assert_eq!(rows(&right), vec![vec!["NULL", "13"]]);
+        let full = query(&db, "SELECT COUNT(*) FROM users u FULL OUTER JOIN orders o ON u.id = o.user_id").unwrap();
+        assert_eq!(rows(&full), vec![vec!["5"]]);
+    }
+
+    #[test]
+    fn test_cross_join() {
+        let db = setup();
+        assert_eq!(rows(&query(&db, "SELECT COUNT(*) FROM users CROSS JOIN orders").unwrap()), vec![vec!["12"]]);
+        assert_eq!(rows(&query(&db, "SELECT COUNT(*) FROM users, orders").unwrap()), vec![vec!["12"]]);
+    }
+
+    #[test]
+    fn test_strategy_selection() {
+        let db = setup();
+        let users = db.get_table("users").unwrap();
+        let orders = db.get_table("orders").unwrap();
+        let strategy_for = |sql: &str, left_table: &Table, right_table: &Table| {
+            let Query::Select(SelectStatement { body: SetExpr::Select(select), .. }) = parse_query(sql).unwrap() else {
+                panic!()
+            };
+            let left = table_columns(left_table, &select.from);
+            let right = table_columns(right_table, &select.joins[0].table);
+            choose_strategy(&select.joins[0], &left, &right, Some(right_table))
+        };
+        // 結合先の主キーとの等値条件なら B+Tree を使う
+        let s = strategy_for("SELECT * FROM orders o JOIN users u ON o.user_id = u.id", orders, users);
+        assert!(matches!(s, JoinStrategy::IndexNestedLoop { .. }));
+        // 主キー以外の等値条件はハッシュ結合
+        let s = strategy_for("SELECT * FROM users u JOIN orders o ON o.user_id = u.id", users, orders);
+        assert!(matches!(s, JoinStrategy::HashJoin { .. }));
+        // RIGHT JOIN では B+Tree 探索を使わない
+        let s = strategy_for("SELECT * FROM orders o RIGHT JOIN users u ON o.user_id = u.id", orders, users);
+        assert!(matches!(s, JoinStrategy::HashJoin { .. }));
+        let s = strategy_for("SELECT * FROM users u JOIN orders o ON o.total > u.id", users, orders);
+        assert_eq!(s, JoinStrategy::NestedLoop);
+    }
+
+    #[test]
+    fn test_ambiguous_column() {
+        let mut db = setup();
+        db.execute("CREATE TABLE archive (oid, note)").unwrap();
+        let err = query(&db, "SELECT oid FROM orders JOIN archive ON orders.oid = archive.oid").unwrap_err();
+        assert!(err.to_string().contains("ambiguous"));
+    }
+}
@@ -0,0 +1,335 @@
+pub mod aggregate;
+pub mod catalog;
+pub mod cost;
+pub mod cte;
+pub mod delete;
+pub mod eval;
+pub mod foreign_key;
+pub mod function;
+pub mod insert;
+pub mod join;
+pub mod operator;
+pub mod order;
+pub mod plan;
+pub mod set_operation;
+pub mod update;
+pub mod window;
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::parser::ast::{BinaryOperator, Expr, Select, SelectItem, SelectStatement, SetExpr, TableRef};
+use crate::table::Table;
+use crate::value::Value;
+use eval::{check_columns, eval, Scope};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// SELECT の結果。カラム名と行の一覧。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultSet {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// 実行中の中間結果のカラム。`table` はテーブル名またはエイリアス（`u.id` の `u`）。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnInfo {
+    pub table: Option<String>,
+    pub name: String,
+}
+
+impl ColumnInfo {
+    /// `table.name` 形式の参照がこのカラムを指すかどうか
+    pub fn matches(&self, table: Option<&str>, name: &str) -> bool {
+        self.name == name && table.is_none_or(|t| self.table.as_deref() == Some(t))
+    }
+}
+
+/// カラム情報付きの行の集まり。FROM 句・JOIN の結果を表す。
+#[derive(Debug, Clone)]
+pub struct Relation {
+    pub columns: Vec<ColumnInfo>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// 1 つの SELECT を実行するための環境。
+/// サブクエリの実行時は `outer` に外側のクエリの現在行が入る。
+#[derive(Clone)]
+pub struct ExecContext<'a> {
+    pub db: &'a Database,
+    pub outer: Option<&'a Scope<'a>>,
+    /// 相関のないサブクエリの結果。キーはサブクエリの AST のアドレス。
+    pub cache: &'a RefCell<HashMap<usize, Rc<ResultSet>>>,
+    /// WITH 句で定義された名前と、その結果。同名のテーブルより優先される。
+    pub ctes: HashMap<String, Rc<ResultSet>>,
+    /// プリペアドステートメントにバインドされた値。`$1` が先頭。
+    pub params: &'a [Value],
+}
+
+/// SELECT 文を実行する。
+pub fn execute_select(db: &Database, statement: &SelectStatement, params: &[Value]) -> Result<ResultSet, DbError> {
+    let cache = RefCell::new(HashMap::new());
+    let ctx = ExecContext { db, outer: None, cache: &cache, ctes: HashMap::new(), params };
+    run_query(&ctx, statement)
+}
+
+/// SELECT 文を実行せずに計画だけを立て、出力カラム名を返す（ビューの定義の確認）。
+pub fn output_names(db: &Database, statement: &SelectStatement) -> Result<Vec<String>, DbError> {
+    let cache = RefCell::new(HashMap::new());
+    let ctx = ExecContext { db, outer: None, cache: &cache, ctes: HashMap::new(), params: &[] };
+    Ok(plan::build(&ctx, statement)?.column_names())
+}
+
+/// `EXPLAIN [ANALYZE]` を実行し、実行計画を 1 行ずつ "QUERY PLAN" カラムに入れて返す。
+pub fn explain(db: &Database, statement: &SelectStatement, analyze: bool, params: &[Value]) -> Result<ResultSet, DbError> {
+    let cache = RefCell::new(HashMap::new());
+    let ctx = ExecContext { db, outer: None, cache: &cache, ctes: HashMap::new(), params };
+    let lines = plan::explain(&ctx, statement, analyze)?;
+    Ok(ResultSet { columns: vec!["QUERY PLAN".to_string()], rows: lines.into_iter().map(|l| vec![Value::Text(l)]).collect() })
+}
+
+/// SELECT 文の実行計画だけを立てる。計画はバインドする値によらないので、プリペアドステートメントが使い回せる。
+pub fn plan_select(db: &Database, statement: &SelectStatement) -> Result<plan::Plan, DbError> {
+    let cache = RefCell::new(HashMap::new());
+    let ctx = ExecContext { db, outer: None, cache: &cache, ctes: HashMap::new(), params: &[] };
+    plan::build(&ctx, statement)
+}
+
+/// `plan_select` で立てた計画を実行する。
+pub fn execute_plan(db: &Database, plan: &plan::Plan, params: &[Value]) -> Result<ResultSet, DbError> {
+    let cache = RefCell::new(HashMap::new());
+    let ctx = ExecContext { db, outer: None, cache: &cache, ctes: HashMap::new(), params };
+    let rows = plan::execute(&ctx, plan)?;
+    Ok(ResultSet { columns: plan.column_names(), rows })
+}
+
+/// SELECT 文（サブクエリを含む）の実行計画を立てて実行する。
+pub fn run_query(ctx: &ExecContext, statement: &SelectStatement) -> Result<ResultSet, DbError> {
+    let plan = plan::build(ctx, statement)?;
+    let rows = plan::execute(ctx, &plan)?;
+    Ok(ResultSet { columns: plan.column_names(), rows })
+}
+
+/// UNION などの集合演算（ORDER BY・LIMIT を含まない部分）の実行計画を立てて実行する。
+pub fn run_set_expr(ctx: &ExecContext, body: &SetExpr) -> Result<ResultSet, DbError> {
+    let plan = plan::build_set_expr(ctx, body)?;
+    let rows = plan::execute(ctx, &plan)?;
+    Ok(ResultSet { columns: plan.column_names(), rows })
+}
+
+/// SELECT 句（とウィンドウ関数）を各行に適用する。集約のない SELECT ブロックの最後の段階。
+/// `extra` は ORDER BY などのために SELECT 句の後ろへ追加で評価する式で、結果の末尾のカラムになる。
+pub fn project_rows(
+    ctx: &ExecContext,
+    columns: &[ColumnInfo],
+    rows: &[Vec<Value>],
+    select: &Select,
+    extra: &[Expr],
+) -> Result<Vec<Vec<Value>>, DbError> {
+    let windows = window::collect(select, extra);
+    let scopes: Vec<Scope> = rows.iter().map(|row| Scope::new(ctx, columns, row)).collect();
+    let window_values = window::compute(&windows, &scopes)?;
+    let mut out = Vec::with_capacity(rows.len());
+    for (scope, values) in scopes.into_iter().zip(&window_values) {
+        out.push(project(&scope.with_windows(&windows, values), &select.select_columns, extra)?);
+    }
+    Ok(out)
+}
+
+/// SELECT 句（と追加の式）を 1 行分評価して出力行を作る。
+pub fn project(scope: &Scope, items: &[SelectItem], extra: &[Expr]) -> Result<Vec<Value>, DbError> {
+    let mut out = Vec::new();
+    for item in items {
+        match item {
+            SelectItem::Wildcard => out.extend(scope.row.iter().cloned()),
+            SelectItem::QualifiedWildcard(q) => {
+                for (i, col) in scope.columns.iter().enumerate() {
+                    if col.table.as_deref() == Some(q.as_str()) {
+                        out.push(scope.row[i].clone());
+                    }
+                }
+            }
+            SelectItem::Expr { expr, .. } => out.push(eval(expr, scope)?),
+        }
+    }
+    for expr in extra {
+        out.push(eval(expr, scope)?);
+    }
+    Ok(out)
+}
+
+/// RETURNING 句のカラム参照を、テーブルを書き換える前に確認する。
+pub fn check_returning(db: &Database, table_ref: &TableRef, items: &[SelectItem]) -> Result<(), DbError> {
+    let cache = RefCell::new(HashMap::new());
+    let ctx = ExecContext { db, outer: None, cache: &cache, ctes: HashMap::new(), params: &[] };
+    let columns = table_columns(lookup_table(db, table_ref.name())?, table_ref);
+    for item in items {
+        if let SelectItem::Expr { expr, .. } = item {
+            check_columns(expr, &columns, &ctx)?;
+        }
+    }
+    output_columns(&columns, items, &[]).map(|_| ())
+}
+
+/// INSERT / UPDATE / DELETE の RETURNING 句を、書き込んだ（削除した）行に対して評価する。
+pub fn returning(
+    db: &Database,
+    table_ref: &TableRef,
+    rows: &[Vec<Value>],
+    items: &[SelectItem],
+    params: &[Value],
+) -> Result<ResultSet, DbError> {
+    let cache = RefCell::new(HashMap::new());
+    let ctx = ExecContext { db, outer: None, cache: &cache, ctes: HashMap::new(), params };
+    let columns = table_columns(lookup_table(db, table_ref.name())?, table_ref);
+    let mut result = ResultSet { columns: output_columns(&columns, items, &[])?, rows: Vec::new() };
+    for row in rows {
+        result.rows.push(project(&Scope::new(&ctx, &columns, row), items, &[])?);
+    }
+    Ok(result)
+}
+
+/// SELECT 句・WHERE 句・GROUP BY 句・HAVING 句（と追加の式）のカラム参照を、行を読む前に確認する。
+pub fn check_references(ctx: &ExecContext, select: &Select, extra: &[Expr], columns: &[ColumnInfo]) -> Result<(), DbError> {
+    for item in &select.select_columns {
+        if let SelectItem::Expr { expr: e, .. } = item {
+            check_columns(e, columns, ctx)?;
+        }
+    }
+    let clauses = select.condition.iter().chain(&select.group_by).chain(&select.having).chain(extra);
+    for e in clauses {
+        check_columns(e, columns, ctx)?;
+    }
+    Ok(())
+}
+
+pub fn lookup_table<'a>(db: &'a Database, name: &str) -> Result<&'a Table, DbError> {
+    db.get_table(name).ok_or_else(|| DbError::UndefinedTable(name.to_string()))
+}
+
+/// 出力カラム名。`*` は全カラムに展開し、式はエイリアスかその SQL 表記を使う。
+pub fn output_columns(columns: &[ColumnInfo], items: &[SelectItem], extra: &[Expr]) -> Result<Vec<String>, DbError> {
+    let mut names = Vec::new();
+    for item in items {
+        match item {
+            SelectItem::Wildcard => names.extend(columns.iter().map(|c| c.name.clone())),
+            SelectItem::QualifiedWildcard(q) => {
+                let before = names.len();
+                names.extend(columns.iter().filter(|c| c.table.as_deref() == Some(q.as_str())).map(|c| c.name.clone()));
+                if names.len() == before {
+                    return Err(DbError::InvalidQuery(format!("Table '{}' is not in the FROM clause.", q)));
+                }
+            }
+            SelectItem::Expr { expr, alias } => names.push(alias.clone().unwrap_or_else(|| expr.to_string())),
+        }
+    }
+    names.extend(extra.iter().map(|e| e.to_string()));
+    Ok(names)
+}
+
+/// テーブルのカラムを、エイリアス（なければテーブル名）で修飾したカラム情報にする。
+pub fn table_columns(table: &Table, table_ref: &TableRef) -> Vec<ColumnInfo> {
+    qualify(&table.columns, table_ref.qualifier())
+}
+
+pub fn qualify(names: &[String], qualifier: &str) -> Vec<ColumnInfo> {
+    names
+        .iter()
+        .map(|c| ColumnInfo { table: Some(qualifier.to_string()), name: c.clone() })
+        .collect()
+}
+
+/// テーブルを読み、WHERE 句を満たす行を取り出す（UPDATE / DELETE の対象行の検索）。
+/// 条件に「主キー = 定数」が含まれていれば B+Tree の search で 1 行に絞り込み、それ以外は全件走査してフィルタリングする。
+pub fn scan(ctx: &ExecContext, table_ref: &TableRef, condition: Option<&Expr>) -> Result<Relation, DbError> {
+    let plan = plan::build_scan(ctx, table_ref, condition)?;
+    let rows = plan::execute(ctx, &plan)?;
+    Ok(Relation { columns: plan.columns, rows })
+}
+
+/// AND で結ばれた条件の中から「主キー = 定数」を探し、定数側の式を返す。
+/// 定数はカラムを参照しない式で、プリペアドステートメントのパラメータも含む。
+pub fn primary_key_lookup<'a>(table: &Table, table_ref: &TableRef, condition: &'a Expr) -> Option<&'a Expr> {
+    let Expr::Binary { left, op, right } = condition else {
+        return None;
+    };
+    match op {
+        BinaryOperator::And => primary_key_lookup(table, table_ref, left)
+            .or_else(|| primary_key_lookup(table, table_ref, right)),
+        BinaryOperator::Eq => match (left.as_ref(), right.as_ref()) {
+            (column, key) | (key, column) if is_primary_key(table, table_ref, column) && is_constant(key) => Some(key),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// 行によらず値が決まる式かどうか。カラム参照・集約・ウィンドウ関数・サブクエリを含まず、NULL リテラルでもない。
+fn is_constant(expr: &Expr) -> bool {
+    let mut constant = !matches!(expr, Expr::Literal(Value::Null));
+    expr.walk(&mut |e| {
+        constant &= !matches!(
+            e,
+            Expr::Column { .. }
+                | Expr::Aggregate { .. }
+                | Expr::Window { .. }
+                | Expr::Subquery(_)
+                | Expr::Exists(_)
+                | Expr::InSubquery { .. }
+        )
+    });
+    constant
+}
+
+/// 式がこのテーブルの主キーカラムへの参照かどうか
+pub fn is_primary_key(table: &Table, table_ref: &TableRef, expr: &Expr) -> bool {
+    match expr {
+        Expr::Column { table: qualifier, name } => {
+            Some(name.as_str()) == table.primary_key()
+                && qualifier.as_deref().is_none_or(|q| q == table_ref.qualifier())
+        }
+        _ => false,
+    }
+}
+
+/// executor のテストで共通に使う、データベースの準備と結果の取り出し。
+#[cfg(test)]
+pub(crate) mod test_util {
+    use super::ResultSet;
+    use crate::database::{Database, QueryResult};
+    use crate::error::DbError;
+    use crate::parser::parse_query;
+
+    /// SQL 文を順に実行して作ったデータベース。
+    pub fn database(statements: &[&str]) -> Database {
+        let mut db = Database::new();
+        for sql in statements {
+            db.execute(sql).unwrap();
+        }
+        db
+    }
+
+    /// 書き込む文を実行する。RETURNING の結果と行数も返す。
+    pub fn execute(db: &mut Database, sql: &str) -> Result<QueryResult, DbError> {
+        db.execute_query(&parse_query(sql)?)
+    }
+
+    /// `Database::query` で SELECT / EXPLAIN などを実行し、結果をカラム名と値の表にする。
+    pub fn query(db: &Database, sql: &str) -> Result<ResultSet, DbError> {
+        let rows = db.query(sql)?;
+        Ok(ResultSet { columns: rows.columns().to_vec(), rows: rows.iter().map(|row| row.values().to_vec()).collect() })
+    }
+
+    /// 結果の値を文字列にする。
+    pub fn rows(result: &ResultSet) -> Vec<Vec<String>> {
+        result.rows.iter().map(|r| r.iter().map(|v| v.to_string()).collect()).collect()
+    }
+
+    /// `EXPLAIN [ANALYZE]` の実行計画の各行。
+    pub fn explain(db: &Database, sql: &str) -> Vec<String> {
+        let result = query(db, sql).unwrap();
+        assert_eq!(result.columns, vec!["QUERY PLAN"]);
+        result.rows.into_iter().map(|r| r[0].to_string()).collect()
+    }
+}
@@ -0,0 +1,477 @@
+use crate::error::DbError;
+use crate::executor::eval::{eval, Scope};
+use crate::executor::join::{JoinStrategy, Probe};
+use crate::executor::plan::{Node, Plan};
+use crate::executor::{
+    aggregate, cte, lookup_table, order, project, project_rows, set_operation, window, ColumnInfo, ExecContext, Relation,
+    ResultSet,
+};
+use crate::parser::ast::{Expr, Join, Select};
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// 行を 1 行ずつ返す演算子（Volcano モデル）。上の演算子が `next` を呼ぶたびに、必要な分だけ下の演算子から行を読む。
+/// 走査・絞り込み・射影・LIMIT は行を流すだけでためず、並べ替えや集約のように全行が必要な演算子だけが入力を読み切る。
+pub trait Operator {
+    /// 次の行を返す。行がなくなれば None。
+    fn next(&mut self) -> Result<Option<Vec<Value>>, DbError>;
+}
+
+type BoxedOperator<'a> = Box<dyn Operator + 'a>;
+
+/// EXPLAIN ANALYZE で記録する、ノードごとの実行結果。`time` は `next` の中で過ごした時間で、下の演算子の分を含む。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NodeStats {
+    pub rows: usize,
+    pub loops: usize,
+    pub time: Duration,
+}
+
+/// ノードごとの実行結果。キーはノードのアドレス。
+pub type Stats = RefCell<HashMap<usize, NodeStats>>;
+
+/// 実行計画を実行して、出力行をすべて返す。
+pub fn execute(ctx: &ExecContext, plan: &Plan, stats: Option<&Stats>) -> Result<Vec<Vec<Value>>, DbError> {
+    drain(open(ctx, plan, stats)?.as_mut())
+}
+
+fn drain(input: &mut dyn Operator) -> Result<Vec<Vec<Value>>, DbError> {
+    let mut rows = Vec::new();
+    while let Some(row) = input.next()? {
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// 実行計画のノードから演算子の木を作る。まだ行は読まない。
+/// `stats` があれば、各演算子が返した行数と所要時間を記録する演算子を重ねる。
+pub fn open<'a>(ctx: &'a ExecContext<'a>, plan: &'a Plan, stats: Option<&'a Stats>) -> Result<BoxedOperator<'a>, DbError> {
+    let operator = open_node(ctx, plan, stats)?;
+    Ok(match stats {
+        Some(stats) => Box::new(Instrumented { input: operator, stats, key: plan as *const Plan as usize, started: false }),
+        None => operator,
+    })
+}
+
+fn open_node<'a>(ctx: &'a ExecContext<'a>, plan: &'a Plan, stats: Option<&'a Stats>) -> Result<BoxedOperator<'a>, DbError> {
+    Ok(match &plan.node {
+        Node::SeqScan { table_ref } => {
+            let rows = lookup_table(ctx.db, table_ref.name())?.rows();
+            Box::new(SeqScan { ctx, columns: &plan.columns, rows: Box::new(rows), predicate: None })
+        }
+        // 全件走査の直後の絞り込みは、カーソルが指す行のまま条件を評価し、満たす行だけを複製する。
+        // EXPLAIN ANALYZE では走査と絞り込みの行数を別々に数えるため、まとめない
+        Node::Filter { input, predicate } if stats.is_none() && matches!(input.node, Node::SeqScan { .. }) => {
+            let Node::SeqScan { table_ref } = &input.node else {
+                unreachable!("checked above");
+            };
+            let rows = lookup_table(ctx.db, table_ref.name())?.rows();
+            Box::new(SeqScan { ctx, columns: &input.columns, rows: Box::new(rows), predicate: Some(predicate) })
+        }
+        Node::IndexSeek { table_ref, key, .. } => {
+            let table = lookup_table(ctx.db, table_ref.name())?;
+            Box::new(Blocking::new(move || {
+                let key = eval(key, &Scope::new(ctx, &[], &[]))?;
+                Ok(if key.is_null() { Vec::new() } else { table.find_by_key(&key).cloned().into_iter().collect() })
+            }))
+        }
+        Node::IndexLookup { .. } => unreachable!("index lookups are probed by their join"),
+        Node::IndexAggregate { table_ref, select } => {
+            let table = lookup_table(ctx.db, table_ref.name())?;
+            Box::new(Blocking::new(move || Ok(aggregate::from_index(ctx, table, select)?.rows)))
+        }
+        Node::CteScan { table_ref } => match ctx.ctes.get(table_ref.name()) {
+            Some(result) => Box::new(CteScan { result: Rc::clone(result), pos: 0 }),
+            None => return Err(DbError::UndefinedTable(table_ref.name().to_string())),
+        },
+        Node::CatalogScan { table, .. } => {
+            let table = *table;
+            Box::new(Blocking::new(move || Ok(table.rows(ctx.db))))
+        }
+        Node::SubqueryScan { input, .. } => open(ctx, input, stats)?,
+        Node::Filter { input, predicate } => Box::new(Filter { ctx, columns: &input.columns, input: open(ctx, input, stats)?, predicate }),
+        Node::Join { left, right, join, strategy } => Box::new(JoinOperator {
+            ctx,
+            left: open(ctx, left, stats)?,
+            left_columns: &left.columns,
+            right,
+            columns: &plan.columns,
+            join,
+            strategy,
+            stats,
+            probe: None,
+            buffer: VecDeque::new(),
+            done: false,
+        }),
+        Node::Aggregate { input, select, extra } => {
+            let mut rows = open(ctx, input, stats)?;
+            Box::new(Blocking::new(move || {
+                let rows = drain(rows.as_mut())?;
+                Ok(aggregate::execute(ctx, &input.columns, rows, select, extra)?.rows)
+            }))
+        }
+        // ウィンドウ関数は同じパーティションの行がすべて揃ってから計算する
+        Node::Project { input, select, extra } if !window::collect(select, extra).is_empty() => {
+            let mut rows = open(ctx, input, stats)?;
+            Box::new(Blocking::new(move || project_rows(ctx, &input.columns, &drain(rows.as_mut())?, select, extra)))
+        }
+        Node::Project { input, select, extra } => {
+            Box::new(Project { ctx, columns: &input.columns, input: open(ctx, input, stats)?, select, extra })
+        }
+        Node::Sort { input, keys, order_by, tie_break } => {
+            let mut rows = open(ctx, input, stats)?;
+            Box::new(Blocking::new(move || {
+                let mut rows = drain(rows.as_mut())?;
+                order::sort_rows(&mut rows, keys, order_by, tie_break.clone());
+                Ok(rows)
+            }))
+        }
+        Node::Distinct { input, key, sorted, .. } => {
+            Box::new(Distinct { input: open(ctx, input, stats)?, key: key.clone(), sorted: *sorted, previous: None, seen: HashSet::new() })
+        }
+        Node::Columns { input, positions } => Box::new(Columns { input: open(ctx, input, stats)?, positions }),
+        Node::Limit { input, limit, offset } => Box::new(Limit {
+            ctx,
+            input: open(ctx, input, stats)?,
+            limit: limit.as_ref(),
+            offset: offset.as_ref(),
+            started: false,
+            remaining: None,
+        }),
+        Node::SetOperation { left, right, op, all } => {
+            let (mut left_rows, mut right_rows) = (open(ctx, left, stats)?, open(ctx, right, stats)?);
+            Box::new(Blocking::new(move || {
+                let l = ResultSet { columns: left.column_names(), rows: drain(left_rows.as_mut())? };
+                let r = ResultSet { columns: right.column_names(), rows: drain(right_rows.as_mut())? };
+                Ok(set_operation::execute(l, *op, *all, r)?.rows)
+            }))
+        }
+        // CTE の結果は本体を実行する間ずっと参照されるので、本体まで含めてここで実行する
+        Node::With { ctes, input } => Box::new(Blocking::new(move || {
+            // 後の CTE からは前の CTE を参照できる
+            let mut bound = ctx.clone();
+            for (c, plan) in ctes {
+                let result = match plan {
+                    Some(plan) => {
+                        let rows = execute(&bound, plan, stats)?;
+                        ResultSet { columns: cte::column_names(c, &plan.column_names())?, rows }
+                    }
+                    None => cte::execute_recursive(&bound, c)?,
+                };
+                bound.ctes.insert(c.name.clone(), Rc::new(result));
+            }
+            execute(&bound, input, stats)
+        })),
+    })
+}
+
+/// 下の演算子が返した行数と、`next` の中で過ごした時間を数える（EXPLAIN ANALYZE）。一度も `next` を呼ばれなければ記録しない。
+struct Instrumented<'a> {
+    input: BoxedOperator<'a>,
+    stats: &'a Stats,
+    key: usize,
+    started: bool,
+}
+
+impl Operator for Instrumented<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, DbError> {
+        let start = Instant::now();
+        let row = self.input.next()?;
+        let mut stats = self.stats.borrow_mut();
+        let entry = stats.entry(self.key).or_default();
+        if !self.started {
+            self.started = true;
+            entry.loops += 1;
+        }
+        entry.rows += usize::from(row.is_some());
+        entry.time += start.elapsed();
+        Ok(row)
+    }
+}
+
+/// 全行をまとめて求める処理
+type Source<'a> = Box<dyn FnOnce() -> Result<Vec<Vec<Value>>, DbError> + 'a>;
+
+/// 最初の `next` で `source` を実行して全行を求め、それを 1 行ずつ返す。入力を読み切る必要のある演算子に使う。
+struct Blocking<'a> {
+    source: Option<Source<'a>>,
+    rows: std::vec::IntoIter<Vec<Value>>,
+}
+
+impl<'a> Blocking<'a> {
+    fn new(source: impl FnOnce() -> Result<Vec<Vec<Value>>, DbError> + 'a) -> Self {
+        Blocking { source: Some(Box::new(source)), rows: Vec::new().into_iter() }
+    }
+}
+
+impl Operator for Blocking<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, DbError> {
+        if let Some(source) = self.source.take() {
+            self.rows = source()?.into_iter();
+        }
+        Ok(self.rows.next())
+    }
+}
+
+/// テーブルの全件走査。B+Tree の葉を辿るカーソルから 1 行ずつ読み、`predicate` を満たす行だけを複製して返す。
+struct SeqScan<'a> {
+    ctx: &'a ExecContext<'a>,
+    columns: &'a [ColumnInfo],
+    rows: Box<dyn Iterator<Item = &'a Vec<Value>> + 'a>,
+    predicate: Option<&'a Expr>,
+}
+
+impl Operator for SeqScan<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, DbError> {
+        for row in self.rows.by_ref() {
+            match self.predicate {
+                Some(p) if !eval(p, &Scope::new(self.ctx, self.columns, row))?.is_truthy() => {}
+                _ => return Ok(Some(row.clone())),
+            }
+        }
+        Ok(None)
+    }
+}
+
+struct CteScan {
+    result: Rc<ResultSet>,
+    pos: usize,
+}
+
+impl Operator for CteScan {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, DbError> {
+        let row = self.result.rows.get(self.pos).cloned();
+        self.pos += 1;
+        Ok(row)
+    }
+}
+
+struct Filter<'a> {
+    ctx: &'a ExecContext<'a>,
+    columns: &'a [ColumnInfo],
+    input: BoxedOperator<'a>,
+    predicate: &'a Expr,
+}
+
+impl Operator for Filter<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, DbError> {
+        while let Some(row) = self.input.next()? {
+            if eval(self.predicate, &Scope::new(self.ctx, self.columns, &row))?.is_truthy() {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// 結合。最初の `next` で右側を準備し（Nested Loop とハッシュ結合では右の行を読み切る）、左の行を 1 行ずつ流して探索する。
+/// Index Nested Loop では右テーブルを走査せず、左の行ごとに B+Tree を引く。
+struct JoinOperator<'a> {
+    ctx: &'a ExecContext<'a>,
+    left: BoxedOperator<'a>,
+    left_columns: &'a [ColumnInfo],
+    right: &'a Plan,
+    columns: &'a [ColumnInfo],
+    join: &'a Join,
+    strategy: &'a JoinStrategy,
+    stats: Option<&'a Stats>,
+    probe: Option<Probe<'a>>,
+    /// 左の 1 行から作った、まだ返していない行
+    buffer: VecDeque<Vec<Value>>,
+    done: bool,
+}
+
+impl<'a> JoinOperator<'a> {
+    fn build(&self) -> Result<Probe<'a>, DbError> {
+        let (ctx, join, columns, left_columns) = (self.ctx, self.join, self.columns, self.left_columns);
+        let scan_right = || -> Result<Relation, DbError> {
+            let rows = execute(ctx, self.right, self.stats)?;
+            Ok(Relation { columns: self.right.columns.clone(), rows })
+        };
+        match (self.strategy, &self.right.node) {
+            (JoinStrategy::IndexNestedLoop { left_key }, Node::IndexLookup { table_ref, .. }) => {
+                // B+Tree を引いた回数と見つかった行数は、右側のノードの実行結果として記録する
+                let stats = self.stats.map(|stats| (stats, self.right as *const Plan as usize));
+                Ok(Probe::index(join, columns, left_columns, lookup_table(ctx.db, table_ref.name())?, left_key, stats))
+            }
+            (JoinStrategy::HashJoin { left_keys, right_keys }, _) => {
+                Probe::hash(ctx, join, columns, left_columns, scan_right()?, left_keys, right_keys)
+            }
+            _ => Ok(Probe::nested_loop(join, columns, left_columns, scan_right()?)),
+        }
+    }
+}
+
+impl Operator for JoinOperator<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, DbError> {
+        loop {
+            if let Some(row) = self.buffer.pop_front() {
+                return Ok(Some(row));
+            }
+            if self.done {
+                return Ok(None);
+            }
+            if self.probe.is_none() {
+                self.probe = Some(self.build()?);
+            }
+            let probe = self.probe.as_mut().expect("built above");
+            match self.left.next()? {
+                Some(l) => probe.probe(self.ctx, &l, &mut self.buffer)?,
+                None => {
+                    self.done = true;
+                    self.buffer.extend(self.probe.take().expect("built above").unmatched());
+                }
+            }
+        }
+    }
+}
+
+/// ウィンドウ関数のない SELECT 句の射影
+struct Project<'a> {
+    ctx: &'a ExecContext<'a>,
+    columns: &'a [ColumnInfo],
+    input: BoxedOperator<'a>,
+    select: &'a Select,
+    extra: &'a [Expr],
+}
+
+impl Operator for Project<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, DbError> {
+        match self.input.next()? {
+            Some(row) => Ok(Some(project(&Scope::new(self.ctx, self.columns, &row), &self.select.select_columns, self.extra)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// `key` の範囲の値が等しい行を取り除く。並べ替え済みなら直前の行とだけ比べ、そうでなければ見た値をハッシュ集合に覚える。
+struct Distinct<'a> {
+    input: BoxedOperator<'a>,
+    key: Range<usize>,
+    sorted: bool,
+    previous: Option<Vec<Value>>,
+    seen: HashSet<Vec<Value>>,
+}
+
+impl Operator for Distinct<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, DbError> {
+        while let Some(row) = self.input.next()? {
+            let key = row[self.key.clone()].to_vec();
+            let new = if self.sorted {
+                self.previous.as_ref() != Some(&key) && {
+                    self.previous = Some(key);
+                    true
+                }
+            } else {
+                self.seen.insert(key)
+            };
+            if new {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+}
+
+struct Columns<'a> {
+    input: BoxedOperator<'a>,
+    positions: &'a [usize],
+}
+
+impl Operator for Columns<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, DbError> {
+        Ok(self.input.next()?.map(|row| self.positions.iter().map(|&i| row[i].clone()).collect()))
+    }
+}
+
+/// LIMIT / OFFSET。OFFSET の行を読み飛ばし、LIMIT の行数を返したら下の演算子をそれ以上読まない。
+struct Limit<'a> {
+    ctx: &'a ExecContext<'a>,
+    input: BoxedOperator<'a>,
+    limit: Option<&'a Expr>,
+    offset: Option<&'a Expr>,
+    started: bool,
+    /// あと何行返せるか。LIMIT がなければ None
+    remaining: Option<usize>,
+}
+
+impl Operator for Limit<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Value>>, DbError> {
+        if !self.started {
+            self.started = true;
+            let offset = order::row_count(self.ctx, self.offset, "OFFSET")?.unwrap_or(0);
+            self.remaining = order::row_count(self.ctx, self.limit, "LIMIT")?;
+            if self.remaining != Some(0) {
+                for _ in 0..offset {
+                    if self.input.next()?.is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+        match &mut self.remaining {
+            Some(0) => return Ok(None),
+            Some(n) => *n -= 1,
+            None => {}
+        }
+        self.input.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::executor::plan;
+    use crate::executor::test_util::{database, explain};
+    use crate::parser::{parse_query, Query};
+
+    fn setup() -> Database {
+        let values: Vec<String> = (1..=1000).map(|id| format!("({}, {})", id, id % 7)).collect();
+        database(&["CREATE TABLE t (id, grp)", &format!("INSERT INTO t VALUES {}", values.join(", "))])
+    }
+
+    #[test]
+    fn test_operators_pull_rows_on_demand() {
+        let db = setup();
+        let Query::Select(statement) = parse_query("SELECT id FROM t WHERE grp = 3").unwrap() else {
+            panic!("not a select");
+        };
+        let cache = RefCell::new(HashMap::new());
+        let ctx = ExecContext { db: &db, outer: None, cache: &cache, ctes: HashMap::new(), params: &[] };
+        let plan = plan::build(&ctx, &statement).unwrap();
+        let mut rows = open(&ctx, &plan, None).unwrap();
+        assert_eq!(rows.next().unwrap(), Some(vec![Value::Integer(3)]));
+        assert_eq!(rows.next().unwrap(), Some(vec![Value::Integer(10)]));
+        assert_eq!(drain(rows.as_mut()).unwrap().len(), 141);
+        assert_eq!(rows.next().unwrap(), None);
+    }
+
+    #[test]
+    fn test_limit_stops_reading() {
+        let db = setup();
+        // 条件を満たす 5 行目までしか走査しない
+        let plan = explain(&db, "EXPLAIN ANALYZE SELECT id FROM t WHERE id % 2 = 0 LIMIT 3 OFFSET 2");
+        assert!(plan[0].starts_with("Limit (LIMIT 3 OFFSET 2) (actual rows=3 "));
+        assert!(plan.iter().any(|l| l.contains("Seq Scan on t (actual rows=10 ")));
+        // 結合でも左の行は必要な分だけ読む
+        let plan = explain(&db, "EXPLAIN ANALYZE SELECT * FROM t a JOIN t b ON a.grp = b.grp LIMIT 2");
+        assert!(plan.iter().any(|l| l.contains("Seq Scan on t a (actual rows=1 ")));
+        assert!(plan.iter().any(|l| l.contains("Seq Scan on t b (actual rows=1000 ")));
+        // LIMIT 0 では何も読まない
+        let plan = explain(&db, "EXPLAIN ANALYZE SELECT id FROM t LIMIT 0");
+        assert!(plan[2].ends_with("Seq Scan on t (never executed)"));
+    }
+
+    #[test]
+    fn test_streaming_distinct() {
+        let db = setup();
+        let plan = explain(&db, "EXPLAIN ANALYZE SELECT DISTINCT grp FROM t LIMIT 3");
+        assert!(plan[0].starts_with("Limit (LIMIT 3) (actual rows=3 "));
+        assert!(plan.iter().any(|l| l.contains("Seq Scan on t (actual rows=3 ")));
+    }
+}
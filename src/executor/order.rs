@@ -0,0 +1,223 @@
+use crate::error::DbError;
+use crate::executor::aggregate::same_expr;
+use crate::executor::eval::{eval, Scope};
+use crate::executor::ExecContext;
+use crate::parser::ast::{Distinct, Expr, OrderByExpr, Select, SelectItem};
+use crate::value::Value;
+use std::cmp::Ordering;
+use std::ops::Range;
+
+/// ORDER BY のキーが指す値の位置
+pub enum SortKey {
+    /// 出力カラム（0 始まり）
+    Output(usize),
+    /// SELECT 句の後ろに追加で評価した式（0 始まり）
+    Extra(usize),
+}
+
+/// SELECT ブロックの ORDER BY と DISTINCT ON のために、SELECT 句の後ろへ追加で評価する式と ORDER BY の各キーを求める。
+/// 出力カラムにない ORDER BY の式が先に、DISTINCT ON の式がその後に並ぶ。追加したカラムは並べ替えと重複除去の後に取り除く。
+pub fn sort_keys(select: &Select, order_by: &[OrderByExpr]) -> Result<(Vec<Expr>, Vec<SortKey>), DbError> {
+    let mut extra = Vec::new();
+    let mut keys = Vec::new();
+    for ob in order_by {
+        match output_position(select, &ob.expr)? {
+            Some(position) => keys.push(SortKey::Output(position)),
+            None => {
+                if select.distinct == Some(Distinct::Row) && !in_wildcard(select, &ob.expr) {
+                    return Err(DbError::InvalidQuery(format!(
+                        "For SELECT DISTINCT, ORDER BY expression '{}' must appear in the select list.",
+                        ob.expr
+                    )));
+                }
+                extra.push(ob.expr.clone());
+                keys.push(SortKey::Extra(extra.len() - 1));
+            }
+        }
+    }
+    if let Some(Distinct::On(exprs)) = &select.distinct {
+        check_distinct_on(exprs, order_by)?;
+        extra.extend(exprs.iter().cloned());
+    }
+    Ok((extra, keys))
+}
+
+/// ソートキーを、追加の式まで含めた行の中の位置に直す。`visible` は SELECT 句の出力カラム数。
+pub fn resolve_keys(keys: &[SortKey], visible: usize) -> Result<Vec<usize>, DbError> {
+    keys.iter()
+        .map(|key| match *key {
+            SortKey::Output(p) if p < visible => Ok(p),
+            SortKey::Output(p) => Err(DbError::InvalidQuery(format!("ORDER BY position {} is not in the select list.", p + 1))),
+            SortKey::Extra(i) => Ok(visible + i),
+        })
+        .collect()
+}
+
+/// UNION などの結果に対する ORDER BY のキーの位置を求める。キーは出力カラムの位置か名前でなければならない。
+pub fn result_keys(columns: &[String], order_by: &[OrderByExpr]) -> Result<Vec<usize>, DbError> {
+    let mut keys = Vec::new();
+    for ob in order_by {
+        let position = match &ob.expr {
+            Expr::Literal(Value::Integer(n)) => (*n >= 1 && *n as usize <= columns.len()).then(|| *n as usize - 1),
+            expr => {
+                let name = expr.to_string();
+                let mut found = columns.iter().enumerate().filter(|(_, c)| **c == name).map(|(i, _)| i);
+                match (found.next(), found.next()) {
+                    (Some(i), None) => Some(i),
+                    _ => None,
+                }
+            }
+        };
+        keys.push(position.ok_or_else(|| {
+            DbError::InvalidQuery(format!("ORDER BY term '{}' does not match any column in the result of the set operation.", ob.expr))
+        })?);
+    }
+    Ok(keys)
+}
+
+/// LIMIT / OFFSET の行数を評価する。NULL は指定なしと同じで、負の数や整数でない値はエラー。
+pub fn row_count(ctx: &ExecContext, expr: Option<&Expr>, clause: &str) -> Result<Option<usize>, DbError> {
+    let Some(expr) = expr else {
+        return Ok(None);
+    };
+    match eval(expr, &Scope::new(ctx, &[], &[]))?.numeric_affinity() {
+        Value::Null => Ok(None),
+        Value::Integer(n) if n >= 0 => Ok(Some(n as usize)),
+        other => Err(DbError::InvalidValue(format!("{} must be a non-negative integer, found '{}'.", clause, other))),
+    }
+}
+
+/// ORDER BY の式が SELECT 句のどの出力カラムを指すか。`ORDER BY 2` のような位置指定、
+/// エイリアス、SELECT 句と同じ式の場合に位置を返す。`*` より後ろの項目は位置が決まらないので None。
+fn output_position(select: &Select, expr: &Expr) -> Result<Option<usize>, DbError> {
+    if let Expr::Literal(Value::Integer(n)) = expr {
+        if *n < 1 {
+            return Err(DbError::InvalidQuery(format!("ORDER BY position {} is not in the select list.", n)));
+        }
+        return Ok(Some(*n as usize - 1));
+    }
+    for (i, item) in select.select_columns.iter().enumerate() {
+        match item {
+            SelectItem::Wildcard | SelectItem::QualifiedWildcard(_) => return Ok(None),
+            SelectItem::Expr { expr: e, alias } => {
+                let is_alias = matches!(expr, Expr::Column { table: None, name } if alias.as_ref() == Some(name));
+                if is_alias || same_expr(e, expr) {
+                    return Ok(Some(i));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// `*` で出力されるカラムへの参照かどうか
+fn in_wildcard(select: &Select, expr: &Expr) -> bool {
+    matches!(expr, Expr::Column { .. })
+        && select.select_columns.iter().any(|item| !matches!(item, SelectItem::Expr { .. }))
+}
+
+/// DISTINCT ON と ORDER BY を併用する場合、ORDER BY の先頭の式は DISTINCT ON の式と一致しなければならない。
+fn check_distinct_on(exprs: &[Expr], order_by: &[OrderByExpr]) -> Result<(), DbError> {
+    if order_by.is_empty() {
+        return Ok(());
+    }
+    let leading = order_by.iter().take(exprs.len());
+    if order_by.len() < exprs.len() || !leading.into_iter().all(|ob| exprs.iter().any(|e| same_expr(e, &ob.expr))) {
+        return Err(DbError::InvalidQuery("SELECT DISTINCT ON expressions must match the initial ORDER BY expressions.".into()));
+    }
+    Ok(())
+}
+
+/// `keys` の位置の値で行を並べ替える。同順位の行は `tie_break` の範囲の値で比べる。
+pub fn sort_rows(rows: &mut [Vec<Value>], keys: &[usize], order_by: &[OrderByExpr], tie_break: Range<usize>) {
+    rows.sort_by(|a, b| {
+        keys.iter()
+            .zip(order_by)
+            .map(|(&k, ob)| compare(&a[k], &b[k], ob))
+            .chain(tie_break.clone().map(|i| a[i].cmp(&b[i])))
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+/// ORDER BY の 1 項目に従って 2 つの値を比べる（ASC / DESC と NULLS FIRST / LAST を考慮する）。
+pub fn compare(a: &Value, b: &Value, ob: &OrderByExpr) -> Ordering {
+    match (a.is_null(), b.is_null()) {
+        (true, true) => Ordering::Equal,
+        (true, false) if ob.nulls_first() => Ordering::Less,
+        (true, false) => Ordering::Greater,
+        (false, true) if ob.nulls_first() => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        _ if ob.descending => b.cmp(a),
+        _ => a.cmp(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::executor::test_util::{database, query, rows};
+
+    fn setup() -> Database {
+        database(&[
+            "CREATE TABLE emp (id, dept, name, salary)",
+            "INSERT INTO emp VALUES (1, 'dev', 'John', 300), (2, 'ops', 'Mike', 200), (3, 'dev', 'Anna', 500), (4, 'ops', 'Kate', 200), (5, 'hr', 'Tom', 100)",
+        ])
+    }
+
+    #[test]
+    fn test_distinct() {
+        let db = setup();
+        let result = query(&db, "SELECT DISTINCT dept FROM emp").unwrap();
+        assert_eq!(rows(&result), vec![vec!["dev"], vec!["ops"], vec!["hr"]]);
+        let result = query(&db, "SELECT DISTINCT dept, salary FROM emp ORDER BY dept DESC").unwrap();
+        assert_eq!(
+            rows(&result),
+            vec![vec!["ops", "200"], vec!["hr", "100"], vec!["dev", "300"], vec!["dev", "500"]]
+        );
+        let err = query(&db, "SELECT DISTINCT dept FROM emp ORDER BY salary").unwrap_err();
+        assert!(err.to_string().contains("must appear in the select list"));
+    }
+
+    #[test]
+    fn test_distinct_on() {
+        let db = setup();
+        // 部署ごとに給与が最も高い社員
+        let result = query(&db, "SELECT DISTINCT ON (dept) dept, name FROM emp ORDER BY dept, salary DESC").unwrap();
+        assert_eq!(result.columns, vec!["dept", "name"]);
+        assert_eq!(rows(&result), vec![vec!["dev", "Anna"], vec!["hr", "Tom"], vec!["ops", "Mike"]]);
+        // ORDER BY がなければ最初に現れた行を残す
+        let result = query(&db, "SELECT DISTINCT ON (salary) name FROM emp").unwrap();
+        assert_eq!(rows(&result), vec![vec!["John"], vec!["Mike"], vec!["Anna"], vec!["Tom"]]);
+        let err = query(&db, "SELECT DISTINCT ON (dept) name FROM emp ORDER BY salary").unwrap_err();
+        assert!(err.to_string().contains("DISTINCT ON"));
+    }
+
+    #[test]
+    fn test_order_by_and_limit() {
+        let db = setup();
+        let result = query(&db, "SELECT name, salary * 2 AS double FROM emp ORDER BY double DESC, 1 LIMIT 3").unwrap();
+        assert_eq!(rows(&result), vec![vec!["Anna", "1000"], vec!["John", "600"], vec!["Kate", "400"]]);
+        // SELECT 句にないカラムや集約でも並べ替えられる
+        let result = query(&db, "SELECT name FROM emp ORDER BY salary, id DESC LIMIT 2 OFFSET 1").unwrap();
+        assert_eq!(rows(&result), vec![vec!["Kate"], vec!["Mike"]]);
+        let result = query(&db, "SELECT dept FROM emp GROUP BY dept ORDER BY SUM(salary) DESC").unwrap();
+        assert_eq!(rows(&result), vec![vec!["dev"], vec!["ops"], vec!["hr"]]);
+        let result = query(&db, "SELECT * FROM emp ORDER BY name LIMIT 1").unwrap();
+        assert_eq!(rows(&result), vec![vec!["3", "dev", "Anna", "500"]]);
+        assert!(query(&db, "SELECT name FROM emp LIMIT -1").is_err());
+    }
+
+    #[test]
+    fn test_order_by_on_set_operation() {
+        let db = setup();
+        let result = query(
+            &db,
+            "SELECT name FROM emp WHERE dept = 'dev' UNION SELECT name FROM emp WHERE dept = 'hr' ORDER BY name LIMIT 2",
+        )
+        .unwrap();
+        assert_eq!(rows(&result), vec![vec!["Anna"], vec!["John"]]);
+        let err = query(&db, "SELECT name FROM emp UNION SELECT name FROM emp ORDER BY salary").unwrap_err();
+        assert!(err.to_string().contains("does not match"));
+    }
+}
@@ -0,0 +1,1007 @@
+use crate::error::DbError;
+use crate::executor::eval::{eval, resolve_column, Scope};
+use crate::executor::catalog::CatalogTable;
+use crate::executor::cost::{self, Estimate};
+use crate::executor::join::{self, JoinStrategy};
+use crate::executor::operator::{self, NodeStats};
+use crate::executor::order;
+use crate::executor::{
+    aggregate, check_references, cte, lookup_table, output_columns, primary_key_lookup, qualify, table_columns, ColumnInfo,
+    ExecContext,
+};
+use crate::parser::ast::{
+    BinaryOperator, Cte, Distinct, Expr, Join, JoinKind, OrderByExpr, Select, SelectItem, SelectStatement, SetExpr,
+    SetOperator, TableRef,
+};
+use crate::stats::ColumnStats;
+use crate::table::Table;
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// コストで結合順を選ぶ FROM 句の要素数の上限。組み合わせは要素数の 2 乗 × 2 の要素数乗で増える
+const MAX_REORDERED_TABLES: usize = 8;
+
+/// 実行計画の 1 ノードと、そのノードが出力する行のカラム。
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub node: Node,
+    pub columns: Vec<ColumnInfo>,
+}
+
+/// 実行計画のノード（物理演算子）。子ノードの出力を受け取って行を返す。
+#[derive(Debug, Clone)]
+pub enum Node {
+    /// テーブルの全件走査
+    SeqScan { table_ref: TableRef },
+    /// 「主キー = 定数」の行を B+Tree の search で取り出す。`key` はカラムを参照しない式
+    IndexSeek { table_ref: TableRef, column: String, key: Expr },
+    /// Index Nested Loop の右側。テーブルを走査せず、左の行ごとに `key` の値で主キーの B+Tree を search する
+    IndexLookup { table_ref: TableRef, column: String, key: Expr },
+    /// `SELECT COUNT(*) FROM t` や `MIN/MAX(主キー)` を値を走査せず B+Tree から求める
+    IndexAggregate { table_ref: TableRef, select: Box<Select> },
+    /// WITH 句で定義された結果の走査
+    CteScan { table_ref: TableRef },
+    /// `information_schema` の仮想テーブルの走査
+    CatalogScan { table_ref: TableRef, table: CatalogTable },
+    /// FROM 句の導出テーブル `(SELECT ...) AS t`
+    SubqueryScan { alias: String, input: Box<Plan> },
+    Filter { input: Box<Plan>, predicate: Expr },
+    Join { left: Box<Plan>, right: Box<Plan>, join: Join, strategy: JoinStrategy },
+    /// GROUP BY・集約・HAVING と SELECT 句の射影（ハッシュ集約）
+    Aggregate { input: Box<Plan>, select: Box<Select>, extra: Vec<Expr> },
+    /// SELECT 句の射影。ウィンドウ関数もここで計算する
+    Project { input: Box<Plan>, select: Box<Select>, extra: Vec<Expr> },
+    Sort { input: Box<Plan>, keys: Vec<usize>, order_by: Vec<OrderByExpr>, tie_break: Range<usize> },
+    /// `key` の範囲の値が等しい行を取り除く。`on` は DISTINCT ON の式（表示用）
+    Distinct { input: Box<Plan>, key: Range<usize>, sorted: bool, on: Vec<Expr> },
+    /// 入力のカラムを位置で選んで並べ直す。ORDER BY・DISTINCT ON のために追加した末尾のカラムを取り除くのと、
+    /// 結合順を変えたときに FROM 句の順へ戻すのに使う
+    Columns { input: Box<Plan>, positions: Vec<usize> },
+    Limit { input: Box<Plan>, limit: Option<Expr>, offset: Option<Expr> },
+    SetOperation { left: Box<Plan>, right: Box<Plan>, op: SetOperator, all: bool },
+    /// WITH 句。CTE を先頭から順に実行してから `input` を実行する。再帰 CTE の計画は None
+    With { ctes: Vec<(Cte, Option<Plan>)>, input: Box<Plan> },
+}
+
+impl Plan {
+    pub fn column_names(&self) -> Vec<String> {
+        self.columns.iter().map(|c| c.name.clone()).collect()
+    }
+
+    fn new(node: Node, columns: Vec<ColumnInfo>) -> Self {
+        Plan { node, columns }
+    }
+
+    /// 条件で絞り込むノードを上に重ねる。
+    fn filter(self, predicate: Option<Expr>) -> Self {
+        match predicate {
+            Some(predicate) => {
+                let columns = self.columns.clone();
+                Plan::new(Node::Filter { input: Box::new(self), predicate }, columns)
+            }
+            None => self,
+        }
+    }
+}
+
+/// SELECT 文の実行計画を立てる。
+pub fn build(ctx: &ExecContext, statement: &SelectStatement) -> Result<Plan, DbError> {
+    Planner::new(ctx).statement(statement)
+}
+
+/// UNION などの集合演算（ORDER BY・LIMIT を含まない部分）の実行計画を立てる。
+pub fn build_set_expr(ctx: &ExecContext, body: &SetExpr) -> Result<Plan, DbError> {
+    Planner::new(ctx).set_expr(body)
+}
+
+/// テーブルから WHERE 句を満たす行を取り出す計画を立てる（UPDATE / DELETE の対象行の検索）。
+pub fn build_scan(ctx: &ExecContext, table_ref: &TableRef, condition: Option<&Expr>) -> Result<Plan, DbError> {
+    let planner = Planner::new(ctx);
+    let leaf = planner.table_ref(table_ref)?;
+    let conjuncts = condition.map(|c| planner.fold_predicate(c.clone())).unwrap_or_default();
+    planner.access(leaf, conjuncts)
+}
+
+/// 出力カラム名を、テーブル名で修飾しないカラム情報にする。
+fn unqualified(names: Vec<String>) -> Vec<ColumnInfo> {
+    names.into_iter().map(|name| ColumnInfo { table: None, name }).collect()
+}
+
+/// 計画を立てるための環境。`ctes` は参照できる CTE の名前とそのカラム名で、
+/// 外側のクエリで束縛済みの CTE と、計画中の WITH 句で定義した CTE を含む。
+struct Planner<'a> {
+    ctx: &'a ExecContext<'a>,
+    ctes: HashMap<String, Vec<String>>,
+}
+
+impl<'a> Planner<'a> {
+    fn new(ctx: &'a ExecContext<'a>) -> Self {
+        let ctes = ctx.ctes.iter().map(|(name, result)| (name.clone(), result.columns.clone())).collect();
+        Planner { ctx, ctes }
+    }
+
+    /// WITH 句 → 本体（SELECT ブロックか集合演算）→ ORDER BY → LIMIT / OFFSET の順に計画を組み立てる。
+    fn statement(&self, statement: &SelectStatement) -> Result<Plan, DbError> {
+        // WITH 句で定義した名前はこの文の中だけで参照できる
+        let mut planner = Planner { ctx: self.ctx, ctes: self.ctes.clone() };
+        let mut ctes = Vec::new();
+        for (recursive, c) in statement.with.iter().flat_map(|with| with.ctes.iter().map(move |c| (with.recursive, c))) {
+            let (plan, names) = match recursive {
+                true if cte::references_statement(&c.query, &c.name) => {
+                    // 再帰 CTE は実行時に繰り返し計画を立てるので、ここではカラムを決めるために非再帰項だけを計画する
+                    let (anchor, _, _) = cte::recursive_terms(c)?;
+                    let anchor = SelectStatement {
+                        with: c.query.with.clone(),
+                        body: anchor.clone(),
+                        order_by: Vec::new(),
+                        limit: None,
+                        offset: None,
+                    };
+                    (None, cte::column_names(c, &planner.statement(&anchor)?.column_names())?)
+                }
+                _ => {
+                    let plan = planner.statement(&c.query)?;
+                    let names = cte::column_names(c, &plan.column_names())?;
+                    (Some(plan), names)
+                }
+            };
+            planner.ctes.insert(c.name.clone(), names);
+            ctes.push((c.clone(), plan));
+        }
+
+        let mut plan = match &statement.body {
+            SetExpr::Select(select) => planner.block(select, &statement.order_by)?,
+            body => {
+                let plan = planner.set_expr(body)?;
+                if statement.order_by.is_empty() {
+                    plan
+                } else {
+                    let keys = order::result_keys(&plan.column_names(), &statement.order_by)?;
+                    let columns = plan.columns.clone();
+                    let node = Node::Sort { input: Box::new(plan), keys, order_by: statement.order_by.clone(), tie_break: 0..0 };
+                    Plan::new(node, columns)
+                }
+            }
+        };
+        if statement.limit.is_some() || statement.offset.is_some() {
+            let columns = plan.columns.clone();
+            let node = Node::Limit { input: Box::new(plan), limit: statement.limit.clone(), offset: statement.offset.clone() };
+            plan = Plan::new(node, columns);
+        }
+        if !ctes.is_empty() {
+            let columns = plan.columns.clone();
+            plan = Plan::new(Node::With { ctes, input: Box::new(plan) }, columns);
+        }
+        Ok(plan)
+    }
+
+    fn set_expr(&self, body: &SetExpr) -> Result<Plan, DbError> {
+        match body {
+            SetExpr::Select(select) => self.block(select, &[]),
+            SetExpr::SetOperation { left, op, all, right } => {
+                let left = self.set_expr(left)?;
+                let right = self.set_expr(right)?;
+                // カラム名は左側の結果のものを使う
+                let columns = left.columns.clone();
+                let node = Node::SetOperation { left: Box::new(left), right: Box::new(right), op: *op, all: *all };
+                Ok(Plan::new(node, columns))
+            }
+        }
+    }
+
+    /// SELECT ブロックに DISTINCT / DISTINCT ON と ORDER BY を重ねる。
+    /// 出力カラムにない ORDER BY の式や DISTINCT ON の式は SELECT 句の後ろへ追加で評価し、最後に取り除く。
+    /// ORDER BY がある場合は並べ替えた後に隣り合う重複を除き（ソートベース）、ない場合はハッシュで除く。
+    fn block(&self, select: &Select, order_by: &[OrderByExpr]) -> Result<Plan, DbError> {
+        if order_by.is_empty() && select.distinct.is_none() {
+            return self.select(select, &[]);
+        }
+        let (extra, keys) = order::sort_keys(select, order_by)?;
+        let mut plan = self.select(select, &extra)?;
+        let visible = plan.columns.len() - extra.len();
+        let keys = order::resolve_keys(&keys, visible)?;
+        let columns = plan.columns.clone();
+
+        let sorted = !order_by.is_empty();
+        if sorted {
+            // DISTINCT では、重複行が隣り合うよう出力カラム全体を最後のキーにする
+            let tie_break = if select.distinct == Some(Distinct::Row) { 0..visible } else { 0..0 };
+            let node = Node::Sort { input: Box::new(plan), keys, order_by: order_by.to_vec(), tie_break };
+            plan = Plan::new(node, columns.clone());
+        }
+        let distinct = match &select.distinct {
+            Some(Distinct::Row) => Some((0..visible, Vec::new())),
+            Some(Distinct::On(exprs)) => {
+                let start = columns.len() - exprs.len();
+                Some((start..columns.len(), exprs.clone()))
+            }
+            None => None,
+        };
+        if let Some((key, on)) = distinct {
+            plan = Plan::new(Node::Distinct { input: Box::new(plan), key, sorted, on }, columns.clone());
+        }
+        if !extra.is_empty() {
+            let node = Node::Columns { input: Box::new(plan), positions: (0..visible).collect() };
+            plan = Plan::new(node, columns[..visible].to_vec());
+        }
+        Ok(plan)
+    }
+
+    /// 1 つの SELECT ブロックの計画を立てる。
+    /// FROM / JOIN → WHERE → GROUP BY / 集約 → HAVING → SELECT 句の射影 の順に処理する。
+    /// WHERE 句は AND で分けて、1 つのテーブルだけを参照する条件をそのテーブルの走査まで押し下げる（述語の押し下げ）。
+    fn select(&self, select: &Select, extra: &[Expr]) -> Result<Plan, DbError> {
+        let conjuncts = select.condition.clone().map(|c| self.fold_predicate(c)).unwrap_or_default();
+        let is_aggregate = aggregate::is_aggregate_query(select, extra);
+        if let (Some(table), true) = (self.base_table(&select.from)?, select.joins.is_empty()) {
+            if is_aggregate && extra.is_empty() && conjuncts.is_empty() && aggregate::uses_index(table, select) {
+                let columns = output_columns(&table_columns(table, &select.from), &select.select_columns, &[])?;
+                let node = Node::IndexAggregate { table_ref: select.from.clone(), select: Box::new(select.clone()) };
+                return Ok(Plan::new(node, unqualified(columns)));
+            }
+        }
+
+        let mut leaves = vec![self.table_ref(&select.from)?];
+        for j in &select.joins {
+            leaves.push(self.table_ref(&j.table)?);
+        }
+        let all_columns: Vec<ColumnInfo> = leaves.iter().flat_map(|l| l.columns.iter().cloned()).collect();
+        check_references(self.ctx, select, extra, &all_columns)?;
+
+        let plan = match self.costed_leaves(&leaves, &select.joins, &all_columns) {
+            Some(costed) => self.join_by_cost(leaves, costed, &select.joins, conjuncts, &all_columns)?,
+            None => self.join_in_order(leaves, &select.joins, conjuncts, &all_columns)?,
+        };
+
+        let columns = unqualified(output_columns(&plan.columns, &select.select_columns, extra)?);
+        let (input, select, extra) = (Box::new(plan), Box::new(select.clone()), extra.to_vec());
+        let node = if is_aggregate { Node::Aggregate { input, select, extra } } else { Node::Project { input, select, extra } };
+        Ok(Plan::new(node, columns))
+    }
+
+    /// FROM 句の要素を書かれた順に結合する（規則による計画）。
+    /// WHERE 句の条件のうち 1 つの要素だけを参照するものはその走査へ押し下げ、残りは結合の後で評価する。
+    fn join_in_order(&self, leaves: Vec<Plan>, joins: &[Join], conjuncts: Vec<Expr>, all_columns: &[ColumnInfo]) -> Result<Plan, DbError> {
+        let mut pushed = vec![Vec::new(); leaves.len()];
+        let mut remaining = Vec::new();
+        for conjunct in conjuncts {
+            match push_target(&conjunct, &leaves, all_columns, joins) {
+                Some(i) => pushed[i].push(conjunct),
+                None => remaining.push(conjunct),
+            }
+        }
+
+        let mut pushed = pushed.into_iter();
+        let mut leaves = leaves.into_iter();
+        let mut plan = self.access(leaves.next().expect("FROM clause"), pushed.next().unwrap_or_default())?;
+        for ((leaf, conjuncts), j) in leaves.zip(pushed).zip(joins) {
+            let mut join = j.clone();
+            join.constraint = join.constraint.map(|c| and_all(self.fold_predicate(c)).unwrap_or(Expr::Literal(Value::Boolean(true))));
+            let table = self.base_table(&join.table)?;
+            let strategy = join::choose_strategy(&join, &plan.columns, &leaf.columns, table);
+            // Index Nested Loop は右テーブルを走査せず B+Tree を引くので、右側の条件は結合の後で確かめる
+            let right = match (&strategy, table) {
+                (JoinStrategy::IndexNestedLoop { left_key }, Some(table)) => {
+                    remaining.extend(conjuncts);
+                    index_lookup(leaf, table, left_key)
+                }
+                _ => self.access(leaf, conjuncts)?,
+            };
+            let mut columns = plan.columns.clone();
+            columns.extend(right.columns.iter().cloned());
+            plan = Plan::new(Node::Join { left: Box::new(plan), right: Box::new(right), join, strategy }, columns);
+        }
+        Ok(plan.filter(and_all(remaining)))
+    }
+
+    /// コストで結合順と実行方式を選べるなら、FROM 句の各要素のテーブルと統計情報を返す。
+    /// 要素がすべて ANALYZE 済みの実テーブルで、結合が INNER / CROSS JOIN だけ、各 ON 句がその JOIN までの要素だけを
+    /// 参照している場合に限る。それ以外は `join_in_order` で書かれた順に結合する。
+    fn costed_leaves(&self, leaves: &[Plan], joins: &[Join], all_columns: &[ColumnInfo]) -> Option<Vec<CostedLeaf<'a>>> {
+        if leaves.len() < 2 || leaves.len() > MAX_REORDERED_TABLES {
+            return None;
+        }
+        let mut visible = leaves[0].columns.len();
+        for (j, leaf) in joins.iter().zip(&leaves[1..]) {
+            visible += leaf.columns.len();
+            if !matches!(j.kind, JoinKind::Inner | JoinKind::Cross) {
+                return None;
+            }
+            let mut in_scope = true;
+            if let Some(c) = &j.constraint {
+                c.walk(&mut |e| {
+                    if let Expr::Column { table, name } = e {
+                        in_scope &= resolve_column(&all_columns[..visible], table.as_deref(), name).is_ok();
+                    }
+                });
+            }
+            if !in_scope {
+                return None;
+            }
+        }
+        leaves
+            .iter()
+            .map(|leaf| {
+                let Node::SeqScan { table_ref } = &leaf.node else {
+                    return None;
+                };
+                let table = self.ctx.db.get_table(table_ref.name())?;
+                let stats = self.ctx.db.table_stats(table_ref.name())?;
+                let stats = leaf.columns.iter().map(|c| stats.column(&c.name)).collect();
+                Some(CostedLeaf { table, stats })
+            })
+            .collect()
+    }
+
+    /// INNER / CROSS JOIN だけの FROM 句について、見積もったコストが最小になる結合順と実行方式を選ぶ。
+    /// 左深木（結合の右側は常に 1 つのテーブル）に限り、結合済みのテーブルの組み合わせごとに最小コストの順序を
+    /// 動的計画法で求める。ON 句と WHERE 句の条件はまとめて扱い、1 つのテーブルだけを参照する条件はその走査へ、
+    /// 複数のテーブルを参照する条件はそれらがすべて揃う結合へ付ける。
+    fn join_by_cost(
+        &self,
+        leaves: Vec<Plan>,
+        costed: Vec<CostedLeaf>,
+        joins: &[Join],
+        mut conjuncts: Vec<Expr>,
+        all_columns: &[ColumnInfo],
+    ) -> Result<Plan, DbError> {
+        for j in joins {
+            conjuncts.extend(j.constraint.clone().map(|c| self.fold_predicate(c)).unwrap_or_default());
+        }
+        let mut pushed = vec![Vec::new(); leaves.len()];
+        let mut spanning = Vec::new();
+        let mut remaining = Vec::new();
+        for conjunct in conjuncts {
+            match referenced_leaves(&conjunct, &leaves, all_columns).as_deref() {
+                Some(&[i]) => pushed[i].push(conjunct),
+                Some(found) if !found.is_empty() => spanning.push((found.iter().map(|i| 1 << i).sum::<usize>(), conjunct)),
+                _ => remaining.push(conjunct),
+            }
+        }
+
+        // 各テーブルを単独で読むときの計画と見積もり
+        let mut accessed = Vec::new();
+        let mut estimates = Vec::new();
+        for (i, leaf) in leaves.iter().enumerate() {
+            let plan = self.access(leaf.clone(), pushed[i].clone())?;
+            let index_seek = matches!(&plan.node, Node::Filter { input, .. } if matches!(input.node, Node::IndexSeek { .. }));
+            let selectivity = and_all(pushed[i].clone()).map_or(1.0, |c| cost::selectivity(&c, &leaf.columns, &costed[i].stats));
+            estimates.push(cost::scan(costed[i].table.row_count() as f64, index_seek, selectivity));
+            accessed.push(plan);
+        }
+
+        // best[set] は set のテーブルを結合する最小コストの順序。部分集合は常に set より小さい番号になる
+        let mut best: Vec<Option<JoinPath>> = vec![None; 1 << leaves.len()];
+        for (i, estimate) in estimates.iter().enumerate() {
+            best[1 << i] = Some(JoinPath { estimate: *estimate, first: i, steps: Vec::new() });
+        }
+        for set in 1..best.len() {
+            let Some(path) = best[set].clone() else {
+                continue;
+            };
+            let order: Vec<usize> = std::iter::once(path.first).chain(path.steps.iter().map(|s| s.0)).collect();
+            let left_columns: Vec<ColumnInfo> = order.iter().flat_map(|&i| leaves[i].columns.iter().cloned()).collect();
+            let left_stats: Vec<_> = order.iter().flat_map(|&i| costed[i].stats.iter().copied()).collect();
+            for j in (0..leaves.len()).filter(|j| set & (1 << j) == 0) {
+                let next = set | 1 << j;
+                let attached: Vec<Expr> =
+                    spanning.iter().filter(|(m, _)| m & next == *m && m & set != *m).map(|(_, c)| c.clone()).collect();
+                let constraint = and_all(attached);
+                let table_ref = match &leaves[j].node {
+                    Node::SeqScan { table_ref } => table_ref.clone(),
+                    _ => unreachable!("costed leaves are table scans"),
+                };
+                let kind = if constraint.is_some() { JoinKind::Inner } else { JoinKind::Cross };
+                let join = Join { kind, table: table_ref, constraint };
+                let columns: Vec<ColumnInfo> = left_columns.iter().chain(&leaves[j].columns).cloned().collect();
+                let stats: Vec<_> = left_stats.iter().chain(&costed[j].stats).copied().collect();
+                let selectivity = join.constraint.as_ref().map_or(1.0, |c| cost::selectivity(c, &columns, &stats));
+                let table_rows = costed[j].table.row_count() as f64;
+                for strategy in join::candidates(&join, &left_columns, &leaves[j].columns, Some(costed[j].table)) {
+                    let estimate = cost::join(&strategy, kind, path.estimate, estimates[j], table_rows, selectivity);
+                    if best[next].as_ref().is_none_or(|b| estimate.cost < b.estimate.cost) {
+                        let mut steps = path.steps.clone();
+                        steps.push((j, join.clone(), strategy));
+                        best[next] = Some(JoinPath { estimate, first: path.first, steps });
+                    }
+                }
+            }
+        }
+
+        let path = best.pop().flatten().expect("every table is joined");
+        let mut plan = accessed[path.first].clone();
+        let mut order = vec![path.first];
+        for (j, join, strategy) in path.steps {
+            // Index Nested Loop は右テーブルを走査せず B+Tree を引くので、右側の条件は結合の後で確かめる
+            let index_nested_loop = matches!(strategy, JoinStrategy::IndexNestedLoop { .. });
+            let right = match &strategy {
+                JoinStrategy::IndexNestedLoop { left_key } => index_lookup(leaves[j].clone(), costed[j].table, left_key),
+                _ => accessed[j].clone(),
+            };
+            let columns: Vec<ColumnInfo> = plan.columns.iter().chain(&right.columns).cloned().collect();
+            plan = Plan::new(Node::Join { left: Box::new(plan), right: Box::new(right), join, strategy }, columns);
+            if index_nested_loop {
+                plan = plan.filter(and_all(pushed[j].clone()));
+            }
+            order.push(j);
+        }
+        let plan = plan.filter(and_all(remaining));
+        if order.iter().enumerate().all(|(pos, &i)| pos == i) {
+            return Ok(plan);
+        }
+        // 結合した順に並んだカラムを、SELECT * などのために FROM 句の順へ戻す
+        let mut offsets = vec![0; leaves.len()];
+        let mut offset = 0;
+        for &i in &order {
+            offsets[i] = offset;
+            offset += leaves[i].columns.len();
+        }
+        let positions = leaves.iter().enumerate().flat_map(|(i, leaf)| {
+            let offset = offsets[i];
+            (0..leaf.columns.len()).map(move |k| offset + k)
+        });
+        let node = Node::Columns { input: Box::new(plan), positions: positions.collect() };
+        Ok(Plan::new(node, all_columns.to_vec()))
+    }
+
+    /// FROM 句の 1 要素を読む葉のノード。
+    fn table_ref(&self, table_ref: &TableRef) -> Result<Plan, DbError> {
+        match table_ref {
+            TableRef::Table { name, .. } if self.ctes.contains_key(name) => {
+                let columns = qualify(&self.ctes[name], table_ref.qualifier());
+                Ok(Plan::new(Node::CteScan { table_ref: table_ref.clone() }, columns))
+            }
+            TableRef::Table { name, .. } if CatalogTable::lookup(name).is_some() => {
+                let table = CatalogTable::lookup(name).unwrap();
+                let columns = qualify(&table.columns(), table_ref.qualifier());
+                Ok(Plan::new(Node::CatalogScan { table_ref: table_ref.clone(), table }, columns))
+            }
+            // マテリアライズドでないビューは、定義の SELECT 文を導出テーブルと同じように展開する。
+            // 定義は外側の WITH 句の CTE を参照しないので、CTE なしで計画を立てる。
+            TableRef::Table { name, .. } if self.ctx.db.get_view(name).is_some_and(|v| !v.materialized) => {
+                let view = self.ctx.db.get_view(name).unwrap();
+                let planner = Planner { ctx: self.ctx, ctes: HashMap::new() };
+                let input = planner.statement(&view.statement()?)?;
+                let names = if view.columns.is_empty() { input.column_names() } else { view.columns.clone() };
+                let alias = table_ref.qualifier().to_string();
+                let columns = qualify(&names, &alias);
+                Ok(Plan::new(Node::SubqueryScan { alias, input: Box::new(input) }, columns))
+            }
+            TableRef::Table { name, .. } => {
+                let columns = table_columns(lookup_table(self.ctx.db, name)?, table_ref);
+                Ok(Plan::new(Node::SeqScan { table_ref: table_ref.clone() }, columns))
+            }
+            TableRef::Derived { subquery, alias } => {
+                let input = self.statement(subquery)?;
+                let columns = qualify(&input.column_names(), alias);
+                Ok(Plan::new(Node::SubqueryScan { alias: alias.clone(), input: Box::new(input) }, columns))
+            }
+        }
+    }
+
+    /// 葉のノードに押し下げた条件を適用する。テーブルの走査で条件に「主キー = 定数」が含まれていれば、
+    /// 全件走査の代わりに B+Tree の search で 1 行に絞り込む。条件全体は取り出した行に対しても確かめる。
+    fn access(&self, leaf: Plan, conjuncts: Vec<Expr>) -> Result<Plan, DbError> {
+        let Some(predicate) = and_all(conjuncts) else {
+            return Ok(leaf);
+        };
+        let leaf = match &leaf.node {
+            Node::SeqScan { table_ref } => {
+                let table = lookup_table(self.ctx.db, table_ref.name())?;
+                match (primary_key_lookup(table, table_ref, &predicate), table.primary_key()) {
+                    (Some(key), Some(column)) => {
+                        let node = Node::IndexSeek { table_ref: table_ref.clone(), column: column.to_string(), key: key.clone() };
+                        Plan::new(node, leaf.columns)
+                    }
+                    _ => leaf,
+                }
+            }
+            _ => leaf,
+        };
+        Ok(leaf.filter(Some(predicate)))
+    }
+
+    /// FROM 句の要素が実テーブルならそのテーブルを返す。CTE の名前やビュー、仮想テーブル、導出テーブルの場合は None。
+    fn base_table(&self, table_ref: &TableRef) -> Result<Option<&'a Table>, DbError> {
+        match table_ref {
+            TableRef::Table { name, .. } if self.ctx.db.get_view(name).is_some_and(|v| !v.materialized) => Ok(None),
+            TableRef::Table { name, .. } if CatalogTable::lookup(name).is_some() => Ok(None),
+            TableRef::Table { name, .. } if !self.ctes.contains_key(name) => lookup_table(self.ctx.db, name).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// WHERE / ON 句を AND で分け、各条件を定数畳み込みする。常に真になった条件は取り除く。
+    fn fold_predicate(&self, predicate: Expr) -> Vec<Expr> {
+        let mut conjuncts = Vec::new();
+        split_conjuncts(predicate, &mut conjuncts);
+        conjuncts
+            .into_iter()
+            .map(|c| self.fold(c))
+            .filter(|c| *c != Expr::Literal(Value::Boolean(true)))
+            .collect()
+    }
+
+    /// カラム・パラメータ・サブクエリを参照しない部分式を、計画時に評価してリテラルにする（定数畳み込み）。
+    /// パラメータは実行ごとに値が変わるので残す。評価がエラーになる式（`1 / 0` など）も、
+    /// 実際に評価されるまでエラーにしないよう実行時まで残す。
+    fn fold(&self, mut expr: Expr) -> Expr {
+        for child in expr.children_mut() {
+            *child = self.fold(std::mem::replace(child, Expr::Literal(Value::Null)));
+        }
+        let foldable = matches!(
+            expr,
+            Expr::Unary { .. }
+                | Expr::Binary { .. }
+                | Expr::IsNull { .. }
+                | Expr::InList { .. }
+                | Expr::Function { .. }
+                | Expr::Case { .. }
+                | Expr::Cast { .. }
+        );
+        if !foldable || !expr.children().iter().all(|c| matches!(c, Expr::Literal(_))) {
+            return expr;
+        }
+        match eval(&expr, &Scope::new(self.ctx, &[], &[])) {
+            Ok(value) => Expr::Literal(value),
+            Err(_) => expr,
+        }
+    }
+}
+
+/// Index Nested Loop の右側のテーブルの走査を、左の行から求めた `left_key` による主キーの検索に置き換える。
+fn index_lookup(leaf: Plan, table: &Table, left_key: &Expr) -> Plan {
+    let Node::SeqScan { table_ref } = leaf.node else {
+        unreachable!("index nested loop joins a base table");
+    };
+    let column = table.primary_key().unwrap_or_default().to_string();
+    Plan::new(Node::IndexLookup { table_ref, column, key: left_key.clone() }, leaf.columns)
+}
+
+fn split_conjuncts(expr: Expr, out: &mut Vec<Expr>) {
+    match expr {
+        Expr::Binary { left, op: BinaryOperator::And, right } => {
+            split_conjuncts(*left, out);
+            split_conjuncts(*right, out);
+        }
+        expr => out.push(expr),
+    }
+}
+
+fn and_all(conjuncts: Vec<Expr>) -> Option<Expr> {
+    conjuncts
+        .into_iter()
+        .reduce(|left, right| Expr::Binary { left: Box::new(left), op: BinaryOperator::And, right: Box::new(right) })
+}
+
+/// 条件が参照する FROM 句の要素の位置を昇順で返す。カラムを参照しない条件では空になる。
+/// 外側のクエリのカラム（相関サブクエリ）や集約・サブクエリを含む条件は、どの要素にも押し下げられないので None。
+fn referenced_leaves(conjunct: &Expr, leaves: &[Plan], columns: &[ColumnInfo]) -> Option<Vec<usize>> {
+    let mut positions = Vec::new();
+    let mut pushable = true;
+    conjunct.walk(&mut |e| match e {
+        Expr::Column { table, name } => match resolve_column(columns, table.as_deref(), name) {
+            Ok(i) => positions.push(i),
+            Err(_) => pushable = false,
+        },
+        Expr::Aggregate { .. } | Expr::Window { .. } | Expr::InSubquery { .. } | Expr::Exists(_) | Expr::Subquery(_) => {
+            pushable = false
+        }
+        _ => {}
+    });
+    if !pushable {
+        return None;
+    }
+    let ends: Vec<usize> = leaves
+        .iter()
+        .scan(0, |end, leaf| {
+            *end += leaf.columns.len();
+            Some(*end)
+        })
+        .collect();
+    let mut found: Vec<usize> = positions.iter().map(|p| ends.partition_point(|end| end <= p)).collect();
+    found.sort_unstable();
+    found.dedup();
+    Some(found)
+}
+
+/// WHERE 句の条件を押し下げられる FROM 句の要素の位置を返す。
+/// 条件のカラム参照がすべて 1 つの要素のカラムに解決でき、その要素が外部結合で NULL 埋めされる側にない場合に限る。
+/// NULL 埋めされる側で先に絞り込むと、結合後に NULL 埋めの行として残ってしまい結果が変わる。
+fn push_target(conjunct: &Expr, leaves: &[Plan], columns: &[ColumnInfo], joins: &[Join]) -> Option<usize> {
+    let found = referenced_leaves(conjunct, leaves, columns)?;
+    let [target] = found[..] else {
+        return None;
+    };
+    // 左側は後続の RIGHT / FULL JOIN で、右側はその JOIN が LEFT / FULL なら NULL 埋めされる
+    let preserved_left = |kind: JoinKind| !matches!(kind, JoinKind::Right | JoinKind::Full);
+    let preserved_right = |kind: JoinKind| !matches!(kind, JoinKind::Left | JoinKind::Full);
+    let own = target == 0 || preserved_right(joins[target - 1].kind);
+    (own && joins[target..].iter().all(|j| preserved_left(j.kind))).then_some(target)
+}
+
+/// コストで結合順を選ぶときの、FROM 句の 1 要素のテーブルとカラムごとの統計情報
+struct CostedLeaf<'a> {
+    table: &'a Table,
+    stats: Vec<Option<&'a ColumnStats>>,
+}
+
+/// 結合順の候補。`first` のテーブルに `steps` の順で（テーブルの位置, 結合, 実行方式）を結合していく
+#[derive(Clone)]
+struct JoinPath {
+    estimate: Estimate,
+    first: usize,
+    steps: Vec<(usize, Join, JoinStrategy)>,
+}
+
+/// 実行計画を実行して、出力行を返す。
+pub fn execute(ctx: &ExecContext, plan: &Plan) -> Result<Vec<Vec<Value>>, DbError> {
+    operator::execute(ctx, plan, None)
+}
+
+/// EXPLAIN の出力。実行計画の木を 1 ノード 1 行で、子ノードを字下げして並べる。
+/// `analyze` では実際に実行して、各ノードが返した行数・実行回数・（子ノードを含む）所要時間を添える。
+pub fn explain(ctx: &ExecContext, statement: &SelectStatement, analyze: bool) -> Result<Vec<String>, DbError> {
+    let plan = build(ctx, statement)?;
+    let mut lines = Vec::new();
+    if !analyze {
+        render(&plan, 0, None, &mut lines);
+        return Ok(lines);
+    }
+    let stats = RefCell::new(HashMap::new());
+    let start = Instant::now();
+    operator::execute(ctx, &plan, Some(&stats))?;
+    let elapsed = start.elapsed();
+    render(&plan, 0, Some(&stats.borrow()), &mut lines);
+    lines.push(format!("Execution Time: {:.3} ms", millis(elapsed)));
+    Ok(lines)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn render(plan: &Plan, depth: usize, stats: Option<&HashMap<usize, NodeStats>>, lines: &mut Vec<String>) {
+    // カラムの除去・並べ替えは表示しない
+    if let Node::Columns { input, .. } = &plan.node {
+        return render(input, depth, stats, lines);
+    }
+    let mut line = indent(depth) + &describe(&plan.node);
+    if let Some(stats) = stats {
+        match stats.get(&(plan as *const Plan as usize)) {
+            Some(s) => line += &format!(" (actual rows={} loops={} time={:.3} ms)", s.rows, s.loops, millis(s.time)),
+            None => line += " (never executed)",
+        }
+    }
+    lines.push(line);
+    match &plan.node {
+        Node::SubqueryScan { input, .. }
+        | Node::Filter { input, .. }
+        | Node::Aggregate { input, .. }
+        | Node::Project { input, .. }
+        | Node::Sort { input, .. }
+        | Node::Distinct { input, .. }
+        | Node::Columns { input, .. }
+        | Node::Limit { input, .. } => render(input, depth + 1, stats, lines),
+        Node::Join { left, right, .. } | Node::SetOperation { left, right, .. } => {
+            render(left, depth + 1, stats, lines);
+            render(right, depth + 1, stats, lines);
+        }
+        Node::With { ctes, input } => {
+            for (c, plan) in ctes {
+                match plan {
+                    Some(plan) => {
+                        lines.push(format!("{}CTE {}", indent(depth + 1), c.name));
+                        render(plan, depth + 2, stats, lines);
+                    }
+                    None => lines.push(format!("{}CTE {} (recursive)", indent(depth + 1), c.name)),
+                }
+            }
+            render(input, depth + 1, stats, lines);
+        }
+        Node::SeqScan { .. }
+        | Node::IndexSeek { .. }
+        | Node::IndexLookup { .. }
+        | Node::IndexAggregate { .. }
+        | Node::CteScan { .. }
+        | Node::CatalogScan { .. } => {}
+    }
+}
+
+fn indent(depth: usize) -> String {
+    match depth {
+        0 => String::new(),
+        _ => "   ".repeat(depth - 1) + "-> ",
+    }
+}
+
+/// ノード 1 行分の説明。条件などは SQL の表記で添える。
+fn describe(node: &Node) -> String {
+    let list = |items: &mut dyn Iterator<Item = String>| items.collect::<Vec<_>>().join(", ");
+    match node {
+        Node::SeqScan { table_ref } => format!("Seq Scan on {}", target(table_ref)),
+        Node::IndexSeek { table_ref, column, key } => format!("Index Seek on {} ({} = {})", target(table_ref), column, key),
+        Node::IndexLookup { table_ref, column, key } => format!("Index Lookup on {} ({} = {})", target(table_ref), column, key),
+        Node::IndexAggregate { table_ref, select } => {
+            format!("Index Aggregate on {} ({})", target(table_ref), list(&mut select.select_columns.iter().map(|i| i.to_string())))
+        }
+        Node::CteScan { table_ref } => format!("CTE Scan on {}", target(table_ref)),
+        Node::CatalogScan { table_ref, .. } => format!("Catalog Scan on {}", target(table_ref)),
+        Node::SubqueryScan { alias, .. } => format!("Subquery Scan on {}", alias),
+        Node::Filter { predicate, .. } => format!("Filter ({})", predicate),
+        Node::Join { join, strategy, .. } => {
+            let (name, inner) = match strategy {
+                JoinStrategy::NestedLoop => ("Nested Loop", ""),
+                JoinStrategy::IndexNestedLoop { .. } => ("Index Nested Loop", ""),
+                JoinStrategy::HashJoin { .. } => ("Hash", " Join"),
+            };
+            let kind = match join.kind {
+                JoinKind::Inner | JoinKind::Cross => inner,
+                JoinKind::Left => " Left Join",
+                JoinKind::Right => " Right Join",
+                JoinKind::Full => " Full Join",
+            };
+            match &join.constraint {
+                Some(c) => format!("{}{} ({})", name, kind, c),
+                None => format!("{}{}", name, kind),
+            }
+        }
+        Node::Aggregate { select, .. } => {
+            let mut s = if select.group_by.is_empty() { "Aggregate".to_string() } else { "Hash Aggregate".to_string() };
+            let mut details = Vec::new();
+            if !select.group_by.is_empty() {
+                details.push(format!("GROUP BY {}", list(&mut select.group_by.iter().map(|e| e.to_string()))));
+            }
+            if let Some(having) = &select.having {
+                details.push(format!("HAVING {}", having));
+            }
+            if !details.is_empty() {
+                s += &format!(" ({})", details.join(" "));
+            }
+            s
+        }
+        Node::Project { select, extra, .. } => {
+            let name = if select.select_columns.iter().any(has_window) || extra.iter().any(Expr::contains_window) {
+                "Window Project"
+            } else {
+                "Project"
+            };
+            format!("{} ({})", name, list(&mut select.select_columns.iter().map(|i| i.to_string())))
+        }
+        Node::Sort { order_by, .. } => format!("Sort ({})", list(&mut order_by.iter().map(|ob| ob.to_string()))),
+        Node::Distinct { sorted, on, .. } => {
+            let name = if *sorted { "Unique" } else { "Hash Distinct" };
+            match on.is_empty() {
+                true => name.to_string(),
+                false => format!("{} (ON {})", name, list(&mut on.iter().map(|e| e.to_string()))),
+            }
+        }
+        Node::Columns { .. } => "Columns".to_string(),
+        Node::Limit { limit, offset, .. } => {
+            let mut details = Vec::new();
+            if let Some(limit) = limit {
+                details.push(format!("LIMIT {}", limit));
+            }
+            if let Some(offset) = offset {
+                details.push(format!("OFFSET {}", offset));
+            }
+            format!("Limit ({})", details.join(" "))
+        }
+        Node::SetOperation { op, all, .. } => {
+            let name = match op {
+                SetOperator::Union => "Union",
+                SetOperator::Intersect => "Intersect",
+                SetOperator::Except => "Except",
+            };
+            if *all { format!("{} All", name) } else { name.to_string() }
+        }
+        Node::With { ctes, .. } => format!("With ({})", list(&mut ctes.iter().map(|(c, _)| c.name.clone()))),
+    }
+}
+
+fn has_window(item: &SelectItem) -> bool {
+    matches!(item, SelectItem::Expr { expr, .. } if expr.contains_window())
+}
+
+/// `users` または `users u` の形の走査対象
+fn target(table_ref: &TableRef) -> String {
+    match table_ref {
+        TableRef::Table { name, alias: Some(alias) } => format!("{} {}", name, alias),
+        _ => table_ref.name().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::executor::test_util::{database, explain, query, rows};
+    use crate::executor::ResultSet;
+    use crate::value::Value;
+
+    fn setup() -> Database {
+        database(&[
+            "CREATE TABLE users (id, name, age)",
+            "CREATE TABLE orders (oid, user_id, total)",
+            "INSERT INTO users VALUES (1, 'John', 30), (2, 'Mike', 40), (3, 'Anna', 25)",
+            "INSERT INTO orders VALUES (10, 1, 100), (11, 1, 50), (12, 2, 70), (13, 9, 30)",
+        ])
+    }
+
+    #[test]
+    fn test_constant_folding_enables_index_seek() {
+        let db = setup();
+        let plan = explain(&db, "EXPLAIN SELECT name FROM users WHERE id = 1 + 1 AND 2 > 1");
+        assert_eq!(plan, vec!["Project (name)", "-> Filter (id = 2)", "   -> Index Seek on users (id = 2)"]);
+        // 評価でエラーになる式は畳み込まず、実行時まで残す
+        let plan = explain(&db, "EXPLAIN SELECT name FROM users WHERE age > 1 / 0");
+        assert_eq!(plan[1], "-> Filter (age > 1 / 0)");
+        // 常に真の条件は取り除く
+        assert_eq!(explain(&db, "EXPLAIN SELECT * FROM users WHERE 1 = 1"), vec!["Project (*)", "-> Seq Scan on users"]);
+    }
+
+    #[test]
+    fn test_predicate_pushdown() {
+        let db = setup();
+        let sql = "SELECT u.name, o.total FROM users u JOIN orders o ON o.user_id = u.id WHERE o.total > 60 AND u.age < 35";
+        let plan = explain(&db, &format!("EXPLAIN {}", sql));
+        assert_eq!(
+            plan,
+            vec![
+                "Project (u.name, o.total)",
+                "-> Hash Join (o.user_id = u.id)",
+                "   -> Filter (u.age < 35)",
+                "      -> Seq Scan on users u",
+                "   -> Filter (o.total > 60)",
+                "      -> Seq Scan on orders o",
+            ]
+        );
+        assert_eq!(rows(&query(&db, sql).unwrap()), vec![vec!["John", "100"]]);
+
+        // LEFT JOIN の NULL 埋めされる側の条件は結合の後で評価する
+        let sql = "SELECT u.name, o.oid FROM users u LEFT JOIN orders o ON o.user_id = u.id WHERE o.oid IS NULL AND u.age > 20";
+        let plan = explain(&db, &format!("EXPLAIN {}", sql));
+        assert_eq!(plan[1], "-> Filter (o.oid IS NULL)");
+        assert_eq!(plan[3], "      -> Filter (u.age > 20)");
+        assert_eq!(rows(&query(&db, sql).unwrap()), vec![vec!["Anna", "NULL"]]);
+    }
+
+    #[test]
+    fn test_explain_analyze_counts_rows() {
+        let db = setup();
+        let plan = explain(&db, "EXPLAIN ANALYZE SELECT name FROM users WHERE age > 26 ORDER BY age DESC LIMIT 1");
+        assert!(plan[0].starts_with("Limit (LIMIT 1) (actual rows=1 loops=1 time="));
+        // LIMIT は 1 行返したところで読むのをやめるので、並べ替えた 2 行のうち 1 行しか読まれない
+        assert!(plan[1].starts_with("-> Sort (age DESC) (actual rows=1 "));
+        assert!(plan[3].starts_with("      -> Filter (age > 26) (actual rows=2 "));
+        assert!(plan.iter().any(|l| l.contains("Seq Scan on users (actual rows=3 ")));
+        assert!(plan.last().unwrap().starts_with("Execution Time: "));
+        // Index Nested Loop では右テーブルを走査せず、左の 1 行ごとに B+Tree を 1 回引く
+        let plan = explain(&db, "EXPLAIN ANALYZE SELECT * FROM orders o JOIN users u ON o.user_id = u.id");
+        assert!(plan[1].starts_with("-> Index Nested Loop (o.user_id = u.id) (actual rows=3 "));
+        assert!(plan[2].starts_with("   -> Seq Scan on orders o (actual rows=4 loops=1 "));
+        assert!(plan[3].starts_with("   -> Index Lookup on users u (id = o.user_id) (actual rows=3 loops=4 "));
+    }
+
+    #[test]
+    fn test_explain_nodes() {
+        let db = setup();
+        assert_eq!(explain(&db, "EXPLAIN SELECT COUNT(*), MAX(id) FROM users"), vec!["Index Aggregate on users (COUNT(*), MAX(id))"]);
+        let plan = explain(&db, "EXPLAIN SELECT age, COUNT(*) FROM users GROUP BY age HAVING COUNT(*) > 1");
+        assert_eq!(plan[0], "Hash Aggregate (GROUP BY age HAVING COUNT(*) > 1)");
+        let plan = explain(&db, "EXPLAIN WITH t AS (SELECT name FROM users) SELECT DISTINCT name FROM t ORDER BY name");
+        assert_eq!(
+            plan,
+            vec!["With (t)", "-> CTE t", "   -> Project (name)", "      -> Seq Scan on users", "-> Unique", "   -> Sort (name)", "      -> Project (name)", "         -> CTE Scan on t"]
+        );
+        let plan = explain(&db, "EXPLAIN SELECT name FROM users UNION ALL SELECT name FROM (SELECT * FROM users) AS s");
+        assert_eq!(plan[0], "Union All");
+        assert_eq!(plan[4], "   -> Subquery Scan on s");
+    }
+
+    #[test]
+    fn test_index_seek_with_parameter() {
+        let mut db = setup();
+        let mut statement = db.prepare("SELECT name FROM users WHERE id = ?").unwrap();
+        statement.bind(1, 2).unwrap();
+        let result = statement.query(&db).unwrap();
+        assert_eq!(result.iter().next().unwrap().get::<String>(0).unwrap(), "Mike");
+        let mut explain = db.prepare("EXPLAIN SELECT name FROM users WHERE id = $1").unwrap();
+        explain.bind(1, 2).unwrap();
+        let plan: Vec<String> = explain.query(&db).unwrap().iter().map(|r| r.get(0).unwrap()).collect();
+        assert_eq!(plan[2], "   -> Index Seek on users (id = $1)");
+        // UPDATE / DELETE の対象行の検索にも同じ計画を使う
+        let mut delete = db.prepare("DELETE FROM users WHERE id = $1").unwrap();
+        delete.bind(1, 3).unwrap();
+        delete.execute(&mut db).unwrap();
+        assert_eq!(rows(&query(&db, "SELECT id FROM users").unwrap()), vec![vec!["1"], vec!["2"]]);
+    }
+
+    fn setup_events() -> Database {
+        let mut db = setup();
+        let values: Vec<String> = (0..200).map(|eid| format!("({}, {}, {})", eid, eid % 3 + 1, eid % 5)).collect();
+        db.execute_batch(&format!("CREATE TABLE events (eid, user_id, kind); INSERT INTO events VALUES {}", values.join(", "))).unwrap();
+        db
+    }
+
+    fn sorted_rows(result: &ResultSet) -> Vec<Vec<String>> {
+        let mut rows = rows(result);
+        rows.sort();
+        rows
+    }
+
+    #[test]
+    fn test_cost_based_join_strategy() {
+        let mut db = setup_events();
+        let sql = "SELECT * FROM events e JOIN users u ON e.user_id = u.id WHERE u.age > 26";
+        // 統計情報がなければ、主キーとの等値条件には規則どおり Index Nested Loop を使う
+        let plan = explain(&db, &format!("EXPLAIN {}", sql));
+        assert_eq!(plan[2], "   -> Index Nested Loop (e.user_id = u.id)");
+        let before = query(&db, sql).unwrap();
+
+        // 左が 200 行あると、行ごとに B+Tree を引くより users を 1 回読んでハッシュ結合する方が安い
+        db.execute("ANALYZE").unwrap();
+        let plan = explain(&db, &format!("EXPLAIN {}", sql));
+        assert_eq!(
+            plan,
+            vec![
+                "Project (*)",
+                "-> Hash Join (e.user_id = u.id)",
+                "   -> Seq Scan on events e",
+                "   -> Filter (u.age > 26)",
+                "      -> Seq Scan on users u",
+            ]
+        );
+        let after = query(&db, sql).unwrap();
+        assert_eq!(after.columns, before.columns);
+        assert_eq!(sorted_rows(&after), sorted_rows(&before));
+    }
+
+    #[test]
+    fn test_cost_based_join_order() {
+        let mut db = setup_events();
+        let sql = "SELECT * FROM users u CROSS JOIN orders o JOIN events e ON e.user_id = u.id AND e.eid = o.oid";
+        // 書かれた順では users と orders の直積を作ってから events を結合する
+        let plan = explain(&db, &format!("EXPLAIN {}", sql));
+        assert_eq!(plan[2], "   -> Nested Loop");
+        let before = query(&db, sql).unwrap();
+
+        // ANALYZE の後は直積を避け、orders → events → users の順に結合する
+        db.execute("ANALYZE").unwrap();
+        let plan = explain(&db, &format!("EXPLAIN {}", sql));
+        assert_eq!(
+            plan,
+            vec![
+                "Project (*)",
+                "-> Hash Join (e.user_id = u.id)",
+                "   -> Index Nested Loop (e.eid = o.oid)",
+                "      -> Seq Scan on orders o",
+                "      -> Index Lookup on events e (eid = o.oid)",
+                "   -> Seq Scan on users u",
+            ]
+        );
+        // 結合順を変えても、カラムは FROM 句の順に並ぶ
+        let after = query(&db, sql).unwrap();
+        assert_eq!(after.columns, before.columns);
+        assert_eq!(after.rows.len(), 4);
+        assert_eq!(sorted_rows(&after), sorted_rows(&before));
+    }
+
+    #[test]
+    fn test_analyze_statement() {
+        let mut db = setup();
+        assert!(db.table_stats("users").is_none());
+        db.execute("ANALYZE users").unwrap();
+        let stats = db.table_stats("users").unwrap();
+        assert_eq!(stats.row_count, 3);
+        assert_eq!(stats.column("age").unwrap().histogram.first(), Some(&Value::Integer(25)));
+        assert!(db.table_stats("orders").is_none());
+        assert_eq!(db.execute("ANALYZE nope").unwrap_err().code(), "42P01");
+        // 外部結合を含む FROM 句は、統計情報があっても書かれた順に結合する
+        db.execute("ANALYZE").unwrap();
+        let plan = explain(&db, "EXPLAIN SELECT * FROM orders o LEFT JOIN users u ON o.user_id = u.id");
+        assert_eq!(plan[1], "-> Index Nested Loop Left Join (o.user_id = u.id)");
+    }
+}
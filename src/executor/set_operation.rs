@@ -0,0 +1,162 @@
+use crate::error::DbError;
+use crate::executor::ResultSet;
+use crate::parser::ast::SetOperator;
+use crate::value::Value;
+use std::collections::{HashMap, HashSet};
+
+/// UNION / INTERSECT / EXCEPT を実行する。カラム名は左側の結果のものを使う。
+/// 重複の判定は行をキーにしたハッシュで行い、NULL 同士は等しいものとして扱う。
+pub fn execute(left: ResultSet, op: SetOperator, all: bool, right: ResultSet) -> Result<ResultSet, DbError> {
+    check_compatible(&left, op, &right)?;
+    let rows = match (op, all) {
+        (SetOperator::Union, true) => left.rows.into_iter().chain(right.rows).collect(),
+        (SetOperator::Union, false) => distinct(left.rows.into_iter().chain(right.rows)),
+        (SetOperator::Intersect, false) => {
+            let right: HashSet<Vec<Value>> = right.rows.into_iter().collect();
+            distinct(left.rows.into_iter().filter(|row| right.contains(row)))
+        }
+        (SetOperator::Except, false) => {
+            let right: HashSet<Vec<Value>> = right.rows.into_iter().collect();
+            distinct(left.rows.into_iter().filter(|row| !right.contains(row)))
+        }
+        // ALL の場合は重複の数を数える。右側に n 個ある行は、INTERSECT ALL では左側から
+        // 最大 n 個残り、EXCEPT ALL では左側から n 個取り除かれる。
+        (SetOperator::Intersect, true) => {
+            let mut counts = count_rows(right.rows);
+            left.rows.into_iter().filter(|row| take(&mut counts, row)).collect()
+        }
+        (SetOperator::Except, true) => {
+            let mut counts = count_rows(right.rows);
+            left.rows.into_iter().filter(|row| !take(&mut counts, row)).collect()
+        }
+    };
+    Ok(ResultSet { columns: left.columns, rows })
+}
+
+/// 両側のカラム数が等しく、各カラムの型が比較できる組み合わせかどうかを確認する。
+/// 型は各カラムで最初に現れた NULL 以外の値で判定し、整数と小数は同じ数値型として扱う。
+pub fn check_compatible(left: &ResultSet, op: SetOperator, right: &ResultSet) -> Result<(), DbError> {
+    if left.columns.len() != right.columns.len() {
+        return Err(DbError::InvalidQuery(format!(
+            "Each {} query must have the same number of columns ({} vs {}).",
+            op,
+            left.columns.len(),
+            right.columns.len()
+        )));
+    }
+    for i in 0..left.columns.len() {
+        if let (Some(l), Some(r)) = (column_type(left, i), column_type(right, i)) {
+            if l != r {
+                return Err(DbError::TypeMismatch(format!("{} types {} and {} cannot be matched (column {}).", op, l, r, i + 1)));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn column_type(result: &ResultSet, index: usize) -> Option<&'static str> {
+    result.rows.iter().find_map(|row| match &row[index] {
+        Value::Null => None,
+        Value::Boolean(_) => Some("boolean"),
+        Value::Integer(_) | Value::Float(_) => Some("numeric"),
+        Value::Text(_) => Some("text"),
+    })
+}
+
+/// 最初に現れた順序を保ったまま重複行を取り除く。
+fn distinct(rows: impl Iterator<Item = Vec<Value>>) -> Vec<Vec<Value>> {
+    let mut seen = HashSet::new();
+    rows.filter(|row| seen.insert(row.clone())).collect()
+}
+
+fn count_rows(rows: Vec<Vec<Value>>) -> HashMap<Vec<Value>, usize> {
+    let mut counts = HashMap::new();
+    for row in rows {
+        *counts.entry(row).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// 残りの個数が 1 以上なら 1 つ減らして true を返す。
+fn take(counts: &mut HashMap<Vec<Value>, usize>, row: &[Value]) -> bool {
+    match counts.get_mut(row) {
+        Some(n) if *n > 0 => {
+            *n -= 1;
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::executor::test_util::{database, query};
+    use crate::executor::ResultSet;
+
+    fn setup() -> Database {
+        database(&[
+            "CREATE TABLE users (id, name)",
+            "CREATE TABLE customers (cid, name)",
+            "INSERT INTO users VALUES (1, 'John'), (2, 'Mike'), (3, 'Anna'), (4, 'Mike')",
+            "INSERT INTO customers VALUES (10, 'Anna'), (11, 'Kate'), (12, 'Mike')",
+        ])
+    }
+
+    fn names(result: &ResultSet) -> Vec<String> {
+        result.rows.iter().map(|r| r[0].to_string()).collect()
+    }
+
+    #[test]
+    fn test_union() {
+        let db = setup();
+        let result = query(&db, "SELECT name FROM users UNION SELECT name FROM customers").unwrap();
+        assert_eq!(result.columns, vec!["name"]);
+        assert_eq!(names(&result), vec!["John", "Mike", "Anna", "Kate"]);
+        let result = query(&db, "SELECT name FROM users UNION ALL SELECT name FROM customers").unwrap();
+        assert_eq!(result.rows.len(), 7);
+    }
+
+    #[test]
+    fn test_intersect_and_except() {
+        let db = setup();
+        let result = query(&db, "SELECT name FROM users INTERSECT SELECT name FROM customers").unwrap();
+        assert_eq!(names(&result), vec!["Mike", "Anna"]);
+        let result = query(&db, "SELECT name FROM users EXCEPT SELECT name FROM customers").unwrap();
+        assert_eq!(names(&result), vec!["John"]);
+        // users には Mike が 2 行、customers には 1 行ある
+        let result = query(&db, "SELECT name FROM users INTERSECT ALL SELECT name FROM customers").unwrap();
+        assert_eq!(names(&result), vec!["Mike", "Anna"]);
+        let result = query(&db, "SELECT name FROM users EXCEPT ALL SELECT name FROM customers").unwrap();
+        assert_eq!(names(&result), vec!["John", "Mike"]);
+    }
+
+    #[test]
+    fn test_precedence() {
+        let db = setup();
+        // INTERSECT が先に評価される: John ∪ (Mike ∩ Mike)
+        let result = query(
+            &db,
+            "SELECT name FROM users WHERE id = 1 UNION SELECT name FROM users WHERE id = 2 \
+             INTERSECT SELECT name FROM customers WHERE cid = 12",
+        )
+        .unwrap();
+        assert_eq!(names(&result), vec!["John", "Mike"]);
+        let result = query(
+            &db,
+            "(SELECT name FROM users WHERE id = 1 UNION SELECT name FROM users WHERE id = 2) \
+             INTERSECT SELECT name FROM customers",
+        )
+        .unwrap();
+        assert_eq!(names(&result), vec!["Mike"]);
+    }
+
+    #[test]
+    fn test_incompatible_operands() {
+        let db = setup();
+        let err = query(&db, "SELECT id, name FROM users UNION SELECT name FROM customers").unwrap_err();
+        assert!(err.to_string().contains("same number of columns"));
+        let err = query(&db, "SELECT id FROM users UNION SELECT name FROM customers").unwrap_err();
+        assert!(err.to_string().contains("cannot be matched"));
+    }
+}
@@ -0,0 +1,94 @@
+use crate::database::Database;
+use crate::error::DbError;
+use crate::executor::eval::{check_columns, Scope};
+use crate::executor::insert::apply_assignments;
+use crate::executor::{lookup_table, scan, table_columns, ExecContext};
+use crate::parser::ast::{TableRef, Update};
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// UPDATE で書き換える行を `(元の主キー, 新しい行)` の組で求める。テーブルはまだ変更しない。
+/// WHERE 句に「主キー = 定数」があれば B+Tree で対象の行を探す。SET 句の右辺は変更前の行で評価する。
+pub fn rows(db: &Database, update: &Update, params: &[Value]) -> Result<Vec<(Value, Vec<Value>)>, DbError> {
+    let cache = RefCell::new(HashMap::new());
+    let ctx = ExecContext { db, outer: None, cache: &cache, ctes: HashMap::new(), params };
+    let table = lookup_table(db, &update.table_name)?;
+    let table_ref = TableRef::Table { name: update.table_name.clone(), alias: update.alias.clone() };
+    let columns = table_columns(table, &table_ref);
+    for e in update.condition.iter().chain(update.assignments.iter().map(|a| &a.value)) {
+        check_columns(e, &columns, &ctx)?;
+    }
+    let relation = scan(&ctx, &table_ref, update.condition.as_ref())?;
+    let mut updates = Vec::with_capacity(relation.rows.len());
+    for row in &relation.rows {
+        let scope = Scope::new(&ctx, &columns, row);
+        updates.push((row[0].clone(), apply_assignments(table, row, &update.assignments, &scope)?));
+    }
+    Ok(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::executor::check_returning;
+    use crate::executor::test_util::{database, execute, query, rows};
+    use crate::parser::ast::TableRef;
+    use crate::parser::{parse_query, Query};
+
+    fn setup() -> Database {
+        database(&["CREATE TABLE users (id, name, age)", "INSERT INTO users VALUES (1, 'John', 30), (2, 'Mike', 40), (3, 'Anna', 25)"])
+    }
+
+    #[test]
+    fn test_update() {
+        let mut db = setup();
+        execute(&mut db, "UPDATE users SET age = age + 1, name = UPPER(name) WHERE age >= 30").unwrap();
+        execute(&mut db, "UPDATE users u SET name = 'Ann' WHERE u.id = 3").unwrap();
+        let result = query(&db, "SELECT * FROM users").unwrap();
+        assert_eq!(rows(&result), vec![vec!["1", "JOHN", "31"], vec!["2", "MIKE", "41"], vec!["3", "Ann", "25"]]);
+    }
+
+    #[test]
+    fn test_update_primary_key() {
+        let mut db = setup();
+        // 主キーをずらす更新でも、更新後のキーが重複しなければ成功する
+        execute(&mut db, "UPDATE users SET id = id + 1").unwrap();
+        let result = query(&db, "SELECT id, name FROM users WHERE id = 4").unwrap();
+        assert_eq!(rows(&result), vec![vec!["4", "Anna"]]);
+        // 重複や NULL になる場合は 1 行も更新しない
+        assert!(execute(&mut db, "UPDATE users SET id = 2 WHERE id = 4").is_err());
+        assert!(execute(&mut db, "UPDATE users SET id = NULL WHERE id = 2").is_err());
+        let result = query(&db, "SELECT id FROM users").unwrap();
+        assert_eq!(rows(&result), vec![vec!["2"], vec!["3"], vec!["4"]]);
+    }
+
+    #[test]
+    fn test_returning() {
+        let mut db = setup();
+        let Query::Update(update) = parse_query("UPDATE users u SET age = age * 2 WHERE id <= 2 RETURNING u.id, age AS doubled, *").unwrap() else {
+            panic!("not an update")
+        };
+        let table_ref = TableRef::Table { name: "users".into(), alias: Some("u".into()) };
+        assert!(check_returning(&db, &table_ref, &update.returning).is_ok());
+        // 書き換えた後の行が返る
+        let result = db.update(&update, &[]).unwrap();
+        assert_eq!(result.rows_affected, 2);
+        assert_eq!(result.columns, vec!["u.id", "doubled", "id", "name", "age"]);
+        let written: Vec<Vec<String>> = result.rows.iter().map(|r| r.iter().map(|v| v.to_string()).collect()).collect();
+        assert_eq!(written, vec![vec!["1", "60", "1", "John", "60"], vec!["2", "80", "2", "Mike", "80"]]);
+        let Query::Delete(delete) = parse_query("DELETE FROM users RETURNING nope").unwrap() else { panic!("not a delete") };
+        let table_ref = TableRef::Table { name: "users".into(), alias: None };
+        assert!(check_returning(&db, &table_ref, &delete.returning).unwrap_err().to_string().contains("does not exist"));
+    }
+
+    #[test]
+    fn test_update_errors() {
+        let mut db = setup();
+        let mut update = |sql: &str| execute(&mut db, sql);
+        assert!(update("UPDATE users SET nope = 1").unwrap_err().to_string().contains("does not exist"));
+        assert!(update("UPDATE users SET age = nope WHERE id = 99").unwrap_err().to_string().contains("does not exist"));
+        assert!(update("UPDATE users SET age = 1, age = 2").unwrap_err().to_string().contains("more than once"));
+        assert!(parse_query("UPDATE users SET age = COUNT(*)").is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use crate::error::DbError;
+use crate::executor::aggregate::Accumulator;
+use crate::executor::eval::{eval, Scope};
+use crate::executor::order::compare;
+use crate::parser::ast::{Expr, FrameBound, Select, SelectItem, WindowFunction, WindowSpec};
+use crate::value::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// SELECT 句と追加の式に現れるウィンドウ関数を重複なく集める。
+pub fn collect(select: &Select, extra: &[Expr]) -> Vec<Expr> {
+    let mut found: Vec<Expr> = Vec::new();
+    let items = select.select_columns.iter().filter_map(|item| match item {
+        SelectItem::Expr { expr, .. } => Some(expr),
+        SelectItem::Wildcard | SelectItem::QualifiedWildcard(_) => None,
+    });
+    for e in items.chain(extra) {
+        e.walk(&mut |e| {
+            if matches!(e, Expr::Window { .. }) && !found.contains(e) {
+                found.push(e.clone());
+            }
+        });
+    }
+    found
+}
+
+/// 各行についてウィンドウ関数の値を計算する。戻り値は `[行][windows の位置]`。
+/// 行は PARTITION BY の値で分け、パーティション内を ORDER BY で安定ソートしてから計算する。
+pub fn compute(windows: &[Expr], scopes: &[Scope]) -> Result<Vec<Vec<Value>>, DbError> {
+    let mut values = vec![Vec::with_capacity(windows.len()); scopes.len()];
+    for window in windows {
+        let Expr::Window { func, args, over } = window else {
+            unreachable!("collect only returns window function calls")
+        };
+        let args = scopes
+            .iter()
+            .map(|scope| args.iter().map(|a| eval(a, scope)).collect::<Result<Vec<_>, _>>())
+            .collect::<Result<Vec<_>, _>>()?;
+        for partition in partitions(over, scopes)? {
+            for (pos, value) in evaluate(*func, over, &partition, &args)?.into_iter().enumerate() {
+                values[partition.rows[pos]].push(value);
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// 並べ替え済みのパーティション。`keys` は ORDER BY の値で、`rows` と同じ順に並ぶ。
+struct Partition {
+    rows: Vec<usize>,
+    keys: Vec<Vec<Value>>,
+}
+
+impl Partition {
+    /// 各行について、ORDER BY の値が等しい行（ピア）の範囲
+    fn peers(&self) -> Vec<Range<usize>> {
+        let mut peers = Vec::with_capacity(self.rows.len());
+        let mut start = 0;
+        for end in 1..=self.rows.len() {
+            if end == self.rows.len() || self.keys[end] != self.keys[start] {
+                peers.extend(std::iter::repeat_n(start..end, end - start));
+                start = end;
+            }
+        }
+        peers
+    }
+}
+
+/// 行をパーティションに分け（最初に現れた順）、それぞれを ORDER BY で並べ替える。
+fn partitions(over: &WindowSpec, scopes: &[Scope]) -> Result<Vec<Partition>, DbError> {
+    let mut index: HashMap<Vec<Value>, usize> = HashMap::new();
+    let mut partitions: Vec<Vec<(usize, Vec<Value>)>> = Vec::new();
+    for (i, scope) in scopes.iter().enumerate() {
+        let key = over.partition_by.iter().map(|e| eval(e, scope)).collect::<Result<Vec<_>, _>>()?;
+        let order = over.order_by.iter().map(|ob| eval(&ob.expr, scope)).collect::<Result<Vec<_>, _>>()?;
+        let next = partitions.len();
+        let p = *index.entry(key).or_insert(next);
+        if p == next {
+            partitions.push(Vec::new());
+        }
+        partitions[p].push((i, order));
+    }
+    Ok(partitions
+        .into_iter()
+        .map(|mut rows| {
+            rows.sort_by(|(_, a), (_, b)| {
+                a.iter()
+                    .zip(b)
+                    .zip(&over.order_by)
+                    .map(|((a, b), ob)| compare(a, b, ob))
+                    .find(|o| *o != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal)
+            });
+            let (rows, keys) = rows.into_iter().unzip();
+            Partition { rows, keys }
+        })
+        .collect())
+}
+
+/// 1 つのパーティションについて、並べ替え後の順にウィンドウ関数の値を求める。
+fn evaluate(func: WindowFunction, over: &WindowSpec, partition: &Partition, args: &[Vec<Value>]) -> Result<Vec<Value>, DbError> {
+    let arg = |pos: usize, i: usize| args[partition.rows[pos]].get(i).cloned();
+    let mut values = Vec::with_capacity(partition.rows.len());
+    let mut dense_rank = 0;
+    // 先頭から始まるフレームの集約は、前の行の途中結果に続きの行だけを取り込む
+    let mut running: Option<(Accumulator, usize)> = None;
+    for (pos, peers) in partition.peers().into_iter().enumerate() {
+        let value = match func {
+            WindowFunction::RowNumber => Value::Integer(pos as i64 + 1),
+            WindowFunction::Rank => Value::Integer(peers.start as i64 + 1),
+            WindowFunction::DenseRank => {
+                if peers.start == pos {
+                    dense_rank += 1;
+                }
+                Value::Integer(dense_rank)
+            }
+            WindowFunction::Lag | WindowFunction::Lead => {
+                let offset = match arg(pos, 1).map(Value::numeric_affinity) {
+                    None => 1,
+                    Some(Value::Integer(n)) if n >= 0 => n as usize,
+                    Some(v) => return Err(DbError::InvalidValue(format!("{}() offset must be a non-negative integer, found '{}'", func, v))),
+                };
+                let target = if func == WindowFunction::Lag { pos.checked_sub(offset) } else { pos.checked_add(offset) };
+                match target.filter(|&t| t < partition.rows.len()) {
+                    Some(t) => arg(t, 0).unwrap_or(Value::Null),
+                    None => arg(pos, 2).unwrap_or(Value::Null),
+                }
+            }
+            WindowFunction::FirstValue | WindowFunction::LastValue => {
+                let frame = frame(over, pos, peers, partition.rows.len());
+                let target = if func == WindowFunction::FirstValue { frame.clone().next() } else { frame.clone().last() };
+                target.and_then(|t| arg(t, 0)).unwrap_or(Value::Null)
+            }
+            WindowFunction::Aggregate(agg) => {
+                let frame = frame(over, pos, peers, partition.rows.len());
+                let (mut acc, done) = match running.take() {
+                    Some((acc, done)) if frame.start == 0 && done <= frame.end => (acc, done),
+                    _ => (Accumulator::new(agg, false), frame.start),
+                };
+                for i in done..frame.end {
+                    acc.update(arg(i, 0))?;
+                }
+                let value = acc.finish();
+                if frame.start == 0 {
+                    running = Some((acc, frame.end));
+                }
+                value
+            }
+        };
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// `pos` の行のフレーム（パーティション内の位置の範囲）。
+/// ROWS 指定がなければ、ORDER BY ありは先頭から最後のピアまで、なしはパーティション全体。
+fn frame(over: &WindowSpec, pos: usize, peers: Range<usize>, len: usize) -> Range<usize> {
+    let Some(frame) = &over.frame else {
+        return if over.order_by.is_empty() { 0..len } else { 0..peers.end };
+    };
+    let bound = |b: FrameBound| match b {
+        FrameBound::UnboundedPreceding => 0,
+        FrameBound::Preceding(n) => pos.saturating_sub(n as usize),
+        FrameBound::CurrentRow => pos,
+        FrameBound::Following(n) => pos.saturating_add(n as usize),
+        FrameBound::UnboundedFollowing => len,
+    };
+    let start = bound(frame.start).min(len);
+    let end = bound(frame.end).saturating_add(1).min(len);
+    start..end.max(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::executor::test_util::{database, query, rows};
+
+    fn setup() -> Database {
+        database(&[
+            "CREATE TABLE emp (id, dept, name, salary)",
+            "INSERT INTO emp VALUES (1, 'dev', 'John', 300), (2, 'ops', 'Mike', 200), (3, 'dev', 'Anna', 500), (4, 'ops', 'Kate', 200), (5, 'dev', 'Tom', 300)",
+        ])
+    }
+
+    #[test]
+    fn test_ranking() {
+        let db = setup();
+        let result = query(
+            &db,
+            "SELECT name, ROW_NUMBER() OVER (ORDER BY salary DESC, id), RANK() OVER (ORDER BY salary DESC), \
+             DENSE_RANK() OVER (ORDER BY salary DESC) FROM emp ORDER BY id",
+        )
+        .unwrap();
+        assert_eq!(result.columns[1], "ROW_NUMBER() OVER (ORDER BY salary DESC, id)");
+        assert_eq!(
+            rows(&result),
+            vec![
+                vec!["John", "2", "2", "2"],
+                vec!["Mike", "4", "4", "3"],
+                vec!["Anna", "1", "1", "1"],
+                vec!["Kate", "5", "4", "3"],
+                vec!["Tom", "3", "2", "2"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_partition_by() {
+        let db = setup();
+        let result = query(
+            &db,
+            "SELECT name, ROW_NUMBER() OVER (PARTITION BY dept ORDER BY salary DESC, id) AS n, \
+             COUNT(*) OVER (PARTITION BY dept) AS size FROM emp ORDER BY dept, n",
+        )
+        .unwrap();
+        assert_eq!(
+            rows(&result),
+            vec![
+                vec!["Anna", "1", "3"],
+                vec!["John", "2", "3"],
+                vec!["Tom", "3", "3"],
+                vec!["Mike", "1", "2"],
+                vec!["Kate", "2", "2"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_frames() {
+        let db = setup();
+        // 既定のフレームでは同じ値のピアまで含めて累計する
+        let result = query(&db, "SELECT id, SUM(salary) OVER (ORDER BY salary) FROM emp ORDER BY id").unwrap();
+        assert_eq!(rows(&result), vec![vec!["1", "1000"], vec!["2", "400"], vec!["3", "1500"], vec!["4", "400"], vec!["5", "1000"]]);
+        let result = query(
+            &db,
+            "SELECT id, SUM(salary) OVER (ORDER BY id ROWS BETWEEN 1 PRECEDING AND CURRENT ROW), \
+             AVG(salary) OVER (ORDER BY id ROWS BETWEEN CURRENT ROW AND UNBOUNDED FOLLOWING) FROM emp",
+        )
+        .unwrap();
+        assert_eq!(
+            rows(&result),
+            vec![
+                vec!["1", "300", "300.0"],
+                vec!["2", "500", "300.0"],
+                vec!["3", "700", "333.3333333333333"],
+                vec!["4", "700", "250.0"],
+                vec!["5", "500", "300.0"],
+            ]
+        );
+        assert!(query(&db, "SELECT SUM(salary) OVER (ORDER BY id ROWS BETWEEN CURRENT ROW AND 1 PRECEDING) FROM emp").is_err());
+        assert!(query(&db, "SELECT SUM(salary) OVER (ORDER BY id RANGE UNBOUNDED PRECEDING) FROM emp").is_err());
+    }
+
+    #[test]
+    fn test_offset_and_value_functions() {
+        let db = setup();
+        let result = query(
+            &db,
+            "SELECT id, LAG(name) OVER (ORDER BY id), LEAD(name, 2, 'none') OVER (ORDER BY id), \
+             FIRST_VALUE(name) OVER (PARTITION BY dept ORDER BY id), \
+             LAST_VALUE(name) OVER (PARTITION BY dept ORDER BY id ROWS BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING) \
+             FROM emp",
+        )
+        .unwrap();
+        assert_eq!(
+            rows(&result),
+            vec![
+                vec!["1", "NULL", "Anna", "John", "Tom"],
+                vec!["2", "John", "Kate", "Mike", "Kate"],
+                vec!["3", "Mike", "Tom", "John", "Tom"],
+                vec!["4", "Anna", "none", "Mike", "Kate"],
+                vec!["5", "Kate", "none", "John", "Tom"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_window_over_group_by() {
+        let db = setup();
+        let result = query(
+            &db,
+            "SELECT dept, SUM(salary), RANK() OVER (ORDER BY SUM(salary) DESC) AS r FROM emp GROUP BY dept ORDER BY r",
+        )
+        .unwrap();
+        assert_eq!(rows(&result), vec![vec!["dev", "1100", "1"], vec!["ops", "400", "2"]]);
+    }
+
+    #[test]
+    fn test_invalid_window_usage() {
+        let db = setup();
+        let err = query(&db, "SELECT name FROM emp WHERE ROW_NUMBER() OVER (ORDER BY id) = 1").unwrap_err();
+        assert!(err.to_string().contains("not allowed in WHERE"));
+        assert!(query(&db, "SELECT RANK() FROM emp").is_err());
+        assert!(query(&db, "SELECT SUM(ROW_NUMBER() OVER ()) FROM emp").is_err());
+    }
+}
@@ -0,0 +1,39 @@
+//! FerrDB を Rust のプログラムに組み込んで使うためのライブラリ。
+//!
+//! ```
+//! use ferrdb::Database;
+//!
+//! let mut db = Database::new();
+//! db.execute("CREATE TABLE users (id, name, age)")?;
+//! db.execute("INSERT INTO users VALUES (1, 'John', 30), (2, 'Mike', 40)")?;
+//! for row in &db.query("SELECT name, age FROM users WHERE age > 35")? {
+//!     let name: String = row.get("name")?;
+//!     let age: i64 = row.get(1)?;
+//!     assert_eq!((name.as_str(), age), ("Mike", 40));
+//! }
+//! # Ok::<(), ferrdb::DbError>(())
+//! ```
+//!
+//! `Database::open(path)` で開いたデータベースは、書き込みのたびにファイルへ保存される。
+
+mod btree;
+mod database;
+mod error;
+mod executor;
+pub mod parser;
+mod row;
+mod statement;
+mod stats;
+mod table;
+mod trigger;
+mod value;
+
+pub use btree::BPlusTree;
+pub use database::{Database, QueryResult, View};
+pub use error::DbError;
+pub use row::{FromValue, Row, RowIndex, Rows};
+pub use statement::{ParamIndex, Statement};
+pub use stats::{ColumnStats, TableStats};
+pub use table::Table;
+pub use trigger::Trigger;
+pub use value::Value;
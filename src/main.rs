@@ -1,48 +1,550 @@
-mod database;
-mod table;
-mod btree;
-mod parser;
-mod utils;
+use ferrdb::parser::{is_complete, Query};
+use ferrdb::{Database, DbError, QueryResult, Value};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor, Helper};
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
 
-use database::Database;
-// use parser::{parse_create_table, parse_insert_into, parse_select_table, parse_select_where};
-use parser::{parse_query, Query, ParserError};
-use std::io::{self, Write};
+const USAGE: &str = "Usage: ferrdb [OPTIONS] [DB_FILE]
+
+Opens DB_FILE (default: db.json) and reads SQL statements from the terminal or standard input.
+
+Options:
+  -c, --command SQL    Run SQL and exit instead of reading input (can be repeated)
+      --init FILE      Run the statements in FILE before anything else
+      --readonly       Open the database read-only; statements that write fail
+      --format FORMAT  Print results as text (default), table, csv or json
+      --no-autosave    Save only on .save and on exit, not after every statement
+  -h, --help           Show this help";
+
+/// タブ補完の候補にするキーワード。
+const KEYWORDS: &[&str] = &[
+    "SELECT", "FROM", "WHERE", "GROUP", "BY", "HAVING", "ORDER", "LIMIT", "OFFSET", "ASC", "DESC", "DISTINCT", "AS",
+    "JOIN", "INNER", "LEFT", "RIGHT", "FULL", "OUTER", "CROSS", "ON", "AND", "OR", "NOT", "NULL", "IS", "IN", "EXISTS",
+    "UNION", "INTERSECT", "EXCEPT", "ALL", "WITH", "CASE", "WHEN", "THEN", "ELSE", "END", "OVER",
+    "INSERT", "INTO", "VALUES", "DEFAULT", "CONFLICT", "DO", "NOTHING", "REPLACE", "RETURNING", "UPDATE", "SET", "DELETE",
+    "CREATE", "TABLE", "VIEW", "MATERIALIZED", "REFRESH", "TRIGGER", "BEFORE", "AFTER", "FOR", "EACH", "ROW", "BEGIN",
+    "REFERENCES", "FOREIGN", "KEY", "CASCADE", "RESTRICT", "EXPLAIN", "ANALYZE", "SHOW", "TABLES", "DESCRIBE",
+    "COUNT", "SUM", "AVG", "MIN", "MAX",
+];
+
+/// 結果の表の表示形式。
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    /// `Columns: [...]` と `Row: [...]` の行
+    Text,
+    /// 桁をそろえた表
+    Table,
+    Csv,
+    /// 結果ごとに、行をオブジェクトにした JSON の配列
+    Json,
+}
+
+/// コマンドライン引数。
+struct Options {
+    path: String,
+    commands: Vec<String>,
+    init: Option<String>,
+    readonly: bool,
+    format: Format,
+    autosave: bool,
+}
+
+impl Options {
+    /// 引数を解析する。`--help` ならヘルプを表示して終了する。
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options {
+            path: "db.json".to_string(),
+            commands: Vec::new(),
+            init: None,
+            readonly: false,
+            format: Format::Text,
+            autosave: true,
+        };
+        let mut path = None;
+        while let Some(arg) = args.next() {
+            // `--format=csv` の形も受け付ける
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+                _ => (arg.clone(), None),
+            };
+            let mut value = || inline.clone().or_else(|| args.next()).ok_or(format!("{} needs a value", flag));
+            match flag.as_str() {
+                "-c" | "--command" => options.commands.push(value()?),
+                "--init" => options.init = Some(value()?),
+                "--format" => {
+                    options.format = match value()?.to_lowercase().as_str() {
+                        "text" => Format::Text,
+                        "table" => Format::Table,
+                        "csv" => Format::Csv,
+                        "json" => Format::Json,
+                        other => return Err(format!("Unknown format '{}' (expected text, table, csv or json)", other)),
+                    }
+                }
+                "--readonly" => options.readonly = true,
+                "--no-autosave" => options.autosave = false,
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                _ if arg.starts_with('-') => return Err(format!("Unknown option '{}'", arg)),
+                _ if path.is_none() => path = Some(arg),
+                _ => return Err(format!("Unexpected argument '{}'", arg)),
+            }
+        }
+        if let Some(path) = path {
+            options.path = path;
+        }
+        Ok(options)
+    }
+}
+
+/// 開いているデータベースと表示の設定。
+struct Shell {
+    db: Database,
+    format: Format,
+    /// テーブルかビューを作ったので、補完の候補を作り直す必要がある。
+    catalog_changed: bool,
+}
 
 fn main() {
-    let mut db = Database::new();
-    let db_file_path = "db.json";
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+    // 壊れたファイルを上書きしないよう、読み込めなければ起動しない
+    let opened = if options.readonly { Database::open_readonly(&options.path) } else { Database::open(&options.path) };
+    let mut db = match opened {
+        Ok(db) => db,
+        Err(e) => {
+            print_error(&e);
+            std::process::exit(1);
+        }
+    };
+    db.set_autosave(options.autosave);
+    let mut shell = Shell { db, format: options.format, catalog_changed: true };
 
-    db.load_data(db_file_path);
+    if let Some(init) = &options.init {
+        if !shell.run_command(&format!(".read {}", init)) {
+            shell.exit(false);
+        }
+    }
+    // -c があれば、その文だけを実行して終了する
+    if !options.commands.is_empty() {
+        let ok = options.commands.iter().all(|sql| shell.run_script(sql));
+        shell.exit(ok);
+    }
 
-    println!("Welcome to FerrDB CLI. Type 'exit' or 'quit' to quit.");
-    let mut input = String::new();
+    // `ferrdb < script.sql` のように入力が端末でなければ、プロンプトを出さずに最初のエラーで終了する
+    let interactive = io::stdin().is_terminal();
+    let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".ferrdb_history"));
+    let mut editor = None;
+    if interactive {
+        println!("Welcome to FerrDB CLI. Type 'exit' or 'quit' to quit.");
+        editor = new_editor(history.as_ref());
+    }
+    // セミコロンで終わるまで、複数行にわたる文をためておく
+    let mut buffer = String::new();
+    let mut failed = false;
 
     loop {
-        print!("> ");
-        io::stdout().flush().unwrap();
-        input.clear();
-        if io::stdin().read_line(&mut input).is_err() {
-            println!("Error reading input");
-            continue;
+        let prompt = if buffer.trim().is_empty() { "> " } else { "...> " };
+        let input = match &mut editor {
+            Some(editor) => {
+                // 前の文で作ったテーブルやカラムも補完できるよう、作ったときだけ候補を作り直す
+                if std::mem::take(&mut shell.catalog_changed) {
+                    editor.set_helper(Some(SqlHelper::new(&shell.db)));
+                }
+                match editor.readline(prompt) {
+                    Ok(line) => {
+                        if !line.trim().is_empty() {
+                            let _ = editor.add_history_entry(line.as_str());
+                        }
+                        line
+                    }
+                    // Ctrl-C は入力中の文を取り消す
+                    Err(ReadlineError::Interrupted) => {
+                        buffer.clear();
+                        continue;
+                    }
+                    Err(ReadlineError::Eof) => break,
+                    Err(e) => {
+                        println!("Error reading input: {}", e);
+                        break;
+                    }
+                }
+            }
+            None => {
+                if interactive {
+                    print!("{}", prompt);
+                    io::stdout().flush().unwrap();
+                }
+                let mut line = String::new();
+                match io::stdin().read_line(&mut line) {
+                    Ok(0) => break,
+                    Ok(_) => line.trim_end_matches(['\n', '\r']).to_string(),
+                    Err(_) => {
+                        println!("Error reading input");
+                        continue;
+                    }
+                }
+            }
+        };
+
+        // 文の途中でなければ、exit / quit と `.` で始まるコマンドを受け付ける
+        if buffer.trim().is_empty() {
+            let command_line = input.trim().trim_end_matches(';').trim();
+            if command_line.eq_ignore_ascii_case("exit") || command_line.eq_ignore_ascii_case("quit") {
+                break;
+            }
+            if command_line.starts_with('.') {
+                if !shell.run_command(command_line) && !interactive {
+                    failed = true;
+                    break;
+                }
+                continue;
+            }
+        }
+
+        buffer.push_str(&input);
+        buffer.push('\n');
+        if is_complete(&buffer) {
+            let script = std::mem::take(&mut buffer);
+            if !shell.run_script(&script) && !interactive {
+                failed = true;
+                break;
+            }
         }
+    }
+
+    // 最後の文にセミコロンがなくても実行する
+    if !failed && !shell.run_script(&buffer) && !interactive {
+        failed = true;
+    }
+    if let (Some(editor), Some(history)) = (&mut editor, &history) {
+        let _ = editor.save_history(history);
+    }
+    if interactive {
+        println!("Goodbye!");
+    }
+    shell.exit(!failed);
+}
+
+/// 端末用の行エディタを作り、履歴ファイルがあれば読み込む。作れなければ None で、標準入力から読む。
+fn new_editor(history: Option<&PathBuf>) -> Option<Editor<SqlHelper, FileHistory>> {
+    let config = Config::builder()
+        .max_history_size(1000)
+        .and_then(|builder| builder.history_ignore_dups(true))
+        .ok()?
+        .completion_type(CompletionType::List)
+        .build();
+    let mut editor = Editor::with_config(config).ok()?;
+    if let Some(history) = history {
+        let _ = editor.load_history(history);
+    }
+    Some(editor)
+}
+
+/// キーワードと、データベースにあるテーブル・ビュー・カラムの名前をタブで補完する。
+/// `table.` の後ではそのテーブルのカラムだけを候補にする。
+struct SqlHelper {
+    /// テーブルとビューの名前と、そのカラム
+    relations: Vec<(String, Vec<String>)>,
+}
+
+impl SqlHelper {
+    fn new(db: &Database) -> Self {
+        let mut names = db.table_names();
+        names.extend(db.view_names());
+        names.sort();
+        names.dedup();
+        let relations = names.into_iter().map(|name| (name.to_string(), db.relation_columns(name))).collect();
+        SqlHelper { relations }
+    }
+
+    /// `word` で始まる候補。キーワードは `word` が小文字なら小文字にする。
+    fn candidates(&self, word: &str) -> Vec<String> {
+        let matches = |name: &str| name.len() > word.len() && name.to_lowercase().starts_with(&word.to_lowercase());
+        let lowercase = word.chars().all(|c| !c.is_uppercase());
+        let mut candidates: Vec<String> = KEYWORDS
+            .iter()
+            .filter(|kw| matches(kw))
+            .map(|kw| if lowercase { kw.to_lowercase() } else { kw.to_string() })
+            .collect();
+        for (name, columns) in &self.relations {
+            candidates.extend(std::iter::once(name).chain(columns).filter(|n| matches(n)).cloned());
+        }
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+}
+
+impl Completer for SqlHelper {
+    type Candidate = String;
 
-        let command_line = input.trim_end_matches(';').trim();
-        if command_line.eq_ignore_ascii_case("exit") || command_line.eq_ignore_ascii_case("quit") {
-            break;
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let before = &line[..pos];
+        let start = before
+            .char_indices()
+            .rev()
+            .find(|&(_, c)| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let word = &before[start..];
+        if let Some((relation, prefix)) = word.rsplit_once('.') {
+            let columns = self
+                .relations
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(relation))
+                .map_or_else(Vec::new, |(_, columns)| {
+                    columns.iter().filter(|c| c.to_lowercase().starts_with(&prefix.to_lowercase())).cloned().collect()
+                });
+            return Ok((pos - prefix.len(), columns));
         }
-        
-        match parse_query(command_line) {
-            Ok(query) => {
-                db.execute_query(query);
-                db.save_data(&db_file_path);
+        if word.is_empty() {
+            return Ok((pos, Vec::new()));
+        }
+        Ok((start, self.candidates(word)))
+    }
+}
+
+impl Hinter for SqlHelper {
+    type Hint = String;
+}
+
+impl Highlighter for SqlHelper {}
+
+impl Validator for SqlHelper {}
+
+impl Helper for SqlHelper {}
+
+impl Shell {
+    /// 保存して終了する。失敗なら終了コードは 1 になり、`--no-autosave` なら保存もしない。
+    fn exit(&self, ok: bool) -> ! {
+        if ok || self.db.is_autosave() {
+            if let Err(e) = self.db.save() {
+                print_error(&e);
+                std::process::exit(1);
             }
+        }
+        std::process::exit(if ok { 0 } else { 1 })
+    }
+
+    /// `.read file.sql` のような `.` で始まるコマンドを実行する。失敗すれば false を返す。
+    fn run_command(&mut self, command_line: &str) -> bool {
+        let (command, argument) = command_line.split_once(char::is_whitespace).unwrap_or((command_line, ""));
+        let argument = argument.trim();
+        match command {
+            ".read" if !argument.is_empty() => match fs::read_to_string(argument) {
+                Ok(script) => self.run_script(&script),
+                Err(e) => {
+                    eprintln!("Error: cannot read '{}': {}", argument, e);
+                    false
+                }
+            },
+            ".read" => {
+                eprintln!("Usage: .read FILE");
+                false
+            }
+            ".save" => match self.db.save() {
+                Ok(()) => true,
+                Err(e) => {
+                    print_error(&e);
+                    false
+                }
+            },
+            _ => {
+                eprintln!("Error: unknown command '{}'", command);
+                false
+            }
+        }
+    }
+
+    /// `Database::execute_batch_with` でスクリプトの文を順に実行し、結果を表示する。エラーになった文で止まり、false を返す。
+    fn run_script(&mut self, script: &str) -> bool {
+        let (format, catalog_changed) = (self.format, &mut self.catalog_changed);
+        let result = self.db.execute_batch_with(script, |query, result| {
+            *catalog_changed |= matches!(query, Query::CreateTable { .. } | Query::CreateView { .. });
+            format.print_result(query, result)
+        });
+        match result {
+            Ok(()) => true,
             Err(e) => {
-                print!("Error: {:?}", e);
+                print_error(&e);
+                false
+            }
+        }
+    }
+}
+
+impl Format {
+    /// 実行結果を表示する。表があれば表示し、書き込み系の文では完了メッセージを表示する。
+    /// csv と json では結果の表だけを表示する。
+    fn print_result(self, query: &Query, result: &QueryResult) {
+        if matches!(self, Format::Text | Format::Table) {
+            print_message(query, result);
+        }
+        if result.columns.is_empty() {
+            return;
+        }
+        match self {
+            Format::Text => {
+                println!("Columns: {:?}", result.columns);
+                for row in &result.rows {
+                    let row: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+                    println!("Row: {:?}", row);
+                }
+            }
+            Format::Table => print_table(result),
+            Format::Csv => {
+                println!("{}", result.columns.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(","));
+                for row in &result.rows {
+                    let fields: Vec<String> = row
+                        .iter()
+                        .map(|v| if *v == Value::Null { String::new() } else { csv_field(&v.to_string()) })
+                        .collect();
+                    println!("{}", fields.join(","));
+                }
+            }
+            Format::Json => {
+                let rows: Vec<serde_json::Value> = result
+                    .rows
+                    .iter()
+                    .map(|row| serde_json::Value::Object(result.columns.iter().cloned().zip(row.iter().map(json_value)).collect()))
+                    .collect();
+                println!("{}", serde_json::Value::Array(rows));
             }
         }
     }
+}
+
+/// 書き込み系の文の完了メッセージを表示する。
+fn print_message(query: &Query, result: &QueryResult) {
+    match query {
+        Query::CreateTable { table_name, .. } => println!("Table '{}' created.", table_name),
+        Query::Insert(insert) => println!("{} row(s) inserted into '{}'.", result.rows_affected, insert.table_name),
+        Query::Update(update) => println!("{} row(s) updated in '{}'.", result.rows_affected, update.table_name),
+        Query::Delete(delete) => println!("{} row(s) deleted from '{}'.", result.rows_affected, delete.table_name),
+        Query::Analyze { table: Some(table) } => println!("Table '{}' analyzed.", table),
+        Query::Analyze { table: None } => println!("All tables analyzed."),
+        Query::CreateView { name, materialized: false, .. } => println!("View '{}' created.", name),
+        Query::CreateView { name, materialized: true, .. } => println!("Materialized view '{}' created.", name),
+        Query::CreateTrigger { name, .. } => println!("Trigger '{}' created.", name),
+        Query::RefreshMaterializedView { name } => {
+            println!("Materialized view '{}' refreshed ({} row(s)).", name, result.rows_affected)
+        }
+        Query::Select(_)
+        | Query::Explain { .. }
+        | Query::ShowTables
+        | Query::Describe { .. }
+        | Query::ShowCreateTable { .. } => {}
+        Query::Begin => println!("Transaction started."),
+        Query::Commit => println!("Transaction committed."),
+        Query::Rollback => println!("Transaction rolled back."),
+    }
+}
+
+/// 結果を桁をそろえた表にして表示する。数値は右寄せにする。
+fn print_table(result: &QueryResult) {
+    let cells: Vec<Vec<String>> = result.rows.iter().map(|row| row.iter().map(|v| v.to_string()).collect()).collect();
+    let widths: Vec<usize> = result
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| cells.iter().map(|row| row[i].chars().count()).fold(column.chars().count(), usize::max))
+        .collect();
+    let border = format!("+{}+", widths.iter().map(|w| "-".repeat(w + 2)).collect::<Vec<_>>().join("+"));
+    let line = |fields: Vec<String>| format!("| {} |", fields.join(" | "));
+    println!("{}", border);
+    println!("{}", line(result.columns.iter().zip(&widths).map(|(c, w)| format!("{:<w$}", c, w = w)).collect()));
+    println!("{}", border);
+    for (row, values) in cells.iter().zip(&result.rows) {
+        let fields = row
+            .iter()
+            .zip(values)
+            .zip(&widths)
+            .map(|((cell, value), w)| match value {
+                Value::Integer(_) | Value::Float(_) => format!("{:>w$}", cell, w = w),
+                _ => format!("{:<w$}", cell, w = w),
+            })
+            .collect();
+        println!("{}", line(fields));
+    }
+    println!("{}", border);
+    println!("({} row(s))", result.rows.len());
+}
 
-    db.save_data(db_file_path);
-    println!("Goodbye!");
+/// CSV のフィールドにする。カンマ・引用符・改行を含むなら二重引用符で囲む。
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn json_value(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Boolean(b) => (*b).into(),
+        Value::Integer(i) => (*i).into(),
+        Value::Float(x) => (*x).into(),
+        Value::Text(s) => s.clone().into(),
+    }
+}
+
+fn print_error(e: &DbError) {
+    eprintln!("Error [{}]: {}", e.code(), e);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KEYWORDS;
+    use ferrdb::parser::lexer::{tokenize, Token};
+    use ferrdb::parser::parse_query;
+
+    #[test]
+    fn test_keywords_parse() {
+        // 補完した語が構文エラーにならないよう、どのキーワードも解析できる文のどこかで使われていること
+        let statements = [
+            "WITH t AS (SELECT id AS n FROM u) SELECT DISTINCT n FROM t WHERE n IS NOT NULL AND n IN (1) OR NOT EXISTS (SELECT 1 FROM u) \
+             GROUP BY n HAVING COUNT(*) > 0 ORDER BY n ASC, n DESC LIMIT 1 OFFSET 0",
+            "SELECT SUM(a.x), AVG(a.x), MIN(a.x), MAX(a.x), CASE WHEN a.x = 1 THEN 1 ELSE 0 END FROM a INNER JOIN b ON a.id = b.id \
+             LEFT JOIN c ON a.id = c.id RIGHT JOIN d ON a.id = d.id FULL OUTER JOIN e ON a.id = e.id CROSS JOIN f",
+            "SELECT x, ROW_NUMBER() OVER (ORDER BY x) FROM a UNION ALL SELECT x, y FROM b INTERSECT SELECT x, y FROM c EXCEPT SELECT x, y FROM d",
+            "INSERT INTO t VALUES (1, 2) ON CONFLICT (id) DO NOTHING RETURNING *",
+            "INSERT OR REPLACE INTO t VALUES (1, 2)",
+            "UPDATE t SET x = 1",
+            "DELETE FROM t",
+            "CREATE TABLE t (id, x DEFAULT 0, y REFERENCES u ON DELETE CASCADE, FOREIGN KEY (x) REFERENCES u ON UPDATE RESTRICT)",
+            "CREATE VIEW v AS SELECT id FROM u",
+            "CREATE MATERIALIZED VIEW m AS SELECT id FROM u",
+            "REFRESH MATERIALIZED VIEW m",
+            "CREATE TRIGGER g BEFORE INSERT ON t FOR EACH ROW BEGIN DELETE FROM u; END",
+            "CREATE TRIGGER h AFTER UPDATE ON t FOR EACH ROW BEGIN DELETE FROM u; END",
+            "EXPLAIN ANALYZE SELECT id FROM u",
+            "SHOW TABLES",
+            "DESCRIBE t",
+        ];
+        let mut words = Vec::new();
+        for sql in statements {
+            parse_query(sql).unwrap_or_else(|e| panic!("{}: {}", sql, e));
+            words.extend(tokenize(sql).unwrap().into_iter().filter_map(|t| match t {
+                Token::Ident(word) => Some(word.to_uppercase()),
+                _ => None,
+            }));
+        }
+        for keyword in KEYWORDS {
+            assert!(words.iter().any(|w| w == keyword), "{} is not used by any statement", keyword);
+        }
+    }
 }
@@ -0,0 +1,15 @@
+use crate::error::DbError;
+use crate::parser::{Parser, Query};
+
+/// Parse an ANALYZE command. Without a table name every table is analyzed.
+/// Examples:
+///   "ANALYZE;"
+///   "ANALYZE users;"
+pub fn parse_analyze(mut parser: Parser) -> Result<Query, DbError> {
+    if !parser.consume_keyword("ANALYZE") {
+        return Err(DbError::Syntax("Not an ANALYZE command".into()));
+    }
+    let table = if parser.peek_end() { None } else { Some(parser.parse_identifier()?) };
+    parser.expect_end()?;
+    Ok(Query::Analyze { table })
+}
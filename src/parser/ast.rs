@@ -0,0 +1,1030 @@
+use crate::value::Value;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A scalar expression appearing in a select list, WHERE or HAVING clause.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    /// A bind parameter of a prepared statement, numbered from 0.
+    Parameter(usize),
+    /// A column reference, optionally qualified by a table name or alias (`u.id`).
+    Column {
+        table: Option<String>,
+        name: String,
+    },
+    Unary {
+        op: UnaryOperator,
+        expr: Box<Expr>,
+    },
+    Binary {
+        left: Box<Expr>,
+        op: BinaryOperator,
+        right: Box<Expr>,
+    },
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    /// An aggregate call. `arg` is `None` for `COUNT(*)`.
+    Aggregate {
+        func: AggregateFunction,
+        arg: Option<Box<Expr>>,
+        distinct: bool,
+    },
+    /// `expr [NOT] IN (value, ...)`
+    InList {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    /// `expr [NOT] IN (SELECT ...)`
+    InSubquery {
+        expr: Box<Expr>,
+        subquery: Box<SelectStatement>,
+        negated: bool,
+    },
+    /// `EXISTS (SELECT ...)`
+    Exists(Box<SelectStatement>),
+    /// A scalar subquery `(SELECT ...)` returning at most one row of one column.
+    Subquery(Box<SelectStatement>),
+    /// A scalar function call such as `UPPER(name)`.
+    Function {
+        func: ScalarFunction,
+        args: Vec<Expr>,
+    },
+    /// `CASE [operand] WHEN ... THEN ... [ELSE ...] END`. Without an operand each
+    /// WHEN is a condition; with one, it is compared to the operand.
+    Case {
+        operand: Option<Box<Expr>>,
+        branches: Vec<(Expr, Expr)>,
+        else_result: Option<Box<Expr>>,
+    },
+    /// `CAST(expr AS type)`
+    Cast {
+        expr: Box<Expr>,
+        data_type: DataType,
+    },
+    /// A window function call `func(args) OVER (...)`. For `COUNT(*) OVER (...)`
+    /// `args` is empty.
+    Window {
+        func: WindowFunction,
+        args: Vec<Expr>,
+        over: Box<WindowSpec>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Not,
+    Minus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    And,
+    Or,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Modulo,
+    /// String concatenation `||`
+    Concat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl BinaryOperator {
+    /// Binding strength, used to put parentheses back when rendering expressions.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Eq
+            | BinaryOperator::Ne
+            | BinaryOperator::Gt
+            | BinaryOperator::Lt
+            | BinaryOperator::Ge
+            | BinaryOperator::Le => 4,
+            BinaryOperator::Concat => 5,
+            BinaryOperator::Plus | BinaryOperator::Minus => 6,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScalarFunction {
+    Upper,
+    Lower,
+    Length,
+    Substr,
+    Trim,
+    Ltrim,
+    Rtrim,
+    Replace,
+    Abs,
+    Round,
+    Ceil,
+    Floor,
+    Sqrt,
+    Power,
+    Mod,
+    Coalesce,
+    Nullif,
+}
+
+impl ScalarFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "UPPER" => Some(ScalarFunction::Upper),
+            "LOWER" => Some(ScalarFunction::Lower),
+            "LENGTH" => Some(ScalarFunction::Length),
+            "SUBSTR" | "SUBSTRING" => Some(ScalarFunction::Substr),
+            "TRIM" => Some(ScalarFunction::Trim),
+            "LTRIM" => Some(ScalarFunction::Ltrim),
+            "RTRIM" => Some(ScalarFunction::Rtrim),
+            "REPLACE" => Some(ScalarFunction::Replace),
+            "ABS" => Some(ScalarFunction::Abs),
+            "ROUND" => Some(ScalarFunction::Round),
+            "CEIL" | "CEILING" => Some(ScalarFunction::Ceil),
+            "FLOOR" => Some(ScalarFunction::Floor),
+            "SQRT" => Some(ScalarFunction::Sqrt),
+            "POWER" | "POW" => Some(ScalarFunction::Power),
+            "MOD" => Some(ScalarFunction::Mod),
+            "COALESCE" => Some(ScalarFunction::Coalesce),
+            "NULLIF" => Some(ScalarFunction::Nullif),
+            _ => None,
+        }
+    }
+
+    /// The minimum and maximum number of arguments. `None` means no upper limit.
+    pub fn arity(&self) -> (usize, Option<usize>) {
+        match self {
+            ScalarFunction::Upper
+            | ScalarFunction::Lower
+            | ScalarFunction::Length
+            | ScalarFunction::Abs
+            | ScalarFunction::Ceil
+            | ScalarFunction::Floor
+            | ScalarFunction::Sqrt => (1, Some(1)),
+            ScalarFunction::Trim | ScalarFunction::Ltrim | ScalarFunction::Rtrim | ScalarFunction::Round => {
+                (1, Some(2))
+            }
+            ScalarFunction::Substr => (2, Some(3)),
+            ScalarFunction::Power | ScalarFunction::Mod | ScalarFunction::Nullif => (2, Some(2)),
+            ScalarFunction::Replace => (3, Some(3)),
+            ScalarFunction::Coalesce => (1, None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowFunction {
+    RowNumber,
+    Rank,
+    DenseRank,
+    Lag,
+    Lead,
+    FirstValue,
+    LastValue,
+    /// An aggregate function used with OVER, computed over the window frame.
+    Aggregate(AggregateFunction),
+}
+
+impl WindowFunction {
+    /// Functions that can only be used with OVER. Aggregates are looked up separately.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "ROW_NUMBER" => Some(WindowFunction::RowNumber),
+            "RANK" => Some(WindowFunction::Rank),
+            "DENSE_RANK" => Some(WindowFunction::DenseRank),
+            "LAG" => Some(WindowFunction::Lag),
+            "LEAD" => Some(WindowFunction::Lead),
+            "FIRST_VALUE" => Some(WindowFunction::FirstValue),
+            "LAST_VALUE" => Some(WindowFunction::LastValue),
+            _ => None,
+        }
+    }
+
+    /// The minimum and maximum number of arguments.
+    pub fn arity(&self) -> (usize, usize) {
+        match self {
+            WindowFunction::RowNumber | WindowFunction::Rank | WindowFunction::DenseRank => (0, 0),
+            WindowFunction::Lag | WindowFunction::Lead => (1, 3),
+            WindowFunction::FirstValue | WindowFunction::LastValue => (1, 1),
+            WindowFunction::Aggregate(AggregateFunction::Count) => (0, 1),
+            WindowFunction::Aggregate(_) => (1, 1),
+        }
+    }
+}
+
+/// `OVER ([PARTITION BY ...] [ORDER BY ...] [ROWS ...])`
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowSpec {
+    pub partition_by: Vec<Expr>,
+    pub order_by: Vec<OrderByExpr>,
+    /// `None` uses the default frame: the whole partition without ORDER BY, otherwise
+    /// everything from the start of the partition up to the current row and its peers.
+    pub frame: Option<WindowFrame>,
+}
+
+/// `ROWS BETWEEN start AND end`. Only the ROWS mode is supported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowFrame {
+    pub start: FrameBound,
+    pub end: FrameBound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    Preceding(u64),
+    CurrentRow,
+    Following(u64),
+    UnboundedFollowing,
+}
+
+/// Target types of CAST.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataType {
+    Integer,
+    Float,
+    Text,
+    Boolean,
+}
+
+impl DataType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "INTEGER" | "INT" | "BIGINT" => Some(DataType::Integer),
+            "FLOAT" | "REAL" | "DOUBLE" | "NUMERIC" | "DECIMAL" => Some(DataType::Float),
+            "TEXT" | "VARCHAR" | "CHAR" | "STRING" => Some(DataType::Text),
+            "BOOLEAN" | "BOOL" => Some(DataType::Boolean),
+            _ => None,
+        }
+    }
+}
+
+impl AggregateFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "COUNT" => Some(AggregateFunction::Count),
+            "SUM" => Some(AggregateFunction::Sum),
+            "AVG" => Some(AggregateFunction::Avg),
+            "MIN" => Some(AggregateFunction::Min),
+            "MAX" => Some(AggregateFunction::Max),
+            _ => None,
+        }
+    }
+}
+
+impl Expr {
+    /// The expressions directly nested in this one. Subqueries are not included;
+    /// their expressions belong to the subquery.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Literal(_) | Expr::Parameter(_) | Expr::Column { .. } | Expr::Exists(_) | Expr::Subquery(_) => Vec::new(),
+            Expr::Unary { expr, .. }
+            | Expr::IsNull { expr, .. }
+            | Expr::InSubquery { expr, .. }
+            | Expr::Cast { expr, .. } => vec![expr],
+            Expr::Binary { left, right, .. } => vec![left, right],
+            Expr::Aggregate { arg, .. } => arg.iter().map(|a| a.as_ref()).collect(),
+            Expr::InList { expr, list, .. } => std::iter::once(expr.as_ref()).chain(list).collect(),
+            Expr::Function { args, .. } => args.iter().collect(),
+            Expr::Window { args, over, .. } => args
+                .iter()
+                .chain(&over.partition_by)
+                .chain(over.order_by.iter().map(|ob| &ob.expr))
+                .collect(),
+            Expr::Case { operand, branches, else_result } => operand
+                .iter()
+                .map(|e| e.as_ref())
+                .chain(branches.iter().flat_map(|(when, then)| [when, then]))
+                .chain(else_result.iter().map(|e| e.as_ref()))
+                .collect(),
+        }
+    }
+
+    /// Mutable version of [`Expr::children`], used by rewrites such as constant folding.
+    pub fn children_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Expr::Literal(_) | Expr::Parameter(_) | Expr::Column { .. } | Expr::Exists(_) | Expr::Subquery(_) => Vec::new(),
+            Expr::Unary { expr, .. }
+            | Expr::IsNull { expr, .. }
+            | Expr::InSubquery { expr, .. }
+            | Expr::Cast { expr, .. } => vec![expr],
+            Expr::Binary { left, right, .. } => vec![left, right],
+            Expr::Aggregate { arg, .. } => arg.iter_mut().map(|a| a.as_mut()).collect(),
+            Expr::InList { expr, list, .. } => std::iter::once(expr.as_mut()).chain(list).collect(),
+            Expr::Function { args, .. } => args.iter_mut().collect(),
+            Expr::Window { args, over, .. } => args
+                .iter_mut()
+                .chain(&mut over.partition_by)
+                .chain(over.order_by.iter_mut().map(|ob| &mut ob.expr))
+                .collect(),
+            Expr::Case { operand, branches, else_result } => operand
+                .iter_mut()
+                .map(|e| e.as_mut())
+                .chain(branches.iter_mut().flat_map(|(when, then)| [when, then]))
+                .chain(else_result.iter_mut().map(|e| e.as_mut()))
+                .collect(),
+        }
+    }
+
+    /// Calls `f` on this expression and every expression nested in it, without
+    /// descending into subqueries.
+    pub fn walk(&self, f: &mut impl FnMut(&Expr)) {
+        f(self);
+        for child in self.children() {
+            child.walk(f);
+        }
+    }
+
+    /// Returns true if this expression contains an aggregate call anywhere inside it.
+    /// Aggregates inside a subquery belong to the subquery and are not counted.
+    pub fn contains_aggregate(&self) -> bool {
+        let mut found = false;
+        self.walk(&mut |e| found |= matches!(e, Expr::Aggregate { .. }));
+        found
+    }
+
+    /// Returns true if this expression contains a window function call.
+    pub fn contains_window(&self) -> bool {
+        let mut found = false;
+        self.walk(&mut |e| found |= matches!(e, Expr::Window { .. }));
+        found
+    }
+}
+
+/// One entry of the select list.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    Wildcard,
+    /// `alias.*`
+    QualifiedWildcard(String),
+    /// `expr [[AS] alias]`
+    Expr { expr: Expr, alias: Option<String> },
+}
+
+
+/// An entry of the FROM clause: a table with an optional alias (`users AS u` or `users u`),
+/// or a derived table `(SELECT ...) AS alias`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableRef {
+    Table { name: String, alias: Option<String> },
+    Derived { subquery: Box<SelectStatement>, alias: String },
+}
+
+impl TableRef {
+    /// The name columns of this table are qualified with: the alias if given, otherwise the table name
+    /// without its schema (`tables` for `information_schema.tables`).
+    pub fn qualifier(&self) -> &str {
+        match self {
+            TableRef::Table { name, alias } => alias.as_deref().unwrap_or_else(|| name.rsplit('.').next().unwrap_or(name)),
+            TableRef::Derived { alias, .. } => alias,
+        }
+    }
+
+    /// The table name, or the alias of a derived table.
+    pub fn name(&self) -> &str {
+        match self {
+            TableRef::Table { name, .. } => name,
+            TableRef::Derived { alias, .. } => alias,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Right,
+    Full,
+    Cross,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+    pub kind: JoinKind,
+    pub table: TableRef,
+    /// The ON condition. Always `None` for CROSS JOIN.
+    pub constraint: Option<Expr>,
+}
+
+/// A complete SELECT statement, as used at the top level and in subqueries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectStatement {
+    pub with: Option<With>,
+    pub body: SetExpr,
+    pub order_by: Vec<OrderByExpr>,
+    pub limit: Option<Expr>,
+    pub offset: Option<Expr>,
+}
+
+/// One key of ORDER BY: `expr [ASC | DESC] [NULLS FIRST | NULLS LAST]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderByExpr {
+    pub expr: Expr,
+    pub descending: bool,
+    /// `None` uses the default: NULLs sort as the smallest value.
+    pub nulls_first: Option<bool>,
+}
+
+impl OrderByExpr {
+    pub fn nulls_first(&self) -> bool {
+        self.nulls_first.unwrap_or(!self.descending)
+    }
+}
+
+/// `WITH [RECURSIVE] name [(columns)] AS (SELECT ...), ...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct With {
+    pub recursive: bool,
+    pub ctes: Vec<Cte>,
+}
+
+/// One common table expression of a WITH clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cte {
+    pub name: String,
+    /// Optional column names overriding those of the query.
+    pub columns: Vec<String>,
+    pub query: SelectStatement,
+}
+
+/// The body of a SELECT statement: a single SELECT block, or blocks combined
+/// with UNION / INTERSECT / EXCEPT.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetExpr {
+    Select(Box<Select>),
+    SetOperation {
+        left: Box<SetExpr>,
+        op: SetOperator,
+        /// `true` for the `ALL` variants, which keep duplicate rows.
+        all: bool,
+        right: Box<SetExpr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOperator {
+    Union,
+    Intersect,
+    Except,
+}
+
+impl SetOperator {
+    /// INTERSECT binds more tightly than UNION and EXCEPT, as in standard SQL.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            SetOperator::Union | SetOperator::Except => 1,
+            SetOperator::Intersect => 2,
+        }
+    }
+}
+
+/// `DISTINCT` or `DISTINCT ON (expr, ...)` after SELECT.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Distinct {
+    /// Remove rows whose output values are all equal.
+    Row,
+    /// Keep only the first row for each value of the expressions.
+    On(Vec<Expr>),
+}
+
+/// A single SELECT block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    pub distinct: Option<Distinct>,
+    pub select_columns: Vec<SelectItem>,
+    pub from: TableRef,
+    pub joins: Vec<Join>,
+    pub condition: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+}
+
+/// A column of CREATE TABLE: `name [DEFAULT expr]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    pub default: Option<Expr>,
+}
+
+/// `column REFERENCES table [(column)] [ON DELETE action] [ON UPDATE action] [DEFERRABLE INITIALLY DEFERRED]`,
+/// written after a column definition or as a table-level `FOREIGN KEY (column) REFERENCES ...`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForeignKey {
+    pub column: String,
+    pub table: String,
+    /// The referenced column; `None` means the primary key of `table`.
+    pub referenced: Option<String>,
+    pub on_delete: ReferentialAction,
+    pub on_update: ReferentialAction,
+    /// `INITIALLY DEFERRED`: checked once the statement and its triggers have finished
+    /// instead of right after each write.
+    #[serde(default)]
+    pub deferred: bool,
+}
+
+/// What happens to referencing rows when the referenced row is deleted or its key changes.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ReferentialAction {
+    /// Fail if referencing rows remain (the default); a deferred constraint checks this at the end.
+    #[default]
+    NoAction,
+    /// Fail right away if referencing rows remain, even when the constraint is deferred.
+    Restrict,
+    Cascade,
+    SetNull,
+}
+
+/// `INSERT INTO table [(columns)] VALUES (...), ... | SELECT ... [ON CONFLICT ...] [RETURNING ...]`.
+/// `columns` is empty when no column list is given, and `returning` when there is no RETURNING clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert {
+    pub table_name: String,
+    pub columns: Vec<String>,
+    pub source: InsertSource,
+    pub on_conflict: Option<OnConflict>,
+    pub returning: Vec<SelectItem>,
+}
+
+/// `UPDATE table [alias] SET column = expr, ... [WHERE ...] [RETURNING ...]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    pub table_name: String,
+    pub alias: Option<String>,
+    pub assignments: Vec<Assignment>,
+    pub condition: Option<Expr>,
+    pub returning: Vec<SelectItem>,
+}
+
+/// `DELETE FROM table [alias] [WHERE ...] [RETURNING ...]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delete {
+    pub table_name: String,
+    pub alias: Option<String>,
+    pub condition: Option<Expr>,
+    pub returning: Vec<SelectItem>,
+}
+
+/// What to do when an inserted row has the same key as an existing row:
+/// `ON CONFLICT [(columns)] DO ...`, or `INSERT OR REPLACE` / `INSERT OR IGNORE`.
+/// `target` is empty when no conflict columns are given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnConflict {
+    pub target: Vec<String>,
+    pub action: ConflictAction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConflictAction {
+    /// Skip the new row (`DO NOTHING`, `INSERT OR IGNORE`).
+    Nothing,
+    /// `DO UPDATE SET ... [WHERE ...]`. The new row is available as `excluded`.
+    Update { assignments: Vec<Assignment>, condition: Option<Expr> },
+    /// Overwrite the existing row with the new one (`INSERT OR REPLACE`).
+    Replace,
+}
+
+/// `column = expr` in a SET clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub column: String,
+    pub value: Expr,
+}
+
+/// When a trigger runs relative to the write that fires it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TriggerTiming {
+    Before,
+    After,
+}
+
+/// The kind of write that fires a trigger.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TriggerEvent {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// Where the rows of an INSERT come from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InsertSource {
+    Values(Vec<Vec<Expr>>),
+    Select(Box<SelectStatement>),
+}
+
+impl fmt::Display for AggregateFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AggregateFunction::Count => "COUNT",
+            AggregateFunction::Sum => "SUM",
+            AggregateFunction::Avg => "AVG",
+            AggregateFunction::Min => "MIN",
+            AggregateFunction::Max => "MAX",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            BinaryOperator::Eq => "=",
+            BinaryOperator::Ne => "<>",
+            BinaryOperator::Gt => ">",
+            BinaryOperator::Lt => "<",
+            BinaryOperator::Ge => ">=",
+            BinaryOperator::Le => "<=",
+            BinaryOperator::And => "AND",
+            BinaryOperator::Or => "OR",
+            BinaryOperator::Plus => "+",
+            BinaryOperator::Minus => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::Concat => "||",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// Renders an expression back to SQL text. Used as the column heading for
+/// select list entries.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(Value::Text(s)) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expr::Literal(v) => write!(f, "{}", v),
+            Expr::Parameter(index) => write!(f, "${}", index + 1),
+            Expr::Column { table: Some(table), name } => write!(f, "{}.{}", table, name),
+            Expr::Column { table: None, name } => write!(f, "{}", name),
+            Expr::Unary { op: UnaryOperator::Not, expr } => write!(f, "NOT {}", Operand(expr, 3)),
+            Expr::Unary { op: UnaryOperator::Minus, expr } => write!(f, "-{}", Operand(expr, u8::MAX)),
+            Expr::Binary { left, op, right } => {
+                let p = op.precedence();
+                write!(f, "{} {} {}", Operand(left, p), op, Operand(right, p + 1))
+            }
+            Expr::IsNull { expr, negated } => {
+                write!(f, "{} IS {}NULL", expr, if *negated { "NOT " } else { "" })
+            }
+            Expr::Aggregate { func, arg, distinct } => {
+                let distinct = if *distinct { "DISTINCT " } else { "" };
+                match arg {
+                    Some(arg) => write!(f, "{}({}{})", func, distinct, arg),
+                    None => write!(f, "{}({}*)", func, distinct),
+                }
+            }
+            Expr::InList { expr, list, negated } => {
+                write!(f, "{} {}IN (", expr, if *negated { "NOT " } else { "" })?;
+                write_list(f, list)?;
+                write!(f, ")")
+            }
+            Expr::InSubquery { expr, subquery, negated } => {
+                write!(f, "{} {}IN ({})", expr, if *negated { "NOT " } else { "" }, subquery)
+            }
+            Expr::Exists(subquery) => write!(f, "EXISTS ({})", subquery),
+            Expr::Subquery(subquery) => write!(f, "({})", subquery),
+            Expr::Function { func, args } => {
+                write!(f, "{}(", func)?;
+                write_list(f, args)?;
+                write!(f, ")")
+            }
+            Expr::Case { operand, branches, else_result } => {
+                write!(f, "CASE")?;
+                if let Some(operand) = operand {
+                    write!(f, " {}", operand)?;
+                }
+                for (when, then) in branches {
+                    write!(f, " WHEN {} THEN {}", when, then)?;
+                }
+                if let Some(else_result) = else_result {
+                    write!(f, " ELSE {}", else_result)?;
+                }
+                write!(f, " END")
+            }
+            Expr::Cast { expr, data_type } => write!(f, "CAST({} AS {})", expr, data_type),
+            Expr::Window { func, args, over } => {
+                write!(f, "{}(", func)?;
+                if args.is_empty() && *func == WindowFunction::Aggregate(AggregateFunction::Count) {
+                    write!(f, "*")?;
+                }
+                write_list(f, args)?;
+                write!(f, ") OVER ({})", over)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ScalarFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ScalarFunction::Upper => "UPPER",
+            ScalarFunction::Lower => "LOWER",
+            ScalarFunction::Length => "LENGTH",
+            ScalarFunction::Substr => "SUBSTR",
+            ScalarFunction::Trim => "TRIM",
+            ScalarFunction::Ltrim => "LTRIM",
+            ScalarFunction::Rtrim => "RTRIM",
+            ScalarFunction::Replace => "REPLACE",
+            ScalarFunction::Abs => "ABS",
+            ScalarFunction::Round => "ROUND",
+            ScalarFunction::Ceil => "CEIL",
+            ScalarFunction::Floor => "FLOOR",
+            ScalarFunction::Sqrt => "SQRT",
+            ScalarFunction::Power => "POWER",
+            ScalarFunction::Mod => "MOD",
+            ScalarFunction::Coalesce => "COALESCE",
+            ScalarFunction::Nullif => "NULLIF",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for WindowFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            WindowFunction::RowNumber => "ROW_NUMBER",
+            WindowFunction::Rank => "RANK",
+            WindowFunction::DenseRank => "DENSE_RANK",
+            WindowFunction::Lag => "LAG",
+            WindowFunction::Lead => "LEAD",
+            WindowFunction::FirstValue => "FIRST_VALUE",
+            WindowFunction::LastValue => "LAST_VALUE",
+            WindowFunction::Aggregate(func) => return write!(f, "{}", func),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for WindowSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if !self.partition_by.is_empty() {
+            parts.push(format!("PARTITION BY {}", join_display(&self.partition_by)));
+        }
+        if !self.order_by.is_empty() {
+            parts.push(format!("ORDER BY {}", join_display(&self.order_by)));
+        }
+        if let Some(frame) = &self.frame {
+            parts.push(format!("ROWS BETWEEN {} AND {}", frame.start, frame.end));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl fmt::Display for FrameBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameBound::UnboundedPreceding => write!(f, "UNBOUNDED PRECEDING"),
+            FrameBound::Preceding(n) => write!(f, "{} PRECEDING", n),
+            FrameBound::CurrentRow => write!(f, "CURRENT ROW"),
+            FrameBound::Following(n) => write!(f, "{} FOLLOWING", n),
+            FrameBound::UnboundedFollowing => write!(f, "UNBOUNDED FOLLOWING"),
+        }
+    }
+}
+
+fn join_display<T: fmt::Display>(items: &[T]) -> String {
+    items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+impl fmt::Display for TriggerTiming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerTiming::Before => write!(f, "BEFORE"),
+            TriggerTiming::After => write!(f, "AFTER"),
+        }
+    }
+}
+
+impl fmt::Display for TriggerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerEvent::Insert => write!(f, "INSERT"),
+            TriggerEvent::Update => write!(f, "UPDATE"),
+            TriggerEvent::Delete => write!(f, "DELETE"),
+        }
+    }
+}
+
+impl fmt::Display for ReferentialAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReferentialAction::NoAction => write!(f, "NO ACTION"),
+            ReferentialAction::Restrict => write!(f, "RESTRICT"),
+            ReferentialAction::Cascade => write!(f, "CASCADE"),
+            ReferentialAction::SetNull => write!(f, "SET NULL"),
+        }
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DataType::Integer => "INTEGER",
+            DataType::Float => "FLOAT",
+            DataType::Text => "TEXT",
+            DataType::Boolean => "BOOLEAN",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An operand of an operator, parenthesized when it is a binary expression
+/// that binds more loosely than `min_precedence`.
+struct Operand<'a>(&'a Expr, u8);
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Expr::Binary { op, .. } if op.precedence() < self.1 => write!(f, "({})", self.0),
+            expr => write!(f, "{}", expr),
+        }
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for SelectItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectItem::Wildcard => write!(f, "*"),
+            SelectItem::QualifiedWildcard(q) => write!(f, "{}.*", q),
+            SelectItem::Expr { expr, alias: Some(alias) } => write!(f, "{} AS {}", expr, alias),
+            SelectItem::Expr { expr, alias: None } => write!(f, "{}", expr),
+        }
+    }
+}
+
+impl fmt::Display for TableRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableRef::Table { name, alias: Some(alias) } => write!(f, "{} AS {}", name, alias),
+            TableRef::Table { name, alias: None } => write!(f, "{}", name),
+            TableRef::Derived { subquery, alias } => write!(f, "({}) AS {}", subquery, alias),
+        }
+    }
+}
+
+impl fmt::Display for Join {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            JoinKind::Inner => "JOIN",
+            JoinKind::Left => "LEFT JOIN",
+            JoinKind::Right => "RIGHT JOIN",
+            JoinKind::Full => "FULL JOIN",
+            JoinKind::Cross => "CROSS JOIN",
+        };
+        write!(f, "{} {}", kind, self.table)?;
+        if let Some(c) = &self.constraint {
+            write!(f, " ON {}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SetOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            SetOperator::Union => "UNION",
+            SetOperator::Intersect => "INTERSECT",
+            SetOperator::Except => "EXCEPT",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl fmt::Display for SelectStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(with) = &self.with {
+            write!(f, "{} ", with)?;
+        }
+        write!(f, "{}", self.body)?;
+        if !self.order_by.is_empty() {
+            write!(f, " ORDER BY ")?;
+            write_list(f, &self.order_by)?;
+        }
+        if let Some(limit) = &self.limit {
+            write!(f, " LIMIT {}", limit)?;
+        }
+        if let Some(offset) = &self.offset {
+            write!(f, " OFFSET {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for OrderByExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expr)?;
+        if self.descending {
+            write!(f, " DESC")?;
+        }
+        match self.nulls_first {
+            Some(true) => write!(f, " NULLS FIRST"),
+            Some(false) => write!(f, " NULLS LAST"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for With {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WITH {}", if self.recursive { "RECURSIVE " } else { "" })?;
+        write_list(f, &self.ctes)
+    }
+}
+
+impl fmt::Display for Cte {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.columns.is_empty() {
+            write!(f, " (")?;
+            write_list(f, &self.columns)?;
+            write!(f, ")")?;
+        }
+        write!(f, " AS ({})", self.query)
+    }
+}
+
+impl fmt::Display for SetExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetExpr::Select(select) => write!(f, "{}", select),
+            SetExpr::SetOperation { left, op, all, right } => {
+                write_set_operand(f, left, op.precedence())?;
+                write!(f, " {}{} ", op, if *all { " ALL" } else { "" })?;
+                write_set_operand(f, right, op.precedence() + 1)
+            }
+        }
+    }
+}
+
+/// Writes an operand of a set operation, parenthesized when it binds more
+/// loosely than `min_precedence`.
+fn write_set_operand(f: &mut fmt::Formatter<'_>, operand: &SetExpr, min_precedence: u8) -> fmt::Result {
+    match operand {
+        SetExpr::SetOperation { op, .. } if op.precedence() < min_precedence => write!(f, "({})", operand),
+        _ => write!(f, "{}", operand),
+    }
+}
+
+/// Renders a SELECT block back to SQL text.
+impl fmt::Display for Select {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SELECT ")?;
+        match &self.distinct {
+            Some(Distinct::Row) => write!(f, "DISTINCT ")?,
+            Some(Distinct::On(exprs)) => {
+                write!(f, "DISTINCT ON (")?;
+                write_list(f, exprs)?;
+                write!(f, ") ")?;
+            }
+            None => {}
+        }
+        write_list(f, &self.select_columns)?;
+        write!(f, " FROM {}", self.from)?;
+        for join in &self.joins {
+            write!(f, " {}", join)?;
+        }
+        if let Some(c) = &self.condition {
+            write!(f, " WHERE {}", c)?;
+        }
+        if !self.group_by.is_empty() {
+            write!(f, " GROUP BY ")?;
+            write_list(f, &self.group_by)?;
+        }
+        if let Some(h) = &self.having {
+            write!(f, " HAVING {}", h)?;
+        }
+        Ok(())
+    }
+}
@@ -1,21 +1,134 @@
-use crate::parser::{ParserError, Query};
+use crate::error::DbError;
+use crate::parser::ast::{ColumnDef, ForeignKey, ReferentialAction};
+use crate::parser::lexer::Token;
+use crate::parser::{Parser, Query};
+
 /// Parse a CREATE TABLE query.
-/// Example: "CREATE TABLE users (id, name, age);"
-pub fn parse_create_table(query: &str) -> Result<Query, ParserError> {
-    let query = query.trim_end_matches(';').trim();
-    let prefix = "CREATE TABLE";
-    if !query.to_uppercase().starts_with(prefix) {
-        return Err(ParserError::InvalidSyntax("Not a CREATE TABLE command".into()));
+/// Examples:
+///   "CREATE TABLE users (id, name, age);"
+///   "CREATE TABLE users (id, name, age DEFAULT 20);"
+///   "CREATE TABLE orders (id, user_id REFERENCES users ON DELETE CASCADE, total);"
+///   "CREATE TABLE orders (id, user_id, FOREIGN KEY (user_id) REFERENCES users (id) ON UPDATE SET NULL);"
+pub fn parse_create_table(mut parser: Parser) -> Result<Query, DbError> {
+    if !(parser.consume_keyword("CREATE") && parser.consume_keyword("TABLE")) {
+        return Err(DbError::Syntax("Not a CREATE TABLE command".into()));
     }
-    let after_prefix = query[prefix.len()..].trim();
-    let parts: Vec<&str> = after_prefix.splitn(2, '(').collect();
-    if parts.len() != 2 {
-        return Err(ParserError::InvalidSyntax("Missing column definitions".into()));
+    let table_name = parser.parse_identifier()?;
+    if !parser.consume(&Token::LParen) {
+        return Err(DbError::Syntax("Missing column definitions".into()));
+    }
+    let mut columns = Vec::new();
+    let mut foreign_keys = Vec::new();
+    loop {
+        let is_table_constraint = parser.peek_keyword("FOREIGN")
+            && matches!(parser.peek_nth(1), Some(Token::Ident(word)) if word.eq_ignore_ascii_case("KEY"));
+        if is_table_constraint {
+            parser.pos += 2;
+            parser.expect(&Token::LParen)?;
+            let column = parser.parse_identifier()?;
+            if parser.peek() == Some(&Token::Comma) {
+                return Err(DbError::Syntax("A foreign key can only have one column".into()));
+            }
+            parser.expect(&Token::RParen)?;
+            parser.expect_keyword("REFERENCES")?;
+            foreign_keys.push(parser.parse_references(column)?);
+        } else {
+            let (column, foreign_key) = parser.parse_column_def()?;
+            columns.push(column);
+            foreign_keys.extend(foreign_key);
+        }
+        if !parser.consume(&Token::Comma) {
+            break;
+        }
+    }
+    parser.expect(&Token::RParen)?;
+    parser.expect_end()?;
+    if columns.is_empty() {
+        return Err(DbError::Syntax("Missing column definitions".into()));
+    }
+    Ok(Query::CreateTable { table_name, columns, foreign_keys })
+}
+
+impl Parser {
+    /// Parse `name [DEFAULT expr] [REFERENCES ...]`, with the two clauses in either order.
+    fn parse_column_def(&mut self) -> Result<(ColumnDef, Option<ForeignKey>), DbError> {
+        let name = self.parse_identifier()?;
+        let mut default = None;
+        let mut foreign_key = None;
+        loop {
+            if default.is_none() && self.consume_keyword("DEFAULT") {
+                default = Some(self.parse_expr()?);
+            } else if foreign_key.is_none() && self.consume_keyword("REFERENCES") {
+                foreign_key = Some(self.parse_references(name.clone())?);
+            } else {
+                break;
+            }
+        }
+        Ok((ColumnDef { name, default }, foreign_key))
+    }
+
+    /// Parse what follows REFERENCES: `table [(column)]` and then the ON DELETE / ON UPDATE actions
+    /// and deferrability in any order.
+    fn parse_references(&mut self, column: String) -> Result<ForeignKey, DbError> {
+        let table = self.parse_identifier()?;
+        let referenced = if self.consume(&Token::LParen) {
+            let referenced = self.parse_identifier()?;
+            self.expect(&Token::RParen)?;
+            Some(referenced)
+        } else {
+            None
+        };
+        let mut foreign_key = ForeignKey {
+            column,
+            table,
+            referenced,
+            on_delete: ReferentialAction::NoAction,
+            on_update: ReferentialAction::NoAction,
+            deferred: false,
+        };
+        loop {
+            if self.consume_keyword("ON") {
+                if self.consume_keyword("DELETE") {
+                    foreign_key.on_delete = self.parse_referential_action()?;
+                } else if self.consume_keyword("UPDATE") {
+                    foreign_key.on_update = self.parse_referential_action()?;
+                } else {
+                    return Err(self.unexpected("DELETE or UPDATE"));
+                }
+            } else if self.consume_keyword("NOT") {
+                self.expect_keyword("DEFERRABLE")?;
+                if self.peek_keyword("INITIALLY") && matches!(self.peek_nth(1), Some(Token::Ident(w)) if w.eq_ignore_ascii_case("DEFERRED")) {
+                    return Err(DbError::Syntax("A NOT DEFERRABLE constraint cannot be INITIALLY DEFERRED".into()));
+                }
+            } else if self.consume_keyword("DEFERRABLE") {
+                // DEFERRABLE alone is INITIALLY IMMEDIATE, and there is no SET CONSTRAINTS to change it
+            } else if self.consume_keyword("INITIALLY") {
+                if self.consume_keyword("DEFERRED") {
+                    foreign_key.deferred = true;
+                } else if self.consume_keyword("IMMEDIATE") {
+                    foreign_key.deferred = false;
+                } else {
+                    return Err(self.unexpected("DEFERRED or IMMEDIATE"));
+                }
+            } else {
+                return Ok(foreign_key);
+            }
+        }
+    }
+
+    fn parse_referential_action(&mut self) -> Result<ReferentialAction, DbError> {
+        if self.consume_keyword("CASCADE") {
+            Ok(ReferentialAction::Cascade)
+        } else if self.consume_keyword("RESTRICT") {
+            Ok(ReferentialAction::Restrict)
+        } else if self.consume_keyword("SET") {
+            self.expect_keyword("NULL")?;
+            Ok(ReferentialAction::SetNull)
+        } else if self.consume_keyword("NO") {
+            self.expect_keyword("ACTION")?;
+            Ok(ReferentialAction::NoAction)
+        } else {
+            Err(self.unexpected("CASCADE, RESTRICT, SET NULL or NO ACTION"))
+        }
     }
-    let table_name = parts[0].trim().to_string();
-    let columns_str = parts[1].trim_end_matches(')').trim();
-    let columns = columns_str.split(',')
-        .map(|s| s.trim().to_string())
-        .collect();
-    Ok(Query::CreateTable { table_name, columns })
 }
@@ -0,0 +1,19 @@
+use crate::error::DbError;
+use crate::parser::ast::Delete;
+use crate::parser::{Parser, Query};
+
+/// Parse a DELETE query.
+/// Examples:
+///   "DELETE FROM users WHERE id = 1;"
+///   "DELETE FROM users WHERE age < 20 RETURNING *;"
+pub fn parse_delete(mut parser: Parser) -> Result<Query, DbError> {
+    if !(parser.consume_keyword("DELETE") && parser.consume_keyword("FROM")) {
+        return Err(DbError::Syntax("Not a DELETE command".into()));
+    }
+    let table_name = parser.parse_identifier()?;
+    let alias = parser.parse_alias()?;
+    let condition = parser.parse_where()?;
+    let returning = parser.parse_returning()?;
+    parser.expect_end()?;
+    Ok(Query::Delete(Delete { table_name, alias, condition, returning }))
+}
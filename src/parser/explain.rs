@@ -0,0 +1,20 @@
+use crate::error::DbError;
+use crate::parser::lexer::Token;
+use crate::parser::{Parser, Query};
+
+/// Parse an EXPLAIN query. Only SELECT statements can be explained.
+/// Examples:
+///   "EXPLAIN SELECT * FROM users WHERE id = 1;"
+///   "EXPLAIN ANALYZE SELECT u.name, o.total FROM users u JOIN orders o ON o.user_id = u.id;"
+pub fn parse_explain(mut parser: Parser) -> Result<Query, DbError> {
+    if !parser.consume_keyword("EXPLAIN") {
+        return Err(DbError::Syntax("Not an EXPLAIN command".into()));
+    }
+    let analyze = parser.consume_keyword("ANALYZE");
+    if !parser.peek_select() && parser.peek() != Some(&Token::LParen) {
+        return Err(parser.unexpected("SELECT"));
+    }
+    let statement = parser.parse_select_statement()?;
+    parser.expect_end()?;
+    Ok(Query::Explain { analyze, statement })
+}
@@ -0,0 +1,396 @@
+use crate::error::DbError;
+use crate::parser::ast::{
+    AggregateFunction, BinaryOperator, DataType, Expr, FrameBound, ScalarFunction, UnaryOperator, WindowFrame, WindowFunction,
+    WindowSpec,
+};
+use crate::parser::lexer::Token;
+use crate::parser::{is_reserved, Parser};
+use crate::value::Value;
+
+/// Expression parsing by precedence climbing, loosest binding first:
+/// OR, AND, NOT, comparison / IS NULL / IN, `||`, `+ -`, `* / %`, unary minus, primary.
+impl Parser {
+    pub fn parse_expr(&mut self) -> Result<Expr, DbError> {
+        self.parse_or()
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, DbError> {
+        let mut left = self.parse_and()?;
+        while self.consume_keyword("OR") {
+            let right = self.parse_and()?;
+            left = binary(left, BinaryOperator::Or, right);
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, DbError> {
+        let mut left = self.parse_not()?;
+        while self.consume_keyword("AND") {
+            let right = self.parse_not()?;
+            left = binary(left, BinaryOperator::And, right);
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, DbError> {
+        if self.consume_keyword("NOT") {
+            let expr = self.parse_not()?;
+            return Ok(Expr::Unary { op: UnaryOperator::Not, expr: Box::new(expr) });
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, DbError> {
+        let left = self.parse_concat()?;
+        if self.consume_keyword("IS") {
+            let negated = self.consume_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(Expr::IsNull { expr: Box::new(left), negated });
+        }
+        let negated = self.peek_keyword("NOT") && matches!(self.peek_nth(1), Some(Token::Ident(w)) if w.eq_ignore_ascii_case("IN"));
+        if negated {
+            self.next_token();
+        }
+        if self.consume_keyword("IN") {
+            return self.parse_in(left, negated);
+        }
+        let op = match self.peek() {
+            Some(Token::Eq) => BinaryOperator::Eq,
+            Some(Token::NotEq) => BinaryOperator::Ne,
+            Some(Token::Lt) => BinaryOperator::Lt,
+            Some(Token::LtEq) => BinaryOperator::Le,
+            Some(Token::Gt) => BinaryOperator::Gt,
+            Some(Token::GtEq) => BinaryOperator::Ge,
+            _ => return Ok(left),
+        };
+        self.next_token();
+        let right = self.parse_concat()?;
+        Ok(binary(left, op, right))
+    }
+
+    fn parse_concat(&mut self) -> Result<Expr, DbError> {
+        let mut left = self.parse_additive()?;
+        while self.consume(&Token::Concat) {
+            let right = self.parse_additive()?;
+            left = binary(left, BinaryOperator::Concat, right);
+        }
+        Ok(left)
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, DbError> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => BinaryOperator::Plus,
+                Some(Token::Minus) => BinaryOperator::Minus,
+                _ => return Ok(left),
+            };
+            self.next_token();
+            let right = self.parse_multiplicative()?;
+            left = binary(left, op, right);
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, DbError> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => BinaryOperator::Multiply,
+                Some(Token::Slash) => BinaryOperator::Divide,
+                Some(Token::Percent) => BinaryOperator::Modulo,
+                _ => return Ok(left),
+            };
+            self.next_token();
+            let right = self.parse_unary()?;
+            left = binary(left, op, right);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, DbError> {
+        if self.consume(&Token::Minus) {
+            let expr = self.parse_unary()?;
+            return Ok(match expr {
+                Expr::Literal(Value::Integer(i)) => Expr::Literal(Value::Integer(-i)),
+                Expr::Literal(Value::Float(f)) => Expr::Literal(Value::Float(-f)),
+                other => Expr::Unary { op: UnaryOperator::Minus, expr: Box::new(other) },
+            });
+        }
+        self.consume(&Token::Plus);
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, DbError> {
+        match self.next_token() {
+            Some(Token::Number(n)) => parse_number_literal(&n),
+            Some(Token::String(s)) => Ok(Expr::Literal(Value::Text(s))),
+            Some(Token::Parameter(index)) => Ok(Expr::Parameter(index)),
+            Some(Token::LParen) => {
+                if self.peek_select() {
+                    let subquery = self.parse_select_statement()?;
+                    self.expect(&Token::RParen)?;
+                    return Ok(Expr::Subquery(Box::new(subquery)));
+                }
+                let expr = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(word)) => {
+                if word.eq_ignore_ascii_case("NULL") {
+                    return Ok(Expr::Literal(Value::Null));
+                }
+                if word.eq_ignore_ascii_case("TRUE") {
+                    return Ok(Expr::Literal(Value::Boolean(true)));
+                }
+                if word.eq_ignore_ascii_case("FALSE") {
+                    return Ok(Expr::Literal(Value::Boolean(false)));
+                }
+                if word.eq_ignore_ascii_case("EXISTS") {
+                    self.expect(&Token::LParen)?;
+                    let subquery = self.parse_select_statement()?;
+                    self.expect(&Token::RParen)?;
+                    return Ok(Expr::Exists(Box::new(subquery)));
+                }
+                if word.eq_ignore_ascii_case("CASE") {
+                    return self.parse_case();
+                }
+                if word.eq_ignore_ascii_case("CAST") && self.peek() == Some(&Token::LParen) {
+                    return self.parse_cast();
+                }
+                if self.peek() == Some(&Token::LParen) {
+                    return self.parse_function_call(&word);
+                }
+                if is_reserved(&word) {
+                    return Err(DbError::Syntax(format!("Unexpected keyword '{}'", word)));
+                }
+                if self.consume(&Token::Dot) {
+                    let name = self.parse_identifier()?;
+                    return Ok(Expr::Column { table: Some(word), name });
+                }
+                Ok(Expr::Column { table: None, name: word })
+            }
+            Some(token) => Err(DbError::Syntax(format!("Unexpected token {:?} in expression", token))),
+            None => Err(DbError::Syntax("Unexpected end of query in expression".into())),
+        }
+    }
+
+    /// Parse the parenthesized part of `expr [NOT] IN (...)`: either a value list or a subquery.
+    fn parse_in(&mut self, expr: Expr, negated: bool) -> Result<Expr, DbError> {
+        self.expect(&Token::LParen)?;
+        let expr = Box::new(expr);
+        if self.peek_select() {
+            let subquery = Box::new(self.parse_select_statement()?);
+            self.expect(&Token::RParen)?;
+            return Ok(Expr::InSubquery { expr, subquery, negated });
+        }
+        let list = self.parse_comma_separated(Parser::parse_expr)?;
+        self.expect(&Token::RParen)?;
+        Ok(Expr::InList { expr, list, negated })
+    }
+
+    /// Parse the rest of `CASE [operand] WHEN ... THEN ... [ELSE ...] END`.
+    fn parse_case(&mut self) -> Result<Expr, DbError> {
+        let operand = if self.peek_keyword("WHEN") { None } else { Some(Box::new(self.parse_expr()?)) };
+        let mut branches = Vec::new();
+        while self.consume_keyword("WHEN") {
+            let when = self.parse_expr()?;
+            self.expect_keyword("THEN")?;
+            branches.push((when, self.parse_expr()?));
+        }
+        if branches.is_empty() {
+            return Err(self.unexpected("WHEN"));
+        }
+        let else_result = if self.consume_keyword("ELSE") { Some(Box::new(self.parse_expr()?)) } else { None };
+        self.expect_keyword("END")?;
+        Ok(Expr::Case { operand, branches, else_result })
+    }
+
+    /// Parse the rest of `CAST(expr AS type)`.
+    fn parse_cast(&mut self) -> Result<Expr, DbError> {
+        self.expect(&Token::LParen)?;
+        let expr = self.parse_expr()?;
+        self.expect_keyword("AS")?;
+        let data_type = match self.next_token() {
+            Some(Token::Ident(name)) => DataType::from_name(&name)
+                .ok_or_else(|| DbError::Syntax(format!("Unknown type '{}' in CAST", name)))?,
+            _ => return Err(DbError::Syntax("Expected a type name in CAST".into())),
+        };
+        self.expect(&Token::RParen)?;
+        Ok(Expr::Cast { expr: Box::new(expr), data_type })
+    }
+
+    /// Parse `name(...)` once the name has been consumed: a scalar, window or aggregate
+    /// function. An aggregate followed by OVER becomes a window function.
+    fn parse_function_call(&mut self, name: &str) -> Result<Expr, DbError> {
+        if let Some(func) = ScalarFunction::from_name(name) {
+            return self.parse_scalar_function(func);
+        }
+        if let Some(func) = WindowFunction::from_name(name) {
+            let args = self.parse_argument_list()?;
+            let (min, max) = func.arity();
+            check_arity(&func, args.len(), min, Some(max))?;
+            if !self.consume_keyword("OVER") {
+                return Err(DbError::Syntax(format!("{} requires an OVER clause", func)));
+            }
+            return self.finish_window(func, args);
+        }
+        let aggregate = self.parse_aggregate(name)?;
+        if !self.consume_keyword("OVER") {
+            return Ok(aggregate);
+        }
+        let Expr::Aggregate { func, arg, distinct } = aggregate else { unreachable!() };
+        if distinct {
+            return Err(DbError::Syntax("DISTINCT is not supported for window functions".into()));
+        }
+        self.finish_window(WindowFunction::Aggregate(func), arg.map(|a| vec![*a]).unwrap_or_default())
+    }
+
+    fn finish_window(&mut self, func: WindowFunction, args: Vec<Expr>) -> Result<Expr, DbError> {
+        if args.iter().any(Expr::contains_window) {
+            return Err(DbError::Syntax("Window function calls cannot be nested".into()));
+        }
+        let over = self.parse_window_spec()?;
+        Ok(Expr::Window { func, args, over: Box::new(over) })
+    }
+
+    /// Parse `(...)` after OVER.
+    fn parse_window_spec(&mut self) -> Result<WindowSpec, DbError> {
+        self.expect(&Token::LParen)?;
+        let partition_by = if self.consume_keyword("PARTITION") {
+            self.expect_keyword("BY")?;
+            self.parse_comma_separated(Parser::parse_expr)?
+        } else {
+            Vec::new()
+        };
+        let order_by = if self.consume_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            self.parse_comma_separated(Parser::parse_order_by_expr)?
+        } else {
+            Vec::new()
+        };
+        let frame = if self.consume_keyword("ROWS") {
+            Some(self.parse_window_frame()?)
+        } else if self.peek_keyword("RANGE") || self.peek_keyword("GROUPS") {
+            return Err(DbError::Syntax("Only ROWS window frames are supported".into()));
+        } else {
+            None
+        };
+        self.expect(&Token::RParen)?;
+        Ok(WindowSpec { partition_by, order_by, frame })
+    }
+
+    /// Parse `BETWEEN start AND end` or a single `start` (ending at the current row) after ROWS.
+    fn parse_window_frame(&mut self) -> Result<WindowFrame, DbError> {
+        let (start, end) = if self.consume_keyword("BETWEEN") {
+            let start = self.parse_frame_bound()?;
+            self.expect_keyword("AND")?;
+            (start, self.parse_frame_bound()?)
+        } else {
+            (self.parse_frame_bound()?, FrameBound::CurrentRow)
+        };
+        let rank = |bound: FrameBound| match bound {
+            FrameBound::UnboundedPreceding => 0,
+            FrameBound::Preceding(_) => 1,
+            FrameBound::CurrentRow => 2,
+            FrameBound::Following(_) => 3,
+            FrameBound::UnboundedFollowing => 4,
+        };
+        if start == FrameBound::UnboundedFollowing
+            || end == FrameBound::UnboundedPreceding
+            || rank(start) > rank(end)
+        {
+            return Err(DbError::Syntax(format!("Invalid window frame: ROWS BETWEEN {} AND {}", start, end)));
+        }
+        Ok(WindowFrame { start, end })
+    }
+
+    fn parse_frame_bound(&mut self) -> Result<FrameBound, DbError> {
+        if self.consume_keyword("UNBOUNDED") {
+            if self.consume_keyword("PRECEDING") {
+                return Ok(FrameBound::UnboundedPreceding);
+            }
+            self.expect_keyword("FOLLOWING")?;
+            return Ok(FrameBound::UnboundedFollowing);
+        }
+        if self.consume_keyword("CURRENT") {
+            self.expect_keyword("ROW")?;
+            return Ok(FrameBound::CurrentRow);
+        }
+        let n = match self.next_token() {
+            Some(Token::Number(n)) => n
+                .parse::<u64>()
+                .map_err(|_| DbError::Syntax(format!("Invalid frame offset '{}'", n)))?,
+            _ => return Err(DbError::Syntax("Expected a window frame bound".into())),
+        };
+        if self.consume_keyword("PRECEDING") {
+            return Ok(FrameBound::Preceding(n));
+        }
+        self.expect_keyword("FOLLOWING")?;
+        Ok(FrameBound::Following(n))
+    }
+
+    /// Parse the parenthesized arguments of an aggregate call.
+    fn parse_aggregate(&mut self, name: &str) -> Result<Expr, DbError> {
+        let func = AggregateFunction::from_name(name)
+            .ok_or_else(|| DbError::Syntax(format!("Unknown function '{}'", name)))?;
+        self.expect(&Token::LParen)?;
+        let distinct = self.consume_keyword("DISTINCT");
+        if self.consume(&Token::Star) {
+            if func != AggregateFunction::Count || distinct {
+                return Err(DbError::Syntax(format!("{}(*) is not allowed", func)));
+            }
+            self.expect(&Token::RParen)?;
+            return Ok(Expr::Aggregate { func, arg: None, distinct: false });
+        }
+        let arg = self.parse_expr()?;
+        if arg.contains_aggregate() || arg.contains_window() {
+            return Err(DbError::Syntax("Aggregate function calls cannot be nested".into()));
+        }
+        self.expect(&Token::RParen)?;
+        Ok(Expr::Aggregate { func, arg: Some(Box::new(arg)), distinct })
+    }
+
+    /// Parse the argument list of a scalar function and check the argument count.
+    fn parse_scalar_function(&mut self, func: ScalarFunction) -> Result<Expr, DbError> {
+        let args = self.parse_argument_list()?;
+        let (min, max) = func.arity();
+        check_arity(&func, args.len(), min, max)?;
+        Ok(Expr::Function { func, args })
+    }
+
+    /// Parse `(expr, ...)`, which may be empty.
+    fn parse_argument_list(&mut self) -> Result<Vec<Expr>, DbError> {
+        self.expect(&Token::LParen)?;
+        let args = if self.peek() == Some(&Token::RParen) {
+            Vec::new()
+        } else {
+            self.parse_comma_separated(Parser::parse_expr)?
+        };
+        self.expect(&Token::RParen)?;
+        Ok(args)
+    }
+}
+
+fn check_arity(func: &dyn std::fmt::Display, count: usize, min: usize, max: Option<usize>) -> Result<(), DbError> {
+    if count >= min && max.is_none_or(|max| count <= max) {
+        return Ok(());
+    }
+    let expected = match max {
+        Some(max) if max == min => min.to_string(),
+        Some(max) => format!("{} to {}", min, max),
+        None => format!("at least {}", min),
+    };
+    Err(DbError::Syntax(format!("{} expects {} argument(s), got {}", func, expected, count)))
+}
+
+fn binary(left: Expr, op: BinaryOperator, right: Expr) -> Expr {
+    Expr::Binary { left: Box::new(left), op, right: Box::new(right) }
+}
+
+fn parse_number_literal(text: &str) -> Result<Expr, DbError> {
+    if let Ok(i) = text.parse::<i64>() {
+        return Ok(Expr::Literal(Value::Integer(i)));
+    }
+    text.parse::<f64>()
+        .map(|f| Expr::Literal(Value::Float(f)))
+        .map_err(|_| DbError::Syntax(format!("Invalid number '{}'", text)))
+}
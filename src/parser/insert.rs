@@ -1,27 +1,114 @@
-use crate::parser::{ParserError, Query};
+use crate::error::DbError;
+use crate::parser::ast::{Assignment, ConflictAction, Expr, Insert, InsertSource, OnConflict};
+use crate::parser::lexer::Token;
+use crate::parser::{Parser, Query};
 
 /// Parse an INSERT query.
-/// Example: "INSERT INTO users VALUES (1, 'John', 30);"
-pub fn parse_insert(query: &str) -> Result<Query, ParserError> {
-    let query = query.trim_end_matches(';').trim();
-    let prefix = "INSERT INTO";
-    if !query.to_uppercase().starts_with(prefix) {
-        return Err(ParserError::InvalidSyntax("Not an INSERT command".into()));
+/// Examples:
+///   "INSERT INTO users VALUES (1, 'John', 30);"
+///   "INSERT INTO users (id, name) VALUES (2, 'Mike'), (3, 'Anna');"
+///   "INSERT INTO archive SELECT * FROM users WHERE age > 60;"
+///   "INSERT INTO users VALUES (1, 'John', 31) ON CONFLICT (id) DO UPDATE SET age = excluded.age;"
+///   "INSERT OR REPLACE INTO users VALUES (1, 'John', 31);"
+///   "INSERT INTO users (id, name) VALUES (4, 'Kate') RETURNING *;"
+pub fn parse_insert(mut parser: Parser) -> Result<Query, DbError> {
+    if !parser.consume_keyword("INSERT") {
+        return Err(DbError::Syntax("Not an INSERT command".into()));
     }
-    let after_prefix = query[prefix.len()..].trim();
-    let parts: Vec<&str> = after_prefix.splitn(2, "VALUES").collect();
-    if parts.len() != 2 {
-        return Err(ParserError::InvalidSyntax("Missing VALUES clause".into()));
+    let or_action = if parser.consume_keyword("OR") {
+        if parser.consume_keyword("REPLACE") {
+            Some(ConflictAction::Replace)
+        } else if parser.consume_keyword("IGNORE") {
+            Some(ConflictAction::Nothing)
+        } else {
+            return Err(parser.unexpected("REPLACE or IGNORE"));
+        }
+    } else {
+        None
+    };
+    parser.expect_keyword("INTO")?;
+    let mut insert = parser.parse_insert_body()?;
+    if let Some(action) = or_action {
+        if insert.on_conflict.is_some() {
+            return Err(DbError::Syntax("INSERT OR ... cannot be combined with ON CONFLICT".into()));
+        }
+        insert.on_conflict = Some(OnConflict { target: Vec::new(), action });
+    }
+    parser.expect_end()?;
+    Ok(Query::Insert(insert))
+}
+
+impl Parser {
+    /// Parse everything after `INSERT INTO`.
+    fn parse_insert_body(&mut self) -> Result<Insert, DbError> {
+        let table_name = self.parse_identifier()?;
+        // `(` may start either a column list or a parenthesized SELECT
+        let starts_query = matches!(self.peek_nth(1), Some(Token::Ident(word)) if word.eq_ignore_ascii_case("SELECT") || word.eq_ignore_ascii_case("WITH"));
+        let columns = if self.peek() == Some(&Token::LParen) && !starts_query {
+            self.parse_column_list()?
+        } else {
+            Vec::new()
+        };
+        let source = if self.consume_keyword("VALUES") {
+            InsertSource::Values(self.parse_comma_separated(Parser::parse_values_row)?)
+        } else if self.peek_select() || self.peek() == Some(&Token::LParen) {
+            InsertSource::Select(Box::new(self.parse_select_statement()?))
+        } else {
+            return Err(self.unexpected("VALUES or SELECT"));
+        };
+        let on_conflict = if self.consume_keyword("ON") {
+            self.expect_keyword("CONFLICT")?;
+            Some(self.parse_on_conflict()?)
+        } else {
+            None
+        };
+        let returning = self.parse_returning()?;
+        Ok(Insert { table_name, columns, source, on_conflict, returning })
+    }
+
+    /// Parse one `(expr, ...)` row of VALUES.
+    fn parse_values_row(&mut self) -> Result<Vec<Expr>, DbError> {
+        self.expect(&Token::LParen)?;
+        let row = self.parse_comma_separated(Parser::parse_expr)?;
+        self.expect(&Token::RParen)?;
+        if row.iter().any(|e| e.contains_aggregate() || e.contains_window()) {
+            return Err(DbError::Syntax("Aggregate and window functions are not allowed in VALUES".into()));
+        }
+        Ok(row)
+    }
+
+    fn parse_column_list(&mut self) -> Result<Vec<String>, DbError> {
+        self.expect(&Token::LParen)?;
+        let columns = self.parse_comma_separated(Parser::parse_identifier)?;
+        self.expect(&Token::RParen)?;
+        Ok(columns)
+    }
+
+    /// Parse `[(columns)] DO NOTHING | DO UPDATE SET ... [WHERE ...]` after `ON CONFLICT`.
+    fn parse_on_conflict(&mut self) -> Result<OnConflict, DbError> {
+        let target = if self.peek() == Some(&Token::LParen) { self.parse_column_list()? } else { Vec::new() };
+        self.expect_keyword("DO")?;
+        let action = if self.consume_keyword("NOTHING") {
+            ConflictAction::Nothing
+        } else if self.consume_keyword("UPDATE") {
+            self.expect_keyword("SET")?;
+            let assignments = self.parse_comma_separated(Parser::parse_assignment)?;
+            let condition = if self.consume_keyword("WHERE") { Some(self.parse_expr()?) } else { None };
+            ConflictAction::Update { assignments, condition }
+        } else {
+            return Err(self.unexpected("NOTHING or UPDATE"));
+        };
+        Ok(OnConflict { target, action })
+    }
+
+    /// Parse `column = expr` of a SET clause.
+    pub fn parse_assignment(&mut self) -> Result<Assignment, DbError> {
+        let column = self.parse_identifier()?;
+        self.expect(&Token::Eq)?;
+        let value = self.parse_expr()?;
+        if value.contains_aggregate() || value.contains_window() {
+            return Err(DbError::Syntax("Aggregate and window functions are not allowed in SET".into()));
+        }
+        Ok(Assignment { column, value })
     }
-    let table_name = parts[0].trim().to_string();
-    let values_str = parts[1]
-        .trim()
-        .trim_start_matches('(')
-        .trim_end_matches(')')
-        .trim();
-    let values = values_str
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .collect();
-    Ok(Query::Insert { table_name, values })
 }
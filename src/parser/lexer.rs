@@ -0,0 +1,233 @@
+use crate::error::DbError;
+use std::fmt;
+
+/// A single lexical token of a query.
+/// Keywords are not distinguished from identifiers here; the parser compares
+/// `Ident` tokens case-insensitively when it expects a keyword.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Number(String),
+    /// A quoted literal. Both single and double quotes produce a string,
+    /// matching how the parser has always treated values.
+    String(String),
+    Comma,
+    LParen,
+    RParen,
+    Dot,
+    Semicolon,
+    Star,
+    Plus,
+    Minus,
+    Slash,
+    Percent,
+    Concat,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    /// A bind parameter (`?`, `$1` or `:name`), already numbered from 0.
+    Parameter(usize),
+}
+
+/// Split a query string into tokens, skipping whitespace and comments
+/// (`-- ...` to the end of the line and `/* ... */`).
+pub fn tokenize(input: &str) -> Result<Vec<Token>, DbError> {
+    Ok(tokenize_with_parameters(input)?.0)
+}
+
+/// Like `tokenize`, but also return the bind parameters in index order: the name of each
+/// `:name` parameter, or None for positional ones.
+/// As in SQLite, `?` takes the index after the largest one used so far, `$N` and `?N` are index N,
+/// and a repeated `:name` refers to the same parameter.
+pub fn tokenize_with_parameters(input: &str) -> Result<(Vec<Token>, Vec<Option<String>>), DbError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut parameters: Vec<Option<String>> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            if i >= chars.len() {
+                return Err(DbError::Syntax("Unterminated comment".into()));
+            }
+            i += 2;
+            continue;
+        }
+        if c == '\'' || c == '"' || c == '`' {
+            let (text, next) = read_quoted(&chars, i)?;
+            tokens.push(if c == '`' { Token::Ident(text) } else { Token::String(text) });
+            i = next;
+            continue;
+        }
+        if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Token::Number(chars[start..i].iter().collect()));
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            continue;
+        }
+        if c == '?' || c == '$' || c == ':' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let name: String = chars[start..i].iter().collect();
+            let index = match c {
+                '?' if name.is_empty() => {
+                    parameters.push(None);
+                    parameters.len() - 1
+                }
+                '$' | '?' => match name.parse::<usize>() {
+                    Ok(n) if n >= 1 => {
+                        if n > parameters.len() {
+                            parameters.resize(n, None);
+                        }
+                        n - 1
+                    }
+                    _ => return Err(DbError::Syntax(format!("Invalid parameter '{}{}'", c, name))),
+                },
+                ':' if !name.is_empty() && !name.starts_with(|d: char| d.is_ascii_digit()) => {
+                    match parameters.iter().position(|p| p.as_deref() == Some(name.as_str())) {
+                        Some(index) => index,
+                        None => {
+                            parameters.push(Some(name));
+                            parameters.len() - 1
+                        }
+                    }
+                }
+                _ => return Err(DbError::Syntax(format!("Invalid parameter '{}{}'", c, name))),
+            };
+            tokens.push(Token::Parameter(index));
+            continue;
+        }
+        let next = chars.get(i + 1).copied();
+        let (token, width) = match (c, next) {
+            ('|', Some('|')) => (Token::Concat, 2),
+            ('<', Some('=')) => (Token::LtEq, 2),
+            ('<', Some('>')) => (Token::NotEq, 2),
+            ('>', Some('=')) => (Token::GtEq, 2),
+            ('!', Some('=')) => (Token::NotEq, 2),
+            ('=', Some('=')) => (Token::Eq, 2),
+            (',', _) => (Token::Comma, 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('.', _) => (Token::Dot, 1),
+            (';', _) => (Token::Semicolon, 1),
+            ('*', _) => (Token::Star, 1),
+            ('+', _) => (Token::Plus, 1),
+            ('-', _) => (Token::Minus, 1),
+            ('/', _) => (Token::Slash, 1),
+            ('%', _) => (Token::Percent, 1),
+            ('=', _) => (Token::Eq, 1),
+            ('<', _) => (Token::Lt, 1),
+            ('>', _) => (Token::Gt, 1),
+            _ => {
+                return Err(DbError::Syntax(format!("Unexpected character '{}'", c)));
+            }
+        };
+        tokens.push(token);
+        i += width;
+    }
+    Ok((tokens, parameters))
+}
+
+/// Turn tokens back into query text that tokenizes to the same tokens.
+pub fn to_sql(tokens: &[Token]) -> String {
+    tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(word) if word.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                && word.chars().all(|c| c.is_alphanumeric() || c == '_') => write!(f, "{}", word),
+            Token::Ident(word) => write!(f, "`{}`", word.replace('`', "``")),
+            Token::Number(number) => write!(f, "{}", number),
+            Token::String(text) => write!(f, "'{}'", text.replace('\'', "''")),
+            Token::Parameter(index) => write!(f, "${}", index + 1),
+            token => {
+                let symbol = match token {
+                    Token::Comma => ",",
+                    Token::LParen => "(",
+                    Token::RParen => ")",
+                    Token::Dot => ".",
+                    Token::Semicolon => ";",
+                    Token::Star => "*",
+                    Token::Plus => "+",
+                    Token::Minus => "-",
+                    Token::Slash => "/",
+                    Token::Percent => "%",
+                    Token::Concat => "||",
+                    Token::Eq => "=",
+                    Token::NotEq => "<>",
+                    Token::Lt => "<",
+                    Token::LtEq => "<=",
+                    Token::Gt => ">",
+                    Token::GtEq => ">=",
+                    _ => unreachable!(),
+                };
+                write!(f, "{}", symbol)
+            }
+        }
+    }
+}
+
+/// Read a quoted section starting at `start`. A doubled quote character inside
+/// the section stands for the quote itself (e.g. 'it''s').
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize), DbError> {
+    let quote = chars[start];
+    let mut text = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        if chars[i] == quote {
+            if chars.get(i + 1) == Some(&quote) {
+                text.push(quote);
+                i += 2;
+                continue;
+            }
+            return Ok((text, i + 1));
+        }
+        text.push(chars[i]);
+        i += 1;
+    }
+    Err(DbError::Syntax("Unterminated quoted string".into()))
+}
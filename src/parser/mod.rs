@@ -1,57 +1,250 @@
+pub mod analyze;
+pub mod ast;
 pub mod create;
+pub mod delete;
+pub mod explain;
+pub mod expr;
 pub mod insert;
+pub mod lexer;
+pub mod script;
 pub mod select;
+pub mod show;
+pub mod transaction;
+pub mod trigger;
+pub mod update;
+pub mod view;
 
+pub use analyze::parse_analyze;
 pub use create::parse_create_table;
+pub use delete::parse_delete;
+pub use explain::parse_explain;
 pub use insert::parse_insert;
+pub use script::{is_complete, split_statements};
 pub use select::parse_select;
+pub use show::{parse_describe, parse_show};
+pub use transaction::parse_transaction;
+pub use trigger::parse_create_trigger;
+pub use update::parse_update;
+pub use view::{parse_create_view, parse_refresh};
 
-#[derive(Debug)]
-pub enum ParserError {
-    InvalidSyntax(String),
-}
+use crate::error::DbError;
+use ast::{ColumnDef, Delete, ForeignKey, Insert, SelectStatement, TriggerEvent, TriggerTiming, Update};
+use lexer::{tokenize, Token};
 
 #[derive(Debug, Clone)]
 pub enum Query {
-    CreateTable { table_name: String, columns: Vec<String> },
-    Insert { table_name: String, values: Vec<String> },
-    Select {
-        table_name: String,
-        select_columns: Vec<String>,
-        condition: Option<Condition>,
-    },
-    // ここにUpdate, Delete, Joinなどを後々拡張
+    /// `foreign_keys` collects both column-level `REFERENCES` and table-level `FOREIGN KEY` constraints.
+    CreateTable { table_name: String, columns: Vec<ColumnDef>, foreign_keys: Vec<ForeignKey> },
+    Insert(Insert),
+    Select(SelectStatement),
+    Update(Update),
+    Delete(Delete),
+    /// `EXPLAIN [ANALYZE] <select>`
+    Explain { analyze: bool, statement: SelectStatement },
+    /// `ANALYZE [table]`; `None` analyzes every table.
+    Analyze { table: Option<String> },
+    /// `CREATE [MATERIALIZED] VIEW name [(columns)] AS <select>`; `columns` is empty when omitted.
+    CreateView { name: String, columns: Vec<String>, statement: SelectStatement, materialized: bool },
+    RefreshMaterializedView { name: String },
+    /// `CREATE TRIGGER name BEFORE|AFTER INSERT|UPDATE|DELETE ON table FOR EACH ROW BEGIN ... END`;
+    /// `body` holds the SQL of each statement, still referring to `NEW` / `OLD`.
+    CreateTrigger { name: String, timing: TriggerTiming, event: TriggerEvent, table: String, body: Vec<String> },
+    ShowTables,
+    /// `DESCRIBE table` / `DESC table`
+    Describe { table: String },
+    /// `SHOW CREATE TABLE table`; for a view it shows the CREATE VIEW statement.
+    ShowCreateTable { table: String },
+    /// `BEGIN` / `START TRANSACTION`
+    Begin,
+    /// `COMMIT` / `END`
+    Commit,
+    Rollback,
 }
 
-#[derive(Debug, Clone)]
-pub struct Condition {
-    pub column: String,
-    pub operator: Operator,
-    pub value: String,
+impl Query {
+    /// Whether the statement only reads: SELECT, EXPLAIN and the catalog commands. BEGIN, COMMIT and ROLLBACK
+    /// write nothing themselves, so a read-only database can run a transaction and reject only the writes inside it.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Query::Select(_)
+                | Query::Explain { .. }
+                | Query::ShowTables
+                | Query::Describe { .. }
+                | Query::ShowCreateTable { .. }
+                | Query::Begin
+                | Query::Commit
+                | Query::Rollback
+        )
+    }
+
+    /// Whether the database has to be saved after the statement: every statement that is not read-only,
+    /// and COMMIT, which makes the writes of its transaction permanent.
+    pub fn needs_save(&self) -> bool {
+        !self.is_read_only() || matches!(self, Query::Commit)
+    }
 }
 
-#[derive(Debug, Clone)]
-pub enum Operator {
-    Eq,
-    Ne,
-    Gt,
-    Lt,
-    Ge,
-    Le,
+/// Keywords that can never be used as a bare column name.
+const RESERVED_KEYWORDS: &[&str] = &[
+    "SELECT", "FROM", "WHERE", "GROUP", "BY", "HAVING", "AND", "OR", "NOT", "IS", "DISTINCT", "AS",
+    "JOIN", "INNER", "LEFT", "RIGHT", "FULL", "OUTER", "CROSS", "ON", "IN", "EXISTS", "UNION", "INTERSECT",
+    "EXCEPT", "ALL", "WITH", "CASE", "WHEN", "THEN", "ELSE", "END", "ORDER", "LIMIT", "OFFSET", "ASC", "DESC",
+    "OVER", "SET", "RETURNING",
+];
+
+/// A cursor over the tokens of a single query, shared by the statement parsers.
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    pub fn new(query: &str) -> Result<Self, DbError> {
+        Ok(Parser::from_tokens(tokenize(query)?))
+    }
+
+    pub fn from_tokens(tokens: Vec<Token>) -> Self {
+        Parser { tokens, pos: 0 }
+    }
+
+    pub fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    /// Look `n` tokens ahead without consuming anything (`peek_nth(0)` is `peek()`).
+    pub fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + n)
+    }
+
+    pub fn next_token(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// Consume the next token if it is equal to `token`.
+    pub fn consume(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn expect(&mut self, token: &Token) -> Result<(), DbError> {
+        if self.consume(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("{:?}", token)))
+        }
+    }
+
+    pub fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    /// Consume the next token if it is the given keyword (case-insensitive).
+    pub fn consume_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_keyword(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn expect_keyword(&mut self, keyword: &str) -> Result<(), DbError> {
+        if self.consume_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(keyword))
+        }
+    }
+
+    /// Parse a table or column name.
+    pub fn parse_identifier(&mut self) -> Result<String, DbError> {
+        match self.peek() {
+            Some(Token::Ident(word)) if !is_reserved(word) => {
+                let word = word.clone();
+                self.pos += 1;
+                Ok(word)
+            }
+            _ => Err(self.unexpected("identifier")),
+        }
+    }
+
+    /// Parse one or more items separated by commas.
+    pub fn parse_comma_separated<T>(
+        &mut self,
+        mut parse_item: impl FnMut(&mut Parser) -> Result<T, DbError>,
+    ) -> Result<Vec<T>, DbError> {
+        let mut items = vec![parse_item(self)?];
+        while self.consume(&Token::Comma) {
+            items.push(parse_item(self)?);
+        }
+        Ok(items)
+    }
+
+    /// Whether only an optional trailing semicolon is left.
+    pub fn peek_end(&self) -> bool {
+        matches!(self.peek(), None | Some(Token::Semicolon))
+    }
+
+    /// Make sure nothing but an optional trailing semicolon is left.
+    pub fn expect_end(&mut self) -> Result<(), DbError> {
+        self.consume(&Token::Semicolon);
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.unexpected("end of query")),
+        }
+    }
+
+    pub fn unexpected(&self, expected: &str) -> DbError {
+        match self.peek() {
+            Some(token) => DbError::Syntax(format!("Expected {}, found {:?}", expected, token)),
+            None => DbError::Syntax(format!("Expected {}, found end of query", expected)),
+        }
+    }
+}
+
+pub fn is_reserved(word: &str) -> bool {
+    RESERVED_KEYWORDS.iter().any(|kw| kw.eq_ignore_ascii_case(word))
 }
 
 /// The main entry point for parsing a query. This function dispatches to the appropriate
-/// parser based on the query prefix.
-pub fn parse_query(query: &str) -> Result<Query, ParserError> {
-    let trimmed = query.trim();
-    let upper = trimmed.to_uppercase();
-    if upper.starts_with("CREATE TABLE") {
-        parse_create_table(trimmed)
-    } else if upper.starts_with("INSERT INTO") {
-        parse_insert(trimmed)
-    } else if upper.starts_with("SELECT") {
-        parse_select(trimmed)
-    } else {
-        Err(ParserError::InvalidSyntax("Unknown command".into()))
-    }
-}
\ No newline at end of file
+/// parser based on the leading keywords, so line breaks and comments before or between them are fine.
+pub fn parse_query(query: &str) -> Result<Query, DbError> {
+    parse_tokens(tokenize(query)?)
+}
+
+/// Parse a query that has already been tokenized, e.g. by `tokenize_with_parameters`.
+pub fn parse_tokens(tokens: Vec<Token>) -> Result<Query, DbError> {
+    let keyword = |n: usize| match tokens.get(n) {
+        Some(Token::Ident(word)) => word.to_uppercase(),
+        _ => String::new(),
+    };
+    let (first, second) = (keyword(0), keyword(1));
+    let starts_with_paren = tokens.first() == Some(&Token::LParen);
+    let parser = Parser::from_tokens(tokens);
+    match (first.as_str(), second.as_str()) {
+        ("CREATE", "TABLE") => parse_create_table(parser),
+        ("CREATE", "VIEW" | "MATERIALIZED") => parse_create_view(parser),
+        ("CREATE", "TRIGGER") => parse_create_trigger(parser),
+        ("REFRESH", _) => parse_refresh(parser),
+        ("INSERT", _) => parse_insert(parser),
+        ("UPDATE", _) => parse_update(parser),
+        ("DELETE", _) => parse_delete(parser),
+        ("SHOW", _) => parse_show(parser),
+        ("DESC" | "DESCRIBE", _) => parse_describe(parser),
+        ("EXPLAIN", _) => parse_explain(parser),
+        ("ANALYZE", _) => parse_analyze(parser),
+        ("BEGIN" | "START" | "COMMIT" | "END" | "ROLLBACK", _) => parse_transaction(parser),
+        ("SELECT" | "WITH", _) => parse_select(parser),
+        _ if starts_with_paren => parse_select(parser),
+        _ => Err(DbError::Syntax("Unknown command".into())),
+    }
+}
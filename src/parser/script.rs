@@ -0,0 +1,105 @@
+/// Split a script into its statements at the semicolons that end them.
+/// Semicolons inside quotes, comments and the BEGIN ... END body of a CREATE TRIGGER do not
+/// split. The statements are returned trimmed and without their semicolon; a last statement
+/// without one is included, and parts holding only whitespace and comments are dropped.
+/// Example:
+///   "INSERT INTO t VALUES ('a;b'); -- done\nSELECT * FROM t" gives
+///   ["INSERT INTO t VALUES ('a;b')", "SELECT * FROM t"]
+pub fn split_statements(script: &str) -> Vec<String> {
+    let (mut statements, rest) = scan(script);
+    if let Some(rest) = rest {
+        statements.push(rest);
+    }
+    statements
+}
+
+/// Whether the script ends with a complete statement, i.e. nothing but whitespace and comments
+/// follows its last terminating semicolon and no quote or comment is left open.
+pub fn is_complete(script: &str) -> bool {
+    scan(script).1.is_none()
+}
+
+/// Return the terminated statements and the unterminated rest, if it holds anything but
+/// whitespace and comments.
+fn scan(script: &str) -> (Vec<String>, Option<String>) {
+    let chars: Vec<char> = script.chars().collect();
+    let mut statements = Vec::new();
+    // Where the current statement starts, once something other than whitespace and comments is seen
+    let mut start: Option<usize> = None;
+    // The leading words of the current statement, to recognize CREATE TRIGGER
+    let mut words: Vec<String> = Vec::new();
+    // How many BEGIN / CASE blocks of a trigger body are open
+    let mut depth = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            let from = i;
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            if i >= chars.len() {
+                // An open comment keeps the statement unfinished, even if it is otherwise empty
+                let rest = chars[start.unwrap_or(from)..].iter().collect();
+                return (statements, Some(rest));
+            }
+            i += 2;
+            continue;
+        }
+        if c == ';' && depth == 0 {
+            if let Some(s) = start.take() {
+                statements.push(chars[s..i].iter().collect::<String>().trim().to_string());
+            }
+            words.clear();
+            i += 1;
+            continue;
+        }
+        start.get_or_insert(i);
+        if c == '\'' || c == '"' || c == '`' {
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    // A doubled quote stands for the quote itself
+                    Some(&q) if q == c && chars.get(i + 1) == Some(&c) => i += 2,
+                    Some(&q) if q == c => break,
+                    Some(_) => i += 1,
+                    None => return (statements, Some(chars[start.unwrap()..].iter().collect())),
+                }
+            }
+            i += 1;
+            continue;
+        }
+        if c.is_alphanumeric() || c == '_' {
+            let from = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word = chars[from..i].iter().collect::<String>().to_uppercase();
+            let in_trigger = words.len() >= 2 && words[0] == "CREATE" && words[1] == "TRIGGER";
+            match word.as_str() {
+                "BEGIN" if in_trigger && depth == 0 => depth += 1,
+                "CASE" if depth > 0 => depth += 1,
+                "END" if depth > 0 => depth -= 1,
+                _ => {}
+            }
+            if words.len() < 2 {
+                words.push(word);
+            }
+            continue;
+        }
+        i += 1;
+    }
+    let rest = start.map(|s| chars[s..].iter().collect::<String>().trim().to_string());
+    (statements, rest)
+}
@@ -1,61 +1,292 @@
-use crate::parser::{ParserError, Query, Condition, Operator};
-use crate::utils::trim_quotes;
+use crate::error::DbError;
+use crate::parser::ast::{
+    Cte, Distinct, Expr, Join, JoinKind, OrderByExpr, Select, SelectItem, SelectStatement, SetExpr, SetOperator, TableRef, With,
+};
+use crate::parser::lexer::Token;
+use crate::parser::{is_reserved, Parser, Query};
 
 /// Parse a SELECT query.
 /// Examples:
 ///   "SELECT id, name FROM users;"
 ///   "SELECT id, name FROM users WHERE age = '30';"
-pub fn parse_select(query: &str) -> Result<Query, ParserError> {
-    let query = query.trim_end_matches(';').trim();
-    let prefix = "SELECT";
-    if !query.to_uppercase().starts_with(prefix) {
-        return Err(ParserError::InvalidSyntax("Not a SELECT command".into()));
-    }
-    let without_select = query[prefix.len()..].trim();
-    // Split into SELECT columns and the rest using " FROM "
-    let parts: Vec<&str> = without_select.splitn(2, " FROM ").collect();
-    if parts.len() != 2 {
-        return Err(ParserError::InvalidSyntax("Missing FROM clause in SELECT".into()));
-    }
-    let columns_str = parts[0].trim();
-    let select_columns: Vec<String> = if columns_str == "*" {
-        vec!["*".to_string()]
-    } else {
-        columns_str.split(',')
-            .map(|s| s.trim().to_string())
-            .collect()
-    };
-    let remaining = parts[1].trim();
-    // Check if there's a WHERE clause
-    if remaining.to_uppercase().contains(" WHERE ") {
-        let parts: Vec<&str> = remaining.splitn(2, " WHERE ").collect();
-        if parts.len() != 2 {
-            return Err(ParserError::InvalidSyntax("Invalid WHERE clause".into()));
-        }
-        let table_name = parts[0].trim().to_string();
-        let condition_str = parts[1].trim();
-        let cond_parts: Vec<&str> = condition_str.splitn(2, '=').collect();
-        if cond_parts.len() != 2 {
-            return Err(ParserError::InvalidSyntax("Invalid condition format".into()));
-        }
-        let cond_column = cond_parts[0].trim().to_string();
-        let cond_value = trim_quotes(cond_parts[1].trim());
-        let condition = Condition {
-            column: cond_column,
-            operator: Operator::Eq, // For simplicity, only '=' is supported here.
-            value: cond_value,
+///   "SELECT age, COUNT(*) FROM users GROUP BY age HAVING COUNT(*) > 1;"
+///   "SELECT price * qty AS total, UPPER(name) FROM items;"
+///   "SELECT DISTINCT ON (dept) dept, name FROM emp ORDER BY dept, salary DESC LIMIT 10;"
+///   "SELECT u.name, o.total FROM users u LEFT JOIN orders o ON o.user_id = u.id;"
+///   "SELECT name FROM users WHERE id IN (SELECT user_id FROM orders);"
+///   "SELECT * FROM (SELECT age, COUNT(*) FROM users GROUP BY age) AS t;"
+///   "SELECT name FROM users UNION SELECT name FROM customers;"
+///   "WITH RECURSIVE sub(id) AS (SELECT id FROM emp WHERE id = 1 UNION SELECT e.id FROM emp e JOIN sub ON e.boss = sub.id) SELECT * FROM sub;"
+pub fn parse_select(mut parser: Parser) -> Result<Query, DbError> {
+    if !parser.peek_select() && parser.peek() != Some(&Token::LParen) {
+        return Err(DbError::Syntax("Not a SELECT command".into()));
+    }
+    let select = parser.parse_select_statement()?;
+    parser.expect_end()?;
+    Ok(Query::Select(select))
+}
+
+impl Parser {
+    /// Parse a full SELECT statement, including a leading WITH clause and any
+    /// UNION / INTERSECT / EXCEPT.
+    pub fn parse_select_statement(&mut self) -> Result<SelectStatement, DbError> {
+        let with = if self.consume_keyword("WITH") { Some(self.parse_with()?) } else { None };
+        let body = self.parse_set_expr(0)?;
+        let order_by = if self.consume_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            self.parse_comma_separated(Parser::parse_order_by_expr)?
+        } else {
+            Vec::new()
+        };
+        let mut limit = None;
+        let mut offset = None;
+        if self.consume_keyword("LIMIT") {
+            limit = Some(self.parse_expr()?);
+        }
+        if self.consume_keyword("OFFSET") {
+            offset = Some(self.parse_expr()?);
+        }
+        Ok(SelectStatement { with, body, order_by, limit, offset })
+    }
+
+    pub fn parse_order_by_expr(&mut self) -> Result<OrderByExpr, DbError> {
+        let expr = self.parse_expr()?;
+        let descending = if self.consume_keyword("DESC") {
+            true
+        } else {
+            self.consume_keyword("ASC");
+            false
         };
-        Ok(Query::Select {
-            table_name,
-            select_columns,
-            condition: Some(condition),
-        })
-    } else {
-        let table_name = remaining.to_string();
-        Ok(Query::Select {
-            table_name,
-            select_columns,
-            condition: None,
-        })
-    }
-}
\ No newline at end of file
+        let nulls_first = if self.consume_keyword("NULLS") {
+            if self.consume_keyword("FIRST") {
+                Some(true)
+            } else {
+                self.expect_keyword("LAST")?;
+                Some(false)
+            }
+        } else {
+            None
+        };
+        Ok(OrderByExpr { expr, descending, nulls_first })
+    }
+
+    /// Parse the common table expressions following WITH.
+    fn parse_with(&mut self) -> Result<With, DbError> {
+        let recursive = self.consume_keyword("RECURSIVE");
+        let ctes = self.parse_comma_separated(|p| {
+            let name = p.parse_identifier()?;
+            let columns = if p.consume(&Token::LParen) {
+                let columns = p.parse_comma_separated(Parser::parse_identifier)?;
+                p.expect(&Token::RParen)?;
+                columns
+            } else {
+                Vec::new()
+            };
+            p.expect_keyword("AS")?;
+            p.expect(&Token::LParen)?;
+            let query = p.parse_select_statement()?;
+            p.expect(&Token::RParen)?;
+            Ok(Cte { name, columns, query })
+        })?;
+        Ok(With { recursive, ctes })
+    }
+
+    /// Whether the next tokens start a statement that `parse_select_statement` accepts.
+    pub fn peek_select(&self) -> bool {
+        self.peek_keyword("SELECT") || self.peek_keyword("WITH")
+    }
+
+    /// Parse set operations by precedence climbing. All set operators are left-associative.
+    fn parse_set_expr(&mut self, min_precedence: u8) -> Result<SetExpr, DbError> {
+        let mut left = self.parse_set_operand()?;
+        loop {
+            let op = if self.peek_keyword("UNION") {
+                SetOperator::Union
+            } else if self.peek_keyword("INTERSECT") {
+                SetOperator::Intersect
+            } else if self.peek_keyword("EXCEPT") {
+                SetOperator::Except
+            } else {
+                return Ok(left);
+            };
+            if op.precedence() < min_precedence {
+                return Ok(left);
+            }
+            self.next_token();
+            let all = self.consume_keyword("ALL");
+            if !all {
+                self.consume_keyword("DISTINCT");
+            }
+            let right = self.parse_set_expr(op.precedence() + 1)?;
+            left = SetExpr::SetOperation { left: Box::new(left), op, all, right: Box::new(right) };
+        }
+    }
+
+    /// Parse a single SELECT block or a parenthesized set expression.
+    fn parse_set_operand(&mut self) -> Result<SetExpr, DbError> {
+        if self.consume(&Token::LParen) {
+            let expr = self.parse_set_expr(0)?;
+            self.expect(&Token::RParen)?;
+            return Ok(expr);
+        }
+        Ok(SetExpr::Select(Box::new(self.parse_select_block()?)))
+    }
+
+    fn parse_select_block(&mut self) -> Result<Select, DbError> {
+        self.expect_keyword("SELECT")?;
+        let distinct = if self.consume_keyword("DISTINCT") {
+            if self.consume_keyword("ON") {
+                self.expect(&Token::LParen)?;
+                let exprs = self.parse_comma_separated(Parser::parse_expr)?;
+                self.expect(&Token::RParen)?;
+                Some(Distinct::On(exprs))
+            } else {
+                Some(Distinct::Row)
+            }
+        } else {
+            self.consume_keyword("ALL");
+            None
+        };
+        let select_columns = self.parse_comma_separated(Parser::parse_select_item)?;
+        if !self.consume_keyword("FROM") {
+            return Err(DbError::Syntax("Missing FROM clause in SELECT".into()));
+        }
+        let from = self.parse_table_ref()?;
+        let joins = self.parse_joins()?;
+        let condition = self.parse_where()?;
+        let group_by = if self.consume_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            self.parse_comma_separated(Parser::parse_expr)?
+        } else {
+            Vec::new()
+        };
+        let having = if self.consume_keyword("HAVING") {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        let clauses = group_by.iter().map(|e| ("GROUP BY", e)).chain(having.iter().map(|e| ("HAVING", e)));
+        for (clause, expr) in clauses {
+            if expr.contains_window() {
+                return Err(DbError::Syntax(format!("Window functions are not allowed in {}", clause)));
+            }
+        }
+        Ok(Select { distinct, select_columns, from, joins, condition, group_by, having })
+    }
+
+    /// Parse an optional `WHERE condition`. Aggregate and window functions are not allowed there.
+    pub fn parse_where(&mut self) -> Result<Option<Expr>, DbError> {
+        if !self.consume_keyword("WHERE") {
+            return Ok(None);
+        }
+        let condition = self.parse_expr()?;
+        if condition.contains_aggregate() {
+            return Err(DbError::Syntax("Aggregate functions are not allowed in WHERE".into()));
+        }
+        if condition.contains_window() {
+            return Err(DbError::Syntax("Window functions are not allowed in WHERE".into()));
+        }
+        Ok(Some(condition))
+    }
+
+    /// Parse an optional `RETURNING item, ...` of INSERT / UPDATE / DELETE.
+    pub fn parse_returning(&mut self) -> Result<Vec<SelectItem>, DbError> {
+        if !self.consume_keyword("RETURNING") {
+            return Ok(Vec::new());
+        }
+        let items = self.parse_comma_separated(Parser::parse_select_item)?;
+        for item in &items {
+            if let SelectItem::Expr { expr, .. } = item {
+                if expr.contains_aggregate() || expr.contains_window() {
+                    return Err(DbError::Syntax("Aggregate and window functions are not allowed in RETURNING".into()));
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    fn parse_select_item(&mut self) -> Result<SelectItem, DbError> {
+        if self.consume(&Token::Star) {
+            return Ok(SelectItem::Wildcard);
+        }
+        if let (Some(Token::Ident(qualifier)), Some(Token::Dot), Some(Token::Star)) =
+            (self.peek_nth(0), self.peek_nth(1), self.peek_nth(2))
+        {
+            let qualifier = qualifier.clone();
+            self.pos += 3;
+            return Ok(SelectItem::QualifiedWildcard(qualifier));
+        }
+        let expr = self.parse_expr()?;
+        let alias = self.parse_alias()?;
+        Ok(SelectItem::Expr { expr, alias })
+    }
+
+    /// Parse `[schema.]name [[AS] alias]` or `(SELECT ...) [AS] alias`.
+    fn parse_table_ref(&mut self) -> Result<TableRef, DbError> {
+        if self.consume(&Token::LParen) {
+            let subquery = Box::new(self.parse_select_statement()?);
+            self.expect(&Token::RParen)?;
+            let alias = self
+                .parse_alias()?
+                .ok_or_else(|| DbError::Syntax("A subquery in FROM must have an alias".into()))?;
+            return Ok(TableRef::Derived { subquery, alias });
+        }
+        let mut name = self.parse_identifier()?;
+        // A schema-qualified name such as information_schema.tables
+        if self.peek() == Some(&Token::Dot) {
+            self.next_token();
+            name = format!("{}.{}", name, self.parse_identifier()?);
+        }
+        let alias = self.parse_alias()?;
+        Ok(TableRef::Table { name, alias })
+    }
+
+    /// Parse an optional `[AS] alias`.
+    pub fn parse_alias(&mut self) -> Result<Option<String>, DbError> {
+        let has_alias = self.consume_keyword("AS")
+            || matches!(self.peek(), Some(Token::Ident(word)) if !is_reserved(word));
+        if has_alias {
+            Ok(Some(self.parse_identifier()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parse the joins following the first table of the FROM clause.
+    /// A comma between tables is the same as CROSS JOIN.
+    fn parse_joins(&mut self) -> Result<Vec<Join>, DbError> {
+        let mut joins = Vec::new();
+        loop {
+            let kind = if self.consume(&Token::Comma) {
+                joins.push(Join { kind: JoinKind::Cross, table: self.parse_table_ref()?, constraint: None });
+                continue;
+            } else if self.consume_keyword("CROSS") {
+                JoinKind::Cross
+            } else if self.consume_keyword("INNER") {
+                JoinKind::Inner
+            } else if self.consume_keyword("LEFT") {
+                self.consume_keyword("OUTER");
+                JoinKind::Left
+            } else if self.consume_keyword("RIGHT") {
+                self.consume_keyword("OUTER");
+                JoinKind::Right
+            } else if self.consume_keyword("FULL") {
+                self.consume_keyword("OUTER");
+                JoinKind::Full
+            } else if self.peek_keyword("JOIN") {
+                JoinKind::Inner
+            } else {
+                return Ok(joins);
+            };
+            self.expect_keyword("JOIN")?;
+            let table = self.parse_table_ref()?;
+            let constraint = if kind == JoinKind::Cross {
+                None
+            } else {
+                self.expect_keyword("ON")?;
+                Some(self.parse_expr()?)
+            };
+            joins.push(Join { kind, table, constraint });
+        }
+    }
+}
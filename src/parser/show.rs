@@ -0,0 +1,37 @@
+use crate::error::DbError;
+use crate::parser::{Parser, Query};
+
+/// Parse SHOW TABLES or SHOW CREATE TABLE.
+/// Examples:
+///   "SHOW TABLES;"
+///   "SHOW CREATE TABLE users;"
+pub fn parse_show(mut parser: Parser) -> Result<Query, DbError> {
+    if !parser.consume_keyword("SHOW") {
+        return Err(DbError::Syntax("Not a SHOW command".into()));
+    }
+    let query = if parser.consume_keyword("TABLES") {
+        Query::ShowTables
+    } else if parser.consume_keyword("CREATE") {
+        // A view is shown with its CREATE VIEW statement, so SHOW CREATE VIEW is the same command
+        if !(parser.consume_keyword("TABLE") || parser.consume_keyword("VIEW")) {
+            return Err(parser.unexpected("TABLE or VIEW"));
+        }
+        Query::ShowCreateTable { table: parser.parse_identifier()? }
+    } else {
+        return Err(parser.unexpected("TABLES or CREATE TABLE"));
+    };
+    parser.expect_end()?;
+    Ok(query)
+}
+
+/// Parse DESCRIBE (or DESC) followed by a table or view name.
+/// Example:
+///   "DESCRIBE users;"
+pub fn parse_describe(mut parser: Parser) -> Result<Query, DbError> {
+    if !(parser.consume_keyword("DESCRIBE") || parser.consume_keyword("DESC")) {
+        return Err(DbError::Syntax("Not a DESCRIBE command".into()));
+    }
+    let table = parser.parse_identifier()?;
+    parser.expect_end()?;
+    Ok(Query::Describe { table })
+}
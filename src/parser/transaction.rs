@@ -0,0 +1,28 @@
+use crate::error::DbError;
+use crate::parser::{Parser, Query};
+
+/// Parse BEGIN, COMMIT or ROLLBACK. `START TRANSACTION` and `END` are accepted as in PostgreSQL,
+/// and each may be followed by TRANSACTION or WORK.
+/// Examples:
+///   "BEGIN;"
+///   "COMMIT TRANSACTION;"
+///   "ROLLBACK;"
+pub fn parse_transaction(mut parser: Parser) -> Result<Query, DbError> {
+    let query = if parser.consume_keyword("BEGIN") {
+        Query::Begin
+    } else if parser.consume_keyword("START") {
+        parser.expect_keyword("TRANSACTION")?;
+        Query::Begin
+    } else if parser.consume_keyword("COMMIT") || parser.consume_keyword("END") {
+        Query::Commit
+    } else if parser.consume_keyword("ROLLBACK") {
+        Query::Rollback
+    } else {
+        return Err(DbError::Syntax("Not a transaction command".into()));
+    };
+    if !parser.consume_keyword("TRANSACTION") {
+        parser.consume_keyword("WORK");
+    }
+    parser.expect_end()?;
+    Ok(query)
+}
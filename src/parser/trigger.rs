@@ -0,0 +1,64 @@
+use crate::error::DbError;
+use crate::parser::ast::{TriggerEvent, TriggerTiming};
+use crate::parser::lexer::{to_sql, Token};
+use crate::parser::{parse_query, Parser, Query};
+
+/// Parse a CREATE TRIGGER query. The body is one or more INSERT, UPDATE, DELETE or SELECT statements
+/// between BEGIN and END, each ending with a semicolon, that can read the row as `NEW.column` / `OLD.column`.
+/// Example:
+///   "CREATE TRIGGER log_names AFTER UPDATE ON users FOR EACH ROW BEGIN INSERT INTO log VALUES (OLD.name, NEW.name); END;"
+pub fn parse_create_trigger(mut parser: Parser) -> Result<Query, DbError> {
+    if !(parser.consume_keyword("CREATE") && parser.consume_keyword("TRIGGER")) {
+        return Err(DbError::Syntax("Not a CREATE TRIGGER command".into()));
+    }
+    // The body is stored and run later, when there is nothing to bind parameters to
+    if parser.tokens.iter().any(|t| matches!(t, Token::Parameter(_))) {
+        return Err(DbError::Syntax("Parameters are not allowed in a trigger".into()));
+    }
+    let name = parser.parse_identifier()?;
+    let timing = if parser.consume_keyword("BEFORE") {
+        TriggerTiming::Before
+    } else if parser.consume_keyword("AFTER") {
+        TriggerTiming::After
+    } else {
+        return Err(parser.unexpected("BEFORE or AFTER"));
+    };
+    let event = if parser.consume_keyword("INSERT") {
+        TriggerEvent::Insert
+    } else if parser.consume_keyword("UPDATE") {
+        TriggerEvent::Update
+    } else if parser.consume_keyword("DELETE") {
+        TriggerEvent::Delete
+    } else {
+        return Err(parser.unexpected("INSERT, UPDATE or DELETE"));
+    };
+    parser.expect_keyword("ON")?;
+    let table = parser.parse_identifier()?;
+    for keyword in ["FOR", "EACH", "ROW", "BEGIN"] {
+        parser.expect_keyword(keyword)?;
+    }
+    let mut body = Vec::new();
+    // No statement can start with END, and a semicolon only ever ends a statement
+    // (one inside a string literal is part of the String token).
+    while !parser.peek_keyword("END") {
+        let start = parser.pos;
+        while !matches!(parser.peek(), None | Some(Token::Semicolon)) {
+            parser.pos += 1;
+        }
+        if start == parser.pos {
+            return Err(parser.unexpected("statement"));
+        }
+        let sql = to_sql(&parser.tokens[start..parser.pos]);
+        parser.expect(&Token::Semicolon)?;
+        match parse_query(&sql)? {
+            Query::Insert(_) | Query::Update(_) | Query::Delete(_) | Query::Select(_) => body.push(sql),
+            _ => return Err(DbError::Syntax("Only INSERT, UPDATE, DELETE and SELECT are allowed in a trigger body".into())),
+        }
+    }
+    parser.expect_keyword("END")?;
+    parser.expect_end()?;
+    if body.is_empty() {
+        return Err(DbError::Syntax("A trigger body needs at least one statement".into()));
+    }
+    Ok(Query::CreateTrigger { name, timing, event, table, body })
+}
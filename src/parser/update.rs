@@ -0,0 +1,21 @@
+use crate::error::DbError;
+use crate::parser::ast::Update;
+use crate::parser::{Parser, Query};
+
+/// Parse an UPDATE query.
+/// Examples:
+///   "UPDATE users SET age = age + 1 WHERE id = 1;"
+///   "UPDATE users u SET name = UPPER(u.name) WHERE u.age > 30 RETURNING id, name;"
+pub fn parse_update(mut parser: Parser) -> Result<Query, DbError> {
+    if !parser.consume_keyword("UPDATE") {
+        return Err(DbError::Syntax("Not an UPDATE command".into()));
+    }
+    let table_name = parser.parse_identifier()?;
+    let alias = parser.parse_alias()?;
+    parser.expect_keyword("SET")?;
+    let assignments = parser.parse_comma_separated(Parser::parse_assignment)?;
+    let condition = parser.parse_where()?;
+    let returning = parser.parse_returning()?;
+    parser.expect_end()?;
+    Ok(Query::Update(Update { table_name, alias, assignments, condition, returning }))
+}
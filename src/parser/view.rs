@@ -0,0 +1,48 @@
+use crate::error::DbError;
+use crate::parser::lexer::Token;
+use crate::parser::{Parser, Query};
+
+/// Parse a CREATE [MATERIALIZED] VIEW query. The optional column list renames the columns of the SELECT.
+/// Examples:
+///   "CREATE VIEW adults AS SELECT * FROM users WHERE age >= 20;"
+///   "CREATE MATERIALIZED VIEW totals (user_id, total) AS SELECT user_id, SUM(total) FROM orders GROUP BY user_id;"
+pub fn parse_create_view(mut parser: Parser) -> Result<Query, DbError> {
+    if !parser.consume_keyword("CREATE") {
+        return Err(DbError::Syntax("Not a CREATE VIEW command".into()));
+    }
+    let materialized = parser.consume_keyword("MATERIALIZED");
+    if !parser.consume_keyword("VIEW") {
+        return Err(DbError::Syntax("Not a CREATE VIEW command".into()));
+    }
+    // The definition is stored and re-run later, when there is nothing to bind parameters to
+    if parser.tokens.iter().any(|t| matches!(t, Token::Parameter(_))) {
+        return Err(DbError::Syntax("Parameters are not allowed in a view definition".into()));
+    }
+    let name = parser.parse_identifier()?;
+    let columns = if parser.consume(&Token::LParen) {
+        let columns = parser.parse_comma_separated(Parser::parse_identifier)?;
+        parser.expect(&Token::RParen)?;
+        columns
+    } else {
+        Vec::new()
+    };
+    parser.expect_keyword("AS")?;
+    if !parser.peek_select() && parser.peek() != Some(&Token::LParen) {
+        return Err(parser.unexpected("SELECT"));
+    }
+    let statement = parser.parse_select_statement()?;
+    parser.expect_end()?;
+    Ok(Query::CreateView { name, columns, statement, materialized })
+}
+
+/// Parse a REFRESH MATERIALIZED VIEW query.
+/// Example:
+///   "REFRESH MATERIALIZED VIEW totals;"
+pub fn parse_refresh(mut parser: Parser) -> Result<Query, DbError> {
+    if !(parser.consume_keyword("REFRESH") && parser.consume_keyword("MATERIALIZED") && parser.consume_keyword("VIEW")) {
+        return Err(DbError::Syntax("Not a REFRESH MATERIALIZED VIEW command".into()));
+    }
+    let name = parser.parse_identifier()?;
+    parser.expect_end()?;
+    Ok(Query::RefreshMaterializedView { name })
+}
@@ -0,0 +1,215 @@
+use crate::database::QueryResult;
+use crate::error::DbError;
+use crate::value::Value;
+use std::sync::Arc;
+
+/// `Database::query` の結果。カラム名と行の並び。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rows {
+    columns: Arc<[String]>,
+    rows: Vec<Row>,
+}
+
+impl Rows {
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Row> {
+        self.rows.iter()
+    }
+}
+
+impl From<QueryResult> for Rows {
+    fn from(result: QueryResult) -> Self {
+        let columns: Arc<[String]> = result.columns.into();
+        let rows = result.rows.into_iter().map(|values| Row { columns: columns.clone(), values }).collect();
+        Rows { columns, rows }
+    }
+}
+
+impl IntoIterator for Rows {
+    type Item = Row;
+    type IntoIter = std::vec::IntoIter<Row>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rows.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Rows {
+    type Item = &'a Row;
+    type IntoIter = std::slice::Iter<'a, Row>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rows.iter()
+    }
+}
+
+/// 結果の 1 行。`get` でカラム位置かカラム名を指定して値を取り出す。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    columns: Arc<[String]>,
+    values: Vec<Value>,
+}
+
+impl Row {
+    /// 値を `T` に変換して取り出す。NULL を受け取るには `Option<T>` を使う。
+    pub fn get<T: FromValue>(&self, index: impl RowIndex) -> Result<T, DbError> {
+        let i = index.position(&self.columns)?;
+        T::from_value(&self.values[i])
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+}
+
+/// `Row::get` に渡せるカラムの指定。カラム位置（0 始まり）かカラム名。
+pub trait RowIndex {
+    fn position(&self, columns: &[String]) -> Result<usize, DbError>;
+}
+
+impl RowIndex for usize {
+    fn position(&self, columns: &[String]) -> Result<usize, DbError> {
+        if *self < columns.len() {
+            Ok(*self)
+        } else {
+            Err(DbError::UndefinedColumn(self.to_string()))
+        }
+    }
+}
+
+impl RowIndex for &str {
+    fn position(&self, columns: &[String]) -> Result<usize, DbError> {
+        columns.iter().position(|c| c == self).ok_or_else(|| DbError::UndefinedColumn(self.to_string()))
+    }
+}
+
+/// `Value` から Rust の型への変換。型指定のないカラムなので、数値に見える文字列は数値として読む。
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self, DbError>;
+}
+
+fn mismatch(value: &Value, type_name: &str) -> DbError {
+    DbError::TypeMismatch(format!("Cannot convert '{}' to {}", value, type_name))
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self, DbError> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<Self, DbError> {
+        match value.clone().numeric_affinity() {
+            Value::Integer(i) => Ok(i),
+            _ => Err(mismatch(value, "i64")),
+        }
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: &Value) -> Result<Self, DbError> {
+        i64::from_value(value).and_then(|i| i32::try_from(i).map_err(|_| mismatch(value, "i32")))
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, DbError> {
+        match value {
+            Value::Boolean(_) => Err(mismatch(value, "f64")),
+            _ => value.as_f64().ok_or_else(|| mismatch(value, "f64")),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, DbError> {
+        match value {
+            Value::Boolean(b) => Ok(*b),
+            Value::Integer(0) => Ok(false),
+            Value::Integer(1) => Ok(true),
+            _ => Err(mismatch(value, "bool")),
+        }
+    }
+}
+
+/// NULL 以外の値はすべて文字列にできる（CAST(... AS TEXT) と同じ表記）。
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, DbError> {
+        match value {
+            Value::Null => Err(mismatch(value, "String")),
+            Value::Text(s) => Ok(s.clone()),
+            other => Ok(other.to_string()),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self, DbError> {
+        match value {
+            Value::Null => Ok(None),
+            other => T::from_value(other).map(Some),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows() -> Rows {
+        Rows::from(QueryResult {
+            columns: vec!["id".into(), "name".into(), "score".into(), "active".into()],
+            rows: vec![
+                vec![Value::Integer(1), Value::Text("John".into()), Value::Float(1.5), Value::Boolean(true)],
+                vec![Value::Integer(2), Value::Null, Value::Text("42".into()), Value::Integer(0)],
+            ],
+            rows_affected: 0,
+        })
+    }
+
+    #[test]
+    fn test_get() {
+        let rows = rows();
+        assert_eq!(rows.columns(), ["id", "name", "score", "active"]);
+        assert_eq!(rows.len(), 2);
+        let first = rows.iter().next().unwrap();
+        assert_eq!(first.get::<i64>(0).unwrap(), 1);
+        assert_eq!(first.get::<String>("name").unwrap(), "John");
+        assert_eq!(first.get::<f64>("score").unwrap(), 1.5);
+        assert!(first.get::<bool>("active").unwrap());
+        assert_eq!(first.get::<String>("id").unwrap(), "1");
+        let second: Vec<Row> = rows.into_iter().skip(1).collect();
+        // 数値に見える文字列は数値として読める
+        assert_eq!(second[0].get::<i32>("score").unwrap(), 42);
+        assert_eq!(second[0].get::<Option<String>>("name").unwrap(), None);
+        assert!(!second[0].get::<bool>(3).unwrap());
+    }
+
+    #[test]
+    fn test_get_errors() {
+        let rows = rows();
+        let first = rows.iter().next().unwrap();
+        assert_eq!(first.get::<i64>("name").unwrap_err().code(), "42804");
+        assert_eq!(first.get::<i64>("score").unwrap_err().code(), "42804");
+        assert_eq!(first.get::<i64>("nope").unwrap_err().code(), "42703");
+        assert_eq!(first.get::<i64>(9).unwrap_err().code(), "42703");
+        let second = rows.iter().nth(1).unwrap();
+        assert!(second.get::<String>("name").unwrap_err().to_string().contains("NULL"));
+    }
+}
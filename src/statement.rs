@@ -0,0 +1,224 @@
+use crate::database::{Database, QueryResult};
+use crate::error::DbError;
+use crate::executor::{self, plan::Plan};
+use crate::parser::ast::SelectStatement;
+use crate::parser::lexer::tokenize_with_parameters;
+use crate::parser::{parse_tokens, Query};
+use crate::row::Rows;
+use crate::value::Value;
+use std::cell::RefCell;
+
+/// `Database::prepare` で作るプリペアドステートメント。解析済みの文と SELECT 文の実行計画を持ち、
+/// パラメータに値をバインドし直して何度でも実行できる。値は SQL の文字列に埋め込まれないので、
+/// 文字列連結と違って値の中身が SQL として解釈されることはない。
+#[derive(Debug, Clone)]
+pub struct Statement {
+    query: Query,
+    /// パラメータごとの名前（`:name` の `name`）。`?` / `$1` のパラメータは None。
+    names: Vec<Option<String>>,
+    values: Vec<Option<Value>>,
+    /// SELECT 文の実行計画と、計画を立てたときのカタログの版（`Database::catalog_version`）。
+    plan: RefCell<Option<(u64, Plan)>>,
+}
+
+impl Statement {
+    pub(crate) fn new(sql: &str) -> Result<Self, DbError> {
+        let (tokens, names) = tokenize_with_parameters(sql)?;
+        let query = parse_tokens(tokens)?;
+        let values = vec![None; names.len()];
+        Ok(Statement { query, names, values, plan: RefCell::new(None) })
+    }
+
+    /// パラメータの数。`$3` だけを使う文でも 3 になる。
+    pub fn parameter_count(&self) -> usize {
+        self.names.len()
+    }
+
+    /// パラメータに値をバインドする。`index` は 1 始まりの位置か、`":name"` / `"$1"` の形の名前。
+    pub fn bind(&mut self, index: impl ParamIndex, value: impl Into<Value>) -> Result<&mut Self, DbError> {
+        let i = index.position(&self.names)?;
+        self.values[i] = Some(value.into());
+        Ok(self)
+    }
+
+    /// バインドした値をすべて消す。
+    pub fn clear_bindings(&mut self) {
+        self.values.iter_mut().for_each(|v| *v = None);
+    }
+
+    /// 文を実行し、書き込んだ（削除した）行数を返す。`Database::execute` と同じく、
+    /// `open` で開いたデータベースでは SELECT 以外の文の後にファイルへ保存する（`set_autosave(false)` なら保存しない）。
+    pub fn execute(&self, db: &mut Database) -> Result<usize, DbError> {
+        let result = db.execute_query_with_params(&self.query, &self.params()?)?;
+        if self.query.needs_save() && db.is_autosave() {
+            db.save()?;
+        }
+        Ok(result.rows_affected)
+    }
+
+    /// SELECT 文を実行して結果の行を返す。
+    pub fn query(&self, db: &Database) -> Result<Rows, DbError> {
+        match &self.query {
+            Query::Select(select) => {
+                let params = self.params()?;
+                let result = self.with_plan(db, select, |plan| executor::execute_plan(db, plan, &params))?;
+                Ok(QueryResult::from(result).into())
+            }
+            Query::Explain { analyze, statement } => Ok(db.explain(statement, *analyze, &self.params()?)?.into()),
+            Query::ShowTables => Ok(db.show_tables().into()),
+            Query::Describe { table } => Ok(db.describe(table)?.into()),
+            Query::ShowCreateTable { table } => Ok(db.show_create_table(table)?.into()),
+            _ => Err(DbError::InvalidQuery("query() only runs SELECT, EXPLAIN, SHOW and DESCRIBE statements; use execute() instead.".into())),
+        }
+    }
+
+    /// SELECT 文の実行計画を渡して `f` を呼ぶ。テーブルやビューの定義、統計情報が
+    /// 前回計画を立てたときから変わっていれば（別のデータベースで実行する場合も）、計画を立て直す。
+    fn with_plan<T>(&self, db: &Database, select: &SelectStatement, f: impl FnOnce(&Plan) -> Result<T, DbError>) -> Result<T, DbError> {
+        let mut cached = self.plan.borrow_mut();
+        let version = db.catalog_version();
+        if !matches!(&*cached, Some((v, _)) if *v == version) {
+            *cached = Some((version, executor::plan_select(db, select)?));
+        }
+        let (_, plan) = cached.as_ref().expect("plan was just built");
+        f(plan)
+    }
+
+    /// バインドした値を `$1` から順に並べる。値のないパラメータがあればエラー。
+    fn params(&self) -> Result<Vec<Value>, DbError> {
+        self.values
+            .iter()
+            .zip(&self.names)
+            .enumerate()
+            .map(|(i, (value, name))| {
+                value.clone().ok_or_else(|| {
+                    let name = name.as_ref().map_or_else(|| format!("${}", i + 1), |n| format!(":{}", n));
+                    DbError::InvalidValue(format!("No value is bound to parameter '{}'.", name))
+                })
+            })
+            .collect()
+    }
+}
+
+/// `Statement::bind` に渡せるパラメータの指定。1 始まりの位置か名前。
+pub trait ParamIndex {
+    /// 0 始まりの位置を返す。
+    fn position(&self, names: &[Option<String>]) -> Result<usize, DbError>;
+}
+
+impl ParamIndex for usize {
+    fn position(&self, names: &[Option<String>]) -> Result<usize, DbError> {
+        if (1..=names.len()).contains(self) {
+            Ok(self - 1)
+        } else {
+            Err(DbError::UndefinedParameter(format!("${}", self)))
+        }
+    }
+}
+
+impl ParamIndex for &str {
+    fn position(&self, names: &[Option<String>]) -> Result<usize, DbError> {
+        let undefined = || DbError::UndefinedParameter(self.to_string());
+        match self.strip_prefix('$').or_else(|| self.strip_prefix('?')) {
+            Some(n) => n.parse::<usize>().map_err(|_| undefined())?.position(names).map_err(|_| undefined()),
+            None => {
+                let name = self.strip_prefix(':').unwrap_or(self);
+                names.iter().position(|n| n.as_deref() == Some(name)).ok_or_else(undefined)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::value::Value;
+
+    fn setup() -> Database {
+        let mut db = Database::new();
+        db.execute("CREATE TABLE users (id, name, age)").unwrap();
+        db.execute("INSERT INTO users VALUES (1, 'John', 30), (2, 'Mike', 40), (3, 'Anna', 25)").unwrap();
+        db
+    }
+
+    fn names(db: &Database, sql: &str) -> Vec<String> {
+        db.query(sql).unwrap().iter().map(|r| r.get::<String>("name").unwrap()).collect()
+    }
+
+    #[test]
+    fn test_positional_parameters() {
+        let db = setup();
+        let mut stmt = db.prepare("SELECT name FROM users WHERE id = ?").unwrap();
+        assert_eq!(stmt.parameter_count(), 1);
+        // 同じ文を値だけ変えて何度も実行できる
+        for (id, name) in [(1, "John"), (3, "Anna")] {
+            let rows = stmt.bind(1, id).unwrap().query(&db).unwrap();
+            assert_eq!(rows.iter().next().unwrap().get::<String>(0).unwrap(), name);
+        }
+        let mut stmt = db.prepare("SELECT name FROM users WHERE age > $2 AND id <> $1 ORDER BY id").unwrap();
+        stmt.bind(1, 2).unwrap().bind("$2", 20).unwrap();
+        let rows: Vec<String> = stmt.query(&db).unwrap().iter().map(|r| r.get(0).unwrap()).collect();
+        assert_eq!(rows, vec!["John", "Anna"]);
+    }
+
+    #[test]
+    fn test_named_parameters() {
+        let mut db = setup();
+        let mut insert = db.prepare("INSERT INTO users VALUES (:id, :name, :age) RETURNING id").unwrap();
+        assert_eq!(insert.parameter_count(), 3);
+        insert.bind(":id", 4).unwrap().bind("name", "Robert'); DROP TABLE users; --").unwrap().bind(":age", None::<i64>).unwrap();
+        assert_eq!(insert.execute(&mut db).unwrap(), 1);
+        // 値は SQL として解釈されず、そのまま格納される
+        assert_eq!(names(&db, "SELECT name FROM users WHERE id = 4"), vec!["Robert'); DROP TABLE users; --"]);
+        let mut update = db.prepare("UPDATE users SET age = :age WHERE name = :name OR id = :age").unwrap();
+        assert_eq!(update.parameter_count(), 2);
+        update.bind(":age", 1).unwrap().bind(":name", "Anna").unwrap();
+        assert_eq!(update.execute(&mut db).unwrap(), 2);
+        assert_eq!(names(&db, "SELECT name FROM users WHERE age = 1 ORDER BY id"), vec!["John", "Anna"]);
+    }
+
+    #[test]
+    fn test_parameter_errors() {
+        let mut db = setup();
+        let mut stmt = db.prepare("SELECT name FROM users WHERE id = ? OR name = :name").unwrap();
+        assert_eq!(stmt.bind(3, 1).unwrap_err().code(), "42P02");
+        assert_eq!(stmt.bind(":nope", 1).unwrap_err().code(), "42P02");
+        stmt.bind(1, 1).unwrap();
+        let err = stmt.query(&db).unwrap_err();
+        assert!(err.to_string().contains("':name'"));
+        stmt.bind(":name", Value::Null).unwrap();
+        assert_eq!(stmt.query(&db).unwrap().len(), 1);
+        stmt.clear_bindings();
+        assert!(stmt.query(&db).is_err());
+        // 値のないパラメータを含む文をそのまま実行するとエラー
+        assert!(db.execute("DELETE FROM users WHERE id = ?").unwrap_err().to_string().contains("'$1'"));
+        assert!(db.prepare("SELECT * FROM users WHERE id = $0").is_err());
+    }
+
+    #[test]
+    fn test_plan_is_cached() {
+        let mut db = setup();
+        let mut stmt = db.prepare("SELECT name FROM users WHERE id = ?").unwrap();
+        let version = |stmt: &super::Statement| stmt.plan.borrow().as_ref().map(|(v, _)| *v);
+        assert_eq!(version(&stmt), None);
+        stmt.bind(1, 1).unwrap().query(&db).unwrap();
+        let planned = version(&stmt);
+        // 値を変えても計画は立て直さない
+        let rows = stmt.bind(1, 2).unwrap().query(&db).unwrap();
+        assert_eq!(rows.iter().next().unwrap().get::<String>(0).unwrap(), "Mike");
+        assert_eq!(version(&stmt), planned);
+        // 統計情報やテーブルの定義が変わると立て直す
+        db.execute("ANALYZE users").unwrap();
+        stmt.query(&db).unwrap();
+        assert_ne!(version(&stmt), planned);
+        let analyzed = version(&stmt);
+        db.execute("CREATE TABLE other (id)").unwrap();
+        stmt.query(&db).unwrap();
+        assert_ne!(version(&stmt), analyzed);
+        // 別のデータベースで実行しても、そちらのテーブルで計画を立てる
+        let mut other = Database::new();
+        other.execute("CREATE TABLE users (id, name)").unwrap();
+        other.execute("INSERT INTO users VALUES (2, 'Kate')").unwrap();
+        assert_eq!(stmt.query(&other).unwrap().iter().next().unwrap().get::<String>(0).unwrap(), "Kate");
+    }
+}
@@ -0,0 +1,133 @@
+use crate::table::Table;
+use crate::value::Value;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// 等深ヒストグラムのバケット数
+pub const HISTOGRAM_BUCKETS: usize = 10;
+
+/// `ANALYZE` で集めた 1 テーブル分の統計情報。実行計画のコストの見積もりに使う。
+/// 書き込みでは更新しないので、次の `ANALYZE` までは古い値のままになる。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableStats {
+    /// `ANALYZE` した時点の行数
+    pub row_count: usize,
+    /// テーブルのカラムと同じ順に並ぶ
+    pub columns: Vec<ColumnStats>,
+}
+
+/// 1 カラム分の統計情報
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnStats {
+    pub name: String,
+    /// NULL 以外の異なる値の数
+    pub distinct: usize,
+    /// NULL の行の割合（0.0 〜 1.0）
+    pub null_fraction: f64,
+    /// NULL 以外の値を昇順に並べ、ほぼ同じ行数ずつに区切った境界値（等深ヒストグラム）。
+    /// 先頭が最小値、末尾が最大値で、値が 1 つもなければ空。
+    pub histogram: Vec<Value>,
+}
+
+impl TableStats {
+    /// テーブルを全件走査して統計情報を集める。
+    pub fn collect(table: &Table) -> Self {
+        let columns = table
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, name)| ColumnStats::collect(name, table.rows().map(|row| &row[i])))
+            .collect();
+        TableStats { row_count: table.row_count(), columns }
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnStats> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+}
+
+impl ColumnStats {
+    fn collect<'a>(name: &str, values: impl Iterator<Item = &'a Value>) -> Self {
+        let mut total = 0;
+        // '1' と 1 が同じ値になるよう、数値アフィニティを適用してから数える
+        let mut sorted: Vec<Value> = values
+            .inspect(|_| total += 1)
+            .filter(|v| !v.is_null())
+            .map(|v| v.clone().numeric_affinity())
+            .collect();
+        sorted.sort();
+        let distinct = sorted.iter().collect::<HashSet<_>>().len();
+        let null_fraction = if total == 0 { 0.0 } else { (total - sorted.len()) as f64 / total as f64 };
+        let histogram = match sorted.len() {
+            0 => Vec::new(),
+            n => (0..=HISTOGRAM_BUCKETS).map(|b| sorted[b * (n - 1) / HISTOGRAM_BUCKETS].clone()).collect(),
+        };
+        ColumnStats { name: name.to_string(), distinct, null_fraction, histogram }
+    }
+
+    /// `column = 定数` を満たす行の割合の見積もり。値は一様に分布しているとみなす。
+    pub fn eq_selectivity(&self) -> f64 {
+        match self.distinct {
+            0 => 0.0,
+            n => (1.0 - self.null_fraction) / n as f64,
+        }
+    }
+
+    /// `column < value` を満たす行の割合の見積もり。ヒストグラムで `value` が何番目のバケットに入るかを求め、
+    /// 数値ならバケット内を線形補間する。
+    pub fn lt_selectivity(&self, value: &Value) -> f64 {
+        let bounds = &self.histogram;
+        let value = value.clone().numeric_affinity();
+        let (Some(first), Some(last)) = (bounds.first(), bounds.last()) else {
+            return 0.0;
+        };
+        let fraction = if value.sql_cmp(first) != Some(Ordering::Greater) {
+            0.0
+        } else if value.sql_cmp(last) == Some(Ordering::Greater) {
+            1.0
+        } else {
+            let buckets = (bounds.len() - 1) as f64;
+            let i = bounds.windows(2).position(|b| value.sql_cmp(&b[1]) != Some(Ordering::Greater)).unwrap_or(0);
+            let within = match (bounds[i].as_f64(), bounds[i + 1].as_f64(), value.as_f64()) {
+                (Some(lo), Some(hi), Some(v)) if hi > lo => (v - lo) / (hi - lo),
+                _ => 0.5,
+            };
+            (i as f64 + within) / buckets
+        };
+        fraction * (1.0 - self.null_fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect() {
+        let mut table = Table::new(vec!["id".into(), "age".into()], vec![Value::Null, Value::Null]);
+        let rows = (1..=100).map(|i| vec![Value::Integer(i), if i % 4 == 0 { Value::Null } else { Value::Integer(i % 10) }]);
+        table.insert_rows(rows.collect()).unwrap();
+        let stats = TableStats::collect(&table);
+        assert_eq!(stats.row_count, 100);
+        let id = stats.column("id").unwrap();
+        assert_eq!((id.distinct, id.null_fraction), (100, 0.0));
+        assert_eq!(id.histogram.len(), HISTOGRAM_BUCKETS + 1);
+        assert_eq!((id.histogram[0].clone(), id.histogram[10].clone()), (Value::Integer(1), Value::Integer(100)));
+        let age = stats.column("age").unwrap();
+        assert_eq!((age.distinct, age.null_fraction), (10, 0.25));
+        assert!((age.eq_selectivity() - 0.075).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_lt_selectivity() {
+        let mut table = Table::new(vec!["id".into()], vec![Value::Null]);
+        table.insert_rows((1..=1000).map(|i| vec![Value::Integer(i)]).collect()).unwrap();
+        let id = TableStats::collect(&table).columns.remove(0);
+        assert_eq!(id.lt_selectivity(&Value::Integer(0)), 0.0);
+        assert_eq!(id.lt_selectivity(&Value::Integer(2000)), 1.0);
+        assert!((id.lt_selectivity(&Value::Integer(250)) - 0.25).abs() < 0.01);
+        // 数値に見える文字列も数値として比べる
+        assert!((id.lt_selectivity(&Value::Text("500".into())) - 0.5).abs() < 0.01);
+    }
+}
@@ -1,101 +1,162 @@
 use serde::{Deserialize, Serialize};
 use crate::btree::BPlusTree;
+use crate::error::DbError;
+use crate::parser::ast::ForeignKey;
+use crate::value::Value;
+use std::collections::HashSet;
 
-#[derive(Serialize, Deserialize)]
+/// 主キーの値から B+Tree のキーを作る。行には書かれたとおりの値を格納するが、`'1'` と `1` や
+/// `'01'` と `1` のように数値として等しいキーは、どちらを先に格納しても同じキーとして扱う。
+fn tree_key(key: &Value) -> Value {
+    key.clone().numeric_affinity()
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Table {
     pub columns: Vec<String>,
-    pub data: BPlusTree<String, Vec<String>>,
+    /// カラムごとの既定値。INSERT でカラムが省略されたときに使う（DEFAULT 指定がなければ NULL）。
+    #[serde(default)]
+    pub defaults: Vec<Value>,
+    pub data: BPlusTree<Value, Vec<Value>>,
+    /// このテーブルから他のテーブル（自分自身も可）の主キーへの外部キー。
+    #[serde(default)]
+    pub foreign_keys: Vec<ForeignKey>,
 }
 
 impl Table {
-    pub fn new(columns: Vec<String>) -> Self {
+    pub fn new(columns: Vec<String>, defaults: Vec<Value>) -> Self {
         Table {
             columns,
+            defaults,
             data: BPlusTree::new(2), // プライマリキーは最初のカラム（例: id）を想定
+            foreign_keys: Vec::new(),
         }
     }
-    
+
+    /// `index` 番目のカラムの既定値
+    pub fn default_value(&self, index: usize) -> Value {
+        self.defaults.get(index).cloned().unwrap_or(Value::Null)
+    }
+
     /// B+Tree のルートを取得するゲッター
-    pub fn get_root(&self) -> Option<&crate::btree::BPlusTreeNode<String, Vec<String>>> {
+    pub fn get_root(&self) -> Option<&crate::btree::BPlusTreeNode<Value, Vec<Value>>> {
         self.data.get_root()
     }
 
-    /// 全ての行を取得する (B+Tree の葉ノードを連結リストとして辿る)
-    /// 全ての行を取得する（B+Tree の全葉ノードを再帰的に走査）
-    pub fn get_all_rows(&self) -> Vec<Vec<String>> {
-        let mut rows = Vec::new();
-        if let Some(root) = self.get_root() {
-            Self::traverse_leaves(root, &mut rows);
+    /// プライマリキーのカラム名（最初のカラム）
+    pub fn primary_key(&self) -> Option<&str> {
+        self.columns.first().map(|s| s.as_str())
+    }
+
+    /// 全ての行を取得する（B+Tree の全葉ノードを主キーの順に走査）
+    pub fn get_all_rows(&self) -> Vec<Vec<Value>> {
+        self.rows().cloned().collect()
+    }
+
+    /// 全ての行を主キーの順に 1 行ずつ返すカーソル。行は複製しない。
+    pub fn rows(&self) -> impl Iterator<Item = &Vec<Value>> {
+        self.data.iter().map(|(_, row)| row)
+    }
+
+    /// プライマリキーで 1 行を検索する（B+Tree の search を使用）。
+    pub fn find_by_key(&self, key: &Value) -> Option<&Vec<Value>> {
+        self.data.search(&tree_key(key))
+    }
+
+    /// 行数。B+Tree のキー数を数えるだけで、行の値は走査しない。
+    pub fn row_count(&self) -> usize {
+        self.data.key_count()
+    }
+
+    /// 行をまとめて挿入し、格納した行を返す。最初のカラムの値をプライマリキーとして B+Tree に登録する。
+    /// 主キーが NULL の行や、既存の行・同じ文の中の他の行と主キーが重複する行があれば、1 行も挿入しない。
+    pub fn insert_rows(&mut self, rows: Vec<Vec<Value>>) -> Result<Vec<Vec<Value>>, DbError> {
+        let rows = self.prepare_rows(rows)?;
+        if let Some(row) = rows.iter().find(|row| self.find_by_key(&row[0]).is_some()) {
+            return Err(DbError::UniqueViolation(row[0].to_string()));
         }
-        rows
-    }
-    
-    fn traverse_leaves(node: &crate::btree::BPlusTreeNode<String, Vec<String>>, rows: &mut Vec<Vec<String>>) {
-        if node.is_leaf() {
-            if let Some(vals) = node.values() {
-                for row in vals {
-                    rows.push(row.clone());
-                }
-            }
-        } else if let Some(children) = node.children() {
-            for child in children {
-                Self::traverse_leaves(child, rows);
-            }
+        for row in &rows {
+            self.data.insert(tree_key(&row[0]), row.clone());
         }
+        Ok(rows)
     }
 
-    /// データ行を挿入する。最初のカラムの値をプライマリキーとして B+Tree に登録する。
-    pub fn insert(&mut self, values: Vec<String>) {
-        let key = values.get(0).cloned().unwrap_or_default();
-        if let Some(_) = self.data.search(&key) {
-            println!("Error: Duplicate primary key '{}'. Insert failed.", key);
-            return;
+    /// 行をまとめて書き込み、格納した行を返す。同じ主キーの行があれば置き換える。
+    pub fn upsert_rows(&mut self, rows: Vec<Vec<Value>>) -> Result<Vec<Vec<Value>>, DbError> {
+        let rows = self.prepare_rows(rows)?;
+        for row in &rows {
+            self.data.upsert(tree_key(&row[0]), row.clone());
         }
-        self.data.insert(key, values);
+        Ok(rows)
     }
 
-    /// 全件検索して結果を表示する
-    pub fn select_all_with_columns(&self, selected_columns: Vec<String>) {
-        println!("Columns: {:?}", selected_columns);
-        let rows = self.get_all_rows();
-        // 表示するカラムが "*" の場合は全カラム表示
-        if selected_columns.len() == 1 && selected_columns[0] == "*" {
-            for row in rows {
-                println!("Row: {:?}", row);
-            }
-        } else {
-            // selected_columns に応じたカラムのインデックスを特定する
-            let indices: Vec<usize> = selected_columns.iter()
-                .filter_map(|col| self.columns.iter().position(|c| c == col))
-                .collect();
-            for row in rows {
-                let filtered: Vec<&String> = indices.iter().filter_map(|&i| row.get(i)).collect();
-                println!("Row: {:?}", filtered);
+    /// `(元の主キー, 新しい行)` の組で行をまとめて更新し、格納した行を返す。
+    /// 主キーを変える行は、いったん削除してから新しいキーで挿入する。
+    /// 新しい主キーが NULL になる行や、更新しない行・他の更新後の行と主キーが重複する行があれば、1 行も更新しない。
+    pub fn update_rows(&mut self, updates: Vec<(Value, Vec<Value>)>) -> Result<Vec<Vec<Value>>, DbError> {
+        let (old_keys, rows): (Vec<Value>, Vec<Vec<Value>>) = updates.into_iter().unzip();
+        let rows = self.prepare_rows(rows)?;
+        let old_keys: Vec<Value> = old_keys.iter().map(tree_key).collect();
+        let moved: HashSet<&Value> = old_keys.iter().zip(&rows).filter(|(old, row)| **old != tree_key(&row[0])).map(|(old, _)| old).collect();
+        for (old, row) in old_keys.iter().zip(&rows) {
+            let key = tree_key(&row[0]);
+            if *old != key && self.data.search(&key).is_some() && !moved.contains(&key) {
+                return Err(DbError::UniqueViolation(row[0].to_string()));
             }
         }
+        for key in &moved {
+            self.data.remove(key);
+        }
+        for row in &rows {
+            self.data.upsert(tree_key(&row[0]), row.clone());
+        }
+        Ok(rows)
     }
 
-    /// WHERE 句検索：指定カラムの値が search_value と一致する行をフィルタリングして返す
-    /// プライマリキーの場合は B+Tree の search を使い、その他は全件走査してフィルタリングする
-    pub fn select_where(&self, column_name: &str, search_value: &str) -> Vec<Vec<String>> {
-        // 主キー検索：最初のカラムの場合
-        if self.columns.get(0).map(|s| s == column_name).unwrap_or(false) {
-            if let Some(row) = self.data.search(&search_value.to_string()) {
-                return vec![row.clone()];
-            }
-            return Vec::new();
+    /// 主キーで行をまとめて削除し、削除した行を返す。
+    pub fn delete_rows(&mut self, keys: &[Value]) -> Vec<Vec<Value>> {
+        keys.iter().filter_map(|key| self.data.remove(&tree_key(key))).collect()
+    }
+
+    /// 書き込みを取り消す。`written` の主キーの行を消してから、`replaced` の行を元どおりに格納する。
+    pub fn revert(&mut self, written: &[Value], replaced: Vec<Vec<Value>>) {
+        for key in written {
+            self.data.remove(&tree_key(key));
+        }
+        for row in replaced {
+            self.data.upsert(tree_key(&row[0]), row);
         }
-        // それ以外は全件走査してフィルタリング
-        let all_rows = self.get_all_rows();
-        let column_index = self.columns.iter().position(|col| col == column_name);
-        if let Some(index) = column_index {
-            all_rows
-                .into_iter()
-                .filter(|row| row.get(index) == Some(&search_value.to_string()))
-                .collect()
-        } else {
-            println!("Error: Column '{}' does not exist.", column_name);
-            Vec::new()
+    }
+
+    /// 書き込む前の行を検証する。値は書かれたとおりに格納し、`'007'` のような文字列を数値にはしない。
+    fn prepare_rows(&self, rows: Vec<Vec<Value>>) -> Result<Vec<Vec<Value>>, DbError> {
+        let mut keys = HashSet::new();
+        for row in &rows {
+            let key = row.first().cloned().unwrap_or(Value::Null);
+            if key.is_null() {
+                return Err(DbError::NotNullViolation(self.primary_key().unwrap_or_default().to_string()));
+            }
+            if !keys.insert(tree_key(&key)) {
+                return Err(DbError::UniqueViolation(key.to_string()));
+            }
         }
+        Ok(rows)
+    }
+
+    /// 主キーをそのまま B+Tree のキーにしていた旧形式のテーブルを、行を入れ直して作り直す。
+    pub fn rebuild(self) -> Result<Table, DbError> {
+        let mut table = Table::new(self.columns, self.defaults);
+        table.foreign_keys = self.foreign_keys;
+        table.insert_rows(self.data.iter().map(|(_, row)| row.clone()).collect())?;
+        Ok(table)
+    }
+
+    /// 値をすべて文字列で保存していた旧形式のテーブルを、数値に見える値を数値にして作り直す。
+    pub fn migrate(self) -> Result<Table, DbError> {
+        let affinity = |row: Vec<Value>| row.into_iter().map(Value::numeric_affinity).collect::<Vec<_>>();
+        let mut table = Table::new(self.columns, affinity(self.defaults));
+        table.foreign_keys = self.foreign_keys;
+        table.insert_rows(self.data.iter().map(|(_, row)| affinity(row.clone())).collect())?;
+        Ok(table)
     }
 }
@@ -0,0 +1,157 @@
+use crate::error::DbError;
+use crate::parser::ast::{TriggerEvent, TriggerTiming};
+use crate::parser::lexer::{to_sql, tokenize, Token};
+use crate::parser::{is_reserved, parse_query, Query};
+use crate::value::Value;
+use serde::{Deserialize, Serialize};
+
+/// トリガーに渡す 1 行分の (NEW, OLD)。INSERT では OLD が、DELETE では NEW が None。
+pub type RowPair<'a> = (Option<&'a [Value]>, Option<&'a [Value]>);
+
+/// `CREATE TRIGGER` で定義した行トリガー。書き込む行ごとに本体の文を実行する。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trigger {
+    pub name: String,
+    pub timing: TriggerTiming,
+    pub event: TriggerEvent,
+    pub table: String,
+    /// 本体の文の SQL。`NEW.column` / `OLD.column` を含んだまま保存し、発火するたびに解析する。
+    pub body: Vec<String>,
+}
+
+impl Trigger {
+    /// 本体の文を解析し、`NEW.column` / `OLD.column` をパラメータに置き換える。
+    /// `columns` はテーブルのカラムで、パラメータは `params` の並び（NEW のカラム、続いて OLD のカラム）を指す。
+    pub fn compile(&self, columns: &[String]) -> Result<Vec<Query>, DbError> {
+        self.body.iter().map(|sql| parse_query(&to_sql(&self.bind_rows(tokenize(sql)?, columns)?))).collect()
+    }
+
+    fn bind_rows(&self, mut tokens: Vec<Token>, columns: &[String]) -> Result<Vec<Token>, DbError> {
+        // `FROM hist old` のように NEW / OLD をテーブル名や別名に使うと、`old.x` がどちらを指すか決められない
+        if let Some(word) = row_names_as_relation(&tokens) {
+            return Err(DbError::InvalidQuery(format!(
+                "'{}' cannot be used as a table name or alias in a trigger body; {}.column refers to the {} row.",
+                word,
+                word.to_uppercase(),
+                self.event
+            )));
+        }
+        let mut i = 0;
+        while i + 2 < tokens.len() {
+            let row = match &tokens[i] {
+                Token::Ident(word) if word.eq_ignore_ascii_case("NEW") || word.eq_ignore_ascii_case("OLD") => word.to_uppercase(),
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let (Token::Dot, Token::Ident(name)) = (&tokens[i + 1], &tokens[i + 2]) else {
+                i += 1;
+                continue;
+            };
+            // INSERT には変更前の行が、DELETE には変更後の行がない
+            let offset = match (row.as_str(), self.event) {
+                ("NEW", TriggerEvent::Delete) | ("OLD", TriggerEvent::Insert) => {
+                    return Err(DbError::InvalidQuery(format!("{} triggers have no {} row.", self.event, row)))
+                }
+                ("NEW", _) => 0,
+                _ => columns.len(),
+            };
+            let index = columns
+                .iter()
+                .position(|c| c == name)
+                .ok_or_else(|| DbError::UndefinedColumn(format!("{}.{}", row, name)))?;
+            tokens.splice(i..i + 3, [Token::Parameter(offset + index)]);
+            i += 1;
+        }
+        Ok(tokens)
+    }
+}
+
+/// NEW / OLD がテーブル名や別名の位置（AS・`)`・FROM・JOIN や、予約語でない語の直後で `.` が続かないところ）に
+/// あれば、その語を返す。
+fn row_names_as_relation(tokens: &[Token]) -> Option<&str> {
+    tokens.windows(2).enumerate().find_map(|(i, pair)| {
+        let Token::Ident(word) = &pair[1] else {
+            return None;
+        };
+        if !(word.eq_ignore_ascii_case("NEW") || word.eq_ignore_ascii_case("OLD")) || tokens.get(i + 2) == Some(&Token::Dot) {
+            return None;
+        }
+        let relation = match &pair[0] {
+            Token::RParen => true,
+            Token::Ident(prev) => ["AS", "FROM", "JOIN"].iter().any(|kw| prev.eq_ignore_ascii_case(kw)) || !is_reserved(prev),
+            _ => false,
+        };
+        relation.then_some(word.as_str())
+    })
+}
+
+/// 本体の文に渡すパラメータ。`width` はテーブルのカラム数で、ない側の行は NULL で埋める。
+pub fn params(row: RowPair, width: usize) -> Vec<Value> {
+    let (new, old) = row;
+    [new, old]
+        .into_iter()
+        .flat_map(|values| match values {
+            Some(values) => values.to_vec(),
+            None => vec![Value::Null; width],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile() {
+        let trigger = Trigger {
+            name: "t".into(),
+            timing: TriggerTiming::After,
+            event: TriggerEvent::Update,
+            table: "users".into(),
+            body: vec!["INSERT INTO log VALUES (old.id, NEW.name, 'it''s')".into()],
+        };
+        let columns = vec!["id".to_string(), "name".to_string()];
+        let Query::Insert(insert) = trigger.compile(&columns).unwrap().remove(0) else {
+            panic!("not an insert");
+        };
+        assert_eq!(insert.table_name, "log");
+        let sql = to_sql(&trigger.bind_rows(tokenize(&trigger.body[0]).unwrap(), &columns).unwrap());
+        assert_eq!(sql, "INSERT INTO log VALUES ( $3 , $2 , 'it''s' )");
+        assert_eq!(
+            params((Some(&[Value::Integer(1), Value::from("7")]), None), 2),
+            vec![Value::Integer(1), Value::from("7"), Value::Null, Value::Null]
+        );
+
+        let err = Trigger { event: TriggerEvent::Insert, ..trigger.clone() }.compile(&columns).unwrap_err();
+        assert_eq!(err.to_string(), "INSERT triggers have no OLD row.");
+        assert_eq!(trigger.compile(&["id".to_string()]).unwrap_err().code(), "42703");
+    }
+
+    #[test]
+    fn test_row_names_as_alias() {
+        let trigger = |sql: &str| Trigger {
+            name: "t".into(),
+            timing: TriggerTiming::After,
+            event: TriggerEvent::Update,
+            table: "users".into(),
+            body: vec![sql.into()],
+        };
+        let columns = vec!["id".to_string(), "x".to_string()];
+        // 別名の old.x を OLD の行のカラムに置き換えず、エラーにする
+        for sql in [
+            "INSERT INTO log SELECT old.x FROM hist old",
+            "INSERT INTO log SELECT Old.x FROM hist AS Old",
+            "INSERT INTO log SELECT new.x FROM hist h JOIN new ON h.id = new.id",
+            "INSERT INTO log SELECT new.x FROM (SELECT 1 AS x) new",
+            "UPDATE old SET x = old.x",
+        ] {
+            let err = trigger(sql).compile(&columns).unwrap_err();
+            assert!(err.to_string().contains("cannot be used as a table name or alias"), "{}", sql);
+        }
+        // カラム名や値としての new / old はそのまま使える
+        let sql = "INSERT INTO log (old, new) SELECT old, NEW.x FROM hist WHERE old = OLD.id";
+        assert!(trigger(sql).compile(&columns).is_ok());
+    }
+}
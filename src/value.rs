@@ -0,0 +1,328 @@
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// テーブルに格納される値、および式評価の結果を表す型。
+/// JSON へはタグなしで保存する（NULL は null、数値は number、文字列は string）。
+#[derive(Debug, Clone)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Value::Integer(_) | Value::Float(_))
+    }
+
+    /// 型指定のないカラム向けの数値アフィニティ。
+    /// 数値として解釈できる文字列は Integer / Float に変換し、それ以外はそのまま返す。
+    /// これにより `VALUES ('1', ...)` と `VALUES (1, ...)` が同じキーとして扱われる。
+    pub fn numeric_affinity(self) -> Value {
+        match self {
+            Value::Text(s) => match parse_number(&s) {
+                Some(v) => v,
+                None => Value::Text(s),
+            },
+            other => other,
+        }
+    }
+
+    /// 数値として扱える場合は f64 を返す（数値に見える文字列も含む）。
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            Value::Text(s) => match parse_number(s)? {
+                Value::Integer(i) => Some(i as f64),
+                Value::Float(f) => Some(f),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// WHERE 句などで使う真偽判定。NULL は偽として扱う。
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Boolean(b) => *b,
+            Value::Integer(i) => *i != 0,
+            Value::Float(f) => *f != 0.0,
+            Value::Text(_) => self.as_f64().map(|f| f != 0.0).unwrap_or(false),
+        }
+    }
+
+    /// SQL の比較演算子の意味での比較。どちらかが NULL の場合は None を返す。
+    /// 文字列と数値の比較では、文字列が数値として解釈できれば数値として比較する。
+    pub fn sql_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Null, _) | (_, Value::Null) => None,
+            (Value::Text(_), n) if n.is_numeric() => {
+                let coerced = self.clone().numeric_affinity();
+                Some(coerced.cmp(other))
+            }
+            (n, Value::Text(_)) if n.is_numeric() => {
+                let coerced = other.clone().numeric_affinity();
+                Some(self.cmp(&coerced))
+            }
+            _ => Some(self.cmp(other)),
+        }
+    }
+
+    fn type_rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Boolean(_) => 1,
+            Value::Integer(_) | Value::Float(_) => 2,
+            Value::Text(_) => 3,
+        }
+    }
+}
+
+/// 文字列を数値として解釈する。整数として読めれば Integer、そうでなければ Float を試す。
+pub fn parse_number(s: &str) -> Option<Value> {
+    let trimmed = s.trim();
+    if trimmed.is_empty() || !trimmed.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    if let Ok(i) = trimmed.parse::<i64>() {
+        return Some(Value::Integer(i));
+    }
+    match trimmed.parse::<f64>() {
+        Ok(f) if f.is_finite() => Some(Value::Float(f)),
+        _ => None,
+    }
+}
+
+/// 整数と浮動小数点数を、f64 に丸めずに数として比較する。
+/// 2^53 を超える整数でも、等しくなるのは Float がちょうど同じ整数を表すときだけ。
+fn cmp_integer_float(i: i64, f: f64) -> Ordering {
+    // i64 の範囲は [-2^63, 2^63)
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if f.is_nan() {
+        return (i as f64).total_cmp(&f);
+    }
+    if f >= LIMIT {
+        return Ordering::Less;
+    }
+    if f < -LIMIT {
+        return Ordering::Greater;
+    }
+    let whole = f.trunc();
+    i.cmp(&(whole as i64)).then_with(|| 0.0.partial_cmp(&(f - whole)).unwrap_or(Ordering::Equal))
+}
+
+/// B+Tree のキーや GROUP BY のグループ化に使う全順序。
+/// NULL < 真偽値 < 数値 < 文字列 の順で、Integer と Float は数値として比較する。
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Boolean(a), Value::Boolean(b)) => a.cmp(b),
+            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+            (Value::Integer(a), Value::Float(b)) => cmp_integer_float(*a, *b),
+            (Value::Float(a), Value::Integer(b)) => cmp_integer_float(*b, *a).reverse(),
+            // 0.0 と -0.0 は等しく、NaN は total_cmp の順に並べる
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b).unwrap_or_else(|| a.total_cmp(b)),
+            (Value::Text(a), Value::Text(b)) => a.cmp(b),
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_rank().hash(state);
+        match self {
+            Value::Null => {}
+            Value::Boolean(b) => b.hash(state),
+            Value::Integer(i) => i.hash(state),
+            // 整数値と等しい Float は Integer と同じハッシュにする（Eq との整合性のため）
+            Value::Float(f) => {
+                if f.fract() == 0.0 && *f >= i64::MIN as f64 && *f < i64::MAX as f64 {
+                    (*f as i64).hash(state)
+                } else {
+                    f.to_bits().hash(state)
+                }
+            }
+            Value::Text(s) => s.hash(state),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Boolean(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Float(x) => {
+                if x.fract() == 0.0 && x.is_finite() {
+                    write!(f, "{:.1}", x)
+                } else {
+                    write!(f, "{}", x)
+                }
+            }
+            Value::Text(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => serializer.serialize_none(),
+            Value::Boolean(b) => serializer.serialize_bool(*b),
+            Value::Integer(i) => serializer.serialize_i64(*i),
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::Text(s) => serializer.serialize_str(s),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ValueVisitor;
+
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = Value;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("null, a boolean, a number or a string")
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+                Ok(Value::Null)
+            }
+
+            fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+                Ok(Value::Null)
+            }
+
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+                Ok(Value::Boolean(v))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+                Ok(Value::Integer(v))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+                match i64::try_from(v) {
+                    Ok(i) => Ok(Value::Integer(i)),
+                    Err(_) => Ok(Value::Float(v as f64)),
+                }
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+                Ok(Value::Float(v))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+                Ok(Value::Text(v.to_string()))
+            }
+
+            fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+                Ok(Value::Text(v))
+            }
+        }
+
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+/// プリペアドステートメントへのバインドなど、Rust の値から `Value` を作るための変換。
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Integer(v)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(v: i32) -> Self {
+        Value::Integer(v as i64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Float(v)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Boolean(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Text(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Text(v)
+    }
+}
+
+/// None は NULL になる。
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::Null, Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash(value: &Value) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_integer_float_equality() {
+        // 2^53 + 1 は f64 で表せないので、2^53 の Float とは等しくない
+        let big = Value::Integer(9_007_199_254_740_993);
+        assert_ne!(big, Value::Float(9_007_199_254_740_992.0));
+        assert!(big > Value::Float(9_007_199_254_740_992.0));
+        assert!(Value::Float(9_007_199_254_740_994.0) > big);
+        assert!(Value::Integer(i64::MAX) < Value::Float(9_223_372_036_854_775_808.0));
+        assert!(Value::Integer(1) < Value::Float(1.5) && Value::Integer(-1) > Value::Float(-1.5));
+        // 等しい値は同じハッシュになる
+        for (a, b) in [(Value::Integer(3), Value::Float(3.0)), (Value::Integer(0), Value::Float(-0.0)), (Value::Integer(1 << 60), Value::Float((1u64 << 60) as f64))] {
+            assert_eq!(a, b);
+            assert_eq!(hash(&a), hash(&b));
+        }
+    }
+}